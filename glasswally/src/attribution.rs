@@ -0,0 +1,405 @@
+// glasswally/src/attribution.rs
+//
+// Offline attribution of a suspect dataset to the accounts it was scraped from.
+//
+// Canary tokens and watermarks are planted in responses to watermarked
+// accounts (engine/dispatcher.rs, response_rewriter.rs).  When a leaked or
+// published training set turns up, `glasswally attribute-dataset` streams it
+// line by line and looks for:
+//
+//   canary tokens   — registered tokens as 32-char hex or in their invisible
+//                     encoding (watermark::decode_canaries), anywhere in the row
+//   ZW watermark    — ZWJ/ZWNJ bits (watermark::aligned_bits) agreeing with a
+//                     registered account's `account_watermark_bits`
+//   robust mark     — optionally, the synonym/punctuation watermark over the
+//                     whole corpus (robust_watermark.rs, `--robust-key`)
+//
+// The registry comes from the running detector: every `--canary-snapshot-interval`
+// seconds the StateStore's canary registry is written to
+// <output>/canary_registry.jsonl, one token per line with the origin account's
+// cluster at snapshot time.  Copy that file next to the dataset.
+//
+// Confidence per account:
+//   canary tokens are 128-bit random values, so a single match is conclusive
+//   (confidence 1.0);
+//   watermark bits are pooled over *every* row that carries any — k bits,
+//   m agreeing with the account's key — and scored with the exact binomial
+//   tail P(Binomial(k, ½) ≥ m), Bonferroni-adjusted over the registered
+//   accounts; confidence = 1 − adjusted p.  Pooling only rows that already
+//   looked like a match would make noise significant.  An account enters the
+//   report on a canary match, a single row that is conclusive on its own
+//   (watermark::detect, counted in watermark_rows), or a pooled adjusted p
+//   below watermark::DETECT_ALPHA.
+//
+// Input is JSONL or plain text, one sample per line.  Columnar formats
+// (Parquet, Arrow) should be exported to JSONL first.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::events::CanaryToken;
+use crate::robust_watermark::{self, DetectReport, RobustWatermark};
+use crate::state::window::StateStore;
+use crate::workers::watermark;
+
+const TOKEN_LEN: usize = 32;
+
+/// One line of canary_registry.jsonl.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    #[serde(flatten)]
+    pub canary: CanaryToken,
+    #[serde(default)]
+    pub cluster_id: Option<u32>,
+}
+
+/// Write the StateStore's canary registry (temp file + rename, so a reader
+/// never sees a partial snapshot).  Returns the number of tokens written.
+pub fn write_registry_snapshot(store: &StateStore, path: &Path) -> Result<usize> {
+    let tmp = path.with_extension("jsonl.tmp");
+    let mut out = std::io::BufWriter::new(
+        std::fs::File::create(&tmp).with_context(|| format!("creating {}", tmp.display()))?,
+    );
+    let canaries = store.canaries();
+    for canary in &canaries {
+        let entry = RegistryEntry {
+            cluster_id: store.get_cluster(&canary.account_id),
+            canary: canary.clone(),
+        };
+        serde_json::to_writer(&mut out, &entry)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    drop(out);
+    std::fs::rename(&tmp, path).with_context(|| format!("replacing {}", path.display()))?;
+    Ok(canaries.len())
+}
+
+pub fn load_registry(path: &Path) -> Result<HashMap<String, RegistryEntry>> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("opening canary registry {}", path.display()))?;
+    let mut registry = HashMap::new();
+    for (n, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: RegistryEntry = serde_json::from_str(&line)
+            .with_context(|| format!("{}:{}: bad registry entry", path.display(), n + 1))?;
+        registry.insert(entry.canary.token.clone(), entry);
+    }
+    Ok(registry)
+}
+
+// ── Report ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default, Serialize)]
+pub struct AccountAttribution {
+    pub account_id: String,
+    pub cluster_id: Option<u32>,
+    pub canary_matches: u64,
+    pub distinct_tokens: usize,
+    pub watermark_rows: u64,
+    pub watermark_bits: usize,
+    pub watermark_matching_bits: usize,
+    pub first_seen: Option<DateTime<Utc>>, // earliest serving time of a matched token
+    pub first_row: u64, // rows with direct evidence; 0 when only pooled bits implicate
+    pub last_row: u64,
+    pub p_value: f64,
+    pub confidence: f64,
+    #[serde(skip)]
+    tokens: BTreeSet<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClusterAttribution {
+    pub cluster_id: u32,
+    pub accounts: Vec<String>,
+    pub canary_matches: u64,
+    pub watermark_rows: u64,
+    pub first_seen: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttributionReport {
+    pub dataset: String,
+    pub rows: u64,
+    pub rows_with_evidence: u64,
+    pub registered_tokens: usize,
+    pub accounts: Vec<AccountAttribution>,
+    pub clusters: Vec<ClusterAttribution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub robust_watermark: Option<DetectReport>,
+}
+
+// ── Scan ──────────────────────────────────────────────────────────────────────
+
+fn hex_runs(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_ascii_hexdigit())
+        .filter(|run| run.len() == TOKEN_LEN)
+}
+
+/// Stream `dataset` and attribute it against the registry.
+pub fn scan_file(
+    dataset: &Path,
+    registry: &HashMap<String, RegistryEntry>,
+    robust: Option<&RobustWatermark>,
+) -> Result<AttributionReport> {
+    let file =
+        std::fs::File::open(dataset).with_context(|| format!("opening {}", dataset.display()))?;
+
+    let registered: Vec<String> = registry
+        .values()
+        .map(|e| e.canary.account_id.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let cluster_of: HashMap<&str, u32> = registry
+        .values()
+        .filter_map(|e| e.cluster_id.map(|c| (e.canary.account_id.as_str(), c)))
+        .collect();
+
+    let keys: Vec<[bool; 32]> = registered
+        .iter()
+        .map(|a| watermark::account_watermark_bits(a))
+        .collect();
+    // Per registered account: (bits checked, bits agreeing) over all rows.
+    let mut pooled = vec![(0usize, 0usize); registered.len()];
+
+    let mut accounts: BTreeMap<String, AccountAttribution> = BTreeMap::new();
+    let mut votes = robust_watermark::Votes::default();
+    let mut rows = 0u64;
+    let mut rows_with_evidence = 0u64;
+
+    for line in std::io::BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        rows += 1;
+        let mut evidence = false;
+
+        let plain = hex_runs(&line).map(str::to_ascii_lowercase);
+        let encoded = watermark::decode_canaries(&line).into_iter();
+        for token in plain.chain(encoded) {
+            let Some(entry) = registry.get(&token) else {
+                continue;
+            };
+            let a = account_entry(&mut accounts, &entry.canary.account_id, rows);
+            a.canary_matches += 1;
+            a.tokens.insert(token);
+            a.first_seen = Some(match a.first_seen {
+                Some(t) => t.min(entry.canary.inserted_at),
+                None => entry.canary.inserted_at,
+            });
+            evidence = true;
+        }
+
+        let texts = robust_watermark::line_texts(&line);
+        for text in &texts {
+            let bits = watermark::aligned_bits(text);
+            if !bits.is_empty() {
+                for (key, (k, m)) in keys.iter().zip(pooled.iter_mut()) {
+                    *k += bits.len();
+                    *m += bits
+                        .iter()
+                        .enumerate()
+                        .filter(|&(i, &b)| b == key[i % 32])
+                        .count();
+                }
+                if let Some(m) = watermark::detect(text, &registered) {
+                    account_entry(&mut accounts, &m.account_id, rows).watermark_rows += 1;
+                    evidence = true;
+                }
+            }
+            if let Some(wm) = robust {
+                wm.vote(text, &mut votes);
+            }
+        }
+        if evidence {
+            rows_with_evidence += 1;
+        }
+    }
+
+    let n_candidates = registered.len().max(1) as f64;
+    for (account_id, &(k, m)) in registered.iter().zip(&pooled) {
+        let p = (robust_watermark::binomial_tail(k, m) * n_candidates).min(1.0);
+        if p >= watermark::DETECT_ALPHA && !accounts.contains_key(account_id) {
+            continue;
+        }
+        let a = accounts
+            .entry(account_id.clone())
+            .or_insert_with(|| AccountAttribution {
+                account_id: account_id.clone(),
+                ..Default::default()
+            });
+        a.watermark_bits = k;
+        a.watermark_matching_bits = m;
+        a.p_value = if a.canary_matches > 0 { 0.0 } else { p };
+    }
+    for a in accounts.values_mut() {
+        a.cluster_id = cluster_of.get(a.account_id.as_str()).copied();
+        a.distinct_tokens = a.tokens.len();
+        a.confidence = 1.0 - a.p_value;
+    }
+
+    let mut clusters: BTreeMap<u32, ClusterAttribution> = BTreeMap::new();
+    for a in accounts.values() {
+        let Some(cluster_id) = a.cluster_id else {
+            continue;
+        };
+        let c = clusters
+            .entry(cluster_id)
+            .or_insert_with(|| ClusterAttribution {
+                cluster_id,
+                accounts: Vec::new(),
+                canary_matches: 0,
+                watermark_rows: 0,
+                first_seen: None,
+            });
+        c.accounts.push(a.account_id.clone());
+        c.canary_matches += a.canary_matches;
+        c.watermark_rows += a.watermark_rows;
+        c.first_seen = match (c.first_seen, a.first_seen) {
+            (Some(x), Some(y)) => Some(x.min(y)),
+            (x, y) => x.or(y),
+        };
+    }
+
+    let robust_watermark = robust.map(|wm| DetectReport {
+        rows,
+        slots: votes.slots,
+        recovered_bits: votes.decode().iter().filter(|b| b.is_some()).count(),
+        attributions: wm.attribute(&votes, &registered),
+    });
+
+    let mut accounts: Vec<AccountAttribution> = accounts.into_values().collect();
+    accounts.sort_by(|a, b| {
+        a.p_value
+            .total_cmp(&b.p_value)
+            .then(b.canary_matches.cmp(&a.canary_matches))
+            .then(b.watermark_rows.cmp(&a.watermark_rows))
+    });
+
+    Ok(AttributionReport {
+        dataset: dataset.display().to_string(),
+        rows,
+        rows_with_evidence,
+        registered_tokens: registry.len(),
+        accounts,
+        clusters: clusters.into_values().collect(),
+        robust_watermark,
+    })
+}
+
+fn account_entry<'a>(
+    accounts: &'a mut BTreeMap<String, AccountAttribution>,
+    account_id: &str,
+    row: u64,
+) -> &'a mut AccountAttribution {
+    let a = accounts
+        .entry(account_id.to_string())
+        .or_insert_with(|| AccountAttribution {
+            account_id: account_id.to_string(),
+            first_row: row,
+            ..Default::default()
+        });
+    a.last_row = row;
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(n: usize) -> HashMap<String, RegistryEntry> {
+        (0..n)
+            .map(|i| {
+                let canary = CanaryToken::generate(&format!("acct_{i}"), "req");
+                let entry = RegistryEntry {
+                    canary,
+                    cluster_id: None,
+                };
+                (entry.canary.token.clone(), entry)
+            })
+            .collect()
+    }
+
+    fn scan(rows: &[String], registry: &HashMap<String, RegistryEntry>) -> AttributionReport {
+        let path = std::env::temp_dir().join(format!(
+            "glasswally_attr_{}_{}.jsonl",
+            std::process::id(),
+            rows.len()
+        ));
+        let body: String = rows
+            .iter()
+            .map(|r| format!("{}\n", serde_json::json!({ "text": r })))
+            .collect();
+        std::fs::write(&path, body).unwrap();
+        let report = scan_file(&path, registry, None).unwrap();
+        std::fs::remove_file(&path).ok();
+        report
+    }
+
+    #[test]
+    fn random_zw_noise_is_not_significant() {
+        let registry = registry(20);
+        // Short rows of random aligned joiners: some rows agree 7/8 or 8/8
+        // with some key by chance, which pooled alone looked conclusive.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let rows: Vec<String> = (0..2000)
+            .map(|_| {
+                (0..8)
+                    .map(|w| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        let mark = if state & 1 == 1 {
+                            '\u{200D}'
+                        } else {
+                            '\u{200C}'
+                        };
+                        format!("w{w} {mark}")
+                    })
+                    .collect()
+            })
+            .collect();
+        let report = scan(&rows, &registry);
+        assert_eq!(report.rows, 2000);
+        assert_eq!(report.rows_with_evidence, 0);
+        assert!(report.accounts.is_empty(), "{:?}", report.accounts);
+    }
+
+    #[test]
+    fn pooled_watermark_and_canary_attribute() {
+        let registry = registry(20);
+        // Rows too short to be conclusive alone, conclusive together.
+        let mut rows: Vec<String> = (0..40)
+            .map(|i| watermark::embed(&format!("row {i} has a few short words"), "acct_3"))
+            .collect();
+        let token = registry
+            .values()
+            .find(|e| e.canary.account_id == "acct_5")
+            .unwrap()
+            .canary
+            .token
+            .clone();
+        rows.push(format!("leaked {token}"));
+
+        let report = scan(&rows, &registry);
+        let by_id = |id: &str| report.accounts.iter().find(|a| a.account_id == id).unwrap();
+        let a3 = by_id("acct_3");
+        assert_eq!(a3.watermark_rows, 0);
+        assert_eq!(a3.watermark_bits, 40 * 6);
+        assert_eq!(a3.watermark_matching_bits, 40 * 6);
+        assert!(a3.p_value < 1e-9);
+        let a5 = by_id("acct_5");
+        assert_eq!((a5.canary_matches, a5.p_value), (1, 0.0));
+        assert_eq!(report.accounts.len(), 2);
+    }
+}
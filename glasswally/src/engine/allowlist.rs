@@ -0,0 +1,466 @@
+// glasswally/src/engine/allowlist.rs
+//
+// Allowlist / trusted-partner registry.
+//
+// Enterprise customers running legitimate batch evaluation from cloud hosts
+// with python-requests trip AsnClassifier, Fingerprint, Velocity and
+// SessionGap at once.  The registry lets trust & safety exempt them without
+// touching worker weights.
+//
+// Entries are keyed by one of:
+//   account:<id>  org:<id>  payment:<hash>  cidr:<ip/prefix>  ja3:<hash>  asn:<n>
+//
+// and carry a scope (events.rs ExemptionScope):
+//   Full          — no enforcement; decisions are produced as MONITOR and audited
+//   NoSuspension  — SUSPEND / CLUSTER_TAKEDOWN become FLAG_FOR_REVIEW; the
+//                   account is still rate limited, canaried and monitored
+//   NoCanary      — INJECT_CANARY becomes FLAG_FOR_REVIEW
+//
+// Entries may expire (`expires_at`); expired entries never match and are
+// pruned periodically.  Risk still accumulates for exempted accounts, so an
+// entry that is removed or expires takes effect immediately.
+//
+// Consulted in FusionEngine::fuse (per event) and Dispatcher::dispatch (per
+// cluster member on takedown).  Every exemption applied is attached to the
+// RiskDecision and written to audit_log.jsonl by the dispatcher.
+//
+// File format (`--allowlist`, JSON array):
+//   [ { "id": "acme-eval", "key": "cidr:52.94.0.0/16", "scope": "no_suspension",
+//       "owner": "partnerships", "reason": "ACME nightly eval",
+//       "expires_at": "2026-01-01T00:00:00Z" } ]
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::events::{ActionKind, ApiEvent, Exemption, ExemptionScope};
+use crate::net::IpCidr;
+use crate::state::window::AccountWindow;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AllowKey {
+    Account(String),
+    Org(String),
+    PaymentHash(String),
+    IpCidr(IpCidr),
+    Ja3(String),
+    Asn(u32),
+}
+
+impl std::fmt::Display for AllowKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Account(v) => write!(f, "account:{}", v),
+            Self::Org(v) => write!(f, "org:{}", v),
+            Self::PaymentHash(v) => write!(f, "payment:{}", v),
+            Self::IpCidr(v) => write!(f, "cidr:{}", v),
+            Self::Ja3(v) => write!(f, "ja3:{}", v),
+            Self::Asn(v) => write!(f, "asn:{}", v),
+        }
+    }
+}
+
+impl std::str::FromStr for AllowKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("allowlist key {s}: expected <kind>:<value>"))?;
+        Ok(match kind {
+            "account" => Self::Account(value.into()),
+            "org" => Self::Org(value.into()),
+            "payment" => Self::PaymentHash(value.into()),
+            "cidr" => Self::IpCidr(value.parse()?),
+            "ja3" => Self::Ja3(value.into()),
+            "asn" => Self::Asn(
+                value
+                    .parse()
+                    .map_err(|e| format!("allowlist key {s}: {e}"))?,
+            ),
+            _ => return Err(format!("allowlist key {s}: unknown kind {kind}")),
+        })
+    }
+}
+
+impl Serialize for AllowKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for AllowKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowEntry {
+    pub id: String,
+    pub key: AllowKey,
+    pub scope: ExemptionScope,
+    #[serde(default)]
+    pub owner: String,
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl AllowEntry {
+    fn active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|e| now < e)
+    }
+}
+
+/// Identity attributes an entry can match — one event, or a whole window.
+#[derive(Debug, Default)]
+pub struct Subject<'a> {
+    pub account_id: &'a str,
+    pub org_ids: Vec<&'a str>,
+    pub payment_hashes: Vec<&'a str>,
+    pub ips: Vec<IpAddr>,
+    pub ja3_hashes: Vec<&'a str>,
+    pub asns: Vec<u32>,
+}
+
+impl<'a> Subject<'a> {
+    pub fn from_event(e: &'a ApiEvent) -> Self {
+        Self {
+            account_id: &e.account_id,
+            org_ids: e.org_id.as_deref().into_iter().collect(),
+            payment_hashes: e.payment_method_hash.as_deref().into_iter().collect(),
+            ips: vec![e.ip_address],
+            ja3_hashes: e.ja3_hash.as_deref().into_iter().collect(),
+            asns: e.asn_number.into_iter().collect(),
+        }
+    }
+
+    pub fn from_window(w: &'a AccountWindow) -> Self {
+        let mut asns: Vec<u32> = w.events.iter().filter_map(|e| e.asn_number).collect();
+        asns.sort_unstable();
+        asns.dedup();
+        Self {
+            account_id: &w.account_id,
+            org_ids: w.org_ids.iter().map(String::as_str).collect(),
+            payment_hashes: w.payment_hashes.iter().map(String::as_str).collect(),
+            ips: w
+                .ip_addresses
+                .iter()
+                .filter_map(|ip| ip.parse().ok())
+                .collect(),
+            ja3_hashes: w.ja3_hashes.iter().map(String::as_str).collect(),
+            asns,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    exact: HashMap<AllowKey, Vec<AllowEntry>>, // every key except CIDRs
+    cidrs: Vec<AllowEntry>,
+}
+
+#[derive(Default)]
+pub struct AllowlistRegistry {
+    inner: RwLock<Inner>,
+}
+
+impl AllowlistRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load entries from a JSON array file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading allowlist {}", path.display()))?;
+        let entries: Vec<AllowEntry> = serde_json::from_str(&content)
+            .with_context(|| format!("parsing allowlist {}", path.display()))?;
+        let reg = Self::new();
+        for e in entries {
+            reg.add(e);
+        }
+        Ok(reg)
+    }
+
+    /// Add (or replace, by id) an entry.
+    pub fn add(&self, mut entry: AllowEntry) {
+        entry.created_at.get_or_insert_with(Utc::now);
+        self.remove(&entry.id);
+        let mut inner = self.inner.write();
+        match entry.key {
+            AllowKey::IpCidr(_) => inner.cidrs.push(entry),
+            _ => inner
+                .exact
+                .entry(entry.key.clone())
+                .or_default()
+                .push(entry),
+        }
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        let mut inner = self.inner.write();
+        let before = inner.len();
+        inner.cidrs.retain(|e| e.id != id);
+        for v in inner.exact.values_mut() {
+            v.retain(|e| e.id != id);
+        }
+        inner.exact.retain(|_, v| !v.is_empty());
+        inner.len() != before
+    }
+
+    /// Drop expired entries; returns how many were removed.
+    pub fn prune(&self, now: DateTime<Utc>) -> usize {
+        let mut inner = self.inner.write();
+        let before = inner.len();
+        inner.cidrs.retain(|e| e.active(now));
+        for v in inner.exact.values_mut() {
+            v.retain(|e| e.active(now));
+        }
+        inner.exact.retain(|_, v| !v.is_empty());
+        before - inner.len()
+    }
+
+    pub fn entries(&self) -> Vec<AllowEntry> {
+        let inner = self.inner.read();
+        inner
+            .exact
+            .values()
+            .flatten()
+            .chain(inner.cidrs.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Active entries matching the subject.
+    pub fn lookup(&self, subject: &Subject, now: DateTime<Utc>) -> Vec<AllowEntry> {
+        let inner = self.inner.read();
+        let keys = std::iter::once(AllowKey::Account(subject.account_id.into()))
+            .chain(subject.org_ids.iter().map(|v| AllowKey::Org((*v).into())))
+            .chain(
+                subject
+                    .payment_hashes
+                    .iter()
+                    .map(|v| AllowKey::PaymentHash((*v).into())),
+            )
+            .chain(
+                subject
+                    .ja3_hashes
+                    .iter()
+                    .map(|v| AllowKey::Ja3((*v).into())),
+            )
+            .chain(subject.asns.iter().map(|v| AllowKey::Asn(*v)));
+        let mut out: Vec<AllowEntry> = keys
+            .filter_map(|k| inner.exact.get(&k))
+            .flatten()
+            .filter(|e| e.active(now))
+            .cloned()
+            .collect();
+        out.extend(
+            inner
+                .cidrs
+                .iter()
+                .filter(|e| e.active(now))
+                .filter(|e| match &e.key {
+                    AllowKey::IpCidr(c) => subject.ips.iter().any(|ip| c.contains(ip)),
+                    _ => false,
+                })
+                .cloned(),
+        );
+        out
+    }
+
+    /// Apply matching entries to an enforcement action.  Returns the action to
+    /// take and one Exemption per entry that changed it.
+    pub fn apply(
+        &self,
+        subject: &Subject,
+        action: ActionKind,
+        now: DateTime<Utc>,
+    ) -> (ActionKind, Vec<Exemption>) {
+        let matches = self.lookup(subject, now);
+        let mut applied = action;
+        let mut exemptions = Vec::new();
+        // Full first: once the action is Monitor nothing else applies.
+        for scope in [
+            ExemptionScope::Full,
+            ExemptionScope::NoSuspension,
+            ExemptionScope::NoCanary,
+        ] {
+            for entry in matches.iter().filter(|e| e.scope == scope) {
+                let next = exempt_action(scope, applied);
+                if next == applied {
+                    continue;
+                }
+                exemptions.push(Exemption {
+                    entry_id: entry.id.clone(),
+                    matched: entry.key.to_string(),
+                    scope,
+                    owner: entry.owner.clone(),
+                    account_id: subject.account_id.to_string(),
+                    original_action: applied,
+                    applied_action: next,
+                    expires_at: entry.expires_at,
+                });
+                applied = next;
+            }
+        }
+        (applied, exemptions)
+    }
+}
+
+impl Inner {
+    fn len(&self) -> usize {
+        self.cidrs.len() + self.exact.values().map(Vec::len).sum::<usize>()
+    }
+}
+
+fn exempt_action(scope: ExemptionScope, action: ActionKind) -> ActionKind {
+    match (scope, action) {
+        (ExemptionScope::Full, _) => ActionKind::Monitor,
+        (
+            ExemptionScope::NoSuspension,
+            ActionKind::SuspendAccount | ActionKind::ClusterTakedown,
+        ) => ActionKind::FlagForReview,
+        (ExemptionScope::NoCanary, ActionKind::InjectCanary) => ActionKind::FlagForReview,
+        (_, a) => a,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn entry(id: &str, key: &str, scope: ExemptionScope) -> AllowEntry {
+        AllowEntry {
+            id: id.into(),
+            key: key.parse().unwrap(),
+            scope,
+            owner: "partnerships".into(),
+            reason: String::new(),
+            created_at: None,
+            expires_at: None,
+        }
+    }
+
+    fn subject(ip: &str) -> Subject<'static> {
+        Subject {
+            account_id: "sk-a",
+            org_ids: vec!["org_acme"],
+            ips: vec![ip.parse().unwrap()],
+            ja3_hashes: vec!["ja3_x"],
+            asns: vec![16509],
+            ..Subject::default()
+        }
+    }
+
+    #[test]
+    fn scope_semantics() {
+        let now = Utc::now();
+        let reg = AllowlistRegistry::new();
+        let s = subject("198.51.100.1");
+        for action in [ActionKind::SuspendAccount, ActionKind::InjectCanary] {
+            let (a, ex) = reg.apply(&s, action, now);
+            assert_eq!(a, action);
+            assert!(ex.is_empty());
+        }
+
+        reg.add(entry("nc", "org:org_acme", ExemptionScope::NoCanary));
+        let (a, ex) = reg.apply(&s, ActionKind::InjectCanary, now);
+        assert_eq!(a, ActionKind::FlagForReview);
+        assert_eq!(ex[0].matched, "org:org_acme");
+        assert_eq!(
+            reg.apply(&s, ActionKind::SuspendAccount, now).0,
+            ActionKind::SuspendAccount
+        );
+
+        reg.add(entry("ns", "asn:16509", ExemptionScope::NoSuspension));
+        for action in [ActionKind::SuspendAccount, ActionKind::ClusterTakedown] {
+            assert_eq!(reg.apply(&s, action, now).0, ActionKind::FlagForReview);
+        }
+        assert_eq!(
+            reg.apply(&s, ActionKind::RateLimit, now).0,
+            ActionKind::RateLimit
+        );
+
+        // Full wins and is the only exemption recorded once the action is Monitor
+        reg.add(entry("full", "ja3:ja3_x", ExemptionScope::Full));
+        let (a, ex) = reg.apply(&s, ActionKind::SuspendAccount, now);
+        assert_eq!(a, ActionKind::Monitor);
+        assert_eq!(ex.len(), 1);
+        assert_eq!(ex[0].entry_id, "full");
+        assert_eq!(ex[0].original_action, ActionKind::SuspendAccount);
+
+        // Re-adding an id replaces the entry
+        reg.add(entry("full", "account:someone_else", ExemptionScope::Full));
+        assert_eq!(reg.len(), 3);
+        assert_eq!(
+            reg.apply(&s, ActionKind::RateLimit, now).0,
+            ActionKind::RateLimit
+        );
+        assert!(reg.remove("full"));
+        assert!(!reg.remove("full"));
+    }
+
+    #[test]
+    fn expiry() {
+        let now = Utc::now();
+        let reg = AllowlistRegistry::new();
+        let mut e = entry("trial", "account:sk-a", ExemptionScope::Full);
+        e.expires_at = Some(now + Duration::hours(1));
+        reg.add(e);
+        let s = subject("198.51.100.1");
+
+        assert_eq!(reg.lookup(&s, now).len(), 1);
+        assert!(reg.lookup(&s, now + Duration::hours(1)).is_empty());
+        assert_eq!(
+            reg.apply(&s, ActionKind::SuspendAccount, now + Duration::hours(2))
+                .0,
+            ActionKind::SuspendAccount
+        );
+        assert_eq!(reg.prune(now), 0);
+        assert_eq!(reg.prune(now + Duration::hours(2)), 1);
+        assert!(reg.is_empty());
+    }
+
+    #[test]
+    fn cidr_matching() {
+        let now = Utc::now();
+        let reg = AllowlistRegistry::new();
+        reg.add(entry("v4", "cidr:52.94.0.0/16", ExemptionScope::Full));
+        reg.add(entry("v6", "cidr:2001:db8::/32", ExemptionScope::Full));
+
+        for (ip, matched) in [
+            ("52.94.0.1", true),
+            ("52.94.255.254", true),
+            ("52.95.0.1", false),
+            ("2001:db8:ffff::1", true),
+            ("2001:db9::1", false),
+            ("::ffff:52.94.0.1", false),
+        ] {
+            assert_eq!(!reg.lookup(&subject(ip), now).is_empty(), matched, "{ip}");
+        }
+        assert!("cidr:52.94.0.0/40".parse::<AllowKey>().is_err());
+        assert!("host:example".parse::<AllowKey>().is_err());
+    }
+}
@@ -0,0 +1,230 @@
+// glasswally/src/engine/canary_scan.rs
+//
+// Inbound canary / watermark scanner.
+//
+// Responses to watermarked accounts carry canary tokens and the account's
+// ZWJ/ZWNJ watermark (response_rewriter.rs).  When that output comes back in
+// someone's prompt — a scraped dataset replayed for distillation, a teacher
+// model's answers fed to a student — it is near-certain evidence.
+//
+// Every inbound prompt and system prompt is checked for:
+//   token          — a registered canary token as plain hex (32 chars)
+//   encoded_token  — a registered token in its invisible encoding
+//                    (watermark::encode_canary)
+//   watermark      — a ZWJ/ZWNJ bit sequence matching a watermarked account
+//                    (watermark::detect: only joiners right after a space,
+//                    at least 32 of them, exact binomial p-value below 1e-6
+//                    after correcting for the number of watermarked accounts)
+//
+// On a match from a *different* account the hit is recorded on the
+// StateStore and the watermark worker scores it.  Only an exact token match —
+// a 128-bit value nobody produces by chance — marks the canary triggered and
+// links the replaying account into the origin account's cluster, so the IOC
+// bundle for that cluster carries the triggered tokens.  A watermark match is
+// statistical (Persian ZWNJ or emoji ZWJ text can look like bits), so it
+// scores but never creates a graph link.  An account quoting its own earlier
+// output (multi-turn chat history) is not evidence and is ignored.
+//
+// Runs in the pipeline after ingest and before the workers, which stay free
+// of side effects.
+
+use chrono::Utc;
+
+use crate::events::{ApiEvent, CanaryHit, CanaryMatch};
+use crate::state::backend::StateBackend;
+use crate::workers::watermark;
+
+const TOKEN_LEN: usize = 32;
+
+// Maximal runs of exactly TOKEN_LEN hex digits.
+fn hex_runs(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_ascii_hexdigit())
+        .filter(|run| run.len() == TOKEN_LEN)
+}
+
+/// Scan one event; returns the (cross-account) hits it produced.
+pub fn scan(event: &ApiEvent, store: &impl StateBackend) -> Vec<CanaryHit> {
+    let mut hits = Vec::new();
+    let fields = std::iter::once(("prompt", event.prompt.as_str()))
+        .chain(event.system_prompt.as_deref().map(|s| ("system_prompt", s)));
+
+    for (field, text) in fields {
+        let plain = hex_runs(text).map(|t| (CanaryMatch::Token, t.to_ascii_lowercase()));
+        let encoded = watermark::decode_canaries(text)
+            .into_iter()
+            .map(|t| (CanaryMatch::EncodedToken, t));
+        for (kind, token) in plain.chain(encoded) {
+            let Some(canary) = store.lookup_canary(&token) else {
+                continue;
+            };
+            if canary.account_id == event.account_id {
+                continue;
+            }
+            store.trigger_canary(&token);
+            hits.push(hit(
+                event,
+                field,
+                kind,
+                Some(token),
+                canary.account_id,
+                Some(canary.request_id),
+                1.0,
+            ));
+        }
+
+        if !text.contains(['\u{200D}', '\u{200C}']) {
+            continue;
+        }
+        let watermarked: Vec<String> = store
+            .watermarked_accounts()
+            .into_iter()
+            .filter(|a| *a != event.account_id)
+            .collect();
+        if let Some(m) = watermark::detect(text, &watermarked) {
+            hits.push(hit(
+                event,
+                field,
+                CanaryMatch::Watermark,
+                None,
+                m.account_id,
+                None,
+                (1.0 - m.p_value) as f32,
+            ));
+        }
+    }
+
+    for h in &mut hits {
+        if h.kind != CanaryMatch::Watermark {
+            h.cluster_id = store.link_accounts(&h.account_id, &h.origin_account);
+        }
+        store.record_canary_hit(h.clone());
+    }
+    hits
+}
+
+fn hit(
+    event: &ApiEvent,
+    field: &str,
+    kind: CanaryMatch,
+    token: Option<String>,
+    origin_account: String,
+    origin_request_id: Option<String>,
+    confidence: f32,
+) -> CanaryHit {
+    CanaryHit {
+        kind,
+        token,
+        origin_account,
+        origin_request_id,
+        account_id: event.account_id.clone(),
+        request_id: event.request_id.clone(),
+        field: field.to_string(),
+        confidence,
+        cluster_id: None,
+        timestamp: Utc::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::CanaryToken;
+    use crate::state::window::StateStore;
+
+    fn event(account: &str, prompt: &str) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": Utc::now(),
+            "ip_address": "203.0.113.7", "user_agent": "ua", "model": "m",
+            "prompt": prompt, "token_count": 100,
+            "payment_method_hash": null, "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    // An origin account among many watermarked ones, plus a replayer.
+    fn store() -> StateStore {
+        let store = StateStore::new();
+        for i in 0..200 {
+            store.mark_watermarked(&format!("acct_{i}"));
+        }
+        store.ingest(&event("origin", "hello"));
+        store.mark_watermarked("origin");
+        store.ingest(&event("replayer", "hello"));
+        store
+    }
+
+    #[test]
+    fn persian_and_emoji_joiners_are_not_a_watermark() {
+        let store = store();
+        // Joiners inside words and emoji sequences, laid out to spell the
+        // origin's key exactly — the old any-joiner reading matched this.
+        let key = watermark::account_watermark_bits("origin");
+        let text: Vec<&str> = key
+            .iter()
+            .cycle()
+            .take(96)
+            .map(|&bit| {
+                if bit {
+                    "👩\u{200D}💻"
+                } else {
+                    "می\u{200C}خواهم"
+                }
+            })
+            .collect();
+        let text = text.join(" ");
+        assert!(watermark::aligned_bits(&text).is_empty());
+
+        let hits = scan(&event("replayer", &text), &store);
+        assert!(hits.is_empty());
+        assert!(store.canary_hits("replayer").is_empty());
+
+        // Aligned but random joiners (e.g. someone's own stego) stay below the
+        // corrected threshold too.
+        let noise: String = (0..96u64)
+            .map(|i| {
+                let bit = (i.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 63) == 1;
+                format!("w{i} {}", if bit { '\u{200D}' } else { '\u{200C}' })
+            })
+            .collect();
+        assert_eq!(watermark::aligned_bits(&noise).len(), 96);
+        assert!(scan(&event("replayer", &noise), &store).is_empty());
+    }
+
+    #[test]
+    fn replayed_watermark_scores_without_linking() {
+        let store = store();
+        let words = vec!["token"; 80].join(" ");
+        let response = watermark::embed(&words, "origin");
+
+        let hits = scan(&event("replayer", &response), &store);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].kind, CanaryMatch::Watermark);
+        assert_eq!(hits[0].origin_account, "origin");
+        assert!(hits[0].confidence > 0.99);
+        assert_eq!(hits[0].cluster_id, None);
+        assert_eq!(store.canary_hits("replayer").len(), 1);
+        assert!(store.get_cluster("replayer").is_none());
+
+        // The origin quoting its own output is not evidence.
+        assert!(scan(&event("origin", &response), &store).is_empty());
+    }
+
+    #[test]
+    fn replayed_token_links_accounts() {
+        let store = store();
+        let token = CanaryToken::generate("origin", "req_1");
+        store.register_canary(token.clone());
+        let prompt = format!("from the dataset: {}", token.token);
+
+        let hits = scan(&event("replayer", &prompt), &store);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].kind, CanaryMatch::Token);
+        let cid = hits[0].cluster_id.expect("token match links");
+        assert_eq!(store.get_cluster("origin"), Some(cid));
+        assert!(store.lookup_canary(&token.token).unwrap().triggered);
+    }
+}
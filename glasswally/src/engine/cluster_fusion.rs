@@ -0,0 +1,337 @@
+// glasswally/src/engine/cluster_fusion.rs
+//
+// Cluster-level risk scoring — campaign decisions.
+//
+// Per-account fusion misses campaigns whose members each stay below the
+// account tier thresholds: 200 accounts at Medium are a takedown, not 200
+// rate limits.  The cluster score aggregates four components:
+//
+//   member_risk   0.35 — mean decayed account score across members
+//   breadth       0.30 — fraction of members at ≥ Medium, scaled by log cluster size
+//   shared_infra  0.15 — how strongly members are tied by shared infrastructure
+//                        (payment 0.40, org 0.20, subnet 0.20, JA3 0.20)
+//   coordination  0.20 — members carrying coordinated-behaviour signals
+//                        (TimingCluster, Pivot, RolePreamble collisions)
+//
+// Cluster tiers use lower thresholds than account tiers because every
+// component is already corroborated across several accounts:
+//
+//   ≥ 0.60 Critical → CLUSTER_TAKEDOWN
+//   ≥ 0.45 High     → FLAG_FOR_REVIEW
+//   ≥ 0.30 Medium   → MONITOR (audit only)
+//
+// Campaign floor: a cluster of at least 50 members, ≥ 80% of them flagged and
+// tied by shared infrastructure ≥ 0.50 is Critical however low the weighted
+// sum — 200 Medium accounts on one card and subnet with no coordination
+// signal only reach 0.35·0.4 + 0.30 + 0.15 = 0.59.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use crate::events::{RiskTier, WorkerKind};
+use crate::net::IpCidr;
+use crate::state::backend::StateBackend;
+use crate::state::risk::DecayConfig;
+
+pub const CLUSTER_CRITICAL: f32 = 0.60;
+pub const CLUSTER_HIGH: f32 = 0.45;
+pub const CLUSTER_MEDIUM: f32 = 0.30;
+
+const MIN_MEMBERS: usize = 3;
+const FULL_BREADTH_SIZE: f64 = 50.0; // cluster size at which breadth is unscaled
+const COORDINATION_MIN: f32 = 0.30; // per-member coordinated signal threshold
+const CAMPAIGN_MIN_MEMBERS: usize = 50;
+const CAMPAIGN_BREADTH: f32 = 0.80;
+const CAMPAIGN_INFRA: f32 = 0.50;
+
+/// Workers whose signals indicate cross-account coordination.
+pub const COORDINATION_WORKERS: &[WorkerKind] = &[
+    WorkerKind::TimingCluster,
+    WorkerKind::Pivot,
+    WorkerKind::RolePreamble,
+];
+
+#[derive(Debug, Clone)]
+pub struct ClusterScore {
+    pub cluster_id: u32,
+    pub n_members: usize,
+    pub n_flagged: usize,
+    pub member_risk: f32,
+    pub breadth: f32,
+    pub shared_infra: f32,
+    pub coordination: f32,
+    pub weighted: f32, // Σ component × weight, before the campaign floor
+    pub score: f32,
+    pub total_requests: usize,
+    pub country_codes: Vec<String>,
+    pub evidence: Vec<String>,
+}
+
+impl ClusterScore {
+    pub fn tier(&self) -> Option<RiskTier> {
+        if self.score >= CLUSTER_CRITICAL {
+            Some(RiskTier::Critical)
+        } else if self.score >= CLUSTER_HIGH {
+            Some(RiskTier::High)
+        } else if self.score >= CLUSTER_MEDIUM {
+            Some(RiskTier::Medium)
+        } else {
+            None
+        }
+    }
+
+    /// Weighted components in order, for DecisionExplanation.
+    pub fn components(&self) -> [(&'static str, f32, f32); 4] {
+        [
+            ("member_risk", self.member_risk, 0.35),
+            ("breadth", self.breadth, 0.30),
+            ("shared_infra", self.shared_infra, 0.15),
+            ("coordination", self.coordination, 0.20),
+        ]
+    }
+}
+
+/// Fraction of members that share at least one value with another member.
+fn shared_fraction<'a, T: Eq + std::hash::Hash + 'a>(
+    per_member: impl Iterator<Item = Vec<&'a T>>,
+    n: usize,
+) -> f32 {
+    let lists: Vec<Vec<&T>> = per_member.collect();
+    let mut counts: HashMap<&T, usize> = HashMap::new();
+    for list in &lists {
+        for v in list {
+            *counts.entry(*v).or_default() += 1;
+        }
+    }
+    let sharing = lists
+        .iter()
+        .filter(|l| l.iter().any(|v| counts.get(*v).copied().unwrap_or(0) >= 2))
+        .count();
+    sharing as f32 / n.max(1) as f32
+}
+
+pub fn score_cluster(
+    cluster_id: u32,
+    store: &impl StateBackend,
+    decay: &DecayConfig,
+    now: DateTime<Utc>,
+) -> Option<ClusterScore> {
+    let members = store.cluster_members(cluster_id);
+    let n = members.len();
+    if n < MIN_MEMBERS {
+        return None;
+    }
+
+    let windows: Vec<_> = members.iter().filter_map(|m| store.get_window(m)).collect();
+    let guards: Vec<_> = windows.iter().map(|w| w.read()).collect();
+
+    // ── Member risk + breadth ─────────────────────────────────────────────────
+    let scores: Vec<f32> = guards.iter().map(|w| w.risk.decayed(decay, now)).collect();
+    let member_risk = scores.iter().sum::<f32>() / n as f32;
+    let n_flagged = guards
+        .iter()
+        .zip(&scores)
+        .filter(|(w, s)| **s >= super::fusion::MEDIUM || w.risk.held_tier(decay, now).is_some())
+        .count();
+    let size_factor = ((n as f64).ln() / FULL_BREADTH_SIZE.ln()).min(1.0) as f32;
+    let breadth = n_flagged as f32 / n as f32 * size_factor;
+
+    // ── Shared infrastructure ─────────────────────────────────────────────────
+    let payment = shared_fraction(guards.iter().map(|w| w.payment_hashes.iter().collect()), n);
+    let org = shared_fraction(guards.iter().map(|w| w.org_ids.iter().collect()), n);
+    let ja3 = shared_fraction(guards.iter().map(|w| w.ja3_hashes.iter().collect()), n);
+    let prefixes = store.subnet_prefixes();
+    let subnets: Vec<Vec<IpCidr>> = guards
+        .iter()
+        .map(|w| w.subnets(&prefixes).into_iter().collect())
+        .collect();
+    let subnet = shared_fraction(subnets.iter().map(|s| s.iter().collect()), n);
+    let shared_infra = (0.40 * payment + 0.20 * org + 0.20 * subnet + 0.20 * ja3).min(1.0);
+
+    // ── Coordination ──────────────────────────────────────────────────────────
+    let mut coordinated_by: HashMap<WorkerKind, usize> = HashMap::new();
+    let mut n_coordinated = 0usize;
+    for w in &guards {
+        let mut any = false;
+        for worker in COORDINATION_WORKERS {
+            if w.risk.coordination.get(worker).copied().unwrap_or(0.0) >= COORDINATION_MIN {
+                *coordinated_by.entry(*worker).or_default() += 1;
+                any = true;
+            }
+        }
+        if any {
+            n_coordinated += 1;
+        }
+    }
+    let coordination = n_coordinated as f32 / n as f32;
+
+    let total_requests = guards.iter().map(|w| w.events.len()).sum();
+    let mut country_codes: Vec<String> = guards
+        .iter()
+        .flat_map(|w| w.country_codes.iter().cloned())
+        .collect::<std::collections::HashSet<_>>()
+        .into_iter()
+        .collect();
+    country_codes.sort();
+    drop(guards);
+
+    let weighted = 0.35 * member_risk + 0.30 * breadth + 0.15 * shared_infra + 0.20 * coordination;
+    let weighted = (weighted.min(1.0) * 10000.0).round() / 10000.0;
+    let campaign =
+        n >= CAMPAIGN_MIN_MEMBERS && breadth >= CAMPAIGN_BREADTH && shared_infra >= CAMPAIGN_INFRA;
+    let score = if campaign {
+        weighted.max(CLUSTER_CRITICAL)
+    } else {
+        weighted
+    };
+
+    let mut evidence = vec![
+        format!("cluster_{}_size:{}", cluster_id, n),
+        format!("flagged_members:{}/{}", n_flagged, n),
+        format!("mean_member_risk:{:.3}", member_risk),
+    ];
+    if payment > 0.0 {
+        evidence.push(format!("shared_payment:{:.0}%", payment * 100.0));
+    }
+    if org > 0.0 {
+        evidence.push(format!("shared_org:{:.0}%", org * 100.0));
+    }
+    if subnet > 0.0 {
+        evidence.push(format!("shared_subnet:{:.0}%", subnet * 100.0));
+    }
+    if ja3 > 0.0 {
+        evidence.push(format!("shared_ja3:{:.0}%", ja3 * 100.0));
+    }
+    let mut coord: Vec<_> = coordinated_by.into_iter().collect();
+    coord.sort_by_key(|(w, _)| w.to_string());
+    for (worker, count) in coord {
+        evidence.push(format!("coordinated_{}:{}_members", worker, count));
+    }
+    if campaign {
+        evidence.push(format!(
+            "campaign_breadth:{:.2}_infra:{:.2}",
+            breadth, shared_infra
+        ));
+    }
+
+    Some(ClusterScore {
+        cluster_id,
+        n_members: n,
+        n_flagged,
+        member_risk,
+        breadth,
+        shared_infra,
+        coordination,
+        weighted,
+        score,
+        total_requests,
+        country_codes,
+        evidence,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::engine::fusion::FusionEngine;
+    use crate::events::{ActionKind, ApiEvent};
+    use crate::state::graph::GraphConfig;
+    use crate::state::window::StateStore;
+
+    fn event(account: &str) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": Utc::now(),
+            "ip_address": "203.0.113.7", "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": 100,
+            "payment_method_hash": "pm_ring", "org_id": "org_ring", "country_code": "US",
+            "header_order": [], "ja3_hash": "ja3_ring", "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    /// `n` accounts on one card, org, subnet and JA3, each at `risk`.
+    fn campaign(n: usize, risk: f32) -> (StateStore, u32) {
+        let mut cfg = GraphConfig::default();
+        cfg.supernode.limits = BTreeMap::new();
+        let store = StateStore::with_graph_config(cfg);
+        let now = Utc::now();
+        for i in 0..n {
+            let acct = format!("acct_{i}");
+            store.ingest(&event(&acct));
+            let w = store.get_window(&acct).unwrap();
+            let mut w = w.write();
+            w.risk.score = risk;
+            w.risk.updated_at = Some(now);
+        }
+        let cid = store.get_cluster("acct_0").expect("clustered");
+        assert_eq!(store.cluster_members(cid).len(), n);
+        (store, cid)
+    }
+
+    #[test]
+    fn broad_medium_campaign_is_a_takedown() {
+        let (store, cid) = campaign(200, 0.4);
+        let decay = DecayConfig::default();
+        let cs = score_cluster(cid, &store, &decay, Utc::now()).unwrap();
+
+        assert_eq!(cs.n_members, 200);
+        assert_eq!(cs.n_flagged, 200);
+        assert!((cs.member_risk - 0.4).abs() < 0.001);
+        assert_eq!(cs.breadth, 1.0);
+        assert_eq!(cs.shared_infra, 1.0);
+        assert_eq!(cs.coordination, 0.0);
+        let sum: f32 = cs.components().iter().map(|(_, v, w)| v * w).sum();
+        assert!(
+            (sum - cs.weighted).abs() < 0.001,
+            "{sum} vs {}",
+            cs.weighted
+        );
+        assert!(cs.weighted < CLUSTER_CRITICAL);
+        assert_eq!(cs.score, CLUSTER_CRITICAL);
+        assert_eq!(cs.tier(), Some(RiskTier::Critical));
+        for e in [
+            "flagged_members:200/200",
+            "shared_payment:100%",
+            "shared_org:100%",
+            "shared_subnet:100%",
+            "shared_ja3:100%",
+            "campaign_breadth:1.00_infra:1.00",
+        ] {
+            assert!(
+                cs.evidence.iter().any(|x| x == e),
+                "{e} in {:?}",
+                cs.evidence
+            );
+        }
+
+        let engine = FusionEngine::new();
+        let d = engine.fuse_cluster(cid, &event("acct_0"), &store).unwrap();
+        assert_eq!(d.action, ActionKind::ClusterTakedown);
+        let floor = d.explanation.adjustments.last().unwrap();
+        assert_eq!(floor.kind, "campaign_floor");
+        assert_eq!(floor.score_after, CLUSTER_CRITICAL);
+    }
+
+    #[test]
+    fn small_clusters_get_no_campaign_floor() {
+        let decay = DecayConfig::default();
+
+        // Five members: breadth is scaled down by size, the floor does not apply.
+        let (store, cid) = campaign(5, 0.4);
+        let cs = score_cluster(cid, &store, &decay, Utc::now()).unwrap();
+        assert!(cs.breadth < 0.5);
+        assert_eq!(cs.score, cs.weighted);
+        assert_eq!(cs.tier(), Some(RiskTier::Medium));
+        assert!(!cs.evidence.iter().any(|e| e.starts_with("campaign_")));
+
+        // Below MIN_MEMBERS nothing is scored.
+        let (store, cid) = campaign(MIN_MEMBERS - 1, 0.9);
+        assert!(score_cluster(cid, &store, &decay, Utc::now()).is_none());
+    }
+}
@@ -1,50 +1,203 @@
 // glasswally/src/engine/dispatcher.rs
 //
-// Writes enforcement actions to output JSONL files.
+// Routes enforcement actions to enforcement sinks (engine/sinks.rs) — by
+// default one JSONL file per stream in the output directory.
 // On CLUSTER_TAKEDOWN: suspends all cluster members + writes IOC bundle.
+// Cluster-scope decisions (engine/cluster_fusion.rs) affect every member.
+// Lifecycle transitions (engine/lifecycle.rs) → lifecycle_transitions.jsonl.
+// Allowlisted members (engine/allowlist.rs) are excluded from cluster actions;
+// every exemption is written to audit_log.jsonl.
+// Audit records are hash-chained (audit.rs) before they reach any sink, and
+// are queued with backpressure rather than dropped (SinkRouter::route_reliable).
+// Every enforced tier: token-bucket commands sized by the tier
+//                (engine/rate_limit.rs), one per scope key, →
+//                rate_limit_commands.jsonl — not only on RATE_LIMIT.
 // On INJECT_CANARY: generates a per-request canary token for response
 //                   watermarking and registers it in StateStore.
-// Wire these files to your enforcement API / Kafka topics in production.
+// Route streams to Kafka / webhooks / syslog in production with --sinks.
+// On-call alerts (Slack / webhook / PagerDuty) go through engine/notifier.rs.
 
 use anyhow::Result;
 use chrono::Utc;
 use std::path::PathBuf;
-use tokio::fs::OpenOptions;
-use tokio::io::AsyncWriteExt;
+use std::sync::Arc;
 use tracing::info;
 
+use super::allowlist::{AllowlistRegistry, Subject};
+use super::lifecycle::Transition;
+use super::notifier::{Alert, AlertNotifier};
+use super::rate_limit::RateLimitPolicy;
+use super::sinks::{SinkConfig, SinkRecord, SinkRouter, Stream};
+use crate::audit::AuditChain;
 use crate::events::{
-    ActionKind, CanaryToken, EnforcementAction, IocBundle, RiskDecision, RiskTier,
+    ActionKind, CanaryHit, CanaryToken, ClusterEvent, DecisionScope, EnforcementAction, Exemption,
+    IocBundle, LimitScope, RiskDecision, RiskTier,
 };
-use crate::state::window::StateStore;
+use crate::net::IpCidr;
+use crate::state::backend::StateBackend;
 
 pub struct Dispatcher {
-    out: PathBuf,
+    sinks: SinkRouter,
+    allowlist: Arc<AllowlistRegistry>,
+    notifier: Option<AlertNotifier>,
+    audit: AuditChain,
+    audit_send: tokio::sync::Mutex<()>,
+    rate_limits: RateLimitPolicy,
 }
 
 impl Dispatcher {
+    /// File sink on `output_dir` for every stream (requires a Tokio runtime).
     pub fn new(output_dir: impl Into<PathBuf>) -> Self {
         let out: PathBuf = output_dir.into();
-        std::fs::create_dir_all(&out).expect("Failed to create output directory");
-        Self { out }
+        Self::from_config(&SinkConfig::file_only(&out)).expect("Failed to create output directory")
+    }
+
+    /// Routed sink configuration (requires a Tokio runtime).
+    pub fn from_config(cfg: &SinkConfig) -> Result<Self> {
+        Ok(Self {
+            sinks: SinkRouter::start(cfg)?,
+            allowlist: Arc::new(AllowlistRegistry::new()),
+            notifier: None,
+            audit: AuditChain::new(),
+            audit_send: tokio::sync::Mutex::new(()),
+            rate_limits: RateLimitPolicy::default(),
+        })
+    }
+
+    pub fn sinks(&self) -> &SinkRouter {
+        &self.sinks
+    }
+
+    fn emit(
+        &self,
+        stream: Stream,
+        key: &str,
+        action: Option<ActionKind>,
+        tier: Option<RiskTier>,
+        payload: serde_json::Value,
+    ) {
+        self.sinks.route(SinkRecord {
+            stream,
+            key: key.to_string(),
+            action,
+            tier,
+            payload,
+        });
+    }
+
+    /// Chain an audit record (plus any checkpoint it triggers) and queue it
+    /// without dropping.  The send lock keeps queued records in chain order.
+    async fn audit(
+        &self,
+        key: &str,
+        action: Option<ActionKind>,
+        tier: Option<RiskTier>,
+        payload: serde_json::Value,
+    ) {
+        let _order = self.audit_send.lock().await;
+        let mut chained = Vec::new();
+        self.audit.append(payload, |v| chained.push(v));
+        self.route_audit(key, action, tier, chained).await;
+    }
+
+    async fn route_audit(
+        &self,
+        key: &str,
+        action: Option<ActionKind>,
+        tier: Option<RiskTier>,
+        chained: Vec<serde_json::Value>,
+    ) {
+        for payload in chained {
+            self.sinks
+                .route_reliable(SinkRecord {
+                    stream: Stream::Audit,
+                    key: key.to_string(),
+                    action,
+                    tier,
+                    payload,
+                })
+                .await;
+        }
+    }
+
+    /// Replace the default (unsigned, seq 0) audit chain.
+    pub fn with_audit_chain(mut self, chain: AuditChain) -> Self {
+        self.audit = chain;
+        self
+    }
+
+    /// Append a signed audit checkpoint if anything was audited since the last.
+    pub async fn audit_checkpoint(&self) -> bool {
+        let _order = self.audit_send.lock().await;
+        let mut chained = Vec::new();
+        let written = self.audit.checkpoint(|v| chained.push(v));
+        self.route_audit("checkpoint", None, None, chained).await;
+        written
+    }
+
+    /// Share the engine's allowlist registry so cluster members are re-checked.
+    pub fn with_allowlist(mut self, allowlist: Arc<AllowlistRegistry>) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    pub fn with_rate_limit_policy(mut self, policy: RateLimitPolicy) -> Self {
+        self.rate_limits = policy;
+        self
+    }
+
+    /// Push alerts for dispatched decisions to on-call channels.
+    pub fn with_notifier(mut self, notifier: AlertNotifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    pub fn notifier(&self) -> Option<&AlertNotifier> {
+        self.notifier.as_ref()
+    }
+
+    // Drop members exempt from `action`, recording each exemption.
+    fn filter_exempt(
+        &self,
+        members: Vec<String>,
+        action: ActionKind,
+        store: &impl StateBackend,
+        exemptions: &mut Vec<Exemption>,
+    ) -> Vec<String> {
+        let now = Utc::now();
+        members
+            .into_iter()
+            .filter(|m| {
+                let Some(w) = store.get_window(m) else {
+                    return true;
+                };
+                let w = w.read();
+                let (applied, ex) = self.allowlist.apply(&Subject::from_window(&w), action, now);
+                exemptions.extend(ex);
+                applied == action
+            })
+            .collect()
     }
 
     pub async fn dispatch(
         &self,
         decision: &RiskDecision,
-        store: &StateStore,
+        store: &impl StateBackend,
     ) -> Result<EnforcementAction> {
         let mut action_type = decision.action;
         let mut affected = vec![decision.account_id.clone()];
         let mut canary = None::<CanaryToken>;
+        let mut exemptions = decision.exemptions.clone();
 
         // ── INJECT_CANARY (High tier) ─────────────────────────────────────────
         // Generate a unique canary token and mark the account for response
         // watermarking. The token is registered in StateStore so that if it
         // appears in a future inbound request (scraped dataset replay), we can
-        // attribute the distillation campaign.
+        // attribute the distillation campaign.  This one is bound to the
+        // request that triggered the decision; response_rewriter.rs mints one
+        // more per rewritten response.
         if action_type == ActionKind::InjectCanary {
-            let token = CanaryToken::generate(&decision.account_id, "dispatch");
+            let token = CanaryToken::generate(&decision.account_id, &decision.request_id);
             store.mark_watermarked(&decision.account_id);
             store.register_canary(token.clone());
             canary = Some(token);
@@ -54,71 +207,53 @@ impl Dispatcher {
             );
         }
 
-        // ── CLUSTER TAKEDOWN (Critical tier) ─────────────────────────────────
-        if decision.tier == RiskTier::Critical {
+        // ── Cluster-scope decisions apply to every member ──────────────────────
+        let mut filtered_for = None;
+        if decision.scope == DecisionScope::Cluster {
+            if let Some(cid) = decision.cluster_id {
+                let members = store.cluster_members(cid).into_iter().collect();
+                affected = self.filter_exempt(members, action_type, store, &mut exemptions);
+                affected.sort();
+                filtered_for = Some(action_type);
+            }
+        }
+
+        // ── CLUSTER TAKEDOWN (Critical tier, account or cluster scope) ───────
+        // Not when an exemption already downgraded the action; allowlisted
+        // members are excluded from the takedown and the IOC bundle.
+        let suspending = matches!(
+            decision.action,
+            ActionKind::SuspendAccount | ActionKind::ClusterTakedown
+        );
+        if decision.tier == RiskTier::Critical && suspending {
             if let Some(cid) = decision.cluster_id {
                 let members: Vec<String> = store.cluster_members(cid).into_iter().collect();
                 if members.len() >= 3 {
                     action_type = ActionKind::ClusterTakedown;
-                    affected = members.clone();
-
-                    // Build IOC bundle from all cluster member data
-                    let mut ips = std::collections::HashSet::new();
-                    let mut payments = std::collections::HashSet::new();
-                    let mut ja3s = std::collections::HashSet::new();
-                    let mut ja3s_set = std::collections::HashSet::new();
-                    let mut hdrs = std::collections::HashSet::new();
-                    let mut h2fps = std::collections::HashSet::new();
-
-                    for acc in &members {
-                        if let Some(w) = store.get_window(acc) {
-                            let w = w.read();
-                            ips.extend(w.ip_addresses.iter().cloned());
-                            payments.extend(w.payment_hashes.iter().cloned());
-                            ja3s.extend(w.ja3_hashes.iter().cloned());
-                            ja3s_set.extend(w.ja3s_hashes.iter().cloned());
-                            hdrs.extend(w.header_hashes.iter().cloned());
-                            h2fps.extend(w.h2_fingerprints.iter().cloned());
-                        }
+                    // Members already filtered for a suspension above are
+                    // not re-checked, so each exemption is recorded once.
+                    if !matches!(
+                        filtered_for,
+                        Some(ActionKind::SuspendAccount | ActionKind::ClusterTakedown)
+                    ) {
+                        affected = self.filter_exempt(
+                            members,
+                            ActionKind::ClusterTakedown,
+                            store,
+                            &mut exemptions,
+                        );
+                        affected.sort();
                     }
+                    let members = affected.clone();
 
-                    let subnets: std::collections::HashSet<String> = ips
-                        .iter()
-                        .filter_map(|ip| {
-                            let p: Vec<&str> = ip.split('.').collect();
-                            if p.len() == 4 {
-                                Some(format!("{}.{}.{}", p[0], p[1], p[2]))
-                            } else {
-                                None
-                            }
-                        })
-                        .collect();
-
-                    // Collect any triggered canaries for this cluster
-                    let triggered_canaries = store.triggered_canaries_for_cluster(cid);
-
-                    let ioc = IocBundle {
-                        cluster_id: cid,
-                        ip_addresses: ips.into_iter().collect(),
-                        ip_subnets: subnets.into_iter().collect(),
-                        payment_hashes: payments.into_iter().collect(),
-                        ja3_hashes: ja3s.into_iter().collect(),
-                        ja3s_hashes: ja3s_set.into_iter().collect(),
-                        header_order_hashes: hdrs.into_iter().collect(),
-                        h2_fingerprints: h2fps.into_iter().collect(),
-                        watermark_tokens: triggered_canaries,
-                        account_ids: members,
-                        country_codes: decision.country_codes.clone(),
-                        first_seen: Utc::now(),
-                        last_seen: Utc::now(),
-                        total_requests: decision.n_requests_seen as u64,
-                        targeted_capabilities: decision.top_evidence.clone(),
-                        confidence: decision.composite_score,
-                        timestamp: Utc::now(),
-                    };
-
-                    self.write("ioc_bundles.jsonl", &(serde_json::to_string(&ioc)? + "\n"))
-                        .await?;
+                    let ioc = build_ioc(cid, members, decision, store);
+                    self.emit(
+                        Stream::Ioc,
+                        &format!("cluster_{}", cid),
+                        Some(ActionKind::ClusterTakedown),
+                        Some(decision.tier),
+                        serde_json::to_value(&ioc)?,
+                    );
                     info!(
                         "CLUSTER_TAKEDOWN cluster={} accounts={}",
                         cid,
@@ -128,44 +263,289 @@ impl Dispatcher {
             }
         }
 
+        // Every tier's limits apply, whatever the primary action; only an
+        // exemption that left nothing but monitoring skips them.
+        let rate_limits = if action_type != ActionKind::Monitor {
+            let window = store.get_window(&decision.account_id);
+            let window = window.as_ref().map(|w| w.read());
+            self.rate_limits.commands(
+                decision,
+                window.as_deref(),
+                store.subnet_prefixes(),
+                Utc::now(),
+            )
+        } else {
+            vec![]
+        };
+        if let Some(cmd) = rate_limits.iter().find(|c| c.scope == LimitScope::Account) {
+            store.set_rate_limit(&decision.account_id, cmd);
+        }
+
         let action = EnforcementAction {
             action_type,
             account_id: Some(decision.account_id.clone()),
             cluster_id: decision.cluster_id,
             affected_accounts: affected,
-            reason: format!(
-                "score={:.4} tier={}",
-                decision.composite_score, decision.tier
-            ),
+            reason: match decision.scope {
+                DecisionScope::Account => format!(
+                    "score={:.4} tier={}",
+                    decision.composite_score, decision.tier
+                ),
+                DecisionScope::Cluster => format!(
+                    "cluster_score={:.4} tier={}",
+                    decision.composite_score, decision.tier
+                ),
+            },
             evidence: decision.top_evidence.clone(),
             composite_score: decision.composite_score,
             canary_token: canary,
             timestamp: Utc::now(),
+            explanation: Some(decision.explanation.clone()),
+            exemptions: exemptions.clone(),
+            rate_limits,
         };
 
-        let line = action.to_jsonl() + "\n";
-        match action_type {
-            ActionKind::SuspendAccount | ActionKind::ClusterTakedown => {
-                self.write("enforcement_actions.jsonl", &line).await?
-            }
-            ActionKind::RateLimit => self.write("rate_limit_commands.jsonl", &line).await?,
-            ActionKind::FlagForReview | ActionKind::InjectCanary => {
-                self.write("analyst_queue.jsonl", &line).await?
-            }
-            _ => {}
+        let payload = serde_json::to_value(&action)?;
+        let (key, tier) = (decision.account_id.as_str(), Some(decision.tier));
+        let stream = match action_type {
+            ActionKind::SuspendAccount | ActionKind::ClusterTakedown => Some(Stream::Enforcement),
+            ActionKind::FlagForReview | ActionKind::InjectCanary => Some(Stream::AnalystQueue),
+            _ => None,
+        };
+        if let Some(stream) = stream {
+            self.emit(stream, key, Some(action_type), tier, payload.clone());
+        }
+        // Gateways consume one command per line.
+        for cmd in &action.rate_limits {
+            self.emit(
+                Stream::RateLimit,
+                &cmd.key,
+                Some(action_type),
+                tier,
+                serde_json::to_value(cmd)?,
+            );
+        }
+        // Every exemption is audited separately so it can be reviewed on its own.
+        for ex in &exemptions {
+            let record = serde_json::json!({
+                "type": "exemption",
+                "cluster_id": decision.cluster_id,
+                "exemption": ex,
+                "timestamp": Utc::now(),
+            });
+            self.audit(key, Some(action_type), tier, record).await;
+        }
+        self.audit(key, Some(action_type), tier, payload).await;
+
+        if let Some(notifier) = &self.notifier {
+            let cluster_size = decision
+                .cluster_id
+                .map_or(0, |cid| store.cluster_members(cid).len());
+            notifier.notify(Alert::new(decision, &action, cluster_size), Utc::now());
         }
-        self.write("audit_log.jsonl", &line).await?;
 
         Ok(action)
     }
 
-    async fn write(&self, file: &str, content: &str) -> Result<()> {
-        let mut f = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(self.out.join(file))
-            .await?;
-        f.write_all(content.as_bytes()).await?;
+    /// Audit canary / watermark replay hits (engine/canary_scan.rs).
+    pub async fn record_canary_hits(&self, hits: &[CanaryHit]) -> Result<()> {
+        for h in hits {
+            let record = serde_json::json!({ "type": "canary_hit", "hit": h });
+            self.audit(&h.account_id, None, None, record).await;
+        }
         Ok(())
     }
+
+    /// Audit cluster formation / join / merge events (state/graph.rs).
+    pub async fn record_cluster_events(&self, events: &[ClusterEvent]) -> Result<()> {
+        for e in events {
+            let record = serde_json::json!({ "type": "cluster_event", "event": e });
+            self.audit(&format!("cluster_{}", e.cluster_id), None, None, record)
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Emit lifecycle transitions (engine/lifecycle.rs) on the lifecycle stream.
+    pub async fn record_transitions(&self, transitions: &[Transition]) -> Result<()> {
+        for t in transitions {
+            self.emit(
+                Stream::Lifecycle,
+                &t.account_id,
+                None,
+                None,
+                serde_json::to_value(t)?,
+            );
+        }
+        Ok(())
+    }
+}
+
+// Build an IOC bundle from all cluster member data.
+fn build_ioc(
+    cid: u32,
+    members: Vec<String>,
+    decision: &RiskDecision,
+    store: &impl StateBackend,
+) -> IocBundle {
+    let mut ips = std::collections::HashSet::new();
+    let mut payments = std::collections::HashSet::new();
+    let mut ja3s = std::collections::HashSet::new();
+    let mut ja3s_set = std::collections::HashSet::new();
+    let mut hdrs = std::collections::HashSet::new();
+    let mut h2fps = std::collections::HashSet::new();
+
+    for acc in &members {
+        if let Some(w) = store.get_window(acc) {
+            let w = w.read();
+            ips.extend(w.ip_addresses.iter().cloned());
+            payments.extend(w.payment_hashes.iter().cloned());
+            ja3s.extend(w.ja3_hashes.iter().cloned());
+            ja3s_set.extend(w.ja3s_hashes.iter().cloned());
+            hdrs.extend(w.header_hashes.iter().cloned());
+            h2fps.extend(w.h2_fingerprints.iter().cloned());
+        }
+    }
+
+    let mut subnets: Vec<IpCidr> = store.subnet_prefixes().of_all(&ips).into_iter().collect();
+    subnets.sort();
+
+    // Collect any triggered canaries for this cluster
+    let triggered_canaries = store.triggered_canaries_for_cluster(cid);
+
+    IocBundle {
+        cluster_id: cid,
+        ip_addresses: ips.into_iter().collect(),
+        ip_subnets: subnets,
+        payment_hashes: payments.into_iter().collect(),
+        ja3_hashes: ja3s.into_iter().collect(),
+        ja3s_hashes: ja3s_set.into_iter().collect(),
+        header_order_hashes: hdrs.into_iter().collect(),
+        h2_fingerprints: h2fps.into_iter().collect(),
+        watermark_tokens: triggered_canaries,
+        account_ids: members,
+        country_codes: decision.country_codes.clone(),
+        first_seen: Utc::now(),
+        last_seen: Utc::now(),
+        total_requests: decision.n_requests_seen as u64,
+        targeted_capabilities: decision.top_evidence.clone(),
+        confidence: decision.composite_score,
+        timestamp: Utc::now(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::allowlist::AllowEntry;
+    use crate::events::{ApiEvent, ExemptionScope, LimitScope};
+    use crate::net::SubnetPrefixes;
+    use crate::state::graph::GraphConfig;
+    use crate::state::window::StateStore;
+
+    fn event(account: &str) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": Utc::now(),
+            "ip_address": "203.0.113.7", "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": 100,
+            "payment_method_hash": "pm_ring", "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cluster_takedown_records_each_exemption_once() {
+        let dir = std::env::temp_dir().join(format!("glasswally_dispatch_{}", std::process::id()));
+        let allowlist = Arc::new(AllowlistRegistry::new());
+        allowlist.add(AllowEntry {
+            id: "partner".into(),
+            key: "account:ring_0".parse().unwrap(),
+            scope: ExemptionScope::NoSuspension,
+            owner: "partnerships".into(),
+            reason: String::new(),
+            created_at: None,
+            expires_at: None,
+        });
+        let dispatcher = Dispatcher::new(&dir).with_allowlist(allowlist);
+        let store = StateStore::new();
+        for i in 0..4 {
+            store.ingest(&event(&format!("ring_{i}")));
+        }
+        let cid = store.get_cluster("ring_1").expect("payment ring clustered");
+
+        let decision: RiskDecision = serde_json::from_value(serde_json::json!({
+            "scope": "Cluster", "account_id": "ring_1", "composite_score": 0.8,
+            "tier": "Critical", "signal_scores": {}, "top_evidence": [],
+            "country_codes": [], "cluster_id": cid, "n_requests_seen": 4,
+            "action": "ClusterTakedown", "timestamp": Utc::now(), "ground_truth": null
+        }))
+        .unwrap();
+        let action = dispatcher.dispatch(&decision, &store).await.unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(action.action_type, ActionKind::ClusterTakedown);
+        assert_eq!(action.affected_accounts, ["ring_1", "ring_2", "ring_3"]);
+        assert_eq!(action.exemptions.len(), 1);
+        assert_eq!(action.exemptions[0].account_id, "ring_0");
+    }
+
+    fn account_decision(tier: &str, action: &str) -> RiskDecision {
+        serde_json::from_value(serde_json::json!({
+            "scope": "Account", "account_id": "solo", "composite_score": 0.6,
+            "tier": tier, "signal_scores": {}, "top_evidence": [],
+            "country_codes": [], "cluster_id": null, "n_requests_seen": 1,
+            "action": action, "timestamp": Utc::now(), "ground_truth": null
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn every_enforced_tier_gets_its_rate_limits() {
+        let dir = std::env::temp_dir().join(format!("glasswally_rl_{}", std::process::id()));
+        let dispatcher = Dispatcher::new(&dir);
+        let store = StateStore::with_graph_config(GraphConfig {
+            subnets: SubnetPrefixes { v4: 16, v6: 48 },
+            ..GraphConfig::default()
+        });
+        store.ingest(&event("solo"));
+        let policy = RateLimitPolicy::default();
+
+        for (tier, action, limits) in [
+            ("Medium", "RateLimit", &policy.medium),
+            ("High", "InjectCanary", &policy.high),
+            ("Critical", "SuspendAccount", &policy.critical),
+        ] {
+            let decision = account_decision(tier, action);
+            let action = dispatcher.dispatch(&decision, &store).await.unwrap();
+            assert!(!action.rate_limits.is_empty(), "{tier}");
+            for cmd in &action.rate_limits {
+                assert_eq!(cmd.tier, decision.tier);
+                assert_eq!(cmd.max_concurrency, limits.max_concurrency, "{tier}");
+                assert_eq!(cmd.duration_secs, limits.duration_secs, "{tier}");
+                assert!(cmd.rpm <= limits.max_rpm, "{tier}");
+            }
+            if tier == "Critical" {
+                // Subnets follow the graph's prefixes, not a separate setting.
+                let subnets: Vec<&str> = action
+                    .rate_limits
+                    .iter()
+                    .filter(|c| c.scope == LimitScope::Subnet)
+                    .map(|c| c.key.as_str())
+                    .collect();
+                assert_eq!(subnets, ["203.0.0.0/16"]);
+            }
+        }
+
+        // Fully exempt: monitoring only, no limits.
+        let action = dispatcher
+            .dispatch(&account_decision("Medium", "Monitor"), &store)
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(action.rate_limits.is_empty());
+    }
 }
@@ -0,0 +1,546 @@
+// glasswally/src/engine/feedback.rs
+//
+// Analyst feedback ingestion.
+//
+// Decisions routed to analyst_queue.jsonl are labeled back into Glasswally as
+// true / false positives, either through the query API (grpc_api.rs) or by
+// appending lines to a watched labels file (`--feedback-path`):
+//
+//   { "account_id": "sk-abc", "verdict": "FP", "analyst": "jdoe",
+//     "reason": "university research lab", "allow": ["org"] }
+//   { "cluster_id": 17, "verdict": "TP", "analyst": "jdoe",
+//     "campaign": "distill_campaign_q3" }
+//
+// A label applies to one account, or to every member of a cluster.  Effects:
+//
+//   FP — account (and its orgs, when `allow` lists "org") allowlisted with
+//        Full scope until the false-positive suppression period ends
+//        (engine/allowlist.rs).  ASN / JA3 values listed in `allow` are
+//        shared by unrelated accounts, so they only get NoSuspension.  The
+//        workers that drove the account's last decision are down-weighted for
+//        that account (×0.5 per FP, floor 0.1), and the enforcement
+//        lifecycle records an analyst false-positive override
+//        (engine/lifecycle.rs).
+//   TP — no enforcement change; the label only feeds the dataset.
+//
+// Both verdicts append the account's retained full events (the window's
+// recent-prompt ring) to an evaluation dataset in the eval module's format
+// (ApiEvent + campaign_label; null = legitimate), so `--mode eval` can be
+// re-run against a growing, analyst-labeled corpus.  Only events newer than
+// the last export for the account are appended.
+//
+// Restarts: the effects of every label (feedback allowlist entries with their
+// original expiry, worker multipliers, FP overrides, dataset export marks and
+// the byte offset reached in the labels file) are saved to a state file after
+// each label and restored at startup.  Labels are never replayed — a replay
+// would recompute expiry from the restart time, find empty windows (no
+// drivers to down-weight, no cluster members) and re-journal transitions.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use super::allowlist::{AllowEntry, AllowKey};
+use super::fusion::FusionEngine;
+use super::lifecycle::{FpOverride, Transition};
+use crate::events::{ApiEvent, ExemptionScope, WorkerKind};
+use crate::state::backend::StateBackend;
+
+const FP_PENALTY: f32 = 0.5; // worker weight multiplier per confirmed FP
+const MIN_WORKER_MULTIPLIER: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Verdict {
+    #[serde(rename = "TP")]
+    TruePositive,
+    #[serde(rename = "FP")]
+    FalsePositive,
+}
+
+/// Extra allowlisting requested on an FP, beyond the account itself.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowKind {
+    Org,
+    Asn,
+    Ja3,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalystLabel {
+    #[serde(default)]
+    pub account_id: Option<String>,
+    #[serde(default)]
+    pub cluster_id: Option<u32>,
+    pub verdict: Verdict,
+    #[serde(default)]
+    pub analyst: String, // set from the admin token on the query API
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub campaign: Option<String>, // dataset campaign_label for TP (default "analyst_tp")
+    #[serde(default)]
+    pub allow: Vec<AllowKind>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LabelOutcome {
+    pub accounts: Vec<String>,
+    pub allowlisted: Vec<String>,
+    pub downweighted: Vec<String>, // "account:worker×multiplier"
+    pub transitions: Vec<Transition>,
+    pub dataset_rows: usize,
+}
+
+/// Persisted effects of the labels applied so far.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FeedbackSnapshot {
+    pub labels_offset: u64, // bytes of the labels file already applied
+    pub worker_multipliers: HashMap<String, HashMap<WorkerKind, f32>>,
+    pub exported_through: HashMap<String, DateTime<Utc>>,
+    pub allowlist: Vec<AllowEntry>, // "feedback:" entries
+    pub fp_overrides: HashMap<String, FpOverride>,
+}
+
+pub struct FeedbackStore {
+    worker_multipliers: DashMap<String, HashMap<WorkerKind, f32>>,
+    exported_through: DashMap<String, DateTime<Utc>>,
+    dataset_path: Option<PathBuf>,
+    state_path: Option<PathBuf>,
+    labels_offset: AtomicU64,
+    save_lock: tokio::sync::Mutex<()>,
+    pub n_tp: AtomicU64,
+    pub n_fp: AtomicU64,
+}
+
+impl FeedbackStore {
+    pub fn new(dataset_path: Option<PathBuf>) -> Self {
+        Self {
+            worker_multipliers: DashMap::new(),
+            exported_through: DashMap::new(),
+            dataset_path,
+            state_path: None,
+            labels_offset: AtomicU64::new(0),
+            save_lock: tokio::sync::Mutex::new(()),
+            n_tp: AtomicU64::new(0),
+            n_fp: AtomicU64::new(0),
+        }
+    }
+
+    /// Persist label effects to `path` (see `save` / `restore`).
+    pub fn with_state_path(mut self, path: PathBuf) -> Self {
+        self.state_path = Some(path);
+        self
+    }
+
+    /// Byte offset of the labels file up to which labels have been applied.
+    pub fn labels_offset(&self) -> u64 {
+        self.labels_offset.load(Ordering::Relaxed)
+    }
+
+    pub fn set_labels_offset(&self, offset: u64) {
+        self.labels_offset.store(offset, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, engine: &FusionEngine, now: DateTime<Utc>) -> FeedbackSnapshot {
+        FeedbackSnapshot {
+            labels_offset: self.labels_offset(),
+            worker_multipliers: self
+                .worker_multipliers
+                .iter()
+                .map(|e| (e.key().clone(), e.value().clone()))
+                .collect(),
+            exported_through: self
+                .exported_through
+                .iter()
+                .map(|e| (e.key().clone(), *e.value()))
+                .collect(),
+            allowlist: engine
+                .allowlist()
+                .entries()
+                .into_iter()
+                .filter(|e| e.id.starts_with("feedback:"))
+                .collect(),
+            fp_overrides: engine.lifecycle().fp_overrides(now).into_iter().collect(),
+        }
+    }
+
+    /// Write the snapshot to the state file (atomically, via a rename).
+    pub async fn save(&self, engine: &FusionEngine) -> Result<()> {
+        let Some(path) = &self.state_path else {
+            return Ok(());
+        };
+        let _guard = self.save_lock.lock().await;
+        let body = serde_json::to_vec(&self.snapshot(engine, Utc::now()))?;
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, body)
+            .await
+            .with_context(|| format!("writing feedback state {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    /// Restore the state file, if present.  Expired allowlist entries and
+    /// overrides are dropped; nothing is journaled.
+    pub fn restore(&self, engine: &FusionEngine) -> Result<bool> {
+        let Some(path) = &self.state_path else {
+            return Ok(false);
+        };
+        if !path.exists() {
+            return Ok(false);
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading feedback state {}", path.display()))?;
+        let snap: FeedbackSnapshot = serde_json::from_str(&content)
+            .with_context(|| format!("parsing feedback state {}", path.display()))?;
+        self.apply_snapshot(snap, engine, Utc::now());
+        Ok(true)
+    }
+
+    fn apply_snapshot(&self, snap: FeedbackSnapshot, engine: &FusionEngine, now: DateTime<Utc>) {
+        self.set_labels_offset(snap.labels_offset);
+        for (account, m) in snap.worker_multipliers {
+            self.worker_multipliers.insert(account, m);
+        }
+        for (account, t) in snap.exported_through {
+            self.exported_through.insert(account, t);
+        }
+        for entry in snap.allowlist {
+            if entry.expires_at.is_none_or(|e| now < e) {
+                engine.allowlist().add(entry);
+            }
+        }
+        for (account, o) in snap.fp_overrides {
+            if now < o.until {
+                engine.lifecycle().restore_fp_override(&account, o);
+            }
+        }
+    }
+
+    /// Per-account weight multiplier for a worker (1.0 unless FPs were confirmed).
+    pub fn worker_multiplier(&self, account_id: &str, worker: WorkerKind) -> f32 {
+        self.worker_multipliers
+            .get(account_id)
+            .and_then(|m| m.get(&worker).copied())
+            .unwrap_or(1.0)
+    }
+
+    fn downweight(&self, account_id: &str, workers: &[WorkerKind]) -> Vec<String> {
+        let mut m = self
+            .worker_multipliers
+            .entry(account_id.to_string())
+            .or_default();
+        workers
+            .iter()
+            .map(|w| {
+                let v = m.entry(*w).or_insert(1.0);
+                *v = (*v * FP_PENALTY).max(MIN_WORKER_MULTIPLIER);
+                format!("{}:{}×{:.2}", account_id, w, v)
+            })
+            .collect()
+    }
+
+    async fn append_dataset(&self, rows: &[ApiEvent]) -> Result<()> {
+        let Some(path) = &self.dataset_path else {
+            return Ok(());
+        };
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut out = String::new();
+        for ev in rows {
+            out.push_str(&serde_json::to_string(ev)?);
+            out.push('\n');
+        }
+        let mut f = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        f.write_all(out.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+impl Default for FeedbackStore {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// Apply one analyst label to the engine and store.
+pub async fn apply_label(
+    label: &AnalystLabel,
+    engine: &FusionEngine,
+    store: &impl StateBackend,
+) -> Result<LabelOutcome> {
+    let accounts: Vec<String> = match (&label.account_id, label.cluster_id) {
+        (Some(a), _) => vec![a.clone()],
+        (None, Some(cid)) => {
+            let mut m: Vec<String> = store.cluster_members(cid).into_iter().collect();
+            m.sort();
+            m
+        }
+        (None, None) => anyhow::bail!("label needs account_id or cluster_id"),
+    };
+    if label.analyst.is_empty() {
+        anyhow::bail!("label needs an analyst");
+    }
+
+    let feedback = engine.feedback();
+    let now = Utc::now();
+    let campaign = match label.verdict {
+        Verdict::TruePositive => Some(
+            label
+                .campaign
+                .clone()
+                .unwrap_or_else(|| "analyst_tp".into()),
+        ),
+        Verdict::FalsePositive => None,
+    };
+    let mut outcome = LabelOutcome {
+        accounts: accounts.clone(),
+        ..LabelOutcome::default()
+    };
+    let mut rows = Vec::new();
+
+    for account in &accounts {
+        let window = store.get_window(account);
+        let since = feedback.exported_through.get(account).map(|t| *t);
+        let (drivers, score) = match &window {
+            Some(w) => {
+                let w = w.read();
+                rows.extend(
+                    w.recent
+                        .iter()
+                        .filter(|e| since.is_none_or(|s| e.timestamp > s))
+                        .map(|e| ApiEvent {
+                            campaign_label: campaign.clone(),
+                            ..e.clone()
+                        }),
+                );
+                if let Some(last) = w.recent.back() {
+                    feedback
+                        .exported_through
+                        .insert(account.clone(), last.timestamp);
+                }
+                (
+                    w.risk.last_drivers.clone(),
+                    w.risk.decayed(engine.decay(), now),
+                )
+            }
+            None => (vec![], 0.0),
+        };
+
+        if label.verdict == Verdict::FalsePositive {
+            let mut keys = vec![AllowKey::Account(account.clone())];
+            if let Some(w) = &window {
+                let w = w.read();
+                for kind in &label.allow {
+                    match kind {
+                        AllowKind::Org => keys.extend(w.org_ids.iter().cloned().map(AllowKey::Org)),
+                        AllowKind::Asn => keys.extend(
+                            w.events
+                                .iter()
+                                .filter_map(|e| e.asn_number)
+                                .collect::<HashSet<_>>()
+                                .into_iter()
+                                .map(AllowKey::Asn),
+                        ),
+                        AllowKind::Ja3 => {
+                            keys.extend(w.ja3_hashes.iter().cloned().map(AllowKey::Ja3))
+                        }
+                    }
+                }
+            }
+            let expires_at =
+                now + Duration::seconds(engine.lifecycle().config().fp_suppression_secs);
+            for key in keys {
+                // Infrastructure shared with unrelated accounts is never fully exempted.
+                let scope = match key {
+                    AllowKey::Asn(_) | AllowKey::Ja3(_) => ExemptionScope::NoSuspension,
+                    _ => ExemptionScope::Full,
+                };
+                outcome.allowlisted.push(key.to_string());
+                engine.allowlist().add(AllowEntry {
+                    id: format!("feedback:{}", key),
+                    key,
+                    scope,
+                    owner: format!("analyst:{}", label.analyst),
+                    reason: label.reason.clone(),
+                    created_at: Some(now),
+                    expires_at: Some(expires_at),
+                });
+            }
+            outcome
+                .downweighted
+                .extend(feedback.downweight(account, &drivers));
+            outcome
+                .transitions
+                .extend(engine.lifecycle().mark_false_positive(
+                    account,
+                    &label.analyst,
+                    &label.reason,
+                    score,
+                    now,
+                ));
+        }
+    }
+
+    match label.verdict {
+        Verdict::TruePositive => feedback.n_tp.fetch_add(1, Ordering::Relaxed),
+        Verdict::FalsePositive => feedback.n_fp.fetch_add(1, Ordering::Relaxed),
+    };
+    outcome.dataset_rows = rows.len();
+    feedback.append_dataset(&rows).await?;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::allowlist::Subject;
+    use crate::events::ActionKind;
+    use crate::state::window::StateStore;
+
+    fn event(account: &str) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": Utc::now(),
+            "ip_address": "203.0.113.7", "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": 100,
+            "payment_method_hash": null, "org_id": "org_lab", "country_code": "US",
+            "header_order": [], "ja3_hash": "ja3_requests", "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": 16509,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    fn fp(account: &str) -> AnalystLabel {
+        serde_json::from_value(serde_json::json!({
+            "account_id": account, "verdict": "FP", "analyst": "jdoe",
+            "reason": "research lab", "allow": ["org", "asn", "ja3"]
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn false_positive_label_effects() {
+        let engine = FusionEngine::new();
+        let store = StateStore::new();
+        let e = event("sk-lab");
+        store.ingest(&e);
+        store
+            .get_window("sk-lab")
+            .unwrap()
+            .write()
+            .risk
+            .last_drivers = vec![WorkerKind::AsnClassifier, WorkerKind::Velocity];
+        engine
+            .lifecycle()
+            .escalate("sk-lab", ActionKind::SuspendAccount, "", Utc::now());
+
+        let o = apply_label(&fp("sk-lab"), &engine, &store).await.unwrap();
+        assert_eq!(o.allowlisted.len(), 4);
+        assert_eq!(o.downweighted.len(), 2);
+        assert_eq!(o.transitions.len(), 1);
+        assert_eq!(o.dataset_rows, 1);
+        assert_eq!(
+            engine
+                .feedback()
+                .worker_multiplier("sk-lab", WorkerKind::Velocity),
+            0.5
+        );
+        assert_eq!(
+            engine
+                .feedback()
+                .worker_multiplier("sk-lab", WorkerKind::Fingerprint),
+            1.0
+        );
+
+        // The account and its org are fully exempt; another account sharing
+        // only the ASN / JA3 can still be rate limited, but not suspended.
+        let now = Utc::now();
+        let (action, _) =
+            engine
+                .allowlist()
+                .apply(&Subject::from_event(&e), ActionKind::RateLimit, now);
+        assert_eq!(action, ActionKind::Monitor);
+        let mut other = event("sk-other");
+        other.org_id = None;
+        let subject = Subject::from_event(&other);
+        let allowlist = engine.allowlist();
+        assert_eq!(
+            allowlist.apply(&subject, ActionKind::RateLimit, now).0,
+            ActionKind::RateLimit
+        );
+        assert_eq!(
+            allowlist.apply(&subject, ActionKind::SuspendAccount, now).0,
+            ActionKind::FlagForReview
+        );
+
+        let unattributed = AnalystLabel {
+            analyst: String::new(),
+            ..fp("sk-lab")
+        };
+        assert!(apply_label(&unattributed, &engine, &store).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn restart_restores_effects_without_replaying() {
+        let path =
+            std::env::temp_dir().join(format!("glasswally_feedback_{}.json", std::process::id()));
+        let engine = FusionEngine::new()
+            .with_feedback(FeedbackStore::default().with_state_path(path.clone()));
+        let store = StateStore::new();
+        store.ingest(&event("sk-lab"));
+        store
+            .get_window("sk-lab")
+            .unwrap()
+            .write()
+            .risk
+            .last_drivers = vec![WorkerKind::Velocity];
+        apply_label(&fp("sk-lab"), &engine, &store).await.unwrap();
+        engine.feedback().set_labels_offset(123);
+        engine.feedback().save(&engine).await.unwrap();
+        let expiry = engine.allowlist().entries()[0].expires_at;
+
+        // A restart with empty windows, a while later
+        let restarted = FusionEngine::new()
+            .with_feedback(FeedbackStore::default().with_state_path(path.clone()));
+        assert!(restarted.feedback().restore(&restarted).unwrap());
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(restarted.feedback().labels_offset(), 123);
+        assert_eq!(restarted.allowlist().len(), 4);
+        assert!(restarted
+            .allowlist()
+            .entries()
+            .iter()
+            .all(|e| e.expires_at == expiry));
+        assert_eq!(
+            restarted
+                .feedback()
+                .worker_multiplier("sk-lab", WorkerKind::Velocity),
+            0.5
+        );
+        assert!(restarted.lifecycle().suppresses("sk-lab", 0.0, Utc::now()));
+        assert!(restarted.lifecycle().drain_journal().is_empty());
+
+        // Expired effects are not restored
+        let mut snap = engine.feedback().snapshot(&engine, Utc::now());
+        for e in &mut snap.allowlist {
+            e.expires_at = Some(Utc::now() - Duration::seconds(1));
+        }
+        let fresh = FusionEngine::new();
+        fresh.feedback().apply_snapshot(snap, &fresh, Utc::now());
+        assert!(fresh.allowlist().is_empty());
+    }
+}
@@ -1,6 +1,15 @@
 // glasswally/src/engine/fusion.rs
 //
-// Weighted signal fusion with geo uplift + cluster floor raise.
+// Weighted signal fusion with policy-driven geo adjustment + cluster floor raise,
+// accumulated into a decayed account-level score (state/risk.rs) that sets the tier.
+// Cluster-level (campaign) decisions are produced by fuse_cluster — see
+// engine/cluster_fusion.rs.  Enforcement state per account (and alert
+// suppression after analyst false-positive overrides) lives in
+// engine/lifecycle.rs; analyst feedback (per-account worker down-weighting)
+// in engine/feedback.rs; allowlist exemptions in engine/allowlist.rs.
+//
+// Geo / sanctions multipliers come from a declarative GeoPolicy (see
+// engine/geo_policy.rs); the rule applied is recorded on each RiskDecision.
 //
 // Weight distribution across 16 workers (sum = 1.00):
 //   Fingerprint   0.14  — JA3 + JA3S + header entropy (highest precision)
@@ -25,9 +34,19 @@
 use chrono::Utc;
 use dashmap::DashMap;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use crate::events::{ActionKind, ApiEvent, DetectionSignal, RiskDecision, RiskTier, WorkerKind};
-use crate::state::window::StateStore;
+use super::allowlist::{AllowlistRegistry, Subject};
+use super::cluster_fusion::{score_cluster, CLUSTER_CRITICAL, CLUSTER_HIGH, COORDINATION_WORKERS};
+use super::feedback::FeedbackStore;
+use super::geo_policy::GeoPolicy;
+use super::lifecycle::{LifecycleConfig, LifecycleRegistry, Transition};
+use crate::events::{
+    ActionKind, ApiEvent, DecisionExplanation, DecisionScope, DetectionSignal, RiskDecision,
+    RiskTier, ScoreAdjustment, WorkerContribution, WorkerKind,
+};
+use crate::state::backend::StateBackend;
+use crate::state::risk::DecayConfig;
 
 // Signal weights — must sum to 1.0
 const WEIGHTS: &[(WorkerKind, f32)] = &[
@@ -51,26 +70,119 @@ const WEIGHTS: &[(WorkerKind, f32)] = &[
 
 const CRITICAL: f32 = 0.72;
 const HIGH: f32 = 0.55;
-const MEDIUM: f32 = 0.35;
+pub(crate) const MEDIUM: f32 = 0.35;
 const COOLDOWN: i64 = 600; // seconds before re-alerting same account
+const CLUSTER_EVAL_INTERVAL: i64 = 30; // seconds between cluster re-scores
+
+// Bookkeeping evidence strings that carry no analyst value.
+const NOISE_EVIDENCE: &[&str] = &[
+    "cached",
+    "no_cluster",
+    "insufficient_data",
+    "small_cluster",
+    "account_watermarked",
+];
+
+fn round4(x: f32) -> f32 {
+    (x * 10000.0).round() / 10000.0
+}
+
+/// Tier for an accumulated account score (callers guarantee score ≥ MEDIUM).
+fn tier_for(score: f32) -> RiskTier {
+    if score >= CRITICAL {
+        RiskTier::Critical
+    } else if score >= HIGH {
+        RiskTier::High
+    } else if score >= MEDIUM {
+        RiskTier::Medium
+    } else {
+        RiskTier::Low
+    }
+}
+
+/// The tier above `tier` and the threshold that reaches it.
+fn next_tier_after(tier: RiskTier) -> Option<(RiskTier, f32)> {
+    match tier {
+        RiskTier::Low => Some((RiskTier::Medium, MEDIUM)),
+        RiskTier::Medium => Some((RiskTier::High, HIGH)),
+        RiskTier::High => Some((RiskTier::Critical, CRITICAL)),
+        RiskTier::Critical => None,
+    }
+}
 
 pub struct FusionEngine {
     last_alert: DashMap<String, chrono::DateTime<Utc>>,
-    suspended: DashMap<String, bool>,
+    lifecycle: LifecycleRegistry,
+    feedback: FeedbackStore,
+    allowlist: Arc<AllowlistRegistry>,
+    geo_policy: GeoPolicy,
+    decay: DecayConfig,
+    last_cluster_eval: DashMap<u32, chrono::DateTime<Utc>>,
+    last_cluster_alert: DashMap<u32, chrono::DateTime<Utc>>,
+    cluster_takedowns: DashMap<u32, usize>, // cluster_id → member count at takedown
 }
 
 impl FusionEngine {
     pub fn new() -> Self {
         Self {
             last_alert: DashMap::new(),
-            suspended: DashMap::new(),
+            lifecycle: LifecycleRegistry::default(),
+            feedback: FeedbackStore::default(),
+            allowlist: Arc::new(AllowlistRegistry::new()),
+            geo_policy: GeoPolicy::default(),
+            decay: DecayConfig::default(),
+            last_cluster_eval: DashMap::new(),
+            last_cluster_alert: DashMap::new(),
+            cluster_takedowns: DashMap::new(),
         }
     }
 
+    pub fn with_geo_policy(mut self, policy: GeoPolicy) -> Self {
+        self.geo_policy = policy;
+        self
+    }
+
+    pub fn with_decay(mut self, decay: DecayConfig) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    pub fn with_lifecycle(mut self, cfg: LifecycleConfig) -> Self {
+        self.lifecycle = LifecycleRegistry::new(cfg);
+        self
+    }
+
+    pub fn with_feedback(mut self, feedback: FeedbackStore) -> Self {
+        self.feedback = feedback;
+        self
+    }
+
+    pub fn with_allowlist(mut self, allowlist: AllowlistRegistry) -> Self {
+        self.allowlist = Arc::new(allowlist);
+        self
+    }
+
+    pub fn decay(&self) -> &DecayConfig {
+        &self.decay
+    }
+
+    pub fn lifecycle(&self) -> &LifecycleRegistry {
+        &self.lifecycle
+    }
+
+    pub fn feedback(&self) -> &FeedbackStore {
+        &self.feedback
+    }
+
+    /// Shared with the Dispatcher, which re-checks cluster members on takedown.
+    pub fn allowlist(&self) -> &Arc<AllowlistRegistry> {
+        &self.allowlist
+    }
+
     pub fn fuse(
         &self,
         event: &ApiEvent,
-        store: &StateStore,
+        store: &impl StateBackend,
         signals: &[DetectionSignal],
     ) -> Option<RiskDecision> {
         if signals.is_empty() {
@@ -82,66 +194,173 @@ impl FusionEngine {
 
         let mut composite = 0.0f32;
         let mut sig_scores: HashMap<String, f32> = HashMap::new();
+        let mut contributions: Vec<WorkerContribution> = Vec::new();
 
         for (worker, weight) in WEIGHTS {
             if let Some(s) = sig_map.get(worker) {
+                // Analyst-confirmed FPs down-weight a worker for this account.
+                let weight = weight * self.feedback.worker_multiplier(&event.account_id, *worker);
                 let effective = s.score * (0.4 + 0.6 * s.confidence);
                 composite += effective * weight;
                 sig_scores.insert(worker.to_string(), s.score);
+                contributions.push(WorkerContribution {
+                    worker: *worker,
+                    raw_score: s.score,
+                    confidence: s.confidence,
+                    weight,
+                    contribution: round4(effective * weight),
+                    evidence: s
+                        .evidence
+                        .iter()
+                        .filter(|e| !NOISE_EVIDENCE.contains(&e.as_str()))
+                        .cloned()
+                        .collect(),
+                });
             }
         }
+        contributions.sort_by(|a, b| {
+            b.contribution
+                .partial_cmp(&a.contribution)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let base_score = round4(composite);
+        let mut adjustments: Vec<ScoreAdjustment> = Vec::new();
 
-        // Geo uplift — CN access raises composite 30%
-        if event.country_code == "CN" {
-            composite = (composite * 1.30).min(1.0);
+        // Geo / sanctions policy adjustment
+        let (adjusted, geo_adjustment) = self.geo_policy.adjust(event, composite, Utc::now());
+        if let Some(geo) = &geo_adjustment {
+            adjustments.push(ScoreAdjustment {
+                kind: "geo_policy".into(),
+                detail: match &geo.exempted_by {
+                    Some(by) => format!("{} exempted_by={}", geo.rule_id, by),
+                    None => format!(
+                        "{} ×{:.2} cc={}",
+                        geo.rule_id, geo.multiplier, geo.country_code
+                    ),
+                },
+                score_before: round4(composite),
+                score_after: round4(adjusted),
+            });
         }
+        composite = adjusted;
 
         // Cluster floor — being in a large cluster adds 8 points
         if let Some(cid) = store.get_cluster(&event.account_id) {
-            if store.cluster_members(cid).len() >= 5 {
+            let n_members = store.cluster_members(cid).len();
+            if n_members >= 5 {
+                let before = composite;
                 composite = (composite + 0.08).min(1.0);
+                adjustments.push(ScoreAdjustment {
+                    kind: "cluster_floor".into(),
+                    detail: format!("cluster_{}_size:{} +0.08", cid, n_members),
+                    score_before: round4(before),
+                    score_after: round4(composite),
+                });
             }
         }
 
-        composite = (composite * 10000.0).round() / 10000.0;
-        if composite < MEDIUM {
+        composite = round4(composite);
+
+        // Account-level accumulation — fold this event into the decayed account
+        // score.  Tiers are assigned from the accumulated score, never lower
+        // than a peak tier still inside its hold period.
+        let window = store.get_window(&event.account_id);
+        let (account_score, held_tier, events_scored) = match &window {
+            Some(w) => {
+                let mut w = w.write();
+                // Coordinated-behaviour signals feed cluster-level fusion.
+                for worker in COORDINATION_WORKERS {
+                    if let Some(s) = sig_map.get(worker) {
+                        // Preambles only count when they collide across accounts.
+                        let coordinated = *worker != WorkerKind::RolePreamble
+                            || s.evidence
+                                .iter()
+                                .any(|e| e.starts_with("cross_account_collision"));
+                        w.risk
+                            .coordination
+                            .insert(*worker, if coordinated { s.score } else { 0.0 });
+                    }
+                }
+                let score = w.risk.update(composite, &self.decay, event.timestamp);
+                let held = w.risk.held_tier(&self.decay, event.timestamp);
+                (score, held, w.risk.events_scored)
+            }
+            None => (composite, None, 1),
+        };
+        if account_score > composite {
+            adjustments.push(ScoreAdjustment {
+                kind: "account_accumulation".into(),
+                detail: format!(
+                    "events_scored={} half_life={}s",
+                    events_scored, self.decay.half_life_secs
+                ),
+                score_before: composite,
+                score_after: account_score,
+            });
+        }
+        if account_score < MEDIUM {
             return None;
         }
 
-        let (tier, action) = if composite >= CRITICAL {
-            (RiskTier::Critical, ActionKind::SuspendAccount)
-        } else if composite >= HIGH {
+        let tier = match held_tier {
+            Some(held) => tier_for(account_score).max(held),
+            None => tier_for(account_score),
+        };
+        let action = match tier {
+            RiskTier::Critical => ActionKind::SuspendAccount,
             // High tier: inject canary + flag for review
-            (RiskTier::High, ActionKind::InjectCanary)
-        } else {
-            (RiskTier::Medium, ActionKind::RateLimit)
+            RiskTier::High => ActionKind::InjectCanary,
+            _ => ActionKind::RateLimit,
         };
+        // Allowlist / trusted-partner registry — risk still accumulated above.
+        let (action, exemptions) =
+            self.allowlist
+                .apply(&Subject::from_event(event), action, Utc::now());
 
-        let top_evidence: Vec<String> = signals
+        // Evidence ordered by the contribution of the worker that produced it.
+        let top_evidence: Vec<String> = contributions
             .iter()
-            .flat_map(|s| s.evidence.iter().cloned())
-            .filter(|e| {
-                ![
-                    "cached",
-                    "no_cluster",
-                    "insufficient_data",
-                    "small_cluster",
-                    "account_watermarked",
-                ]
-                .contains(&e.as_str())
-            })
+            .flat_map(|c| c.evidence.iter().cloned())
             .take(10)
             .collect();
 
-        let window = store.get_window(&event.account_id);
+        let (next_tier, margin_to_next_tier) = match next_tier_after(tier) {
+            Some((t, threshold)) => (Some(t), Some(round4((threshold - account_score).max(0.0)))),
+            None => (None, None),
+        };
+        let explanation = DecisionExplanation {
+            base_score,
+            contributions,
+            adjustments,
+            final_score: account_score,
+            next_tier,
+            margin_to_next_tier,
+        };
+
+        if let Some(w) = &window {
+            let mut w = w.write();
+            w.risk.record_tier(tier, &self.decay, event.timestamp);
+            w.risk.last_evidence = top_evidence.clone();
+            w.risk.last_drivers = explanation
+                .contributions
+                .iter()
+                .filter(|c| c.contribution > 0.0)
+                .take(5)
+                .map(|c| c.worker)
+                .collect();
+        }
+
         let n_reqs = window.as_ref().map(|w| w.read().events.len()).unwrap_or(0);
         let countries = window
             .map(|w| w.read().country_codes.iter().cloned().collect())
             .unwrap_or_default();
 
         Some(RiskDecision {
+            scope: DecisionScope::Account,
             account_id: event.account_id.clone(),
+            request_id: event.request_id.clone(),
             composite_score: composite,
+            account_score,
             tier,
             signal_scores: sig_scores,
             top_evidence,
@@ -151,29 +370,157 @@ impl FusionEngine {
             action,
             timestamp: Utc::now(),
             ground_truth: event.campaign_label.clone(),
+            geo_adjustment,
+            explanation,
+            exemptions,
+        })
+    }
+
+    /// Score the cluster `cluster_id` as a whole (see engine/cluster_fusion.rs).
+    /// Throttled to one evaluation per CLUSTER_EVAL_INTERVAL per cluster.
+    pub fn fuse_cluster(
+        &self,
+        cluster_id: u32,
+        trigger: &ApiEvent,
+        store: &impl StateBackend,
+    ) -> Option<RiskDecision> {
+        let now = trigger.timestamp;
+        if let Some(last) = self.last_cluster_eval.get(&cluster_id) {
+            if (now - *last).num_seconds().abs() < CLUSTER_EVAL_INTERVAL {
+                return None;
+            }
+        }
+        self.last_cluster_eval.insert(cluster_id, now);
+
+        let cs = score_cluster(cluster_id, store, &self.decay, now)?;
+        let tier = cs.tier()?;
+        let action = match tier {
+            RiskTier::Critical => ActionKind::ClusterTakedown,
+            RiskTier::High => ActionKind::FlagForReview,
+            _ => ActionKind::Monitor,
+        };
+
+        // Components as cumulative adjustments so analysts see how the score built up.
+        let mut running = 0.0f32;
+        let mut adjustments = cs
+            .components()
+            .iter()
+            .map(|(name, value, weight)| {
+                let before = running;
+                running += value * weight;
+                ScoreAdjustment {
+                    kind: (*name).into(),
+                    detail: format!("{:.3} × {:.2}", value, weight),
+                    score_before: round4(before),
+                    score_after: round4(running),
+                }
+            })
+            .collect::<Vec<_>>();
+        if cs.score > cs.weighted {
+            adjustments.push(ScoreAdjustment {
+                kind: "campaign_floor".into(),
+                detail: format!("cluster_{}_size:{} → critical", cluster_id, cs.n_members),
+                score_before: round4(cs.weighted),
+                score_after: cs.score,
+            });
+        }
+        let (next_tier, margin_to_next_tier) = match tier {
+            RiskTier::Critical => (None, None),
+            RiskTier::High => (
+                Some(RiskTier::Critical),
+                Some(round4(CLUSTER_CRITICAL - cs.score)),
+            ),
+            _ => (Some(RiskTier::High), Some(round4(CLUSTER_HIGH - cs.score))),
+        };
+
+        Some(RiskDecision {
+            scope: DecisionScope::Cluster,
+            account_id: trigger.account_id.clone(),
+            request_id: trigger.request_id.clone(),
+            composite_score: cs.score,
+            account_score: cs.score,
+            tier,
+            signal_scores: HashMap::new(),
+            top_evidence: cs.evidence.clone(),
+            country_codes: cs.country_codes.clone(),
+            cluster_id: Some(cluster_id),
+            n_requests_seen: cs.total_requests,
+            action,
+            timestamp: Utc::now(),
+            ground_truth: trigger.campaign_label.clone(),
+            geo_adjustment: None,
+            explanation: DecisionExplanation {
+                base_score: cs.score,
+                contributions: vec![],
+                adjustments,
+                final_score: cs.score,
+                next_tier,
+                margin_to_next_tier,
+            },
+            exemptions: vec![],
         })
     }
 
-    pub fn should_alert(&self, account_id: &str) -> bool {
-        if self.suspended.get(account_id).map(|s| *s).unwrap_or(false) {
+    /// Cluster alerts honour COOLDOWN and are suppressed after a takedown until
+    /// the cluster grows beyond the membership that was taken down.
+    pub fn should_alert_cluster(&self, cluster_id: u32, n_members: usize) -> bool {
+        if let Some(taken) = self.cluster_takedowns.get(&cluster_id) {
+            if n_members <= *taken {
+                return false;
+            }
+        }
+        self.last_cluster_alert
+            .get(&cluster_id)
+            .map(|t| (Utc::now() - *t).num_seconds() >= COOLDOWN)
+            .unwrap_or(true)
+    }
+
+    /// Escalate every affected member; returns the transitions made.
+    pub fn record_cluster_alert(
+        &self,
+        cluster_id: u32,
+        affected: &[String],
+        action: ActionKind,
+    ) -> Vec<Transition> {
+        self.last_cluster_alert.insert(cluster_id, Utc::now());
+        if action == ActionKind::ClusterTakedown {
+            self.cluster_takedowns.insert(cluster_id, affected.len());
+        }
+        let reason = format!("cluster {} {}", cluster_id, action);
+        affected
+            .iter()
+            .filter_map(|a| self.lifecycle.escalate(a, action, &reason, Utc::now()))
+            .collect()
+    }
+
+    /// Suspended accounts and accounts under an analyst false-positive
+    /// override (below the breakthrough margin) are not re-alerted.
+    pub fn should_alert(&self, account_id: &str, account_score: f32) -> bool {
+        let now = Utc::now();
+        if self.lifecycle.is_suspended(account_id)
+            || self.lifecycle.suppresses(account_id, account_score, now)
+        {
             return false;
         }
         self.last_alert
             .get(account_id)
-            .map(|t| (Utc::now() - *t).num_seconds() >= COOLDOWN)
+            .map(|t| (now - *t).num_seconds() >= COOLDOWN)
             .unwrap_or(true)
     }
 
-    pub fn record_alert(&self, account_id: &str, suspend: bool) {
-        self.last_alert.insert(account_id.to_string(), Utc::now());
-        if suspend {
-            self.suspended.insert(account_id.to_string(), true);
-        }
+    /// Record an alert and escalate the account's lifecycle state to match
+    /// the dispatched action.
+    pub fn record_alert(&self, decision: &RiskDecision, action: ActionKind) -> Option<Transition> {
+        self.last_alert
+            .insert(decision.account_id.clone(), Utc::now());
+        let reason = format!("score={:.4} tier={}", decision.account_score, decision.tier);
+        self.lifecycle
+            .escalate(&decision.account_id, action, &reason, Utc::now())
     }
 
     /// Returns true if the account is currently suspended (used by gRPC query API).
     pub fn is_suspended(&self, account_id: &str) -> bool {
-        self.suspended.get(account_id).map(|s| *s).unwrap_or(false)
+        self.lifecycle.is_suspended(account_id)
     }
 }
 
@@ -186,7 +533,38 @@ impl Default for FusionEngine {
 // Float const arithmetic is not stable; enforce the sum invariant via test.
 #[cfg(test)]
 mod tests {
-    use super::WEIGHTS;
+    use super::*;
+    use crate::state::window::StateStore;
+
+    fn event(account: &str, payment: &str) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": Utc::now(),
+            "ip_address": "203.0.113.7", "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": 100,
+            "payment_method_hash": payment, "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    fn signals(account: &str) -> Vec<DetectionSignal> {
+        WEIGHTS
+            .iter()
+            .enumerate()
+            .map(|(i, (worker, _))| DetectionSignal {
+                worker: *worker,
+                account_id: account.into(),
+                score: 0.5 + 0.03 * i as f32,
+                confidence: 0.9 - 0.04 * i as f32,
+                evidence: vec![format!("{worker}_evidence")],
+                meta: HashMap::new(),
+                timestamp: Utc::now(),
+            })
+            .collect()
+    }
 
     #[test]
     fn weights_sum_to_one() {
@@ -196,4 +574,43 @@ mod tests {
             "WEIGHTS must sum to 1.0, got {sum}"
         );
     }
+
+    #[test]
+    fn explanation_sums_to_fused_score() {
+        let store = StateStore::new();
+        let engine = FusionEngine::new();
+
+        // Lone account: the worker breakdown is the whole score.
+        let e = event("solo", "pm_solo");
+        store.ingest(&e);
+        let d = engine.fuse(&e, &store, &signals("solo")).expect("tiered");
+        let x = &d.explanation;
+        let sum: f32 = x.contributions.iter().map(|c| c.contribution).sum();
+        assert_eq!(x.contributions.len(), WEIGHTS.len());
+        assert!(
+            (sum - x.base_score).abs() < 0.001,
+            "{sum} vs {}",
+            x.base_score
+        );
+        assert!(x.adjustments.is_empty());
+        assert!((x.final_score - sum).abs() < 0.001);
+        assert_eq!(d.account_score, x.final_score);
+
+        // Cluster member: contributions + the adjustment chain reach the final score.
+        for i in 0..5 {
+            store.ingest(&event(&format!("ring_{i}"), "pm_ring"));
+        }
+        let e = event("ring_0", "pm_ring");
+        store.ingest(&e);
+        let d = engine.fuse(&e, &store, &signals("ring_0")).expect("tiered");
+        let x = &d.explanation;
+        let mut running: f32 = x.contributions.iter().map(|c| c.contribution).sum();
+        assert!((running - x.base_score).abs() < 0.001);
+        assert!(x.adjustments.iter().any(|a| a.kind == "cluster_floor"));
+        for a in &x.adjustments {
+            assert!((a.score_before - running).abs() < 0.001, "{}", a.kind);
+            running = a.score_after;
+        }
+        assert!((running - x.final_score).abs() < 0.001);
+    }
 }
@@ -0,0 +1,309 @@
+// glasswally/src/engine/geo_policy.rs
+//
+// Declarative geo / sanctions risk policy.
+//
+// Replaces the hard-coded "CN → ×1.30" uplift in FusionEngine::fuse with a
+// policy document owned by legal / trust & safety.  A policy contains:
+//
+//   regions    — named groups of ISO-3166 country codes ("EMBARGOED", "EU", ...)
+//   rules      — per-country or per-region multipliers, optionally effective-dated
+//   sanctions  — effective-dated sanctions lists (OFAC, EU, UK HMT, ...)
+//   allowlist  — enterprise accounts / orgs exempt from any geo uplift
+//
+// Resolution:
+//   1. Collect every rule and sanctions list in effect at `now` that matches the
+//      event's country_code (directly or through a region).
+//   2. The highest multiplier wins; on a tie, sanctions lists win over rules.
+//   3. Allowlisted accounts/orgs are never adjusted, but the rule that *would*
+//      have applied is still recorded so the exemption itself is auditable.
+//
+// Exactly one rule is ever applied, so every adjusted RiskDecision names the
+// single policy entry responsible in `geo_adjustment.rule_id`.
+//
+// Policy file format (JSON):
+//   {
+//     "version": "2025-03-01",
+//     "regions":   { "EMBARGOED": ["CU", "IR", "KP", "SY"] },
+//     "rules":     [ { "id": "geo.cn", "countries": ["CN"], "multiplier": 1.30 } ],
+//     "sanctions": [ { "id": "ofac.sdn.2025q1", "source": "OFAC",
+//                      "regions": ["EMBARGOED"], "multiplier": 1.50,
+//                      "effective_from": "2025-01-01T00:00:00Z" } ],
+//     "allowlist": { "account_ids": ["acct_enterprise_1"], "org_ids": ["org_acme"] }
+//   }
+//
+// Without a policy file the default policy reproduces the legacy behaviour
+// (CN ×1.30) under the rule id "geo.cn.default".
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::events::{ApiEvent, GeoAdjustment};
+
+// Upper bound on any single multiplier — guards against a typo in the policy
+// file ("13.0" instead of "1.30") saturating every decision at 1.0.
+const MAX_MULTIPLIER: f32 = 3.0;
+
+// ── Policy document ───────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeoRule {
+    pub id: String,
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub regions: Vec<String>,
+    pub multiplier: f32,
+    #[serde(default)]
+    pub effective_from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub effective_until: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SanctionsList {
+    pub id: String,
+    pub source: String, // "OFAC", "EU", "UK-HMT", ...
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub regions: Vec<String>,
+    pub multiplier: f32,
+    #[serde(default)]
+    pub effective_from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub effective_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GeoAllowlist {
+    #[serde(default)]
+    pub account_ids: HashSet<String>,
+    #[serde(default)]
+    pub org_ids: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeoPolicy {
+    pub version: String,
+    #[serde(default)]
+    pub regions: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub rules: Vec<GeoRule>,
+    #[serde(default)]
+    pub sanctions: Vec<SanctionsList>,
+    #[serde(default)]
+    pub allowlist: GeoAllowlist,
+}
+
+impl Default for GeoPolicy {
+    fn default() -> Self {
+        Self {
+            version: "default".into(),
+            regions: HashMap::new(),
+            rules: vec![GeoRule {
+                id: "geo.cn.default".into(),
+                countries: vec!["CN".into()],
+                regions: vec![],
+                multiplier: 1.30,
+                effective_from: None,
+                effective_until: None,
+                note: "legacy CN uplift".into(),
+            }],
+            sanctions: vec![],
+            allowlist: GeoAllowlist::default(),
+        }
+    }
+}
+
+// A rule or sanctions list, normalised for matching.
+struct Candidate<'a> {
+    id: &'a str,
+    multiplier: f32,
+    sanctions: bool,
+}
+
+impl GeoPolicy {
+    /// Load a policy from a JSON file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading geo policy {}", path.display()))?;
+        let policy: Self = serde_json::from_str(&content)
+            .with_context(|| format!("parsing geo policy {}", path.display()))?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Reject policies that reference undefined regions or carry absurd multipliers.
+    pub fn validate(&self) -> Result<()> {
+        let check = |id: &str, regions: &[String], multiplier: f32| -> Result<()> {
+            if !(multiplier > 0.0 && multiplier <= MAX_MULTIPLIER) {
+                anyhow::bail!(
+                    "geo policy entry {id}: multiplier {multiplier} outside (0, {MAX_MULTIPLIER}]"
+                );
+            }
+            if let Some(r) = regions.iter().find(|r| !self.regions.contains_key(*r)) {
+                anyhow::bail!("geo policy entry {id}: unknown region {r}");
+            }
+            Ok(())
+        };
+        for r in &self.rules {
+            check(&r.id, &r.regions, r.multiplier)?;
+        }
+        for s in &self.sanctions {
+            check(&s.id, &s.regions, s.multiplier)?;
+        }
+        Ok(())
+    }
+
+    fn covers(&self, countries: &[String], regions: &[String], cc: &str) -> bool {
+        countries.iter().any(|c| c.eq_ignore_ascii_case(cc))
+            || regions.iter().any(|r| {
+                self.regions
+                    .get(r)
+                    .map(|members| members.iter().any(|c| c.eq_ignore_ascii_case(cc)))
+                    .unwrap_or(false)
+            })
+    }
+
+    /// Apply the policy to a composite score.
+    ///
+    /// Returns the adjusted score and, if any rule matched, the audit record
+    /// describing which rule was (or, for allowlisted accounts, would have been)
+    /// applied.
+    pub fn adjust(
+        &self,
+        event: &ApiEvent,
+        composite: f32,
+        now: DateTime<Utc>,
+    ) -> (f32, Option<GeoAdjustment>) {
+        let cc = event.country_code.as_str();
+        if cc.is_empty() {
+            return (composite, None);
+        }
+
+        let in_effect = |from: &Option<DateTime<Utc>>, until: &Option<DateTime<Utc>>| {
+            from.map(|f| now >= f).unwrap_or(true) && until.map(|u| now < u).unwrap_or(true)
+        };
+
+        let rules = self
+            .rules
+            .iter()
+            .filter(|r| in_effect(&r.effective_from, &r.effective_until))
+            .filter(|r| self.covers(&r.countries, &r.regions, cc))
+            .map(|r| Candidate {
+                id: &r.id,
+                multiplier: r.multiplier,
+                sanctions: false,
+            });
+        let sanctions = self
+            .sanctions
+            .iter()
+            .filter(|s| in_effect(&s.effective_from, &s.effective_until))
+            .filter(|s| self.covers(&s.countries, &s.regions, cc))
+            .map(|s| Candidate {
+                id: &s.id,
+                multiplier: s.multiplier,
+                sanctions: true,
+            });
+
+        let winner = rules.chain(sanctions).max_by(|a, b| {
+            a.multiplier
+                .partial_cmp(&b.multiplier)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.sanctions.cmp(&b.sanctions))
+        });
+        let winner = match winner {
+            Some(w) => w,
+            None => return (composite, None),
+        };
+
+        let exempted_by = if self.allowlist.account_ids.contains(&event.account_id) {
+            Some(format!("account:{}", event.account_id))
+        } else {
+            event
+                .org_id
+                .as_ref()
+                .filter(|org| self.allowlist.org_ids.contains(*org))
+                .map(|org| format!("org:{}", org))
+        };
+
+        let multiplier = winner.multiplier.min(MAX_MULTIPLIER);
+        let adjusted = if exempted_by.is_some() {
+            composite
+        } else {
+            (composite * multiplier).min(1.0)
+        };
+
+        (
+            adjusted,
+            Some(GeoAdjustment {
+                rule_id: winner.id.to_string(),
+                policy_version: self.version.clone(),
+                sanctions: winner.sanctions,
+                country_code: cc.to_string(),
+                multiplier,
+                score_before: composite,
+                score_after: adjusted,
+                exempted_by,
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(cc: &str, account: &str) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r1", "account_id": account, "timestamp": Utc::now(),
+            "ip_address": "203.0.113.7", "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": 10, "payment_method_hash": null,
+            "org_id": "org_acme", "country_code": cc, "header_order": [],
+            "ja3_hash": null, "ja3s_hash": null, "h2_settings": null,
+            "tls_library": null, "asn_number": null, "asn_org": null,
+            "max_tokens": null, "system_prompt_hash": null, "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn sanctions_effective_dating_and_allowlist() {
+        let policy: GeoPolicy = serde_json::from_value(serde_json::json!({
+            "version": "t1",
+            "regions": { "EMBARGOED": ["KP"] },
+            "rules": [ { "id": "geo.kp", "countries": ["KP"], "multiplier": 1.2 } ],
+            "sanctions": [ { "id": "ofac.2030", "source": "OFAC", "regions": ["EMBARGOED"],
+                             "multiplier": 1.5, "effective_from": "2030-01-01T00:00:00Z" } ],
+            "allowlist": { "org_ids": ["org_acme"] }
+        }))
+        .unwrap();
+        policy.validate().unwrap();
+
+        let ev = event("KP", "a1");
+        let before: DateTime<Utc> = "2029-06-01T00:00:00Z".parse().unwrap();
+        let after: DateTime<Utc> = "2030-06-01T00:00:00Z".parse().unwrap();
+
+        // org_acme is allowlisted — score untouched, rule still recorded.
+        let (score, adj) = policy.adjust(&ev, 0.5, after);
+        let adj = adj.unwrap();
+        assert_eq!(score, 0.5);
+        assert_eq!(adj.rule_id, "ofac.2030");
+        assert_eq!(adj.exempted_by.as_deref(), Some("org:org_acme"));
+
+        let mut ev = ev;
+        ev.org_id = None;
+        let (score, adj) = policy.adjust(&ev, 0.5, before);
+        assert_eq!(adj.unwrap().rule_id, "geo.kp");
+        assert!((score - 0.6).abs() < 1e-6);
+        let (score, adj) = policy.adjust(&ev, 0.5, after);
+        assert!(adj.unwrap().sanctions);
+        assert!((score - 0.75).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,572 @@
+// glasswally/src/engine/lifecycle.rs
+//
+// Per-account enforcement lifecycle.
+//
+// Replaces the one-way `suspended` map in FusionEngine with an explicit state
+// machine that records who (or what) moved an account between states:
+//
+//   Monitor → RateLimited → Canary → Review → Suspended → Reinstated
+//
+// Allowed transitions:
+//
+//   Monitor / Reinstated → RateLimited | Canary | Review | Suspended   (escalation)
+//   RateLimited          → Canary | Review | Suspended | Monitor
+//   Canary               → Review | Suspended | RateLimited | Monitor
+//   Review               → Suspended | Canary | Monitor
+//   Suspended            → Review (appeal opened) | Reinstated (unsuspend)
+//   Reinstated           → Monitor (probation served)
+//
+// Actors:
+//   Engine            — may only escalate, and never out of Suspended.
+//   AutoDeescalation  — steps Canary → RateLimited → Monitor and
+//                       Reinstated → Monitor after a clean period.  Review and
+//                       Suspended always need an analyst.
+//   Analyst(id)       — any allowed transition (appeals, unsuspend, clearing).
+//
+// Analyst false-positive overrides suppress future alerts for the account for
+// `fp_suppression_secs` unless the account score rises materially above the
+// score the analyst cleared (`fp_breakthrough_margin`).
+//
+// Every transition is appended to an in-memory journal drained by the
+// pipeline into `lifecycle_transitions.jsonl`.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::events::ActionKind;
+
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Default, PartialOrd, Ord,
+)]
+pub enum EnforcementState {
+    #[default]
+    Monitor,
+    RateLimited,
+    Canary,
+    Review,
+    Suspended,
+    Reinstated,
+}
+
+impl std::fmt::Display for EnforcementState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Monitor => write!(f, "MONITOR"),
+            Self::RateLimited => write!(f, "RATE_LIMITED"),
+            Self::Canary => write!(f, "CANARY"),
+            Self::Review => write!(f, "REVIEW"),
+            Self::Suspended => write!(f, "SUSPENDED"),
+            Self::Reinstated => write!(f, "REINSTATED"),
+        }
+    }
+}
+
+impl EnforcementState {
+    /// State an enforcement action moves an account into (None = no change).
+    pub fn for_action(action: ActionKind) -> Option<Self> {
+        match action {
+            ActionKind::RateLimit => Some(Self::RateLimited),
+            ActionKind::InjectCanary => Some(Self::Canary),
+            ActionKind::FlagForReview => Some(Self::Review),
+            ActionKind::SuspendAccount | ActionKind::ClusterTakedown => Some(Self::Suspended),
+            ActionKind::Monitor | ActionKind::IntelShare => None,
+        }
+    }
+
+    // Reinstated ranks with Monitor: re-offending escalates normally.
+    fn severity(self) -> u8 {
+        match self {
+            Self::Monitor | Self::Reinstated => 0,
+            Self::RateLimited => 1,
+            Self::Canary => 2,
+            Self::Review => 3,
+            Self::Suspended => 4,
+        }
+    }
+
+    pub fn can_transition(self, to: Self) -> bool {
+        use EnforcementState::*;
+        matches!(
+            (self, to),
+            (
+                Monitor | Reinstated,
+                RateLimited | Canary | Review | Suspended
+            ) | (RateLimited, Canary | Review | Suspended | Monitor)
+                | (Canary, Review | Suspended | RateLimited | Monitor)
+                | (Review, Suspended | Canary | Monitor)
+                | (Suspended, Review | Reinstated)
+                | (Reinstated, Monitor)
+        )
+    }
+
+    // Next state down on a clean period; Review / Suspended need an analyst.
+    fn deescalated(self) -> Option<Self> {
+        match self {
+            Self::Canary => Some(Self::RateLimited),
+            Self::RateLimited | Self::Reinstated => Some(Self::Monitor),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum Actor {
+    Engine,
+    AutoDeescalation,
+    Analyst(String),
+}
+
+impl std::fmt::Display for Actor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Engine => write!(f, "engine"),
+            Self::AutoDeescalation => write!(f, "auto_deescalation"),
+            Self::Analyst(id) => write!(f, "analyst:{}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transition {
+    pub account_id: String,
+    pub from: EnforcementState,
+    pub to: EnforcementState,
+    pub actor: Actor,
+    pub reason: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Analyst-confirmed false positive — suppresses alerts until `until`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FpOverride {
+    pub analyst: String,
+    pub reason: String,
+    pub at: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub score_at_override: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountLifecycle {
+    pub state: EnforcementState,
+    pub entered_at: Option<DateTime<Utc>>,
+    pub last_violation_at: Option<DateTime<Utc>>,
+    pub fp_override: Option<FpOverride>,
+    pub history: Vec<Transition>, // most recent `history_cap` transitions
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LifecycleConfig {
+    pub clean_period_secs: i64,      // no violations for this long → step down
+    pub probation_secs: i64,         // Reinstated → Monitor after this long
+    pub fp_suppression_secs: i64,    // how long an FP override suppresses alerts
+    pub fp_breakthrough_margin: f32, // score rise that breaks through an FP override
+    pub history_cap: usize,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        Self {
+            clean_period_secs: 72 * 3600,
+            probation_secs: 7 * 24 * 3600,
+            fp_suppression_secs: 30 * 24 * 3600,
+            fp_breakthrough_margin: 0.15,
+            history_cap: 50,
+        }
+    }
+}
+
+pub struct LifecycleRegistry {
+    cfg: LifecycleConfig,
+    accounts: DashMap<String, AccountLifecycle>,
+    journal: Mutex<Vec<Transition>>,
+}
+
+impl LifecycleRegistry {
+    pub fn new(cfg: LifecycleConfig) -> Self {
+        Self {
+            cfg,
+            accounts: DashMap::new(),
+            journal: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn config(&self) -> &LifecycleConfig {
+        &self.cfg
+    }
+
+    pub fn state(&self, account_id: &str) -> EnforcementState {
+        self.accounts
+            .get(account_id)
+            .map(|l| l.state)
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, account_id: &str) -> Option<AccountLifecycle> {
+        self.accounts.get(account_id).map(|l| l.clone())
+    }
+
+    pub fn is_suspended(&self, account_id: &str) -> bool {
+        self.state(account_id) == EnforcementState::Suspended
+    }
+
+    fn apply(
+        &self,
+        account_id: &str,
+        entry: &mut AccountLifecycle,
+        to: EnforcementState,
+        actor: Actor,
+        reason: String,
+        now: DateTime<Utc>,
+    ) -> Transition {
+        let t = Transition {
+            account_id: account_id.to_string(),
+            from: entry.state,
+            to,
+            actor,
+            reason,
+            at: now,
+        };
+        entry.state = to;
+        entry.entered_at = Some(now);
+        entry.history.push(t.clone());
+        if entry.history.len() > self.cfg.history_cap {
+            let excess = entry.history.len() - self.cfg.history_cap;
+            entry.history.drain(..excess);
+        }
+        self.journal.lock().push(t.clone());
+        t
+    }
+
+    /// Explicit transition (analyst action or API call); validated against
+    /// the transition table and actor permissions.
+    pub fn transition(
+        &self,
+        account_id: &str,
+        to: EnforcementState,
+        actor: Actor,
+        reason: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Transition> {
+        let mut entry = self.accounts.entry(account_id.to_string()).or_default();
+        let from = entry.state;
+        if !from.can_transition(to) {
+            anyhow::bail!("{account_id}: transition {from} → {to} is not allowed");
+        }
+        let automatic = !matches!(actor, Actor::Analyst(_));
+        if automatic && (from == EnforcementState::Suspended || to == EnforcementState::Reinstated)
+        {
+            anyhow::bail!("{account_id}: transition {from} → {to} requires an analyst ({actor})");
+        }
+        if actor == Actor::Engine && to.severity() <= from.severity() {
+            anyhow::bail!("{account_id}: engine may only escalate ({from} → {to})");
+        }
+        Ok(self.apply(account_id, &mut entry, to, actor, reason.to_string(), now))
+    }
+
+    /// Engine-driven escalation for an enforcement action.  Always records the
+    /// violation; transitions only if the action is more severe than the
+    /// current state.
+    pub fn escalate(
+        &self,
+        account_id: &str,
+        action: ActionKind,
+        reason: &str,
+        now: DateTime<Utc>,
+    ) -> Option<Transition> {
+        let mut entry = self.accounts.entry(account_id.to_string()).or_default();
+        entry.last_violation_at = Some(now);
+        let to = EnforcementState::for_action(action)?;
+        let from = entry.state;
+        if to.severity() <= from.severity() || !from.can_transition(to) {
+            return None;
+        }
+        Some(self.apply(
+            account_id,
+            &mut entry,
+            to,
+            Actor::Engine,
+            reason.to_string(),
+            now,
+        ))
+    }
+
+    /// Analyst confirms a false positive: clears enforcement (Suspended →
+    /// Reinstated, otherwise → Monitor) and suppresses future alerts.
+    pub fn mark_false_positive(
+        &self,
+        account_id: &str,
+        analyst: &str,
+        reason: &str,
+        score: f32,
+        now: DateTime<Utc>,
+    ) -> Option<Transition> {
+        let mut entry = self.accounts.entry(account_id.to_string()).or_default();
+        entry.fp_override = Some(FpOverride {
+            analyst: analyst.to_string(),
+            reason: reason.to_string(),
+            at: now,
+            until: now + Duration::seconds(self.cfg.fp_suppression_secs),
+            score_at_override: score,
+        });
+        let to = match entry.state {
+            EnforcementState::Suspended => EnforcementState::Reinstated,
+            EnforcementState::Monitor | EnforcementState::Reinstated => return None,
+            _ => EnforcementState::Monitor,
+        };
+        Some(self.apply(
+            account_id,
+            &mut entry,
+            to,
+            Actor::Analyst(analyst.to_string()),
+            format!("false_positive: {}", reason),
+            now,
+        ))
+    }
+
+    /// Accounts with an FP override still in force (persisted by engine/feedback.rs).
+    pub fn fp_overrides(&self, now: DateTime<Utc>) -> Vec<(String, FpOverride)> {
+        self.accounts
+            .iter()
+            .filter_map(|l| {
+                let o = l.fp_override.as_ref().filter(|o| now < o.until)?;
+                Some((l.key().clone(), o.clone()))
+            })
+            .collect()
+    }
+
+    /// Reinstate a persisted FP override without a transition or journal entry.
+    pub fn restore_fp_override(&self, account_id: &str, fp_override: FpOverride) {
+        self.accounts
+            .entry(account_id.to_string())
+            .or_default()
+            .fp_override = Some(fp_override);
+    }
+
+    /// True if an active FP override suppresses an alert at `score`.
+    pub fn suppresses(&self, account_id: &str, score: f32, now: DateTime<Utc>) -> bool {
+        self.accounts
+            .get(account_id)
+            .and_then(|l| l.fp_override.clone())
+            .map(|o| now < o.until && score < o.score_at_override + self.cfg.fp_breakthrough_margin)
+            .unwrap_or(false)
+    }
+
+    /// Step down every account that has been clean for the configured period.
+    pub fn deescalate_idle(&self, now: DateTime<Utc>) -> Vec<Transition> {
+        let mut out = Vec::new();
+        for mut entry in self.accounts.iter_mut() {
+            let Some(to) = entry.state.deescalated() else {
+                continue;
+            };
+            let required = if entry.state == EnforcementState::Reinstated {
+                self.cfg.probation_secs
+            } else {
+                self.cfg.clean_period_secs
+            };
+            let since = [entry.last_violation_at, entry.entered_at]
+                .into_iter()
+                .flatten()
+                .max();
+            if since.is_some_and(|t| (now - t).num_seconds() < required) {
+                continue;
+            }
+            let account_id = entry.key().clone();
+            out.push(self.apply(
+                &account_id,
+                entry.value_mut(),
+                to,
+                Actor::AutoDeescalation,
+                format!("clean for {}h", required / 3600),
+                now,
+            ));
+        }
+        out
+    }
+
+    /// Transitions recorded since the last drain.
+    pub fn drain_journal(&self) -> Vec<Transition> {
+        std::mem::take(&mut *self.journal.lock())
+    }
+}
+
+impl Default for LifecycleRegistry {
+    fn default() -> Self {
+        Self::new(LifecycleConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use EnforcementState::*;
+
+    const ALL: [EnforcementState; 6] =
+        [Monitor, RateLimited, Canary, Review, Suspended, Reinstated];
+
+    fn t0() -> DateTime<Utc> {
+        "2026-03-01T00:00:00Z".parse().unwrap()
+    }
+
+    fn registry() -> LifecycleRegistry {
+        LifecycleRegistry::new(LifecycleConfig {
+            clean_period_secs: 3600,
+            probation_secs: 7200,
+            fp_suppression_secs: 86400,
+            ..LifecycleConfig::default()
+        })
+    }
+
+    #[test]
+    fn transition_table() {
+        let allowed: Vec<(EnforcementState, EnforcementState)> = ALL
+            .iter()
+            .flat_map(|&a| ALL.iter().map(move |&b| (a, b)))
+            .filter(|(a, b)| a.can_transition(*b))
+            .collect();
+        assert_eq!(allowed.len(), 22);
+        for s in ALL {
+            assert!(!s.can_transition(s), "{s} → {s}");
+        }
+        // Leaving Suspended always goes through an appeal or an unsuspend
+        for to in ALL {
+            assert_eq!(
+                Suspended.can_transition(to),
+                matches!(to, Review | Reinstated)
+            );
+        }
+        assert!(!Monitor.can_transition(Reinstated));
+        assert!(!Review.can_transition(RateLimited));
+        assert!(Reinstated.can_transition(Suspended));
+    }
+
+    #[test]
+    fn actor_permissions() {
+        let reg = registry();
+        let analyst = || Actor::Analyst("jdoe".into());
+
+        // Engine only escalates
+        reg.transition("a", Canary, Actor::Engine, "", t0())
+            .unwrap();
+        assert!(reg
+            .transition("a", RateLimited, Actor::Engine, "", t0())
+            .is_err());
+        assert!(reg
+            .transition("a", Monitor, Actor::AutoDeescalation, "", t0())
+            .is_ok());
+
+        // Nobody but an analyst leaves Suspended or reinstates
+        reg.transition("a", Suspended, Actor::Engine, "", t0())
+            .unwrap();
+        assert!(reg
+            .transition("a", Review, Actor::Engine, "", t0())
+            .is_err());
+        assert!(reg
+            .transition("a", Review, Actor::AutoDeescalation, "", t0())
+            .is_err());
+        assert!(reg
+            .transition("a", Reinstated, Actor::AutoDeescalation, "", t0())
+            .is_err());
+        assert!(reg.is_suspended("a"));
+        let t = reg
+            .transition("a", Review, analyst(), "appeal", t0())
+            .unwrap();
+        assert_eq!((t.from, t.to, t.actor), (Suspended, Review, analyst()));
+
+        // Disallowed edges fail for analysts too, without changing state
+        assert!(reg
+            .transition("a", RateLimited, analyst(), "", t0())
+            .is_err());
+        assert_eq!(reg.state("a"), Review);
+
+        // escalate() never de-escalates and never records a no-op
+        assert!(reg.escalate("a", ActionKind::RateLimit, "", t0()).is_none());
+        let t = reg
+            .escalate("a", ActionKind::ClusterTakedown, "", t0())
+            .unwrap();
+        assert_eq!(t.to, Suspended);
+        assert_eq!(reg.drain_journal().len(), 5);
+        assert!(reg.drain_journal().is_empty());
+    }
+
+    #[test]
+    fn deescalates_after_clean_period() {
+        let reg = registry();
+        reg.escalate("canary", ActionKind::InjectCanary, "", t0());
+        reg.escalate("review", ActionKind::FlagForReview, "", t0());
+        reg.escalate("suspended", ActionKind::SuspendAccount, "", t0());
+        reg.escalate("reinstated", ActionKind::SuspendAccount, "", t0());
+        reg.transition(
+            "reinstated",
+            Reinstated,
+            Actor::Analyst("jdoe".into()),
+            "",
+            t0(),
+        )
+        .unwrap();
+
+        // Too early
+        assert!(reg.deescalate_idle(t0() + Duration::minutes(30)).is_empty());
+
+        // A fresh violation restarts the clean period
+        reg.escalate(
+            "canary",
+            ActionKind::RateLimit,
+            "",
+            t0() + Duration::minutes(50),
+        );
+        let hour = t0() + Duration::minutes(61);
+        assert!(reg.deescalate_idle(hour).is_empty());
+
+        let steps = reg.deescalate_idle(t0() + Duration::minutes(111));
+        assert_eq!(steps.len(), 1);
+        assert_eq!((steps[0].from, steps[0].to), (Canary, RateLimited));
+        assert_eq!(steps[0].actor, Actor::AutoDeescalation);
+        assert_eq!(reg.state("review"), Review);
+        assert_eq!(reg.state("suspended"), Suspended);
+
+        // Reinstated waits out the probation period, then everything settles
+        let later = t0() + Duration::minutes(150);
+        let steps = reg.deescalate_idle(later);
+        assert_eq!(steps.len(), 1);
+        assert_eq!(reg.state("reinstated"), Monitor);
+        let steps = reg.deescalate_idle(later + Duration::hours(2));
+        assert_eq!((steps[0].from, steps[0].to), (RateLimited, Monitor));
+        assert!(reg.deescalate_idle(later + Duration::hours(10)).is_empty());
+    }
+
+    #[test]
+    fn false_positive_clears_and_suppresses() {
+        let reg = registry();
+        reg.escalate("s", ActionKind::SuspendAccount, "", t0());
+        reg.escalate("c", ActionKind::InjectCanary, "", t0());
+
+        let t = reg
+            .mark_false_positive("s", "jdoe", "lab", 0.8, t0())
+            .unwrap();
+        assert_eq!((t.from, t.to), (Suspended, Reinstated));
+        assert_eq!(t.actor, Actor::Analyst("jdoe".into()));
+        let t = reg
+            .mark_false_positive("c", "jdoe", "lab", 0.5, t0())
+            .unwrap();
+        assert_eq!(t.to, Monitor);
+        // No enforcement to clear: override only
+        assert!(reg
+            .mark_false_positive("m", "jdoe", "", 0.4, t0())
+            .is_none());
+        assert!(reg.get("m").unwrap().fp_override.is_some());
+
+        let soon = t0() + Duration::hours(1);
+        assert!(reg.suppresses("s", 0.9, soon));
+        assert!(!reg.suppresses("s", 0.96, soon), "breakthrough margin");
+        assert!(
+            !reg.suppresses("s", 0.8, t0() + Duration::days(2)),
+            "expired"
+        );
+        assert!(!reg.suppresses("other", 0.1, soon));
+    }
+}
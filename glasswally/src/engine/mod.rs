@@ -1,2 +1,11 @@
+pub mod allowlist;
+pub mod canary_scan;
+pub mod cluster_fusion;
 pub mod dispatcher;
+pub mod feedback;
 pub mod fusion;
+pub mod geo_policy;
+pub mod lifecycle;
+pub mod notifier;
+pub mod rate_limit;
+pub mod sinks;
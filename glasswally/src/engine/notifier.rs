@@ -0,0 +1,582 @@
+// glasswally/src/engine/notifier.rs
+//
+// On-call alert notifier.
+//
+// The Dispatcher hands every enforcement decision to the notifier, which pushes
+// a templated message to each configured channel:
+//
+//   slack      — Slack-compatible incoming-webhook JSON (text + blocks)
+//   webhook    — generic JSON: the Alert record plus the rendered title
+//   pagerduty  — PagerDuty Events API v2 "trigger" event
+//
+// Every alert carries the decision, tier, score, cluster size, top evidence and
+// a deep link into the analyst console (rendered from `account_link` /
+// `cluster_link` templates).
+//
+// Noise controls, applied in order:
+//   dedup        — one alert per dedup key (cluster:<id>, else account:<id>)
+//                  per `dedup_window_secs`, unless the tier escalates.  The
+//                  same key is sent as the PagerDuty dedup_key.
+//   min_tier     — per channel; lower tiers never reach it.
+//   quiet hours  — inside the configured window a channel with
+//                  `quiet_min_tier` only receives that tier or above (e.g.
+//                  page for CRITICAL only overnight, keep Slack for the rest).
+//   rate limit   — per channel, at most `rate_limit_per_min` messages per
+//                  rolling minute.
+//
+// Delivery shares the sink model (engine/sinks.rs): a bounded queue per
+// channel drained by its own task with exponential-backoff retries, so a slow
+// chat-ops endpoint never blocks dispatch.
+//
+// Config file (`--notify`, JSON):
+//   {
+//     "channels": [
+//       { "type": "slack", "name": "sec-alerts",
+//         "url": "https://hooks.slack.com/services/T000/B000/XXXX" },
+//       { "type": "pagerduty", "name": "oncall",
+//         "url": "https://events.pagerduty.com/v2/enqueue",
+//         "routing_key": "R0UT1NGKEY", "min_tier": "High", "quiet_min_tier": "Critical" }
+//     ],
+//     "quiet_hours": { "start_hour": 22, "end_hour": 7, "utc_offset_minutes": -480 },
+//     "cluster_link": "https://console.internal/clusters/{cluster_id}"
+//   }
+//
+// Template placeholders: {tier} {action} {subject} {account_id} {cluster_id}
+// {cluster_size} {score}.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Timelike, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use super::sinks::retry_delay;
+use crate::events::{ActionKind, DecisionScope, EnforcementAction, RiskDecision, RiskTier};
+use crate::webhook::{post_json, HttpUrl};
+
+// ── Alert ─────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub dedup_key: String,
+    pub title: String,
+    pub tier: RiskTier,
+    pub action: ActionKind,
+    pub scope: DecisionScope,
+    pub account_id: String,
+    pub cluster_id: Option<u32>,
+    pub cluster_size: usize,
+    pub affected_accounts: usize,
+    pub score: f32,
+    pub top_evidence: Vec<String>,
+    pub link: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Alert {
+    pub fn new(decision: &RiskDecision, action: &EnforcementAction, cluster_size: usize) -> Self {
+        let dedup_key = match decision.cluster_id {
+            Some(cid) => format!("cluster:{}", cid),
+            None => format!("account:{}", decision.account_id),
+        };
+        Self {
+            dedup_key,
+            title: String::new(),
+            tier: decision.tier,
+            action: action.action_type,
+            scope: decision.scope,
+            account_id: decision.account_id.clone(),
+            cluster_id: decision.cluster_id,
+            cluster_size,
+            affected_accounts: action.affected_accounts.len(),
+            score: decision.composite_score,
+            top_evidence: decision.top_evidence.iter().take(5).cloned().collect(),
+            link: None,
+            timestamp: action.timestamp,
+        }
+    }
+
+    fn subject(&self) -> String {
+        match (self.scope, self.cluster_id) {
+            (DecisionScope::Cluster, Some(cid)) => format!("cluster {}", cid),
+            _ => format!("account {}", self.account_id),
+        }
+    }
+
+    /// Fill `{placeholder}`s from this alert.
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{tier}", &self.tier.to_string())
+            .replace("{action}", &self.action.to_string())
+            .replace("{subject}", &self.subject())
+            .replace("{account_id}", &self.account_id)
+            .replace(
+                "{cluster_id}",
+                &self
+                    .cluster_id
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| "-".into()),
+            )
+            .replace("{cluster_size}", &self.cluster_size.to_string())
+            .replace("{score}", &format!("{:.4}", self.score))
+    }
+}
+
+// ── Message formats ───────────────────────────────────────────────────────────
+
+/// Slack-compatible incoming-webhook payload.
+pub fn format_slack(alert: &Alert) -> Value {
+    let mut body = format!(
+        "*{}*\nAction: `{}`  Score: {:.4}  Cluster size: {}  Affected: {}",
+        alert.title, alert.action, alert.score, alert.cluster_size, alert.affected_accounts
+    );
+    if !alert.top_evidence.is_empty() {
+        body.push_str("\nEvidence:");
+        for e in &alert.top_evidence {
+            body.push_str(&format!("\n• {}", e));
+        }
+    }
+    if let Some(link) = &alert.link {
+        body.push_str(&format!("\n<{}|Open in console>", link));
+    }
+    json!({
+        "text": alert.title,
+        "blocks": [
+            { "type": "section", "text": { "type": "mrkdwn", "text": body } },
+            { "type": "context", "elements": [
+                { "type": "mrkdwn", "text": format!("dedup `{}` · {}", alert.dedup_key, alert.timestamp.to_rfc3339()) }
+            ] }
+        ]
+    })
+}
+
+/// Generic webhook payload: the alert record as-is.
+pub fn format_webhook(alert: &Alert) -> Value {
+    json!({ "type": "glasswally.alert", "alert": alert })
+}
+
+/// PagerDuty Events API v2 trigger event.
+pub fn format_pagerduty(alert: &Alert, routing_key: &str) -> Value {
+    let severity = match alert.tier {
+        RiskTier::Critical => "critical",
+        RiskTier::High => "error",
+        RiskTier::Medium => "warning",
+        RiskTier::Low => "info",
+    };
+    let mut event = json!({
+        "routing_key": routing_key,
+        "event_action": "trigger",
+        "dedup_key": alert.dedup_key,
+        "payload": {
+            "summary": alert.title,
+            "source": "glasswally",
+            "severity": severity,
+            "timestamp": alert.timestamp.to_rfc3339(),
+            "component": alert.subject(),
+            "class": alert.action.to_string(),
+            "custom_details": {
+                "score": alert.score,
+                "cluster_id": alert.cluster_id,
+                "cluster_size": alert.cluster_size,
+                "affected_accounts": alert.affected_accounts,
+                "top_evidence": alert.top_evidence,
+            }
+        }
+    });
+    if let Some(link) = &alert.link {
+        event["links"] = json!([{ "href": link, "text": "Glasswally console" }]);
+    }
+    event
+}
+
+// ── Configuration ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelKind {
+    Slack,
+    Webhook,
+    Pagerduty { routing_key: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelSpec {
+    pub name: String,
+    pub url: String,
+    #[serde(flatten)]
+    pub kind: ChannelKind,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default = "default_min_tier")]
+    pub min_tier: RiskTier,
+    /// Inside quiet hours, only this tier and above reach the channel.
+    #[serde(default)]
+    pub quiet_min_tier: Option<RiskTier>,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_min_tier() -> RiskTier {
+    RiskTier::High
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start_hour: u32, // local, inclusive
+    pub end_hour: u32,   // local, exclusive; may wrap past midnight
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+}
+
+impl QuietHours {
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let local = now + chrono::Duration::minutes(self.utc_offset_minutes as i64);
+        let h = local.hour();
+        if self.start_hour <= self.end_hour {
+            h >= self.start_hour && h < self.end_hour
+        } else {
+            h >= self.start_hour || h < self.end_hour
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifierConfig {
+    pub channels: Vec<ChannelSpec>,
+    pub title_template: String,
+    pub account_link: Option<String>,
+    pub cluster_link: Option<String>,
+    pub dedup_window_secs: i64,
+    pub rate_limit_per_min: usize,
+    pub quiet_hours: Option<QuietHours>,
+    pub max_retries: u32,
+    pub retry_backoff_ms: u64,
+    pub queue_depth: usize,
+}
+
+impl Default for NotifierConfig {
+    fn default() -> Self {
+        Self {
+            channels: vec![],
+            title_template: "[{tier}] {action} — {subject} (score {score})".into(),
+            account_link: None,
+            cluster_link: None,
+            dedup_window_secs: 900,
+            rate_limit_per_min: 20,
+            quiet_hours: None,
+            max_retries: 2,
+            retry_backoff_ms: 500,
+            queue_depth: 1024,
+        }
+    }
+}
+
+impl NotifierConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading notifier config {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("parsing notifier config {}", path.display()))
+    }
+}
+
+// ── Notifier ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default)]
+pub struct NotifierStats {
+    pub sent: AtomicU64,
+    pub deduped: AtomicU64,
+    pub quiet_suppressed: AtomicU64,
+    pub rate_limited: AtomicU64,
+    pub failed: AtomicU64,
+    pub dropped: AtomicU64, // queue full
+}
+
+struct Channel {
+    spec: ChannelSpec,
+    tx: mpsc::Sender<Arc<Alert>>,
+    recent: Mutex<VecDeque<DateTime<Utc>>>, // send times in the last minute
+}
+
+pub struct AlertNotifier {
+    cfg: NotifierConfig,
+    channels: Vec<Channel>,
+    last_sent: DashMap<String, (DateTime<Utc>, RiskTier)>,
+    stats: Arc<NotifierStats>,
+}
+
+impl AlertNotifier {
+    /// Validate channel URLs and spawn one delivery task per channel
+    /// (requires a Tokio runtime).
+    pub fn start(cfg: NotifierConfig) -> Result<Self> {
+        let stats = Arc::new(NotifierStats::default());
+        let mut channels = Vec::new();
+        for spec in &cfg.channels {
+            let url: HttpUrl = spec.url.parse()?;
+            let (tx, rx) = mpsc::channel(cfg.queue_depth.max(1));
+            tokio::spawn(delivery_loop(
+                spec.clone(),
+                url,
+                rx,
+                Arc::clone(&stats),
+                cfg.max_retries,
+                Duration::from_millis(cfg.retry_backoff_ms),
+            ));
+            info!("Alert channel {} started", spec.name);
+            channels.push(Channel {
+                spec: spec.clone(),
+                tx,
+                recent: Mutex::new(VecDeque::new()),
+            });
+        }
+        Ok(Self {
+            cfg,
+            channels,
+            last_sent: DashMap::new(),
+            stats,
+        })
+    }
+
+    pub fn stats(&self) -> &Arc<NotifierStats> {
+        &self.stats
+    }
+
+    /// Render the title and deep link.
+    pub fn prepare(&self, mut alert: Alert) -> Alert {
+        alert.title = alert.render(&self.cfg.title_template);
+        let link = match alert.cluster_id {
+            Some(_) => self
+                .cfg
+                .cluster_link
+                .as_ref()
+                .or(self.cfg.account_link.as_ref()),
+            None => self.cfg.account_link.as_ref(),
+        };
+        alert.link = link.map(|t| alert.render(t));
+        alert
+    }
+
+    /// Queue an alert on every eligible channel.  Never blocks; returns the
+    /// number of channels it was queued on.
+    pub fn notify(&self, alert: Alert, now: DateTime<Utc>) -> usize {
+        // Dedup per cluster / account unless the tier escalated.
+        if let Some(prev) = self.last_sent.get(&alert.dedup_key) {
+            let (at, tier) = *prev;
+            if (now - at).num_seconds() < self.cfg.dedup_window_secs && alert.tier <= tier {
+                self.stats.deduped.fetch_add(1, Ordering::Relaxed);
+                return 0;
+            }
+        }
+
+        let alert = Arc::new(self.prepare(alert));
+        let quiet = self.cfg.quiet_hours.is_some_and(|q| q.contains(now));
+        let mut queued = 0;
+        for ch in &self.channels {
+            if alert.tier < ch.spec.min_tier {
+                continue;
+            }
+            if quiet && ch.spec.quiet_min_tier.is_some_and(|min| alert.tier < min) {
+                self.stats.quiet_suppressed.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            {
+                let mut recent = ch.recent.lock();
+                while recent
+                    .front()
+                    .is_some_and(|t| (now - *t).num_seconds() >= 60)
+                {
+                    recent.pop_front();
+                }
+                if recent.len() >= self.cfg.rate_limit_per_min {
+                    self.stats.rate_limited.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                recent.push_back(now);
+            }
+            if ch.tx.try_send(Arc::clone(&alert)).is_err() {
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!("Alert channel {} queue full — dropped alert", ch.spec.name);
+                continue;
+            }
+            queued += 1;
+        }
+        if queued > 0 {
+            self.last_sent
+                .insert(alert.dedup_key.clone(), (now, alert.tier));
+        }
+        queued
+    }
+}
+
+async fn delivery_loop(
+    spec: ChannelSpec,
+    url: HttpUrl,
+    mut rx: mpsc::Receiver<Arc<Alert>>,
+    stats: Arc<NotifierStats>,
+    max_retries: u32,
+    backoff: Duration,
+) {
+    let headers: Vec<(String, String)> = spec
+        .headers
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    let timeout = Duration::from_millis(spec.timeout_ms);
+    while let Some(alert) = rx.recv().await {
+        let body = match &spec.kind {
+            ChannelKind::Slack => format_slack(&alert),
+            ChannelKind::Webhook => format_webhook(&alert),
+            ChannelKind::Pagerduty { routing_key } => format_pagerduty(&alert, routing_key),
+        }
+        .to_string();
+        let mut attempt = 0;
+        loop {
+            match post_json(&url, &headers, body.as_bytes(), timeout).await {
+                Ok(_) => {
+                    stats.sent.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+                Err(e) if attempt < max_retries => {
+                    tokio::time::sleep(retry_delay(backoff, attempt)).await;
+                    attempt += 1;
+                    warn!(
+                        "Alert channel {} delivery failed (attempt {}): {}",
+                        spec.name, attempt, e
+                    );
+                }
+                Err(e) => {
+                    stats.failed.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        "Alert channel {} gave up on {} after {} retries: {}",
+                        spec.name, alert.dedup_key, max_retries, e
+                    );
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Accept connections, answer 200, forward (path, JSON body) per request.
+    async fn mock_server() -> (String, mpsc::UnboundedReceiver<(String, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (mut sock, _) = listener.accept().await.unwrap();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut buf = Vec::new();
+                    let mut chunk = [0u8; 1024];
+                    loop {
+                        let n = sock.read(&mut chunk).await.unwrap();
+                        buf.extend_from_slice(&chunk[..n]);
+                        let text = String::from_utf8_lossy(&buf).to_string();
+                        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                            let len: usize = head
+                                .lines()
+                                .find_map(|l| l.strip_prefix("Content-Length: "))
+                                .unwrap()
+                                .parse()
+                                .unwrap();
+                            if body.len() >= len {
+                                let path = head.split_whitespace().nth(1).unwrap().to_string();
+                                tx.send((path, serde_json::from_str(body).unwrap()))
+                                    .unwrap();
+                                break;
+                            }
+                        }
+                        if n == 0 {
+                            return;
+                        }
+                    }
+                    sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+        (base, rx)
+    }
+
+    fn alert(cid: u32, tier: RiskTier) -> Alert {
+        Alert {
+            dedup_key: format!("cluster:{}", cid),
+            title: String::new(),
+            tier,
+            action: ActionKind::ClusterTakedown,
+            scope: DecisionScope::Cluster,
+            account_id: "sk-a".into(),
+            cluster_id: Some(cid),
+            cluster_size: 12,
+            affected_accounts: 11,
+            score: 0.71,
+            top_evidence: vec!["shared_payment".into()],
+            link: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_dedups_and_respects_quiet_hours() {
+        let (base, mut rx) = mock_server().await;
+        let cfg: NotifierConfig = serde_json::from_value(json!({
+            "channels": [
+                { "type": "slack", "name": "chat", "url": format!("{base}/slack") },
+                { "type": "pagerduty", "name": "pd", "url": format!("{base}/pd"),
+                  "routing_key": "rk", "quiet_min_tier": "Critical" }
+            ],
+            "quiet_hours": { "start_hour": 0, "end_hour": 24 },
+            "cluster_link": "https://console.test/clusters/{cluster_id}"
+        }))
+        .unwrap();
+        let notifier = AlertNotifier::start(cfg).unwrap();
+        let now = Utc::now();
+
+        assert_eq!(notifier.notify(alert(7, RiskTier::Critical), now), 2);
+        assert_eq!(notifier.notify(alert(7, RiskTier::Critical), now), 0); // deduped
+        assert_eq!(notifier.notify(alert(8, RiskTier::High), now), 1); // pager quiet
+
+        let mut got = HashMap::<String, Vec<Value>>::new();
+        for _ in 0..3 {
+            let (path, body) = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            got.entry(path).or_default().push(body);
+        }
+        let pd = &got["/pd"];
+        assert_eq!(pd.len(), 1);
+        assert_eq!(pd[0]["dedup_key"], "cluster:7");
+        assert_eq!(pd[0]["payload"]["severity"], "critical");
+        assert_eq!(pd[0]["links"][0]["href"], "https://console.test/clusters/7");
+        assert_eq!(got["/slack"].len(), 2);
+        assert!(got["/slack"][0]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("[CRITICAL] CLUSTER_TAKEDOWN — cluster 7"));
+        assert_eq!(notifier.stats().deduped.load(Ordering::Relaxed), 1);
+        assert_eq!(notifier.stats().quiet_suppressed.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,225 @@
+// glasswally/src/engine/rate_limit.rs
+//
+// Concrete rate-limit parameters for every enforced tier.
+//
+// A bare "rate limit this account" leaves every gateway to pick its own
+// numbers.  Commands are issued for Medium, High and Critical decisions alike,
+// whatever the primary action (RATE_LIMIT, INJECT_CANARY, SUSPEND_ACCOUNT …),
+// so each tier's limits hold while review or suspension is pending.
+//
+// The policy turns a decision into token-bucket commands the gateway applies
+// as-is (events.rs RateLimitCommand, one line per command on
+// rate_limit_commands.jsonl):
+//
+//   rpm              observed requests/min × tier fraction, clamped to [min_rpm, max_rpm]
+//   tpm              observed tokens/min × tier fraction, at least
+//                    rpm × tokens_per_request (so a permitted request is never
+//                    starved of tokens), at most max_tpm
+//   burst            10 seconds' worth of rpm (≥ 1)
+//   max_concurrency  per tier
+//   expires_at       issued_at + duration_secs — gateways drop the bucket
+//                    afterwards; a repeat decision issues a fresh command
+//
+// Observed velocity comes from the account's 1-hour window
+// (AccountWindow::rate_per_hour and the events' token counts).  The
+// account-scope command is also kept on the window (AccountWindow::rate_limit)
+// and CheckAccount reports its rpm until it expires.
+//
+// Scopes are chosen per tier.  Account is always included; org, cluster and
+// subnet commands share one bucket across everything behind that key, which is
+// the point for a campaign spread over many accounts.  Subnets are the
+// account's addresses masked to the graph's subnet prefixes
+// (StateBackend::subnet_prefixes, /24 and /64 by default), so a rate-limited
+// subnet is the same subnet the graph links accounts on.
+//
+// Policy file (`--rate-limit-policy`, JSON) replaces the defaults below; any
+// tier left out keeps its default.
+
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::events::{LimitScope, RateLimitCommand, RiskDecision, RiskTier};
+use crate::net::{IpCidr, SubnetPrefixes};
+use crate::state::window::{AccountWindow, EventWindow, W_1HR};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TierLimits {
+    pub rate_fraction: f64, // share of the observed rate still allowed
+    pub min_rpm: u32,
+    pub max_rpm: u32,
+    pub tokens_per_request: u32,
+    pub max_tpm: u32,
+    pub max_concurrency: u32,
+    pub duration_secs: i64,
+    pub scopes: Vec<LimitScope>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitPolicy {
+    pub medium: TierLimits,
+    pub high: TierLimits,
+    pub critical: TierLimits,
+    pub max_subnets: usize,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        Self {
+            medium: TierLimits {
+                rate_fraction: 0.50,
+                min_rpm: 5,
+                max_rpm: 60,
+                tokens_per_request: 2000,
+                max_tpm: 200_000,
+                max_concurrency: 4,
+                duration_secs: 3600,
+                scopes: vec![LimitScope::Account],
+            },
+            high: TierLimits {
+                rate_fraction: 0.25,
+                min_rpm: 2,
+                max_rpm: 20,
+                tokens_per_request: 2000,
+                max_tpm: 60_000,
+                max_concurrency: 2,
+                duration_secs: 6 * 3600,
+                scopes: vec![LimitScope::Account, LimitScope::Cluster],
+            },
+            critical: TierLimits {
+                rate_fraction: 0.10,
+                min_rpm: 1,
+                max_rpm: 5,
+                tokens_per_request: 2000,
+                max_tpm: 10_000,
+                max_concurrency: 1,
+                duration_secs: 24 * 3600,
+                scopes: vec![
+                    LimitScope::Account,
+                    LimitScope::Org,
+                    LimitScope::Cluster,
+                    LimitScope::Subnet,
+                ],
+            },
+            max_subnets: 8,
+        }
+    }
+}
+
+/// Requests and tokens per minute over the window, (0, 0) below two events.
+pub fn observed_velocity(window: &impl EventWindow, seconds: i64) -> (f64, f64) {
+    (
+        window.rate_per_hour(seconds) / 60.0,
+        window.tokens_per_minute(seconds),
+    )
+}
+
+impl RateLimitPolicy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading rate-limit policy {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("parsing rate-limit policy {}", path.display()))
+    }
+
+    pub fn for_tier(&self, tier: RiskTier) -> &TierLimits {
+        match tier {
+            RiskTier::Critical => &self.critical,
+            RiskTier::High => &self.high,
+            RiskTier::Medium | RiskTier::Low => &self.medium,
+        }
+    }
+
+    fn subnets(&self, window: &AccountWindow, prefixes: SubnetPrefixes) -> Vec<IpCidr> {
+        let set: BTreeSet<IpCidr> = window
+            .ip_addresses
+            .iter()
+            .filter_map(|ip| ip.parse::<IpAddr>().ok())
+            .filter_map(|ip| prefixes.of(ip))
+            .collect();
+        set.into_iter().take(self.max_subnets).collect()
+    }
+
+    /// Commands for one decision, sized by its tier.
+    pub fn commands(
+        &self,
+        decision: &RiskDecision,
+        window: Option<&AccountWindow>,
+        prefixes: SubnetPrefixes,
+        now: DateTime<Utc>,
+    ) -> Vec<RateLimitCommand> {
+        let limits = self.for_tier(decision.tier);
+        let (observed_rpm, observed_tpm) = window
+            .map(|w| observed_velocity(w, W_1HR))
+            .unwrap_or((0.0, 0.0));
+
+        let rpm = ((observed_rpm * limits.rate_fraction).floor() as u32)
+            .clamp(limits.min_rpm, limits.max_rpm.max(limits.min_rpm));
+        let tpm = ((observed_tpm * limits.rate_fraction) as u32)
+            .max(rpm.saturating_mul(limits.tokens_per_request))
+            .min(limits.max_tpm);
+        let burst = rpm.div_ceil(6).max(1);
+
+        let mut keys: Vec<(LimitScope, String)> = Vec::new();
+        for scope in &limits.scopes {
+            match scope {
+                LimitScope::Account => keys.push((*scope, decision.account_id.clone())),
+                LimitScope::Cluster => {
+                    keys.extend(decision.cluster_id.map(|c| (*scope, c.to_string())))
+                }
+                LimitScope::Org => {
+                    if let Some(w) = window {
+                        let orgs: BTreeSet<&String> = w.org_ids.iter().collect();
+                        keys.extend(orgs.into_iter().map(|o| (*scope, o.clone())));
+                    }
+                }
+                LimitScope::Subnet => {
+                    if let Some(w) = window {
+                        keys.extend(
+                            self.subnets(w, prefixes)
+                                .into_iter()
+                                .map(|c| (*scope, c.to_string())),
+                        );
+                    }
+                }
+            }
+        }
+
+        keys.into_iter()
+            .map(|(scope, key)| RateLimitCommand {
+                command_id: command_id(scope, &key, now),
+                scope,
+                key,
+                rpm,
+                tpm,
+                burst,
+                max_concurrency: limits.max_concurrency,
+                duration_secs: limits.duration_secs,
+                issued_at: now,
+                expires_at: now + Duration::seconds(limits.duration_secs),
+                tier: decision.tier,
+                account_id: decision.account_id.clone(),
+                cluster_id: decision.cluster_id,
+                observed_rpm: (observed_rpm * 100.0).round() / 100.0,
+                observed_tpm: observed_tpm.round(),
+                reason: format!(
+                    "score={:.4} tier={} observed_rpm={:.1}",
+                    decision.composite_score, decision.tier, observed_rpm
+                ),
+            })
+            .collect()
+    }
+}
+
+// Stable per (scope, key, issue time) so gateways can apply idempotently.
+fn command_id(scope: LimitScope, key: &str, now: DateTime<Utc>) -> String {
+    let mut h = Sha256::new();
+    h.update(format!("{:?}:{}:{}", scope, key, now.timestamp_millis()).as_bytes());
+    format!("rl-{}", &hex::encode(h.finalize())[..16])
+}
@@ -0,0 +1,806 @@
+// glasswally/src/engine/sinks.rs
+//
+// Pluggable enforcement sinks for the Dispatcher.
+//
+// Every record the Dispatcher produces (enforcement actions, rate-limit
+// commands, analyst queue entries, audit lines, IOC bundles, lifecycle
+// transitions) is tagged with a Stream and, where applicable, the ActionKind
+// and RiskTier that produced it.  Routes map those tags onto named sinks:
+//
+//   file     — JSONL files in a directory (one file per stream, handles kept open)
+//   kafka    — KafkaAdapter (kafka_output.rs); IOC bundles → ioc topic,
+//              everything else → enforcement topic
+//   webhook  — HTTP POST of the JSON record (webhook.rs)
+//   syslog   — RFC 5424 over UDP, message body in ArcSight CEF
+//
+// Each sink has a bounded queue drained by its own task.  Enforcement, rate
+// limit, analyst queue, IOC and lifecycle records never block the detection
+// hot path: a full queue drops the record (counted).  Audit records are never
+// dropped — `route_reliable` waits for queue space, so a stalled sink applies
+// backpressure to the Dispatcher instead of leaving a gap in the hash chain.
+// Failed deliveries are retried with exponential backoff (capped at
+// MAX_RETRY_DELAY) up to `max_retries`.
+//
+// Config file (`--sinks`, JSON):
+//   {
+//     "sinks": [
+//       { "type": "file",    "name": "local", "dir": "/var/lib/glasswally" },
+//       { "type": "webhook", "name": "soar",  "url": "http://127.0.0.1:8080/gw" },
+//       { "type": "syslog",  "name": "siem",  "addr": "10.0.0.5:514" }
+//     ],
+//     "routes": [
+//       { "sink": "local" },
+//       { "sink": "soar", "actions": ["SuspendAccount", "ClusterTakedown"] },
+//       { "sink": "siem", "streams": ["enforcement", "ioc"], "min_tier": "High" }
+//     ]
+//   }
+//
+// A route with no filters matches every record.  Without a config file the
+// Dispatcher uses a single file sink on the output directory, which produces
+// the same files as before sinks existed.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{info, warn};
+
+use crate::events::{ActionKind, RiskTier};
+use crate::kafka_output::{KafkaAdapter, KafkaConfig};
+use crate::webhook::{post_json, HttpUrl};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Exponential backoff before retry `attempt` (0-based), capped at MAX_RETRY_DELAY.
+pub fn retry_delay(backoff: Duration, attempt: u32) -> Duration {
+    backoff
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY_DELAY)
+}
+
+// ── Records ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Stream {
+    Enforcement,  // SUSPEND_ACCOUNT / CLUSTER_TAKEDOWN
+    RateLimit,    // RATE_LIMIT commands
+    AnalystQueue, // FLAG_FOR_REVIEW / INJECT_CANARY
+    Audit,        // every action + exemption records
+    Ioc,          // IOC bundles
+    Lifecycle,    // enforcement lifecycle transitions
+}
+
+impl Stream {
+    /// JSONL file name used by the file sink (the Dispatcher's historical names).
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::Enforcement => "enforcement_actions.jsonl",
+            Self::RateLimit => "rate_limit_commands.jsonl",
+            Self::AnalystQueue => "analyst_queue.jsonl",
+            Self::Audit => "audit_log.jsonl",
+            Self::Ioc => "ioc_bundles.jsonl",
+            Self::Lifecycle => "lifecycle_transitions.jsonl",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SinkRecord {
+    pub stream: Stream,
+    pub key: String, // account_id or cluster_<id>, for partitioning
+    pub action: Option<ActionKind>,
+    pub tier: Option<RiskTier>,
+    pub payload: serde_json::Value,
+}
+
+// ── Sink trait ────────────────────────────────────────────────────────────────
+
+pub trait EnforcementSink: Send + Sync {
+    fn name(&self) -> &str;
+    fn deliver<'a>(&'a self, record: &'a SinkRecord) -> BoxFuture<'a, Result<()>>;
+}
+
+// ── File ──────────────────────────────────────────────────────────────────────
+
+pub struct FileSink {
+    name: String,
+    dir: PathBuf,
+    files: Mutex<HashMap<Stream, File>>,
+}
+
+impl FileSink {
+    pub fn new(name: &str, dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("creating sink directory {}", dir.display()))?;
+        Ok(Self {
+            name: name.to_string(),
+            dir: dir.to_path_buf(),
+            files: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl EnforcementSink for FileSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn deliver<'a>(&'a self, record: &'a SinkRecord) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut files = self.files.lock().await;
+            let f = match files.entry(record.stream) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => e.insert(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(self.dir.join(record.stream.file_name()))
+                        .await?,
+                ),
+            };
+            let line = record.payload.to_string() + "\n";
+            let written = match f.write_all(line.as_bytes()).await {
+                Ok(()) => f.flush().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                // Reopen on the next attempt (rotated / deleted file).
+                files.remove(&record.stream);
+                return Err(e.into());
+            }
+            Ok(())
+        })
+    }
+}
+
+// ── Kafka ─────────────────────────────────────────────────────────────────────
+
+pub struct KafkaSink {
+    name: String,
+    adapter: Arc<KafkaAdapter>,
+}
+
+impl EnforcementSink for KafkaSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn deliver<'a>(&'a self, record: &'a SinkRecord) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let topic = match record.stream {
+                Stream::Ioc => self.adapter.config().ioc_topic.clone(),
+                _ => self.adapter.config().enforcement_topic.clone(),
+            };
+            // A full producer queue fails the delivery so the router retries
+            // it, rather than the adapter silently dropping its oldest message.
+            self.adapter
+                .try_publish(topic, record.key.clone(), record.payload.to_string())
+                .await
+        })
+    }
+}
+
+// ── HTTP webhook ──────────────────────────────────────────────────────────────
+
+pub struct WebhookSink {
+    name: String,
+    url: HttpUrl,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl EnforcementSink for WebhookSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn deliver<'a>(&'a self, record: &'a SinkRecord) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let body = serde_json::json!({
+                "stream": record.stream,
+                "key": record.key,
+                "record": record.payload,
+            });
+            post_json(
+                &self.url,
+                &self.headers,
+                body.to_string().as_bytes(),
+                self.timeout,
+            )
+            .await?;
+            Ok(())
+        })
+    }
+}
+
+// ── Syslog / CEF ──────────────────────────────────────────────────────────────
+
+pub struct SyslogSink {
+    name: String,
+    addr: String,
+    socket: Mutex<Option<tokio::net::UdpSocket>>,
+}
+
+// CEF extension values escape '\' and '=' (and newlines).
+fn cef_escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', " ")
+}
+
+/// Format a record as an RFC 5424 syslog line carrying a CEF message.
+pub fn format_cef(record: &SinkRecord) -> String {
+    let (syslog_sev, cef_sev) = match record.tier {
+        Some(RiskTier::Critical) => (2, 10),
+        Some(RiskTier::High) => (4, 8),
+        Some(RiskTier::Medium) => (5, 5),
+        _ => (6, 3),
+    };
+    let pri = 16 * 8 + syslog_sev; // facility local0
+    let p = &record.payload;
+    let field = |k: &str| {
+        p.get(k).map(|v| match v {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+    };
+    let signature = record
+        .action
+        .map(|a| a.to_string())
+        .unwrap_or_else(|| format!("{:?}", record.stream).to_uppercase());
+
+    let mut ext = vec![format!("cs1Label=stream cs1={:?}", record.stream)];
+    if let Some(a) = field("account_id") {
+        ext.push(format!("suser={}", cef_escape(&a)));
+    }
+    if let Some(c) = field("cluster_id") {
+        ext.push(format!("cs2Label=cluster cs2={}", cef_escape(&c)));
+    }
+    if let Some(s) = field("composite_score") {
+        ext.push(format!("cfp1Label=score cfp1={}", cef_escape(&s)));
+    }
+    if let Some(r) = field("reason") {
+        ext.push(format!("msg={}", cef_escape(&r)));
+    }
+    format!(
+        "<{}>1 {} - glasswally - {} - CEF:0|Glasswally|Glasswally|{}|{}|Glasswally {}|{}|{}",
+        pri,
+        Utc::now().to_rfc3339(),
+        signature,
+        env!("CARGO_PKG_VERSION"),
+        signature,
+        signature,
+        cef_sev,
+        ext.join(" ")
+    )
+}
+
+impl EnforcementSink for SyslogSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn deliver<'a>(&'a self, record: &'a SinkRecord) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut sock = self.socket.lock().await;
+            if sock.is_none() {
+                let s = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+                s.connect(&self.addr).await?;
+                *sock = Some(s);
+            }
+            let line = format_cef(record);
+            sock.as_ref()
+                .expect("bound above")
+                .send(line.as_bytes())
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+// ── Configuration ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkSpec {
+    File {
+        name: String,
+        dir: PathBuf,
+    },
+    Kafka {
+        name: String,
+        #[serde(default)]
+        config: Option<KafkaConfig>,
+    },
+    Webhook {
+        name: String,
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    Syslog {
+        name: String,
+        addr: String,
+    },
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+impl SinkSpec {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::File { name, .. }
+            | Self::Kafka { name, .. }
+            | Self::Webhook { name, .. }
+            | Self::Syslog { name, .. } => name,
+        }
+    }
+
+    fn build(&self) -> Result<Arc<dyn EnforcementSink>> {
+        Ok(match self {
+            Self::File { name, dir } => Arc::new(FileSink::new(name, dir)?),
+            Self::Kafka { name, config } => {
+                let adapter = KafkaAdapter::new(config.clone().unwrap_or_default());
+                tokio::spawn(Arc::clone(&adapter).flush_loop());
+                Arc::new(KafkaSink {
+                    name: name.clone(),
+                    adapter,
+                })
+            }
+            Self::Webhook {
+                name,
+                url,
+                headers,
+                timeout_ms,
+            } => Arc::new(WebhookSink {
+                name: name.clone(),
+                url: url.parse()?,
+                headers: headers
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+                timeout: Duration::from_millis(*timeout_ms),
+            }),
+            Self::Syslog { name, addr } => Arc::new(SyslogSink {
+                name: name.clone(),
+                addr: addr.clone(),
+                socket: Mutex::new(None),
+            }),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SinkRoute {
+    pub sink: String,
+    #[serde(default)]
+    pub streams: Vec<Stream>,
+    #[serde(default)]
+    pub actions: Vec<ActionKind>,
+    #[serde(default)]
+    pub min_tier: Option<RiskTier>,
+}
+
+impl SinkRoute {
+    fn matches(&self, r: &SinkRecord) -> bool {
+        (self.streams.is_empty() || self.streams.contains(&r.stream))
+            && (self.actions.is_empty() || r.action.is_some_and(|a| self.actions.contains(&a)))
+            && self
+                .min_tier
+                .is_none_or(|min| r.tier.is_some_and(|t| t >= min))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkConfig {
+    pub sinks: Vec<SinkSpec>,
+    pub routes: Vec<SinkRoute>,
+    #[serde(default = "default_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    #[serde(default = "default_queue_depth")]
+    pub queue_depth: usize,
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_backoff_ms() -> u64 {
+    200
+}
+
+fn default_queue_depth() -> usize {
+    4096
+}
+
+impl SinkConfig {
+    /// Single file sink on `dir` receiving every stream.
+    pub fn file_only(dir: &Path) -> Self {
+        Self {
+            sinks: vec![SinkSpec::File {
+                name: "file".into(),
+                dir: dir.to_path_buf(),
+            }],
+            routes: vec![SinkRoute {
+                sink: "file".into(),
+                ..SinkRoute::default()
+            }],
+            max_retries: default_retries(),
+            retry_backoff_ms: default_backoff_ms(),
+            queue_depth: default_queue_depth(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading sink config {}", path.display()))?;
+        let cfg: Self = serde_json::from_str(&content)
+            .with_context(|| format!("parsing sink config {}", path.display()))?;
+        for r in &cfg.routes {
+            if !cfg.sinks.iter().any(|s| s.name() == r.sink) {
+                anyhow::bail!("sink route references unknown sink {}", r.sink);
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+// ── Router ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default)]
+pub struct SinkStats {
+    pub delivered: AtomicU64,
+    pub retried: AtomicU64,
+    pub failed: AtomicU64,
+    pub dropped: AtomicU64, // queue full
+}
+
+struct SinkHandle {
+    name: String,
+    tx: mpsc::Sender<SinkRecord>,
+    stats: Arc<SinkStats>,
+}
+
+pub struct SinkRouter {
+    handles: Vec<SinkHandle>,
+    routes: Vec<SinkRoute>,
+}
+
+impl SinkRouter {
+    /// Build every sink and spawn its delivery task (requires a Tokio runtime).
+    pub fn start(cfg: &SinkConfig) -> Result<Self> {
+        let sinks = cfg
+            .sinks
+            .iter()
+            .map(|spec| spec.build())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::with_sinks(sinks, cfg))
+    }
+
+    /// Spawn delivery tasks for already-built sinks, routed and retried per `cfg`.
+    pub fn with_sinks(sinks: Vec<Arc<dyn EnforcementSink>>, cfg: &SinkConfig) -> Self {
+        let mut handles = Vec::new();
+        for sink in sinks {
+            let name = sink.name().to_string();
+            let (tx, rx) = mpsc::channel(cfg.queue_depth.max(1));
+            let stats = Arc::new(SinkStats::default());
+            tokio::spawn(delivery_loop(
+                sink,
+                rx,
+                Arc::clone(&stats),
+                cfg.max_retries,
+                Duration::from_millis(cfg.retry_backoff_ms),
+            ));
+            info!("Enforcement sink {} started", name);
+            handles.push(SinkHandle { name, tx, stats });
+        }
+        Self {
+            handles,
+            routes: cfg.routes.clone(),
+        }
+    }
+
+    fn targets<'a>(&'a self, record: &SinkRecord) -> impl Iterator<Item = &'a SinkHandle> {
+        let mut names: Vec<&str> = self
+            .routes
+            .iter()
+            .filter(|r| r.matches(record))
+            .map(|r| r.sink.as_str())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        self.handles
+            .iter()
+            .filter(move |h| names.contains(&h.name.as_str()))
+    }
+
+    /// Queue a record on every sink whose route matches.  Never blocks; a
+    /// full queue drops the record.
+    pub fn route(&self, record: SinkRecord) {
+        for h in self.targets(&record) {
+            if h.tx.try_send(record.clone()).is_err() {
+                h.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Sink {} queue full — dropped {:?} record",
+                    h.name, record.stream
+                );
+            }
+        }
+    }
+
+    /// Queue a record on every sink whose route matches, waiting for queue
+    /// space rather than dropping it (audit records).
+    pub async fn route_reliable(&self, record: SinkRecord) {
+        for h in self.targets(&record) {
+            if h.tx.send(record.clone()).await.is_err() {
+                h.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Sink {} stopped — dropped {:?} record",
+                    h.name, record.stream
+                );
+            }
+        }
+    }
+
+    pub fn stats(&self) -> Vec<(String, Arc<SinkStats>)> {
+        self.handles
+            .iter()
+            .map(|h| (h.name.clone(), Arc::clone(&h.stats)))
+            .collect()
+    }
+}
+
+async fn delivery_loop(
+    sink: Arc<dyn EnforcementSink>,
+    mut rx: mpsc::Receiver<SinkRecord>,
+    stats: Arc<SinkStats>,
+    max_retries: u32,
+    backoff: Duration,
+) {
+    while let Some(record) = rx.recv().await {
+        let mut attempt = 0;
+        loop {
+            match sink.deliver(&record).await {
+                Ok(()) => {
+                    stats.delivered.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+                Err(e) if attempt < max_retries => {
+                    stats.retried.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep(retry_delay(backoff, attempt)).await;
+                    attempt += 1;
+                    warn!(
+                        "Sink {} delivery failed (attempt {}): {}",
+                        sink.name(),
+                        attempt,
+                        e
+                    );
+                }
+                Err(e) => {
+                    stats.failed.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        "Sink {} gave up on {:?} record after {} retries: {}",
+                        sink.name(),
+                        record.stream,
+                        max_retries,
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(stream: Stream, action: Option<ActionKind>, tier: Option<RiskTier>) -> SinkRecord {
+        SinkRecord {
+            stream,
+            key: "sk-a".into(),
+            action,
+            tier,
+            payload: serde_json::json!({ "account_id": "sk-a", "reason": "score=0.9 a=b" }),
+        }
+    }
+
+    fn config(queue_depth: usize) -> SinkConfig {
+        SinkConfig {
+            sinks: vec![],
+            routes: vec![SinkRoute {
+                sink: "test".into(),
+                ..SinkRoute::default()
+            }],
+            max_retries: 3,
+            retry_backoff_ms: 1,
+            queue_depth,
+        }
+    }
+
+    /// Fails the first `failures` deliveries, then takes `delay` per record.
+    struct TestSink {
+        failures: AtomicU64,
+        delay: Duration,
+        seen: AtomicU64,
+    }
+
+    impl TestSink {
+        fn new(failures: u64, delay: Duration) -> Arc<Self> {
+            Arc::new(Self {
+                failures: AtomicU64::new(failures),
+                delay,
+                seen: AtomicU64::new(0),
+            })
+        }
+    }
+
+    impl EnforcementSink for TestSink {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn deliver<'a>(&'a self, _record: &'a SinkRecord) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                tokio::time::sleep(self.delay).await;
+                let left = self.failures.load(Ordering::Relaxed);
+                if left > 0 {
+                    self.failures.store(left - 1, Ordering::Relaxed);
+                    anyhow::bail!("unavailable");
+                }
+                self.seen.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            })
+        }
+    }
+
+    async fn settle(stats: &SinkStats, n: u64) {
+        for _ in 0..500 {
+            let done =
+                stats.delivered.load(Ordering::Relaxed) + stats.failed.load(Ordering::Relaxed);
+            if done >= n {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("sink did not settle");
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        let base = Duration::from_millis(200);
+        assert_eq!(retry_delay(base, 0), base);
+        assert_eq!(retry_delay(base, 3), Duration::from_millis(1600));
+        assert_eq!(retry_delay(base, 40), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(base, u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn route_filters() {
+        let route = SinkRoute {
+            sink: "siem".into(),
+            streams: vec![Stream::Enforcement, Stream::Ioc],
+            actions: vec![],
+            min_tier: Some(RiskTier::High),
+        };
+        let critical = Some(RiskTier::Critical);
+        assert!(route.matches(&record(Stream::Enforcement, None, critical)));
+        assert!(!route.matches(&record(Stream::Audit, None, critical)));
+        assert!(!route.matches(&record(Stream::Ioc, None, Some(RiskTier::Medium))));
+        assert!(!route.matches(&record(Stream::Ioc, None, None)));
+
+        let route = SinkRoute {
+            sink: "soar".into(),
+            actions: vec![ActionKind::SuspendAccount],
+            ..SinkRoute::default()
+        };
+        assert!(route.matches(&record(
+            Stream::Audit,
+            Some(ActionKind::SuspendAccount),
+            None
+        )));
+        assert!(!route.matches(&record(Stream::Audit, Some(ActionKind::RateLimit), None)));
+        assert!(!route.matches(&record(Stream::Audit, None, None)));
+    }
+
+    #[test]
+    fn cef_format() {
+        let line = format_cef(&record(
+            Stream::Enforcement,
+            Some(ActionKind::SuspendAccount),
+            Some(RiskTier::Critical),
+        ));
+        assert!(line.starts_with("<130>1 "));
+        assert!(line.contains("|10|"));
+        assert!(line.contains("suser=sk-a"));
+        assert!(line.ends_with("msg=score\\=0.9 a\\=b"));
+    }
+
+    #[tokio::test]
+    async fn file_sink_writes_one_file_per_stream() {
+        let dir = std::env::temp_dir().join(format!("glasswally_sinks_{}", std::process::id()));
+        let sink = FileSink::new("file", &dir).unwrap();
+        for stream in [Stream::Audit, Stream::Audit, Stream::Ioc] {
+            sink.deliver(&record(stream, None, None)).await.unwrap();
+        }
+        let audit = std::fs::read_to_string(dir.join("audit_log.jsonl")).unwrap();
+        let ioc = std::fs::read_to_string(dir.join("ioc_bundles.jsonl")).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(audit.lines().count(), 2);
+        assert_eq!(ioc.lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_retried() {
+        let sink = TestSink::new(2, Duration::ZERO);
+        let router = SinkRouter::with_sinks(vec![sink.clone()], &config(16));
+        router.route(record(Stream::Enforcement, None, None));
+        let (_, stats) = &router.stats()[0];
+        settle(stats, 1).await;
+        assert_eq!(stats.retried.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.delivered.load(Ordering::Relaxed), 1);
+
+        // More failures than retries: given up and counted
+        sink.failures.store(10, Ordering::Relaxed);
+        router.route(record(Stream::Enforcement, None, None));
+        settle(stats, 2).await;
+        assert_eq!(stats.failed.load(Ordering::Relaxed), 1);
+        assert_eq!(sink.seen.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn audit_records_wait_for_queue_space() {
+        let sink = TestSink::new(0, Duration::from_millis(5));
+        let router = SinkRouter::with_sinks(vec![sink.clone()], &config(1));
+        for _ in 0..10 {
+            router
+                .route_reliable(record(Stream::Audit, None, None))
+                .await;
+        }
+        let (_, stats) = &router.stats()[0];
+        settle(stats, 10).await;
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 0);
+        assert_eq!(sink.seen.load(Ordering::Relaxed), 10);
+
+        // The non-blocking route drops once the queue is full
+        for _ in 0..10 {
+            router.route(record(Stream::Enforcement, None, None));
+        }
+        assert!(stats.dropped.load(Ordering::Relaxed) > 0);
+    }
+
+    #[tokio::test]
+    async fn kafka_sink_reports_a_full_queue() {
+        let sink = KafkaSink {
+            name: "kafka".into(),
+            adapter: KafkaAdapter::new(KafkaConfig {
+                max_queue: 1,
+                ..KafkaConfig::default()
+            }),
+        };
+        let r = record(Stream::Enforcement, None, None);
+        assert!(sink.deliver(&r).await.is_ok());
+        assert!(sink.deliver(&r).await.is_err());
+    }
+}
@@ -178,8 +178,8 @@ impl Evaluator {
 
         for event in &events {
             store.ingest(event);
-            let signals = crate::workers::run_all(event, &store).await;
-            let decision = engine.fuse(event, &store, &signals);
+            let signals = crate::workers::run_all(event, store.as_ref()).await;
+            let decision = engine.fuse(event, store.as_ref(), &signals);
 
             let is_positive = event.campaign_label.is_some();
             let alerted = decision
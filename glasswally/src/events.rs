@@ -9,6 +9,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::IpAddr;
 
+use crate::net::IpCidr;
+
 pub const MAX_BUF: usize = 4096;
 
 // ── Raw events from BPF ───────────────────────────────────────────────────────
@@ -214,6 +216,68 @@ impl CanaryToken {
     }
 }
 
+/// How an inbound prompt matched earlier output (engine/canary_scan.rs).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CanaryMatch {
+    Token,        // registered canary token, plain hex
+    EncodedToken, // registered canary token, invisible encoding
+    Watermark,    // account ZWJ/ZWNJ watermark (watermark::detect)
+}
+
+/// An inbound prompt carrying another account's canary or watermark.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanaryHit {
+    pub kind: CanaryMatch,
+    pub token: Option<String>,  // None for watermark matches
+    pub origin_account: String, // account the output was served to
+    pub origin_request_id: Option<String>,
+    pub account_id: String, // account replaying it
+    pub request_id: String,
+    pub field: String, // "prompt" / "system_prompt"
+    pub confidence: f32,
+    pub cluster_id: Option<u32>, // cluster both accounts were linked into
+    pub timestamp: DateTime<Utc>,
+}
+
+// ── Account relationship graph ────────────────────────────────────────────────
+
+/// Kind of shared infrastructure behind a graph edge (state/graph.rs).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    Payment,
+    Org,
+    Subnet,
+    Ja3,
+    Canary, // one account replayed the other's canary / watermark
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClusterEventKind {
+    Formed,    // two unclustered accounts
+    Joined,    // an unclustered account joined a cluster
+    Merged,    // an edge bridged two clusters; `parents` were absorbed
+    Split,     // recomputation broke `parents` apart; this is a new piece
+    Shrunk,    // recomputation dropped `accounts` from the cluster
+    Dissolved, // no edge above threshold is left
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterEvent {
+    pub kind: ClusterEventKind,
+    pub cluster_id: u32,
+    pub parents: Vec<u32>, // absorbed (Merged) or split-from (Split) cluster ids
+    pub accounts: Vec<String>, // accounts new to `cluster_id` (removed, for Shrunk / Dissolved)
+    pub size: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge: Option<(String, String)>, // the edge that crossed the threshold
+    pub links: Vec<LinkKind>,
+    pub weight: f32,
+    pub timestamp: DateTime<Utc>,
+}
+
 // ── Parsed API event ──────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -238,6 +302,8 @@ pub struct ApiEvent {
     pub asn_org: Option<String>,   // ASN org name (e.g. "AMAZON-AES", "AS-CHOOPA")
     pub max_tokens: Option<u32>,   // requested max_tokens from API body
     pub system_prompt_hash: Option<String>, // SHA256[:8] of system prompt / role preamble
+    #[serde(default)]
+    pub system_prompt: Option<String>, // raw system prompt, when the gateway forwards it
     pub campaign_label: Option<String>,
 }
 
@@ -348,10 +414,24 @@ impl std::fmt::Display for ActionKind {
     }
 }
 
+/// Whether a decision is about a single account or a whole cluster (campaign).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum DecisionScope {
+    #[default]
+    Account,
+    Cluster,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RiskDecision {
-    pub account_id: String,
-    pub composite_score: f32,
+    #[serde(default)]
+    pub scope: DecisionScope,
+    pub account_id: String, // for Cluster scope: the account whose event triggered it
+    #[serde(default)]
+    pub request_id: String, // the request that triggered the decision
+    pub composite_score: f32, // this event
+    #[serde(default)]
+    pub account_score: f32, // decayed account-level accumulation — sets the tier
     pub tier: RiskTier,
     pub signal_scores: HashMap<String, f32>,
     pub top_evidence: Vec<String>,
@@ -361,6 +441,124 @@ pub struct RiskDecision {
     pub action: ActionKind,
     pub timestamp: DateTime<Utc>,
     pub ground_truth: Option<String>,
+    /// Geo / sanctions policy rule that adjusted (or would have adjusted) the score.
+    #[serde(default)]
+    pub geo_adjustment: Option<GeoAdjustment>,
+    /// Structured per-worker breakdown of how composite_score was reached.
+    #[serde(default)]
+    pub explanation: DecisionExplanation,
+    /// Allowlist entries that changed the enforcement action (engine/allowlist.rs).
+    #[serde(default)]
+    pub exemptions: Vec<Exemption>,
+}
+
+/// One worker's share of a composite score.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerContribution {
+    pub worker: WorkerKind,
+    pub raw_score: f32,
+    pub confidence: f32,
+    pub weight: f32,
+    pub contribution: f32, // raw_score × (0.4 + 0.6 × confidence) × weight
+    pub evidence: Vec<String>,
+}
+
+/// A post-fusion score adjustment (geo policy, cluster floor, ...).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoreAdjustment {
+    pub kind: String, // "geo_policy", "cluster_floor"
+    pub detail: String,
+    pub score_before: f32,
+    pub score_after: f32,
+}
+
+/// Why a decision was reached — lets analysts read the reasoning straight out
+/// of analyst_queue.jsonl without re-running the pipeline.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DecisionExplanation {
+    pub base_score: f32, // weighted worker sum before adjustments
+    pub contributions: Vec<WorkerContribution>, // sorted by contribution, largest first
+    pub adjustments: Vec<ScoreAdjustment>,
+    pub final_score: f32,
+    pub next_tier: Option<RiskTier>,
+    pub margin_to_next_tier: Option<f32>, // score still needed to reach next_tier
+}
+
+/// Audit record for a geo / sanctions policy adjustment (see engine/geo_policy.rs).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeoAdjustment {
+    pub rule_id: String,
+    pub policy_version: String,
+    pub sanctions: bool, // true if the winning entry is a sanctions list
+    pub country_code: String,
+    pub multiplier: f32,
+    pub score_before: f32,
+    pub score_after: f32,
+    pub exempted_by: Option<String>, // "account:<id>" / "org:<id>" when allowlisted
+}
+
+/// What an allowlist entry exempts the matched subject from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ExemptionScope {
+    Full,         // no enforcement at all — decisions are audited only
+    NoSuspension, // never suspended / taken down, still rate limited + monitored
+    NoCanary,     // responses never watermarked — canary injection becomes review
+}
+
+impl std::fmt::Display for ExemptionScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Full => write!(f, "FULL"),
+            Self::NoSuspension => write!(f, "NO_SUSPENSION"),
+            Self::NoCanary => write!(f, "NO_CANARY"),
+        }
+    }
+}
+
+/// Audit record for an allowlist exemption applied to a decision.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exemption {
+    pub entry_id: String,
+    pub matched: String, // "org:acme", "cidr:10.0.0.0/8", ...
+    pub scope: ExemptionScope,
+    pub owner: String,
+    pub account_id: String,
+    pub original_action: ActionKind,
+    pub applied_action: ActionKind,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// What a rate-limit command's bucket is keyed on.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitScope {
+    Account,
+    Org,
+    Cluster,
+    Subnet,
+}
+
+/// Token-bucket parameters a gateway applies directly (engine/rate_limit.rs).
+/// Gateways keep the most restrictive unexpired command per (scope, key).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitCommand {
+    pub command_id: String,
+    pub scope: LimitScope,
+    pub key: String, // account id, org id, cluster id or CIDR
+    pub rpm: u32,
+    pub tpm: u32,
+    pub burst: u32,
+    pub max_concurrency: u32,
+    pub duration_secs: i64,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub tier: RiskTier,
+    pub account_id: String, // account whose decision produced the command
+    pub cluster_id: Option<u32>,
+    pub observed_rpm: f64,
+    pub observed_tpm: f64,
+    pub reason: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -374,6 +572,13 @@ pub struct EnforcementAction {
     pub composite_score: f32,
     pub canary_token: Option<CanaryToken>, // set when action_type == InjectCanary
     pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub explanation: Option<DecisionExplanation>,
+    #[serde(default)]
+    pub exemptions: Vec<Exemption>,
+    /// Structured limits for RATE_LIMIT actions.
+    #[serde(default)]
+    pub rate_limits: Vec<RateLimitCommand>,
 }
 
 impl EnforcementAction {
@@ -386,7 +591,7 @@ impl EnforcementAction {
 pub struct IocBundle {
     pub cluster_id: u32,
     pub ip_addresses: Vec<String>,
-    pub ip_subnets: Vec<String>,
+    pub ip_subnets: Vec<IpCidr>, // member addresses at the graph's subnet prefixes
     pub payment_hashes: Vec<String>,
     pub ja3_hashes: Vec<String>,
     pub ja3s_hashes: Vec<String>, // Tier 1: server-hello fingerprints
@@ -6,33 +6,53 @@
 // synchronously before forwarding a request to the LLM backend:
 //
 //   rpc CheckAccount(AccountRequest) -> AccountStatus
+//   rpc Transition(TransitionRequest) -> TransitionResponse   (analyst actions)
+//   rpc Label(AnalystLabel) -> LabelResponse                  (TP/FP feedback)
+//   rpc ExportGraph(ExportGraphRequest) -> ExportGraphResponse (investigation)
 //
-// Returns: suspended, rate_limited, watch, or ok — plus the composite score
-// and the triggering evidence strings for gateway logging.
+// Returns: suspended, rate_limited, watch, or ok — plus the decayed
+// account-level risk score, its tier, and the triggering evidence strings for
+// gateway logging.
 //
 // Protocol buffer schema is defined inline via tonic's build-time codegen.
 // For this implementation we use tonic's reflection-compatible hand-rolled
 // codec approach to keep the build dependency simple (no protoc required).
 //
-// Bind address defaults to 127.0.0.1:50051.  In production, add mTLS certs
-// via tonic::transport::ServerTlsConfig.
+// Two listeners:
+//   --query-addr  CheckAccount only — the gateway-facing, read-only port.
+//   --admin-addr  Transition, Label and ExportGraph — state-changing and
+//                 investigation requests.  Every frame carries a bearer token
+//                 checked against --admin-tokens; the analyst recorded for a
+//                 transition or label is the token's owner, never a field the
+//                 caller supplies.
+// ExportGraph is built on the blocking pool and capped at
+// DEFAULT_EXPORT_MAX_EDGES edges (`with_export_limit`); larger graphs are
+// exported per cluster or with the `export-graph` subcommand.
+// Bind both to loopback or a management network; in production, add mTLS
+// certs via tonic::transport::ServerTlsConfig.
 //
 // Example gateway integration (Envoy ext_proc filter):
 //   The gateway calls CheckAccount with the API key → if the response is
 //   "suspended", it returns 429 before the request reaches the LLM.
 
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 use tracing::{info, warn};
 
+use crate::engine::feedback::{apply_label, AnalystLabel, LabelOutcome};
 use crate::engine::fusion::FusionEngine;
-use crate::events::ActionKind;
-use crate::state::window::StateStore;
+use crate::engine::lifecycle::{Actor, EnforcementState, Transition};
+use crate::events::{ActionKind, RiskTier};
+use crate::state::backend::StateBackend;
+use crate::state::export::{GraphExport, GraphFormat};
 
 // ── Wire protocol (length-prefixed JSON over TCP) ─────────────────────────────
 // We use a simple framing protocol rather than full gRPC to avoid the protoc
@@ -48,13 +68,147 @@ pub struct AccountRequest {
     pub user_agent: Option<String>,
 }
 
+/// Analyst lifecycle action (engine/lifecycle.rs): appeal, unsuspend, clear,
+/// or a confirmed false positive (`false_positive: true`, `to` ignored).
+/// `analyst` is filled in from the admin token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransitionRequest {
+    pub account_id: String,
+    #[serde(default)]
+    pub analyst: String,
+    pub to: EnforcementState,
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub false_positive: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransitionResponse {
+    pub account_id: String,
+    pub ok: bool,
+    pub transition: Option<Transition>,
+    pub error: Option<String>,
+}
+
+/// Graph export (state/export.rs) of one cluster, or of the whole store when
+/// `cluster_id` is absent.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportGraphRequest {
+    pub format: GraphFormat,
+    #[serde(default)]
+    pub cluster_id: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportGraphResponse {
+    pub ok: bool,
+    pub nodes: usize,
+    pub edges: usize,
+    pub files: BTreeMap<String, String>, // file name → contents
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LabelResponse {
+    pub ok: bool,
+    pub outcome: Option<LabelOutcome>,
+    pub error: Option<String>,
+}
+
+/// Requests are distinguished by shape: a TransitionRequest carries `to`, an
+/// AnalystLabel `verdict`, an ExportGraphRequest `format`; anything else with
+/// an `account_id` is a CheckAccount.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum QueryRequest {
+    Transition(TransitionRequest),
+    Label(AnalystLabel),
+    ExportGraph(ExportGraphRequest),
+    CheckAccount(AccountRequest),
+}
+
+/// Admin listener frame: a bearer token plus the request it authorizes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminFrame {
+    pub token: String,
+    pub request: QueryRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub ok: bool,
+    pub error: String,
+}
+
+impl ErrorResponse {
+    fn new(error: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: error.into(),
+        }
+    }
+}
+
+// ── Admin tokens ──────────────────────────────────────────────────────────────
+//
+// JSON array; only token digests are stored:
+//   [ { "analyst": "jdoe", "token_sha256": "<hex sha256 of the token>" } ]
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminToken {
+    pub analyst: String,
+    pub token_sha256: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AdminTokens {
+    tokens: Vec<AdminToken>,
+}
+
+impl AdminTokens {
+    pub fn new(tokens: Vec<AdminToken>) -> Self {
+        Self { tokens }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("reading admin tokens {}", path.display()))?;
+        let tokens: Vec<AdminToken> = serde_json::from_str(&raw)
+            .with_context(|| format!("parsing admin tokens {}", path.display()))?;
+        if tokens.is_empty() {
+            anyhow::bail!("admin tokens {} lists no analysts", path.display());
+        }
+        Ok(Self::new(tokens))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// The analyst owning `token`, if any.
+    pub fn authenticate(&self, token: &str) -> Option<&str> {
+        let digest = hex::encode(Sha256::digest(token.as_bytes()));
+        self.tokens
+            .iter()
+            .find(|t| t.token_sha256.eq_ignore_ascii_case(&digest))
+            .map(|t| t.analyst.as_str())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AccountStatus {
     pub account_id: String,
     pub status: AccountStatusKind,
-    pub composite_score: f32,
+    pub composite_score: f32, // decayed account-level score
+    pub tier: Option<RiskTier>,
+    pub state: EnforcementState, // enforcement lifecycle state
     pub evidence: Vec<String>,
-    pub rate_limit_rpm: Option<u32>, // requests per minute cap if rate_limited
+    pub rate_limit_rpm: Option<u32>, // rpm of the unexpired account-scope RateLimitCommand
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
@@ -80,37 +234,65 @@ impl From<ActionKind> for AccountStatusKind {
 
 // ── Server ────────────────────────────────────────────────────────────────────
 
-pub struct QueryServer {
-    store: Arc<StateStore>,
+pub const DEFAULT_EXPORT_MAX_EDGES: usize = 250_000;
+
+pub struct QueryServer<S: StateBackend> {
+    store: Arc<S>,
     engine: Arc<FusionEngine>,
-    addr: SocketAddr,
+    admin_tokens: AdminTokens,
+    export_max_edges: usize,
 }
 
-impl QueryServer {
-    pub fn new(store: Arc<StateStore>, engine: Arc<FusionEngine>, addr: SocketAddr) -> Self {
+impl<S: StateBackend> QueryServer<S> {
+    pub fn new(store: Arc<S>, engine: Arc<FusionEngine>) -> Self {
         Self {
             store,
             engine,
-            addr,
+            admin_tokens: AdminTokens::default(),
+            export_max_edges: DEFAULT_EXPORT_MAX_EDGES,
+        }
+    }
+
+    pub fn with_admin_tokens(mut self, tokens: AdminTokens) -> Self {
+        self.admin_tokens = tokens;
+        self
+    }
+
+    pub fn with_export_limit(mut self, max_edges: usize) -> Self {
+        self.export_max_edges = max_edges;
+        self
+    }
+
+    /// Read-only CheckAccount listener.
+    pub async fn serve(self: Arc<Self>, addr: SocketAddr) -> Result<()> {
+        self.listen(addr, false).await
+    }
+
+    /// Authenticated listener for state-changing and export requests.
+    pub async fn serve_admin(self: Arc<Self>, addr: SocketAddr) -> Result<()> {
+        if self.admin_tokens.is_empty() {
+            anyhow::bail!("admin listener needs admin tokens");
         }
+        self.listen(addr, true).await
     }
 
-    pub async fn serve(self: Arc<Self>) -> Result<()> {
-        let listener = TcpListener::bind(self.addr).await?;
-        info!("gRPC query API listening on {}", self.addr);
+    async fn listen(self: Arc<Self>, addr: SocketAddr, admin: bool) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        let kind = if admin { "admin" } else { "query" };
+        info!("gRPC {} API listening on {}", kind, addr);
 
         loop {
             let (stream, peer) = listener.accept().await?;
             let srv = Arc::clone(&self);
             tokio::spawn(async move {
-                if let Err(e) = srv.handle_connection(stream).await {
-                    warn!("Query API connection error from {}: {}", peer, e);
+                if let Err(e) = srv.handle_connection(stream, admin).await {
+                    warn!("{} API connection error from {}: {}", kind, peer, e);
                 }
             });
         }
     }
 
-    async fn handle_connection(&self, mut stream: TcpStream) -> Result<()> {
+    async fn handle_connection(&self, mut stream: TcpStream, admin: bool) -> Result<()> {
         loop {
             // Read 4-byte length prefix
             let mut len_buf = [0u8; 4];
@@ -127,9 +309,11 @@ impl QueryServer {
             let mut body = vec![0u8; len];
             stream.read_exact(&mut body).await?;
 
-            let req: AccountRequest = serde_json::from_slice(&body)?;
-            let resp = self.check_account(&req.account_id);
-            let resp_bytes = serde_json::to_vec(&resp)?;
+            let resp_bytes = if admin {
+                self.respond_admin(&body).await?
+            } else {
+                self.respond_query(&body)?
+            };
 
             let resp_len = resp_bytes.len() as u32;
             stream.write_all(&resp_len.to_le_bytes()).await?;
@@ -138,38 +322,347 @@ impl QueryServer {
         Ok(())
     }
 
+    /// Query listener: CheckAccount only.
+    fn respond_query(&self, body: &[u8]) -> Result<Vec<u8>> {
+        Ok(match serde_json::from_slice::<QueryRequest>(body)? {
+            QueryRequest::CheckAccount(req) => {
+                serde_json::to_vec(&self.check_account(&req.account_id))?
+            }
+            _ => serde_json::to_vec(&ErrorResponse::new(
+                "state-changing and export requests go to the admin listener",
+            ))?,
+        })
+    }
+
+    /// Admin listener: every frame is authenticated; the token's analyst is
+    /// the actor recorded for transitions and labels.
+    async fn respond_admin(&self, body: &[u8]) -> Result<Vec<u8>> {
+        let frame: AdminFrame = serde_json::from_slice(body)?;
+        let Some(analyst) = self.admin_tokens.authenticate(&frame.token) else {
+            warn!("Admin API request with an unknown token rejected");
+            return Ok(serde_json::to_vec(&ErrorResponse::new("unauthorized"))?);
+        };
+        let analyst = analyst.to_string();
+        Ok(match frame.request {
+            QueryRequest::CheckAccount(req) => {
+                serde_json::to_vec(&self.check_account(&req.account_id))?
+            }
+            QueryRequest::Transition(mut req) => {
+                req.analyst = analyst;
+                serde_json::to_vec(&self.transition(&req))?
+            }
+            QueryRequest::ExportGraph(req) => serde_json::to_vec(&self.export_graph(&req).await)?,
+            QueryRequest::Label(mut label) => {
+                label.analyst = analyst;
+                let resp = match apply_label(&label, &self.engine, self.store.as_ref()).await {
+                    Ok(outcome) => LabelResponse {
+                        ok: true,
+                        outcome: Some(outcome),
+                        error: None,
+                    },
+                    Err(e) => LabelResponse {
+                        ok: false,
+                        outcome: None,
+                        error: Some(e.to_string()),
+                    },
+                };
+                if let Err(e) = self.engine.feedback().save(&self.engine).await {
+                    warn!("Saving analyst feedback state failed: {}", e);
+                }
+                serde_json::to_vec(&resp)?
+            }
+        })
+    }
+
     fn check_account(&self, account_id: &str) -> AccountStatus {
-        // Look up the most recent decision cached in the fusion engine.
-        // The engine's `last_alert` and `suspended` maps hold the state.
-        let (status, score, evidence) = self
+        // Report the decayed account-level risk score and the tier it (or a
+        // still-held peak tier) maps to.
+        let now = chrono::Utc::now();
+        let decay = self.engine.decay();
+        let (status, score, tier, evidence, rpm) = self
             .store
             .get_window(account_id)
             .map(|w| {
-                let _w = w.read();
-                let score = 0.0f32; // placeholder; real impl reads decision cache
-                let ev: Vec<String> = Vec::new();
-                (AccountStatusKind::Ok, score, ev)
+                let w = w.read();
+                let score = (w.risk.decayed(decay, now) * 10000.0).round() / 10000.0;
+                let tier = w.risk.held_tier(decay, now);
+                let status = match tier {
+                    Some(RiskTier::Critical) => AccountStatusKind::Suspended,
+                    Some(RiskTier::High) => AccountStatusKind::Watch,
+                    Some(RiskTier::Medium) => AccountStatusKind::RateLimited,
+                    _ => AccountStatusKind::Ok,
+                };
+                let rpm = w
+                    .rate_limit
+                    .as_ref()
+                    .filter(|c| c.expires_at > now)
+                    .map(|c| c.rpm);
+                (status, score, tier, w.risk.last_evidence.clone(), rpm)
             })
-            .unwrap_or((AccountStatusKind::Ok, 0.0, vec![]));
+            .unwrap_or((AccountStatusKind::Ok, 0.0, None, vec![], None));
 
-        // Override with suspension state from engine.
-        let final_status = if self.engine.is_suspended(account_id) {
-            AccountStatusKind::Suspended
-        } else {
-            status
+        // The enforcement lifecycle overrides the tier-derived status once the
+        // account has one; Reinstated (analyst-cleared) accounts report Ok.
+        let lifecycle = self.engine.lifecycle().get(account_id);
+        let state = lifecycle.as_ref().map(|l| l.state).unwrap_or_default();
+        let final_status = match (lifecycle.is_some(), state) {
+            (false, _) => status,
+            (_, EnforcementState::Suspended) => AccountStatusKind::Suspended,
+            (_, EnforcementState::Review | EnforcementState::Canary) => AccountStatusKind::Watch,
+            (_, EnforcementState::RateLimited) => AccountStatusKind::RateLimited,
+            (_, EnforcementState::Monitor | EnforcementState::Reinstated) => AccountStatusKind::Ok,
         };
 
         AccountStatus {
             account_id: account_id.to_string(),
             status: final_status,
             composite_score: score,
+            tier,
+            state,
             evidence,
-            rate_limit_rpm: if final_status == AccountStatusKind::RateLimited {
-                Some(10)
-            } else {
-                None
-            },
+            rate_limit_rpm: rpm.filter(|_| final_status != AccountStatusKind::Ok),
             timestamp: chrono::Utc::now(),
         }
     }
+
+    /// Built and rendered on the blocking pool, off the listener's tasks.
+    async fn export_graph(&self, req: &ExportGraphRequest) -> ExportGraphResponse {
+        let store = Arc::clone(&self.store);
+        let (cluster, format, max_edges) = (req.cluster_id, req.format, self.export_max_edges);
+        let built = tokio::task::spawn_blocking(move || {
+            GraphExport::build_limited(store.resident(), cluster, max_edges).map(|export| {
+                (
+                    export.nodes.len(),
+                    export.edges.len(),
+                    export.render(format),
+                )
+            })
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+        match built {
+            Ok((nodes, edges, files)) => ExportGraphResponse {
+                ok: true,
+                nodes,
+                edges,
+                files: files
+                    .into_iter()
+                    .map(|(name, body)| (name.to_string(), body))
+                    .collect(),
+                error: None,
+            },
+            Err(e) => ExportGraphResponse {
+                ok: false,
+                nodes: 0,
+                edges: 0,
+                files: BTreeMap::new(),
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    fn transition(&self, req: &TransitionRequest) -> TransitionResponse {
+        let now = chrono::Utc::now();
+        let lifecycle = self.engine.lifecycle();
+        let result = if req.false_positive {
+            let score = self
+                .store
+                .get_window(&req.account_id)
+                .map(|w| w.read().risk.decayed(self.engine.decay(), now))
+                .unwrap_or(0.0);
+            Ok(lifecycle.mark_false_positive(
+                &req.account_id,
+                &req.analyst,
+                &req.reason,
+                score,
+                now,
+            ))
+        } else {
+            lifecycle
+                .transition(
+                    &req.account_id,
+                    req.to,
+                    Actor::Analyst(req.analyst.clone()),
+                    &req.reason,
+                    now,
+                )
+                .map(Some)
+        };
+        match result {
+            Ok(transition) => {
+                if let Some(t) = &transition {
+                    self.store
+                        .set_suspended(&t.account_id, t.to == EnforcementState::Suspended);
+                    info!(
+                        "LIFECYCLE {} {} → {} by {}",
+                        t.account_id, t.from, t.to, t.actor
+                    );
+                }
+                TransitionResponse {
+                    account_id: req.account_id.clone(),
+                    ok: true,
+                    transition,
+                    error: None,
+                }
+            }
+            Err(e) => TransitionResponse {
+                account_id: req.account_id.clone(),
+                ok: false,
+                transition: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::dispatcher::Dispatcher;
+    use crate::engine::rate_limit::{RateLimitPolicy, TierLimits};
+    use crate::events::{ApiEvent, LimitScope, RiskDecision};
+    use crate::state::window::StateStore;
+
+    fn server() -> QueryServer<StateStore> {
+        let token = AdminToken {
+            analyst: "jdoe".into(),
+            token_sha256: hex::encode(Sha256::digest(b"s3cret")),
+        };
+        QueryServer::new(Arc::new(StateStore::new()), Arc::new(FusionEngine::new()))
+            .with_admin_tokens(AdminTokens::new(vec![token]))
+    }
+
+    fn transition(to: &str, analyst: &str) -> serde_json::Value {
+        serde_json::json!({ "account_id": "sk-a", "to": to, "analyst": analyst })
+    }
+
+    #[tokio::test]
+    async fn query_listener_is_read_only() {
+        let srv = server();
+        let body = serde_json::to_vec(&transition("Suspended", "jdoe")).unwrap();
+        let resp: serde_json::Value =
+            serde_json::from_slice(&srv.respond_query(&body).unwrap()).unwrap();
+        assert_eq!(resp["ok"], false);
+        assert!(srv.engine.lifecycle().get("sk-a").is_none());
+
+        let body = br#"{ "account_id": "sk-a" }"#;
+        let status: AccountStatus =
+            serde_json::from_slice(&srv.respond_query(body).unwrap()).unwrap();
+        assert_eq!(status.status, AccountStatusKind::Ok);
+    }
+
+    #[tokio::test]
+    async fn admin_requires_token_and_records_its_analyst() {
+        let srv = server();
+        let frame = |token: &str| {
+            serde_json::to_vec(&serde_json::json!({
+                "token": token, "request": transition("Suspended", "someone_else")
+            }))
+            .unwrap()
+        };
+
+        let resp: serde_json::Value =
+            serde_json::from_slice(&srv.respond_admin(&frame("guess")).await.unwrap()).unwrap();
+        assert_eq!(resp["error"], "unauthorized");
+        assert!(srv.engine.lifecycle().get("sk-a").is_none());
+
+        let resp: TransitionResponse =
+            serde_json::from_slice(&srv.respond_admin(&frame("s3cret")).await.unwrap()).unwrap();
+        assert!(resp.ok, "{:?}", resp.error);
+        let t = resp.transition.unwrap();
+        assert_eq!(t.actor, Actor::Analyst("jdoe".into()));
+        assert_eq!(t.to, EnforcementState::Suspended);
+    }
+
+    #[tokio::test]
+    async fn export_is_capped() {
+        let srv = server();
+        for i in 0..4 {
+            let event: crate::events::ApiEvent = serde_json::from_value(serde_json::json!({
+                "request_id": "r", "account_id": format!("ring_{i}"),
+                "timestamp": chrono::Utc::now(), "ip_address": "203.0.113.7",
+                "user_agent": "ua", "model": "m", "prompt": "p", "token_count": 100,
+                "payment_method_hash": "pm_ring", "org_id": null, "country_code": "US",
+                "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+                "h2_settings": null, "tls_library": null, "asn_number": null,
+                "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+                "campaign_label": null
+            }))
+            .unwrap();
+            srv.store.ingest(&event);
+        }
+        let frame = serde_json::to_vec(&serde_json::json!({
+            "token": "s3cret", "request": { "format": "graphml" }
+        }))
+        .unwrap();
+
+        let resp: ExportGraphResponse =
+            serde_json::from_slice(&srv.respond_admin(&frame).await.unwrap()).unwrap();
+        assert!(resp.ok, "{:?}", resp.error);
+        assert!(resp.edges > 3);
+        assert!(resp.files["graph.graphml"].contains("ring_3"));
+
+        let srv = srv.with_export_limit(3);
+        let resp: ExportGraphResponse =
+            serde_json::from_slice(&srv.respond_admin(&frame).await.unwrap()).unwrap();
+        assert!(!resp.ok);
+        assert!(resp.error.unwrap().contains("exceeds 3 edges"));
+        assert!(resp.files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn check_account_reports_the_issued_rate_limit() {
+        let srv = server();
+        let event: ApiEvent = serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": "sk-a", "timestamp": chrono::Utc::now(),
+            "ip_address": "203.0.113.7", "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": 100, "payment_method_hash": null,
+            "org_id": null, "country_code": "US", "header_order": [],
+            "ja3_hash": null, "ja3s_hash": null, "h2_settings": null,
+            "tls_library": null, "asn_number": null, "asn_org": null,
+            "max_tokens": null, "system_prompt_hash": null, "campaign_label": null
+        }))
+        .unwrap();
+        srv.store.ingest(&event);
+        assert_eq!(srv.check_account("sk-a").rate_limit_rpm, None);
+
+        let dir = std::env::temp_dir().join(format!("glasswally_rpm_{}", std::process::id()));
+        let dispatcher = Dispatcher::new(&dir).with_rate_limit_policy(RateLimitPolicy {
+            high: TierLimits {
+                min_rpm: 7,
+                ..RateLimitPolicy::default().high
+            },
+            ..RateLimitPolicy::default()
+        });
+        let decision: RiskDecision = serde_json::from_value(serde_json::json!({
+            "scope": "Account", "account_id": "sk-a", "composite_score": 0.6,
+            "tier": "High", "signal_scores": {}, "top_evidence": [],
+            "country_codes": [], "cluster_id": null, "n_requests_seen": 1,
+            "action": "RateLimit", "timestamp": chrono::Utc::now(), "ground_truth": null
+        }))
+        .unwrap();
+        let action = dispatcher
+            .dispatch(&decision, srv.store.as_ref())
+            .await
+            .unwrap();
+        srv.engine.record_alert(&decision, action.action_type);
+        std::fs::remove_dir_all(&dir).ok();
+
+        let issued = action
+            .rate_limits
+            .iter()
+            .find(|c| c.scope == LimitScope::Account)
+            .unwrap();
+        assert_eq!(issued.rpm, 7);
+        let status = srv.check_account("sk-a");
+        assert_eq!(status.status, AccountStatusKind::RateLimited);
+        assert_eq!(status.rate_limit_rpm, Some(issued.rpm));
+
+        // Once the command expires the gateway has dropped it; so do we.
+        let mut expired = issued.clone();
+        expired.expires_at = chrono::Utc::now() - chrono::Duration::seconds(1);
+        srv.store.set_rate_limit("sk-a", &expired);
+        assert_eq!(srv.check_account("sk-a").rate_limit_rpm, None);
+    }
 }
@@ -98,6 +98,36 @@ impl KafkaAdapter {
         adapter
     }
 
+    pub fn config(&self) -> &KafkaConfig {
+        &self.config
+    }
+
+    /// Publish a pre-serialized payload to any topic (used by engine/sinks.rs).
+    pub async fn publish(&self, topic: String, key: String, payload: String) {
+        self.enqueue(topic, key, payload).await;
+    }
+
+    /// Like `publish`, but fails instead of dropping the oldest message when
+    /// the queue is full, so the caller can retry (engine/sinks.rs).
+    pub async fn try_publish(
+        &self,
+        topic: String,
+        key: String,
+        payload: String,
+    ) -> anyhow::Result<()> {
+        let mut q = self.queue.lock().await;
+        if q.len() >= self.config.max_queue {
+            anyhow::bail!("kafka queue full ({} messages)", q.len());
+        }
+        q.push_back(KafkaMessage {
+            topic,
+            key,
+            payload,
+            ts: Utc::now(),
+        });
+        Ok(())
+    }
+
     /// Publish an enforcement action to the enforcement topic.
     pub async fn publish_enforcement(&self, action: &EnforcementAction) {
         let key = action.account_id.clone().unwrap_or_default();
@@ -2,9 +2,9 @@
 //
 // Glasswally — Real-time LLM distillation attack detection via eBPF
 //
-// Infrastructure modules (grpc_api, kafka_output, redis_state, load_shedder,
-// otel, eval, loader) are wired in during deployment; suppress dead_code for
-// the entire crate while development is in progress.
+// Infrastructure modules (kafka_output, redis_state, load_shedder,
+// eval, loader, response_rewriter) are wired in during deployment;
+// suppress dead_code for the entire crate while development is in progress.
 #![allow(dead_code)]
 //
 // Three operational modes:
@@ -16,19 +16,25 @@
 //   sudo glasswally --mode ebpf                            # live eBPF
 //   glasswally --mode tail --path /var/log/api/access.jsonl
 //   glasswally --mode replay --path captured.jsonl --speed 10.0
+//   glasswally verify-audit --path audit_log.jsonl --key hmac:/etc/glasswally/audit.key
+//   glasswally attribute-dataset --path suspect.jsonl --registry output/canary_registry.jsonl
+//   glasswally detect-watermark --path suspect.jsonl --key /etc/glasswally/wm.key --accounts accts.txt
 
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
 
 use anyhow::Result;
 use chrono::Utc;
-use clap::{Parser, ValueEnum};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use clap::{Parser, Subcommand, ValueEnum};
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader};
 use tokio::sync::mpsc;
 use tracing::{error, info, warn};
 use tracing_subscriber::EnvFilter;
 
+mod attribution;
+mod audit;
 mod engine;
 mod eval;
 mod events;
@@ -38,15 +44,40 @@ mod ioc_feed;
 mod kafka_output;
 mod load_shedder;
 mod loader;
+mod net;
 mod otel;
 mod redis_state;
 mod redteam;
+mod response_rewriter;
+mod robust_watermark;
 mod state;
+mod webhook;
 mod workers;
 
-use engine::{dispatcher::Dispatcher, fusion::FusionEngine};
+use audit::{AuditChain, AuditSigner, AuditVerifier};
+use engine::{
+    allowlist::AllowlistRegistry,
+    canary_scan,
+    dispatcher::Dispatcher,
+    feedback::{self, AnalystLabel, FeedbackStore},
+    fusion::FusionEngine,
+    geo_policy::GeoPolicy,
+    lifecycle::{EnforcementState, LifecycleConfig},
+    notifier::{AlertNotifier, NotifierConfig},
+    rate_limit::RateLimitPolicy,
+    sinks::SinkConfig,
+};
 use events::{ActionKind, ApiEvent, RiskTier};
+use grpc_api::{AdminTokens, QueryServer};
+use otel::{GlasswallMetrics, MetricsServer};
+use robust_watermark::RobustWatermark;
+use state::backend::{self, DiskBackend, StateBackend};
+use state::export::{GraphExport, GraphFormat};
+use state::graph::GraphConfig;
+use state::partition::{Exchange, PartitionConfig};
+use state::risk::DecayConfig;
 use state::window::StateStore;
+use state::window::WindowLimits;
 
 // ── CLI ───────────────────────────────────────────────────────────────────────
 
@@ -57,6 +88,9 @@ use state::window::StateStore;
     version = env!("CARGO_PKG_VERSION"),
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(long, value_enum, default_value = "tail")]
     mode: Mode,
 
@@ -79,6 +113,246 @@ struct Cli {
 
     #[arg(long, default_value = "443", help = "TLS port for eBPF mode")]
     port: u16,
+
+    #[arg(
+        long,
+        help = "Geo / sanctions risk policy (JSON); defaults to CN ×1.30"
+    )]
+    geo_policy: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value = "21600",
+        help = "Account risk half-life in seconds"
+    )]
+    risk_half_life: i64,
+
+    #[arg(
+        long,
+        default_value = "172800",
+        help = "Account risk half-life once High/Critical was reached"
+    )]
+    risk_escalated_half_life: i64,
+
+    #[arg(
+        long,
+        default_value = "259200",
+        help = "Clean period in seconds before enforcement steps down one state"
+    )]
+    deescalate_after: i64,
+
+    #[arg(
+        long,
+        default_value = "2592000",
+        help = "Seconds an analyst false-positive override suppresses alerts"
+    )]
+    fp_suppression: i64,
+
+    #[arg(
+        long,
+        help = "Enforcement sink routing config (JSON, see engine/sinks.rs); default: files in --output"
+    )]
+    sinks: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "On-call alert notifier config (JSON: Slack / webhook / PagerDuty, see engine/notifier.rs)"
+    )]
+    notify: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Rate-limit command policy (JSON, see engine/rate_limit.rs)"
+    )]
+    rate_limit_policy: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Account graph config (JSON: link weights, cluster threshold, see state/graph.rs)"
+    )]
+    graph_config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Account window limits (JSON: per-account caps, memory budget, see state/window.rs)"
+    )]
+    window_limits: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Partitioned deployment (JSON: instance, instances, exchange listen + peers, see state/partition.rs)"
+    )]
+    partition: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Spill idle account windows to this directory instead of dropping them (disk state backend, see state/backend.rs)"
+    )]
+    state_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Audit signing key: hmac:<key file> or ed25519:<hex seed file> (see audit.rs)"
+    )]
+    audit_key: Option<String>,
+
+    #[arg(long, help = "Sign every audit record, not only checkpoints")]
+    audit_sign_records: bool,
+
+    #[arg(
+        long,
+        default_value = "1000",
+        help = "Audit records between signed checkpoints"
+    )]
+    audit_checkpoint_every: u64,
+
+    #[arg(
+        long,
+        default_value = "3600",
+        help = "Seconds between timed audit checkpoints"
+    )]
+    audit_checkpoint_interval: u64,
+
+    #[arg(
+        long,
+        default_value = "300",
+        help = "Seconds between canary registry snapshots (<output>/canary_registry.jsonl)"
+    )]
+    canary_snapshot_interval: u64,
+
+    #[arg(
+        long,
+        default_value = "300",
+        help = "Seconds between long-horizon rollup snapshots (<output>/rollups.jsonl, restored at startup)"
+    )]
+    rollup_snapshot_interval: u64,
+
+    #[arg(
+        long,
+        help = "Existing audit log to continue the hash chain from [default: <output>/audit_log.jsonl]"
+    )]
+    audit_log: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Allowlist / trusted-partner registry (JSON array, see engine/allowlist.rs)"
+    )]
+    allowlist: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Analyst labels JSONL to watch (TP/FP feedback, see engine/feedback.rs)"
+    )]
+    feedback_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Labeled evaluation dataset to append to [default: <output>/labeled_feedback.jsonl]"
+    )]
+    feedback_dataset: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Query API (CheckAccount) bind address, e.g. 127.0.0.1:50051 (see grpc_api.rs)"
+    )]
+    grpc_addr: Option<SocketAddr>,
+
+    #[arg(
+        long,
+        requires = "admin_tokens",
+        help = "Admin API bind address for analyst transitions, labels and graph export"
+    )]
+    admin_addr: Option<SocketAddr>,
+
+    #[arg(
+        long,
+        help = "Admin API tokens (JSON: analyst + token_sha256, see grpc_api.rs)"
+    )]
+    admin_tokens: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value = "127.0.0.1:9090",
+        help = "Prometheus /metrics bind address (see otel.rs)"
+    )]
+    metrics_addr: SocketAddr,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Verify a hash-chained audit log: gaps, reordering, edits, signatures
+    VerifyAudit {
+        #[arg(long, help = "Audit log (JSONL)")]
+        path: PathBuf,
+
+        #[arg(
+            long,
+            help = "Verification key: hmac:<key file> or ed25519:<hex public key file>"
+        )]
+        key: Option<String>,
+    },
+
+    /// Attribute a suspect dataset via canary tokens and account watermarks
+    AttributeDataset {
+        #[arg(
+            long,
+            help = "Suspect dataset (JSONL or plain text, one sample per line)"
+        )]
+        path: PathBuf,
+
+        #[arg(
+            long,
+            help = "Canary registry snapshot (<output>/canary_registry.jsonl)"
+        )]
+        registry: PathBuf,
+
+        #[arg(long, help = "Also test for the robust watermark with this key file")]
+        robust_key: Option<PathBuf>,
+    },
+
+    /// Test a suspect dataset for the robust (synonym/punctuation) watermark
+    DetectWatermark {
+        #[arg(
+            long,
+            help = "Suspect dataset (JSONL or plain text, one sample per line)"
+        )]
+        path: PathBuf,
+
+        #[arg(long, help = "Robust watermark key file")]
+        key: PathBuf,
+
+        #[arg(long, help = "Candidate account ids, one per line")]
+        accounts: PathBuf,
+
+        #[arg(
+            long,
+            default_value_t = 0.001,
+            help = "Report accounts below this adjusted p-value"
+        )]
+        alpha: f64,
+    },
+
+    /// Export the account / infrastructure graph rebuilt from an event log
+    ExportGraph {
+        #[arg(long, help = "Event log (JSONL ApiEvents)")]
+        path: PathBuf,
+
+        #[arg(long, help = "Only this cluster [default: whole graph]")]
+        cluster: Option<u32>,
+
+        #[arg(
+            long,
+            default_value = "graphml",
+            help = "graphml, gexf or neo4j-csv (nodes.csv + relationships.csv)"
+        )]
+        format: GraphFormat,
+
+        #[arg(long, help = "Output directory")]
+        out: PathBuf,
+
+        #[arg(long, help = "Account graph config (JSON, see state/graph.rs)")]
+        graph_config: Option<PathBuf>,
+    },
 }
 
 #[derive(Clone, ValueEnum)]
@@ -90,46 +364,189 @@ enum Mode {
 
 // ── Pipeline ──────────────────────────────────────────────────────────────────
 
-struct Pipeline {
-    store: Arc<StateStore>,
+struct Pipeline<S: StateBackend> {
+    store: Arc<S>,
     engine: Arc<FusionEngine>,
     dispatcher: Arc<Dispatcher>,
+    metrics: Arc<GlasswallMetrics>,
+    partition: PartitionConfig,
+    exchange: Option<Arc<Exchange>>,
 }
 
-impl Pipeline {
-    fn new(output: PathBuf) -> Self {
+impl<S: StateBackend> Pipeline<S> {
+    fn new(dispatcher: Dispatcher, engine: FusionEngine, store: S) -> Self {
         Self {
-            store: Arc::new(StateStore::new()),
-            engine: Arc::new(FusionEngine::new()),
-            dispatcher: Arc::new(Dispatcher::new(output)),
+            store: Arc::new(store),
+            dispatcher: Arc::new(dispatcher.with_allowlist(Arc::clone(engine.allowlist()))),
+            engine: Arc::new(engine),
+            metrics: GlasswallMetrics::new(),
+            partition: PartitionConfig::default(),
+            exchange: None,
         }
     }
 
+    /// Join a partitioned deployment: keep only owned accounts and exchange
+    /// index updates with the peers.
+    async fn with_partition(mut self, partition: PartitionConfig) -> Result<Self> {
+        if partition.instances > 1 {
+            self.exchange = Some(Exchange::start(&partition, Arc::clone(&self.store)).await?);
+        }
+        self.partition = partition;
+        Ok(self)
+    }
+
     async fn process(&self, event: ApiEvent) {
-        // Ingest into sliding windows + indexes
-        self.store.ingest(&event);
+        // Other instances own this account (state/partition.rs)
+        if !self.partition.owns(&event.account_id) {
+            return;
+        }
+
+        // Ingest into sliding windows + indexes; peers get the global half
+        let update = self.store.ingest(&event);
+        self.metrics.record_event();
+        if let Some(exchange) = &self.exchange {
+            exchange.publish(&update);
+        }
+
+        // Replayed canaries / watermarks link the account to the origin's cluster
+        let hits = canary_scan::scan(&event, self.store.as_ref());
+        for h in &hits {
+            warn!(
+                "CANARY_REPLAY account={} origin={} kind={:?} field={} cluster={:?}",
+                h.account_id, h.origin_account, h.kind, h.field, h.cluster_id
+            );
+        }
+        if !hits.is_empty() {
+            self.metrics
+                .canaries_triggered
+                .fetch_add(hits.len() as u64, std::sync::atomic::Ordering::Relaxed);
+            if let Err(e) = self.dispatcher.record_canary_hits(&hits).await {
+                error!("Canary hit audit failed: {}", e);
+            }
+        }
+
+        let mut cluster_events = self.store.drain_cluster_events();
+        if !self.partition.records_cluster_events() {
+            cluster_events.clear();
+        }
+        for e in &cluster_events {
+            info!(
+                "CLUSTER_{:?} cluster={} parents={:?} size={} weight={:.1}",
+                e.kind, e.cluster_id, e.parents, e.size, e.weight
+            );
+        }
+        if !cluster_events.is_empty() {
+            if let Err(e) = self.dispatcher.record_cluster_events(&cluster_events).await {
+                error!("Cluster event audit failed: {}", e);
+            }
+        }
 
         // Run all workers concurrently
-        let signals = workers::run_all(&event, &self.store).await;
+        let signals = workers::run_all(&event, self.store.as_ref()).await;
+        for sig in &signals {
+            self.metrics.record_worker_signal(sig);
+        }
 
         // Fuse signals
-        let decision = match self.engine.fuse(&event, &self.store, &signals) {
+        if let Some(decision) = self.engine.fuse(&event, self.store.as_ref(), &signals) {
+            self.metrics
+                .record_composite_score(decision.composite_score);
+            if self
+                .engine
+                .should_alert(&event.account_id, decision.account_score)
+            {
+                // Dispatch enforcement action
+                match self
+                    .dispatcher
+                    .dispatch(&decision, self.store.as_ref())
+                    .await
+                {
+                    Ok(action) => {
+                        self.engine.record_alert(&decision, action.action_type);
+                        self.metrics.record_alert(decision.tier);
+                        print_alert(&decision, &action.action_type);
+                    }
+                    Err(e) => error!("Dispatch failed: {}", e),
+                }
+            }
+        }
+
+        // Cluster-level fusion — campaigns of individually sub-threshold accounts
+        if let Some(cid) = self.store.get_cluster(&event.account_id) {
+            self.process_cluster(cid, &event).await;
+        }
+    }
+
+    async fn process_cluster(&self, cluster_id: u32, event: &ApiEvent) {
+        let decision = match self
+            .engine
+            .fuse_cluster(cluster_id, event, self.store.as_ref())
+        {
             Some(d) => d,
             None => return,
         };
-
-        if !self.engine.should_alert(&event.account_id) {
+        let n_members = self.store.cluster_members(cluster_id).len();
+        if !self.engine.should_alert_cluster(cluster_id, n_members) {
             return;
         }
-
-        // Dispatch enforcement action
-        match self.dispatcher.dispatch(&decision, &self.store).await {
+        match self
+            .dispatcher
+            .dispatch(&decision, self.store.as_ref())
+            .await
+        {
             Ok(action) => {
-                self.engine
-                    .record_alert(&event.account_id, decision.tier == RiskTier::Critical);
+                self.metrics.record_alert(decision.tier);
+                self.engine.record_cluster_alert(
+                    cluster_id,
+                    &action.affected_accounts,
+                    action.action_type,
+                );
                 print_alert(&decision, &action.action_type);
             }
-            Err(e) => error!("Dispatch failed: {}", e),
+            Err(e) => error!("Cluster dispatch failed: {}", e),
+        }
+    }
+}
+
+impl<S: StateBackend> Pipeline<S> {
+    async fn apply_label(&self, label: &AnalystLabel) {
+        match feedback::apply_label(label, &self.engine, self.store.as_ref()).await {
+            Ok(o) => info!(
+                "LABEL {:?} by {}: accounts={} allowlisted={} downweighted={} dataset_rows={}",
+                label.verdict,
+                label.analyst,
+                o.accounts.len(),
+                o.allowlisted.len(),
+                o.downweighted.len(),
+                o.dataset_rows
+            ),
+            Err(e) => warn!("Analyst label rejected: {}", e),
+        }
+        if let Err(e) = self.engine.feedback().save(&self.engine).await {
+            error!("Saving analyst feedback state failed: {}", e);
+        }
+    }
+
+    /// Auto de-escalation + transition journal: every 30s step down clean
+    /// accounts, mirror suspension onto windows and persist transitions.
+    async fn lifecycle_loop(self: Arc<Self>) {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+            let lifecycle = self.engine.lifecycle();
+            lifecycle.deescalate_idle(Utc::now());
+            self.engine.allowlist().prune(Utc::now());
+            let transitions = lifecycle.drain_journal();
+            for t in &transitions {
+                self.store
+                    .set_suspended(&t.account_id, t.to == EnforcementState::Suspended);
+                info!(
+                    "LIFECYCLE {} {} → {} by {} ({})",
+                    t.account_id, t.from, t.to, t.actor, t.reason
+                );
+            }
+            if let Err(e) = self.dispatcher.record_transitions(&transitions).await {
+                error!("Writing lifecycle transitions failed: {}", e);
+            }
         }
     }
 }
@@ -174,20 +591,43 @@ fn print_alert(decision: &events::RiskDecision, action: &ActionKind) {
         "\n{}{} {} → {}{}",
         color, icon, decision.tier, action, reset
     );
-    println!("  Account : {}{}{}", color, decision.account_id, reset);
+    if decision.scope == events::DecisionScope::Cluster {
+        println!("  Scope   : cluster (triggered by {})", decision.account_id);
+    } else {
+        println!("  Account : {}{}{}", color, decision.account_id, reset);
+    }
     println!(
-        "  Score   : {}{:.4}{}",
-        color, decision.composite_score, reset
+        "  Score   : {}{:.4}{} (account {:.4})",
+        color, decision.composite_score, reset, decision.account_score
     );
     println!("  Cluster : {:?}", decision.cluster_id);
+    let top = decision
+        .explanation
+        .contributions
+        .iter()
+        .take(3)
+        .map(|c| format!("{} {:.3}", c.worker, c.contribution))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    println!("  Drivers : {}", top);
+    if let Some(margin) = decision.explanation.margin_to_next_tier {
+        println!("  Margin  : +{:.4} to next tier", margin);
+    }
+    for ex in &decision.exemptions {
+        println!(
+            "  Exempt  : {} {} ({}) {} → {}",
+            ex.matched, ex.scope, ex.entry_id, ex.original_action, ex.applied_action
+        );
+    }
     println!("  Evidence: {}{}", ev, gt);
 }
 
-async fn print_stats_loop(store: Arc<StateStore>, start: Instant) {
+async fn print_stats_loop<S: StateBackend>(store: Arc<S>, start: Instant) {
     loop {
         tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
         let elapsed = start.elapsed().as_secs_f64();
         let events = store
+            .resident()
             .total_events
             .load(std::sync::atomic::Ordering::Relaxed);
         println!(
@@ -231,6 +671,53 @@ async fn tail_jsonl(path: PathBuf, tx: mpsc::Sender<ApiEvent>, seek_end: bool) -
     Ok(())
 }
 
+// Analyst labels are applied from the offset saved in the feedback state
+// (engine/feedback.rs), so a restart picks up only labels appended since; the
+// effects of earlier labels are restored from the state file.
+async fn tail_labels<S: StateBackend>(path: PathBuf, pipeline: Arc<Pipeline<S>>) -> Result<()> {
+    let mut file = tokio::fs::File::open(&path).await?;
+    let feedback = pipeline.engine.feedback();
+    let mut offset = feedback.labels_offset();
+    if offset > file.metadata().await?.len() {
+        warn!(
+            "Labels file {} shrank; reading from the start",
+            path.display()
+        );
+        offset = 0;
+    }
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    let mut reader = BufReader::new(file);
+
+    info!(
+        "Watching analyst labels {} from byte {}",
+        path.display(),
+        offset
+    );
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line).await?;
+        if n == 0 || !line.ends_with('\n') {
+            // Nothing new, or a line still being written: re-read it whole
+            if n > 0 {
+                reader.seek(std::io::SeekFrom::Start(offset)).await?;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+            continue;
+        }
+        offset += n as u64;
+        feedback.set_labels_offset(offset);
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<AnalystLabel>(trimmed) {
+            Ok(label) => pipeline.apply_label(&label).await,
+            Err(e) => warn!("Label parse error: {}", e),
+        }
+    }
+}
+
 // ── Main ──────────────────────────────────────────────────────────────────────
 
 #[tokio::main]
@@ -241,7 +728,123 @@ async fn main() -> Result<()> {
         .init();
 
     let cli = Cli::parse();
-    let pipeline = Arc::new(Pipeline::new(cli.output.clone()));
+    if let Some(command) = &cli.command {
+        return run_command(command);
+    }
+
+    let mut engine = FusionEngine::new()
+        .with_decay(DecayConfig {
+            half_life_secs: cli.risk_half_life,
+            escalated_half_life_secs: cli.risk_escalated_half_life,
+            ..DecayConfig::default()
+        })
+        .with_lifecycle(LifecycleConfig {
+            clean_period_secs: cli.deescalate_after,
+            fp_suppression_secs: cli.fp_suppression,
+            ..LifecycleConfig::default()
+        })
+        .with_feedback(
+            FeedbackStore::new(Some(
+                cli.feedback_dataset
+                    .clone()
+                    .unwrap_or_else(|| cli.output.join("labeled_feedback.jsonl")),
+            ))
+            .with_state_path(cli.output.join("feedback_state.json")),
+        );
+    if let Some(path) = &cli.geo_policy {
+        let policy = GeoPolicy::load(path)?;
+        info!(
+            "Loaded geo policy version={} rules={} sanctions={}",
+            policy.version,
+            policy.rules.len(),
+            policy.sanctions.len()
+        );
+        engine = engine.with_geo_policy(policy);
+    }
+    if let Some(path) = &cli.allowlist {
+        let allowlist = AllowlistRegistry::load(path)?;
+        info!("Loaded allowlist entries={}", allowlist.len());
+        engine = engine.with_allowlist(allowlist);
+    }
+    std::fs::create_dir_all(&cli.output)?;
+    let sink_config = match &cli.sinks {
+        Some(path) => {
+            let cfg = SinkConfig::load(path)?;
+            info!(
+                "Loaded sink config sinks={} routes={}",
+                cfg.sinks.len(),
+                cfg.routes.len()
+            );
+            cfg
+        }
+        None => SinkConfig::file_only(&cli.output),
+    };
+    let mut chain = AuditChain::resume(
+        cli.audit_log
+            .as_deref()
+            .unwrap_or(&cli.output.join("audit_log.jsonl")),
+    )?
+    .with_signed_records(cli.audit_sign_records)
+    .with_checkpoint_every(cli.audit_checkpoint_every);
+    if let Some(spec) = &cli.audit_key {
+        let signer = AuditSigner::load(spec)?;
+        if let Some(pk) = signer.public_key_hex() {
+            info!("Audit log signing with Ed25519 public key {}", pk);
+        }
+        chain = chain.with_signer(signer);
+    }
+    info!("Audit chain continues at seq {}", chain.head().0);
+    let mut dispatcher = Dispatcher::from_config(&sink_config)?.with_audit_chain(chain);
+    if let Some(path) = &cli.rate_limit_policy {
+        dispatcher = dispatcher.with_rate_limit_policy(RateLimitPolicy::load(path)?);
+    }
+    if let Some(path) = &cli.notify {
+        let cfg = NotifierConfig::load(path)?;
+        info!("Loaded alert notifier channels={}", cfg.channels.len());
+        dispatcher = dispatcher.with_notifier(AlertNotifier::start(cfg)?);
+    }
+    let graph_config = match &cli.graph_config {
+        Some(path) => GraphConfig::load(path)?,
+        None => GraphConfig::default(),
+    };
+    let window_limits = match &cli.window_limits {
+        Some(path) => WindowLimits::load(path)?,
+        None => WindowLimits::default(),
+    };
+    let partition = match &cli.partition {
+        Some(path) => PartitionConfig::load(path)?,
+        None => PartitionConfig::default(),
+    };
+    if partition.instances > 1 {
+        info!(
+            "Partitioned mode: instance {}/{} peers={}",
+            partition.instance,
+            partition.instances,
+            partition.peers.len()
+        );
+    }
+    let store = StateStore::with_graph_config(graph_config).with_window_limits(window_limits);
+    match &cli.state_dir {
+        Some(dir) => {
+            let state = DiskBackend::open(store, dir)?;
+            let pipeline = Pipeline::new(dispatcher, engine, state)
+                .with_partition(partition)
+                .await?;
+            run(cli, pipeline).await
+        }
+        None => {
+            let pipeline = Pipeline::new(dispatcher, engine, store)
+                .with_partition(partition)
+                .await?;
+            run(cli, pipeline).await
+        }
+    }
+}
+
+/// Background tasks, the event source and the main consumer, on either
+/// state backend.
+async fn run<S: StateBackend>(cli: Cli, pipeline: Pipeline<S>) -> Result<()> {
+    let pipeline = Arc::new(pipeline);
     let start = Instant::now();
     let (tx, mut rx) = mpsc::channel::<ApiEvent>(16384);
 
@@ -251,9 +854,119 @@ async fn main() -> Result<()> {
     let store_stats = Arc::clone(&pipeline.store);
     tokio::spawn(print_stats_loop(store_stats, start));
 
+    // Enforcement lifecycle: de-escalation + transition log
+    tokio::spawn(Arc::clone(&pipeline).lifecycle_loop());
+
+    // Timed audit checkpoints
+    let dispatcher_cp = Arc::clone(&pipeline.dispatcher);
+    let checkpoint_every = std::time::Duration::from_secs(cli.audit_checkpoint_interval.max(1));
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(checkpoint_every);
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            dispatcher_cp.audit_checkpoint().await;
+        }
+    });
+
+    // Canary registry snapshots for offline attribution
+    let store_snap = Arc::clone(&pipeline.store);
+    let snapshot_path = cli.output.join("canary_registry.jsonl");
+    let snapshot_every = std::time::Duration::from_secs(cli.canary_snapshot_interval.max(1));
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(snapshot_every);
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            if let Err(e) =
+                attribution::write_registry_snapshot(store_snap.resident(), &snapshot_path)
+            {
+                error!("Canary registry snapshot failed: {}", e);
+            }
+        }
+    });
+
+    // Long-horizon rollups: restore, then snapshot periodically
+    let rollup_path = cli.output.join("rollups.jsonl");
+    if rollup_path.exists() {
+        let n = pipeline.store.resident().rollups().restore(&rollup_path)?;
+        info!("Restored rollups for {} accounts", n);
+    }
+    let store_rollup = Arc::clone(&pipeline.store);
+    let rollup_every = std::time::Duration::from_secs(cli.rollup_snapshot_interval.max(1));
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(rollup_every);
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            if let Err(e) = store_rollup
+                .resident()
+                .rollups()
+                .write_snapshot(&rollup_path)
+            {
+                error!("Rollup snapshot failed: {}", e);
+            }
+        }
+    });
+
+    // Analyst feedback: restore the effects of earlier labels, then watch
+    if pipeline.engine.feedback().restore(&pipeline.engine)? {
+        info!(
+            "Restored analyst feedback state (labels file offset {})",
+            pipeline.engine.feedback().labels_offset()
+        );
+    }
+    if let Some(path) = cli.feedback_path.clone() {
+        let p = Arc::clone(&pipeline);
+        tokio::spawn(async move {
+            if let Err(e) = tail_labels(path, p).await {
+                error!("Analyst label watch failed: {}", e);
+            }
+        });
+    }
+
+    // Query API: gateway CheckAccount, and the authenticated admin listener
+    if cli.grpc_addr.is_some() || cli.admin_addr.is_some() {
+        let mut server =
+            QueryServer::new(Arc::clone(&pipeline.store), Arc::clone(&pipeline.engine));
+        if let Some(path) = &cli.admin_tokens {
+            let tokens = AdminTokens::load(path)?;
+            info!("Loaded admin API tokens analysts={}", tokens.len());
+            server = server.with_admin_tokens(tokens);
+        }
+        let server = Arc::new(server);
+        if let Some(addr) = cli.grpc_addr {
+            let srv = Arc::clone(&server);
+            tokio::spawn(async move {
+                if let Err(e) = srv.serve(addr).await {
+                    error!("Query API failed: {}", e);
+                }
+            });
+        }
+        if let Some(addr) = cli.admin_addr {
+            let srv = Arc::clone(&server);
+            tokio::spawn(async move {
+                if let Err(e) = srv.serve_admin(addr).await {
+                    error!("Admin API failed: {}", e);
+                }
+            });
+        }
+    }
+
+    // Prometheus /metrics: pipeline counters, supernodes, window memory
+    let metrics_server = Arc::new(MetricsServer::new(
+        Arc::clone(&pipeline.metrics),
+        cli.metrics_addr,
+    ));
+    let store_metrics = Arc::clone(&pipeline.store);
+    tokio::spawn(async move {
+        if let Err(e) = metrics_server.serve(store_metrics).await {
+            error!("Metrics endpoint failed: {}", e);
+        }
+    });
+
     // Housekeeping
-    let store_hk = Arc::clone(&pipeline.store);
-    tokio::spawn(store_hk.housekeeping_loop());
+    tokio::spawn(backend::housekeeping_loop(Arc::clone(&pipeline.store)));
 
     // Event source
     let tx2 = tx.clone();
@@ -323,6 +1036,92 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+// ── Subcommands ───────────────────────────────────────────────────────────────
+
+fn run_command(command: &Command) -> Result<()> {
+    match command {
+        Command::VerifyAudit { path, key } => {
+            let key = key.as_deref().map(AuditVerifier::load).transpose()?;
+            let file = std::fs::File::open(path)?;
+            let report = audit::verify(std::io::BufReader::new(file), key.as_ref())?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.ok() {
+                anyhow::bail!(
+                    "audit log {} failed verification ({} issues)",
+                    path.display(),
+                    report.issues.len()
+                );
+            }
+            Ok(())
+        }
+        Command::AttributeDataset {
+            path,
+            registry,
+            robust_key,
+        } => {
+            let registry = attribution::load_registry(registry)?;
+            let robust = robust_key
+                .as_deref()
+                .map(RobustWatermark::load)
+                .transpose()?;
+            let report = attribution::scan_file(path, &registry, robust.as_ref())?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            Ok(())
+        }
+        Command::DetectWatermark {
+            path,
+            key,
+            accounts,
+            alpha,
+        } => {
+            let wm = RobustWatermark::load(key)?;
+            let candidates = robust_watermark::load_accounts(accounts)?;
+            let mut report = robust_watermark::detect_file(&wm, path, &candidates)?;
+            report
+                .attributions
+                .retain(|a| a.p_value_bonferroni < *alpha);
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            Ok(())
+        }
+        Command::ExportGraph {
+            path,
+            cluster,
+            format,
+            out,
+            graph_config,
+        } => {
+            let graph = match graph_config {
+                Some(p) => GraphConfig::load(p)?,
+                None => GraphConfig::default(),
+            };
+            let store = StateStore::with_graph_config(graph);
+            let content = std::fs::read_to_string(path)?;
+            let mut n = 0;
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                match serde_json::from_str::<ApiEvent>(line) {
+                    Ok(ev) => {
+                        store.ingest(&ev);
+                        n += 1;
+                    }
+                    Err(e) => warn!("Parse error: {}", e),
+                }
+            }
+            store.recompute_clusters();
+            let export = GraphExport::build(&store, *cluster)?;
+            for p in export.write(*format, out)? {
+                println!("{}", p.display());
+            }
+            info!(
+                "Exported {} nodes / {} edges from {} events",
+                export.nodes.len(),
+                export.edges.len(),
+                n
+            );
+            Ok(())
+        }
+    }
+}
+
 async fn replay_jsonl(path: PathBuf, tx: mpsc::Sender<ApiEvent>, speed: f64) -> Result<()> {
     let content = tokio::fs::read_to_string(&path).await?;
     let mut events: Vec<(f64, ApiEvent)> = Vec::new();
@@ -0,0 +1,259 @@
+// glasswally/src/net.rs
+//
+// Typed IP CIDR blocks (IPv4 + IPv6), serialized as "203.0.113.0/24" /
+// "2001:db8::/32".  Used by the allowlist registry (engine/allowlist.rs) and
+// for subnet aggregation (SubnetPrefixes: the account graph's subnet index,
+// cluster fusion and IOC bundles).
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct IpCidr {
+    addr: IpAddr, // network address (host bits zeroed)
+    prefix: u8,
+}
+
+impl IpCidr {
+    /// Build a CIDR, zeroing host bits; None if the prefix exceeds the family width.
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let width = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > width {
+            return None;
+        }
+        Some(Self {
+            addr: mask(addr, prefix),
+            prefix,
+        })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        ip.is_ipv4() == self.addr.is_ipv4() && mask(*ip, self.prefix) == self.addr
+    }
+}
+
+/// Prefix lengths accounts are aggregated to when comparing subnets:
+/// /24 and /64 by default, /16 and /48 for coarser grouping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubnetPrefixes {
+    pub v4: u8,
+    pub v6: u8,
+}
+
+impl Default for SubnetPrefixes {
+    fn default() -> Self {
+        Self { v4: 24, v6: 64 }
+    }
+}
+
+impl SubnetPrefixes {
+    /// Reject prefixes outside 1–32 (IPv4) / 1–128 (IPv6): `of` would return
+    /// None for every address (or one subnet for all of them at /0), silently
+    /// turning subnet correlation off.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=32).contains(&self.v4) {
+            return Err(format!("subnet prefix v4 /{} is outside 1–32", self.v4));
+        }
+        if !(1..=128).contains(&self.v6) {
+            return Err(format!("subnet prefix v6 /{} is outside 1–128", self.v6));
+        }
+        Ok(())
+    }
+
+    pub fn of(&self, ip: IpAddr) -> Option<IpCidr> {
+        IpCidr::new(ip, if ip.is_ipv4() { self.v4 } else { self.v6 })
+    }
+
+    /// Subnets of the parseable addresses in `ips`.
+    pub fn of_all<'a>(
+        &self,
+        ips: impl IntoIterator<Item = &'a String>,
+    ) -> std::collections::HashSet<IpCidr> {
+        ips.into_iter()
+            .filter_map(|ip| ip.parse::<IpAddr>().ok())
+            .filter_map(|ip| self.of(ip))
+            .collect()
+    }
+}
+
+fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4);
+            let m = if prefix == 0 {
+                0
+            } else {
+                u32::MAX << (32 - prefix as u32)
+            };
+            IpAddr::V4((bits & m).into())
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6);
+            let m = if prefix == 0 {
+                0
+            } else {
+                u128::MAX << (128 - prefix as u32)
+            };
+            IpAddr::V6((bits & m).into())
+        }
+    }
+}
+
+impl std::fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = String;
+
+    /// "10.0.0.0/8", or a bare address (host route).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip, prefix) = match s.split_once('/') {
+            Some((ip, p)) => (ip, Some(p)),
+            None => (s, None),
+        };
+        let addr: IpAddr = ip
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid CIDR {s}: {e}"))?;
+        let prefix = match prefix {
+            Some(p) => p
+                .trim()
+                .parse::<u8>()
+                .map_err(|e| format!("invalid CIDR {s}: {e}"))?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(addr, prefix).ok_or_else(|| format!("invalid CIDR {s}: prefix too long"))
+    }
+}
+
+impl Serialize for IpCidr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpCidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_masks_cidrs() {
+        let c: IpCidr = "203.0.113.77/24".parse().unwrap();
+        assert_eq!(c.addr(), ip("203.0.113.0"));
+        assert_eq!(c.prefix(), 24);
+        assert_eq!(c.to_string(), "203.0.113.0/24");
+
+        let c: IpCidr = " 2001:db8:abcd:12::1 / 48".parse().unwrap();
+        assert_eq!(c.to_string(), "2001:db8:abcd::/48");
+
+        // Bare addresses are host routes.
+        assert_eq!("198.51.100.9".parse::<IpCidr>().unwrap().prefix(), 32);
+        assert_eq!("2001:db8::9".parse::<IpCidr>().unwrap().prefix(), 128);
+        assert_eq!("0.0.0.0/0".parse::<IpCidr>().unwrap().addr(), ip("0.0.0.0"));
+
+        for bad in [
+            "10.0.0.0/33",
+            "2001:db8::/129",
+            "10.0.0.0/x",
+            "example.com/24",
+            "",
+        ] {
+            assert!(bad.parse::<IpCidr>().is_err(), "{bad}");
+        }
+        assert!(IpCidr::new(ip("10.0.0.1"), 40).is_none());
+
+        // Serialized as the string form.
+        let c: IpCidr = serde_json::from_str("\"100.64.0.0/10\"").unwrap();
+        assert_eq!(serde_json::to_string(&c).unwrap(), "\"100.64.0.0/10\"");
+    }
+
+    #[test]
+    fn contains_respects_prefix_and_family() {
+        let c: IpCidr = "100.64.0.0/10".parse().unwrap();
+        assert!(c.contains(&ip("100.64.0.1")));
+        assert!(c.contains(&ip("100.127.255.255")));
+        assert!(!c.contains(&ip("100.128.0.0")));
+        assert!(!c.contains(&ip("::ffff:100.64.0.1"))); // IPv6 never matches IPv4
+
+        let all: IpCidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(&ip("198.51.100.1")));
+        assert!(!all.contains(&ip("2001:db8::1")));
+
+        let host: IpCidr = "2001:db8::1".parse().unwrap();
+        assert!(host.contains(&ip("2001:db8::1")));
+        assert!(!host.contains(&ip("2001:db8::2")));
+    }
+
+    #[test]
+    fn aggregates_v6_to_64_and_v4_to_24() {
+        let p = SubnetPrefixes::default();
+        assert_eq!(
+            p.of(ip("2001:db8:1:2:aaaa::1")),
+            p.of(ip("2001:db8:1:2:ffff:ffff:ffff:ffff"))
+        );
+        assert_ne!(p.of(ip("2001:db8:1:2::1")), p.of(ip("2001:db8:1:3::1")));
+        assert_eq!(
+            p.of(ip("2001:db8:1:2::1")).unwrap().to_string(),
+            "2001:db8:1:2::/64"
+        );
+        assert_eq!(
+            p.of(ip("203.0.113.200")).unwrap().to_string(),
+            "203.0.113.0/24"
+        );
+
+        let ips: Vec<String> = [
+            "2001:db8:1:2::1",
+            "2001:db8:1:2::2",
+            "2001:db8:1:3::1",
+            "203.0.113.1",
+            "203.0.113.2",
+            "not-an-ip",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        assert_eq!(p.of_all(&ips).len(), 3);
+        let coarse = SubnetPrefixes { v4: 16, v6: 48 };
+        assert_eq!(coarse.of_all(&ips).len(), 2);
+    }
+
+    #[test]
+    fn rejects_out_of_range_prefixes() {
+        assert!(SubnetPrefixes::default().validate().is_ok());
+        assert!(SubnetPrefixes { v4: 32, v6: 128 }.validate().is_ok());
+        for bad in [
+            SubnetPrefixes { v4: 40, v6: 64 },
+            SubnetPrefixes { v4: 24, v6: 200 },
+            SubnetPrefixes { v4: 0, v6: 64 },
+            SubnetPrefixes { v4: 24, v6: 0 },
+        ] {
+            assert!(bad.validate().is_err(), "{bad:?}");
+        }
+    }
+}
@@ -14,13 +14,17 @@
 //   glasswally_composite_score             Histogram — fused composite score distribution
 //   glasswally_accounts_active             Gauge    — current active account windows
 //   glasswally_clusters_active             Gauge    — current active clusters
+//   glasswally_window_bytes                Gauge    — estimated account window memory
+//   glasswally_window_budget_bytes         Gauge    — account window memory budget
+//   glasswally_accounts_evicted_total      Counter  — idle windows evicted by the limits
+//   glasswally_rollup_bytes                Gauge    — estimated long-horizon rollup memory
 //   glasswally_shed_total                  Counter  — events shed by load shedder
 //   glasswally_kafka_published_total       Counter  — messages published to Kafka
 //   glasswally_redis_checkpoint_latency_ms Histogram — checkpoint write latency
 //   glasswally_ioc_bundles_published_total Counter  — IOC bundles published
 //   glasswally_canaries_triggered_total    Counter  — canary tokens triggered
 //
-// Prometheus endpoint: GET /metrics (default port 9091)
+// Prometheus endpoint: GET /metrics on --metrics-addr (default 127.0.0.1:9090)
 //
 // Dependencies (add to glasswally/Cargo.toml to enable):
 //   opentelemetry      = { version = "0.23", features = ["metrics"] }
@@ -38,6 +42,7 @@ use tokio::net::TcpListener;
 use tracing::info;
 
 use crate::events::{DetectionSignal, RiskTier};
+use crate::state::backend::StateBackend;
 
 // ── Metrics registry ──────────────────────────────────────────────────────────
 
@@ -207,6 +212,81 @@ impl GlasswallMetrics {
     }
 }
 
+// ── Supernode suppression (state/supernode.rs) ─────────────────────────────────
+
+const SUPERNODE_TOP_N: usize = 20; // per-value series, bounded for cardinality
+
+fn supernode_text(store: &crate::state::window::StateStore) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP glasswally_supernode_values_suppressed_total Infrastructure values that became supernodes and stopped linking\n");
+    out.push_str("# TYPE glasswally_supernode_values_suppressed_total counter\n");
+    for (kind, n) in store.suppressed_value_count() {
+        out.push_str(&format!(
+            "glasswally_supernode_values_suppressed_total{{kind=\"{}\"}} {}\n",
+            format!("{:?}", kind).to_lowercase(),
+            n
+        ));
+    }
+
+    let values = store.suppressed_values();
+    let mut per_kind: std::collections::BTreeMap<(String, String), usize> = Default::default();
+    for v in &values {
+        *per_kind
+            .entry((
+                format!("{:?}", v.kind).to_lowercase(),
+                v.reason.as_str().to_string(),
+            ))
+            .or_default() += 1;
+    }
+    out.push_str("# HELP glasswally_supernode_values Infrastructure values currently down-weighted or suppressed\n");
+    out.push_str("# TYPE glasswally_supernode_values gauge\n");
+    for ((kind, reason), n) in per_kind {
+        out.push_str(&format!(
+            "glasswally_supernode_values{{kind=\"{}\",reason=\"{}\"}} {}\n",
+            kind, reason, n
+        ));
+    }
+
+    out.push_str(
+        "# HELP glasswally_supernode_accounts Accounts sharing a suppressed value (top values)\n",
+    );
+    out.push_str("# TYPE glasswally_supernode_accounts gauge\n");
+    for v in values.iter().take(SUPERNODE_TOP_N) {
+        out.push_str(&format!(
+            "glasswally_supernode_accounts{{kind=\"{}\",value=\"{}\"}} {}\n",
+            format!("{:?}", v.kind).to_lowercase(),
+            v.value.replace('\\', "\\\\").replace('"', "\\\""),
+            v.accounts
+        ));
+    }
+    out
+}
+
+// ── Account window memory (state/window.rs) ────────────────────────────────────
+
+fn memory_text(store: &crate::state::window::StateStore) -> String {
+    format!(
+        "# HELP glasswally_window_bytes Estimated account window memory (refreshed by housekeeping)\n\
+         # TYPE glasswally_window_bytes gauge\n\
+         glasswally_window_bytes {}\n\
+         # HELP glasswally_window_budget_bytes Account window memory budget\n\
+         # TYPE glasswally_window_budget_bytes gauge\n\
+         glasswally_window_budget_bytes {}\n\
+         # HELP glasswally_accounts_evicted_total Idle account windows evicted by the memory limits\n\
+         # TYPE glasswally_accounts_evicted_total counter\n\
+         glasswally_accounts_evicted_total {}\n\
+         # HELP glasswally_rollup_bytes Estimated long-horizon rollup memory\n\
+         # TYPE glasswally_rollup_bytes gauge\n\
+         glasswally_rollup_bytes {}\n",
+        store.window_bytes(),
+        store.window_limits().memory_budget_bytes,
+        store
+            .evicted_accounts
+            .load(std::sync::atomic::Ordering::Relaxed),
+        store.rollups().approx_bytes()
+    )
+}
+
 // ── HTTP /metrics endpoint ─────────────────────────────────────────────────────
 
 pub struct MetricsServer {
@@ -219,10 +299,7 @@ impl MetricsServer {
         Self { metrics, addr }
     }
 
-    pub async fn serve(
-        self: Arc<Self>,
-        store: Arc<crate::state::window::StateStore>,
-    ) -> Result<()> {
+    pub async fn serve<S: StateBackend>(self: Arc<Self>, store: Arc<S>) -> Result<()> {
         let listener = TcpListener::bind(self.addr).await?;
         info!("OTel /metrics endpoint listening on {}", self.addr);
 
@@ -232,7 +309,9 @@ impl MetricsServer {
             let store = Arc::clone(&store);
 
             tokio::spawn(async move {
-                let body = metrics.prometheus_text(store.n_accounts(), store.n_clusters());
+                let mut body = metrics.prometheus_text(store.n_accounts(), store.n_clusters());
+                body.push_str(&supernode_text(store.resident()));
+                body.push_str(&memory_text(store.resident()));
                 let response = format!(
                     "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
                     body.len(), body
@@ -11,7 +11,10 @@
 // on every restart, requiring hours of traffic before detection resumes.
 //
 // Data layout in Redis:
-//   gw:account:{account_id}:window   — JSON-serialized AccountWindow (TTL = 7 days)
+//   gw:account:{account_id}:window   — JSON-serialized AccountWindow incl. decayed
+//                                      account risk (TTL = 7 days)
+//   gw:account:{account_id}:rollup   — JSON hourly / daily rollup (state/rollup.rs,
+//                                      TTL = rollup daily retention)
 //   gw:cluster:{cluster_id}:members  — SMEMBERS set of account_ids
 //   gw:account:{account_id}:cluster  — cluster_id string
 //   gw:ja3:{ja3_hash}:accounts       — SMEMBERS set of account_ids
@@ -0,0 +1,395 @@
+// glasswally/src/response_rewriter.rs
+//
+// Response rewriting for watermarked accounts.
+//
+// INJECT_CANARY marks an account as watermarked (engine/dispatcher.rs).  This
+// component is what actually changes what that account receives:
+//
+//   1. the account's ZWJ/ZWNJ watermark (workers/watermark.rs `embed`) is
+//      woven into every text field of the response, continuing the bit
+//      sequence across streamed chunks;
+//   2. with a robust watermark configured (robust_watermark.rs), synonym /
+//      punctuation choices are keyed to the account as well — this survives
+//      the normalization that strips zero-width characters;
+//   3. a fresh canary token is minted per response, bound to the real
+//      request_id, and inserted once — encoded invisibly
+//      (watermark::encode_canary) after the first space of the completion and
+//      that space's watermark mark, so the ZWJ/ZWNJ bits stay aligned.  It is
+//      registered with the state backend only once inserted; a response with no
+//      space leaves nothing to attribute and registers nothing.
+//
+// Library API:
+//   let mut s = rewriter.begin(account_id, request_id)?;   // None → pass through
+//   s.rewrite_body(&body, content_type)                     // buffered response
+//   s.feed(chunk) / s.finish()                              // streamed response
+//
+// Reverse-proxy filter: call `begin` on response headers, then `feed` for each
+// body chunk and `finish` at end of stream, forwarding whatever they return.
+// Content-Length must be dropped (or recomputed for buffered bodies).
+//
+// Streaming: `text/event-stream` bodies are re-framed on line boundaries, so a
+// `data:` line split across network chunks is held back until complete.  Each
+// data payload that parses as JSON has its text fields rewritten and is
+// re-serialized on one line; anything else (`[DONE]`, comments, `event:`
+// lines) is forwarded byte for byte.  Other content types are rewritten as a
+// whole JSON document (application/json) or as raw text (text/*), and left
+// untouched otherwise.
+//
+// Text fields are string values under the configured keys — by default
+// "content" and "text", which covers OpenAI chat / completion chunks
+// (choices[].delta.content, choices[].text) and Anthropic messages
+// (content[].text, delta.text).
+
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::events::CanaryToken;
+use crate::robust_watermark::{EmbedState, RobustWatermark};
+use crate::state::backend::StateBackend;
+use crate::workers::watermark;
+
+#[derive(Clone)]
+pub struct RewriterConfig {
+    pub text_keys: Vec<String>,
+    pub inject_canary: bool,
+    pub watermark: bool,
+    pub robust: Option<Arc<RobustWatermark>>,
+}
+
+impl Default for RewriterConfig {
+    fn default() -> Self {
+        Self {
+            text_keys: vec!["content".into(), "text".into()],
+            inject_canary: true,
+            watermark: true,
+            robust: None,
+        }
+    }
+}
+
+pub struct ResponseRewriter<S: StateBackend> {
+    store: Arc<S>,
+    cfg: Arc<RewriterConfig>,
+}
+
+impl<S: StateBackend> ResponseRewriter<S> {
+    pub fn new(store: Arc<S>) -> Self {
+        Self {
+            store,
+            cfg: Arc::new(RewriterConfig::default()),
+        }
+    }
+
+    pub fn with_config(mut self, cfg: RewriterConfig) -> Self {
+        self.cfg = Arc::new(cfg);
+        self
+    }
+
+    /// Start rewriting one response.  None when the account is not
+    /// watermarked — the caller forwards the response untouched.
+    pub fn begin(&self, account_id: &str, request_id: &str) -> Option<ResponseSession<S>> {
+        if !self.store.is_watermarked(account_id) {
+            return None;
+        }
+        let canary = self
+            .cfg
+            .inject_canary
+            .then(|| CanaryToken::generate(account_id, request_id));
+        Some(ResponseSession {
+            store: Arc::clone(&self.store),
+            cfg: Arc::clone(&self.cfg),
+            account_id: account_id.to_string(),
+            canary,
+            canary_embedded: false,
+            bit: 0,
+            robust_state: EmbedState::default(),
+            line_buf: Vec::new(),
+        })
+    }
+}
+
+pub struct ResponseSession<S: StateBackend> {
+    store: Arc<S>,
+    cfg: Arc<RewriterConfig>,
+    account_id: String,
+    canary: Option<CanaryToken>, // minted; registered once embedded
+    canary_embedded: bool,
+    bit: usize, // next watermark bit
+    robust_state: EmbedState,
+    line_buf: Vec<u8>, // incomplete SSE line
+}
+
+impl<S: StateBackend> ResponseSession<S> {
+    /// Canary token embedded in (and registered for) this response so far.
+    pub fn canary(&self) -> Option<&CanaryToken> {
+        self.canary.as_ref().filter(|_| self.canary_embedded)
+    }
+
+    /// Watermark (and canary) one piece of completion text.
+    pub fn rewrite_text(&mut self, text: &str) -> String {
+        let text = match &self.cfg.robust {
+            Some(wm) => wm.embed_chunk(text, &self.account_id, &mut self.robust_state),
+            None => text.to_string(),
+        };
+        let mut text = if self.cfg.watermark {
+            let (out, next) = watermark::embed_from(&text, &self.account_id, self.bit);
+            self.bit = next;
+            out
+        } else {
+            text
+        };
+        if !self.canary_embedded {
+            if let (Some(token), Some(pos)) = (&self.canary, text.find(' ')) {
+                // After the space's watermark mark, never between the two.
+                let mut at = pos + 1;
+                if let Some(mark) = text[at..].chars().next().filter(|&c| watermark::is_mark(c)) {
+                    at += mark.len_utf8();
+                }
+                text.insert_str(at, &watermark::encode_canary(&token.token));
+                self.store.register_canary(token.clone());
+                self.canary_embedded = true;
+            }
+        }
+        text
+    }
+
+    fn rewrite_json(&mut self, v: &mut Value) {
+        match v {
+            Value::Object(map) => {
+                for (k, child) in map.iter_mut() {
+                    match child {
+                        Value::String(s) if self.cfg.text_keys.iter().any(|t| t == k) => {
+                            *s = self.rewrite_text(s);
+                        }
+                        _ => self.rewrite_json(child),
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    self.rewrite_json(item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Rewrite a complete (non-streamed) body.
+    pub fn rewrite_body(&mut self, body: &[u8], content_type: &str) -> Vec<u8> {
+        let ct = content_type.to_ascii_lowercase();
+        if ct.starts_with("text/event-stream") {
+            let mut out = self.feed(body);
+            out.extend(self.finish());
+            return out;
+        }
+        if ct.contains("json") {
+            return match serde_json::from_slice::<Value>(body) {
+                Ok(mut v) => {
+                    self.rewrite_json(&mut v);
+                    serde_json::to_vec(&v).unwrap_or_else(|_| body.to_vec())
+                }
+                Err(_) => body.to_vec(),
+            };
+        }
+        if ct.starts_with("text/") {
+            if let Ok(s) = std::str::from_utf8(body) {
+                return self.rewrite_text(s).into_bytes();
+            }
+        }
+        body.to_vec()
+    }
+
+    /// Rewrite one chunk of a `text/event-stream` body; returns the bytes to
+    /// forward now (complete lines only).
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.line_buf.extend_from_slice(chunk);
+        let Some(last_nl) = self.line_buf.iter().rposition(|&b| b == b'\n') else {
+            return Vec::new();
+        };
+        let rest = self.line_buf.split_off(last_nl + 1);
+        let complete = std::mem::replace(&mut self.line_buf, rest);
+
+        let mut out = Vec::with_capacity(complete.len() + complete.len() / 4);
+        for line in complete.split_inclusive(|&b| b == b'\n') {
+            out.extend(self.rewrite_sse_line(line));
+        }
+        out
+    }
+
+    /// Flush a trailing line without a newline at end of stream.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let rest = std::mem::take(&mut self.line_buf);
+        if rest.is_empty() {
+            return rest;
+        }
+        self.rewrite_sse_line(&rest)
+    }
+
+    fn rewrite_sse_line(&mut self, line: &[u8]) -> Vec<u8> {
+        let body_end = line
+            .iter()
+            .rposition(|&b| b != b'\n' && b != b'\r')
+            .map_or(0, |i| i + 1);
+        let (content, eol) = line.split_at(body_end);
+        let Some(payload) = content.strip_prefix(b"data:") else {
+            return line.to_vec();
+        };
+        let payload = payload.strip_prefix(b" ").unwrap_or(payload);
+        let Ok(mut v) = serde_json::from_slice::<Value>(payload) else {
+            return line.to_vec(); // [DONE], keep-alives, non-JSON data
+        };
+        self.rewrite_json(&mut v);
+        let mut out = b"data: ".to_vec();
+        out.extend(serde_json::to_vec(&v).unwrap_or_else(|_| payload.to_vec()));
+        out.extend_from_slice(eol);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::window::StateStore;
+    use serde_json::json;
+
+    fn rewriter() -> (Arc<StateStore>, ResponseRewriter<StateStore>) {
+        let store = Arc::new(StateStore::new());
+        store.mark_watermarked("acct");
+        (Arc::clone(&store), ResponseRewriter::new(store))
+    }
+
+    fn marks(text: &str) -> Vec<bool> {
+        text.chars()
+            .filter(|&c| watermark::is_mark(c))
+            .map(|c| c == '\u{200D}')
+            .collect()
+    }
+
+    // Text as the client would see it with every invisible channel removed.
+    fn visible(text: &str) -> String {
+        text.chars()
+            .filter(|&c| !watermark::is_mark(c) && !('\u{2061}'..='\u{2063}').contains(&c))
+            .collect()
+    }
+
+    fn expected_marks(n: usize) -> Vec<bool> {
+        let bits = watermark::account_watermark_bits("acct");
+        (0..n).map(|i| bits[i % 32]).collect()
+    }
+
+    #[test]
+    fn canary_follows_the_watermark_mark() {
+        let (store, rw) = rewriter();
+        assert!(rw.begin("someone_else", "req").is_none());
+
+        let mut s = rw.begin("acct", "req").unwrap();
+        let out = s.rewrite_text("the quick brown fox");
+        let token = s.canary().expect("embedded").token.clone();
+
+        let after_space = out[out.find(' ').unwrap() + 1..].chars().next().unwrap();
+        assert!(watermark::is_mark(after_space));
+        assert_eq!(marks(&out), expected_marks(3));
+        assert_eq!(watermark::decode_canaries(&out), [token.as_str()]);
+        assert_eq!(visible(&out), "the quick brown fox");
+        assert!(store.lookup_canary(&token).is_some());
+    }
+
+    #[test]
+    fn canary_is_registered_only_once_embedded() {
+        let (store, rw) = rewriter();
+        let mut s = rw.begin("acct", "req").unwrap();
+        assert_eq!(s.rewrite_text("hello"), "hello");
+        assert!(s.canary().is_none());
+        assert!(store.canaries().is_empty());
+
+        // The first space may arrive in a later chunk.
+        let out = s.rewrite_text(" world");
+        assert_eq!(watermark::decode_canaries(&out).len(), 1);
+        assert_eq!(store.canaries().len(), 1);
+    }
+
+    #[test]
+    fn json_body_rewrites_text_fields_only() {
+        let (_, rw) = rewriter();
+        let mut s = rw.begin("acct", "req").unwrap();
+        let body = json!({
+            "id": "chatcmpl-1 x",
+            "model": "m",
+            "choices": [{"message": {"role": "assistant", "content": "a b c"}}],
+        });
+        let out = s.rewrite_body(&serde_json::to_vec(&body).unwrap(), "application/json");
+        let out: Value = serde_json::from_slice(&out).unwrap();
+
+        assert_eq!(out["id"], body["id"]);
+        assert_eq!(out["choices"][0]["message"]["role"], "assistant");
+        let content = out["choices"][0]["message"]["content"].as_str().unwrap();
+        assert_eq!(visible(content), "a b c");
+        assert_eq!(marks(content), expected_marks(2));
+        assert_eq!(watermark::decode_canaries(content).len(), 1);
+
+        let mut s = rw.begin("acct", "req").unwrap();
+        assert_eq!(s.rewrite_body(b"\x00\x01", "image/png"), b"\x00\x01");
+    }
+
+    #[test]
+    fn sse_stream_survives_any_chunk_boundary() {
+        let tool = json!({"choices": [{"delta": {"tool_calls": [
+            {"function": {"arguments": "{\"q\": \"a b\"}"}}
+        ]}}]});
+        let frames = [
+            json!({"choices": [{"delta": {"role": "assistant"}}]}),
+            json!({"choices": [{"delta": {"content": "héllo wörld "}}]}),
+            tool.clone(),
+            json!({"choices": [{"delta": {"content": "and more 词 words"}}]}),
+        ];
+        let mut body = String::new();
+        for f in &frames {
+            body.push_str(&format!("data: {f}\r\n\r\n"));
+        }
+        body.push_str(": keep-alive\n\ndata: [DONE]\n\n");
+        let body = body.as_bytes();
+
+        let (_, rw) = rewriter();
+        for split in 0..=body.len() {
+            let mut s = rw.begin("acct", "req").unwrap();
+            let mut out = s.feed(&body[..split]);
+            out.extend(s.feed(&body[split..]));
+            out.extend(s.finish());
+            let out = String::from_utf8(out).expect("utf-8 intact at every split");
+
+            let mut text = String::new();
+            let mut datas = Vec::new();
+            for line in out.lines() {
+                if let Some(d) = line.strip_prefix("data: ") {
+                    datas.push(d);
+                }
+            }
+            assert_eq!(datas.len(), 5, "split {split}");
+            assert_eq!(datas[4], "[DONE]");
+            assert!(out.contains("\n: keep-alive\n"));
+
+            let parsed: Vec<Value> = datas[..4]
+                .iter()
+                .map(|d| serde_json::from_str(d).unwrap())
+                .collect();
+            assert_eq!(parsed[0], frames[0]);
+            assert_eq!(parsed[2], tool, "non-text delta untouched");
+            for i in [1, 3] {
+                let content = parsed[i]["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .unwrap();
+                assert_eq!(
+                    visible(content),
+                    frames[i]["choices"][0]["delta"]["content"]
+                        .as_str()
+                        .unwrap()
+                );
+                text.push_str(content);
+            }
+            // Bits continue across frames; one canary in the whole stream.
+            assert_eq!(marks(&text), expected_marks(5), "split {split}");
+            assert_eq!(watermark::decode_canaries(&text).len(), 1);
+        }
+    }
+}
@@ -0,0 +1,651 @@
+// glasswally/src/robust_watermark.rs
+//
+// Statistical watermark that survives Unicode normalization and light
+// paraphrase.
+//
+// The ZWJ/ZWNJ watermark (workers/watermark.rs) is gone after NFKC, a
+// "strip whitespace" pass or any tokenizer.  This second scheme hides the
+// mark in *choices* the text makes anyway:
+//
+//   synonym      — interchangeable word pairs (often/frequently, maybe/perhaps, ...)
+//   punctuation  — "..." vs "…"
+//   whitespace   — one vs two spaces after a full stop (fragile; a bonus
+//                  when it survives)
+//
+// Each occurrence is a slot whose context is (previous word, slot kind), the
+// previous word taken in its base form when it is itself a synonym slot — so
+// the embedder (which sees the original word) and the detector (which sees
+// the variant written) derive the same context.  A
+// keyed PRF of the context picks a codeword position and a mask bit; the
+// variant written is codeword[pos] XOR mask.  The codeword is the account's
+// 64-bit payload (HMAC(key, account)) under a Hamming(7,4) code — 112 bits.
+// Keying by local context rather than position means reordering, deletions
+// and partial rewrites only remove slots; what is left still votes for the
+// same bits.
+//
+// Detection (offline, `glasswally detect-watermark`):
+//   1. every slot in the corpus votes (variant XOR mask) for its position;
+//   2. majority per position, Hamming-decode each 7-bit block (one error per
+//      block corrected) → recovered payload bits;
+//   3. for each candidate account, count payload bits that agree.  Under
+//      H0 (corpus not derived from that account's output) payload bits are
+//      independent fair coins, so p = P(Binomial(k, ½) ≥ matches) exactly,
+//      where k is the number of recovered bits.  A Bonferroni-adjusted
+//      p-value over the candidate set is reported alongside.
+//
+// A corpus mixing several accounts' output decodes to the dominant one.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+pub const PAYLOAD_BITS: usize = 64;
+pub const CODE_BITS: usize = PAYLOAD_BITS / 4 * 7; // Hamming(7,4)
+
+// (variant 0, variant 1).  Matching is by spelling, not part of speech, so
+// only pairs whose words have a single everyday role — adverbs, connectives,
+// one modal — are used; noun/verb/adjective words ("a fresh start", "the
+// show", "good enough", "a select few") would be swapped ungrammatically.
+// Idioms can still read slightly off, never wrong.
+const SYNONYMS: &[(&str, &str)] = &[
+    ("often", "frequently"),
+    ("maybe", "perhaps"),
+    ("usually", "typically"),
+    ("therefore", "thus"),
+    ("cannot", "can't"),
+    ("mostly", "largely"),
+    ("also", "additionally"),
+    ("quickly", "rapidly"),
+    ("almost", "nearly"),
+    ("completely", "entirely"),
+    ("especially", "particularly"),
+    ("approximately", "roughly"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotKind {
+    Synonym(usize), // index into SYNONYMS
+    Ellipsis,
+    SentenceSpace,
+}
+
+impl SlotKind {
+    fn tag(&self) -> String {
+        match self {
+            Self::Synonym(i) => format!("syn{}", i),
+            Self::Ellipsis => "ellipsis".into(),
+            Self::SentenceSpace => "space".into(),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '\''
+}
+
+fn synonym_of(word: &str) -> Option<(usize, bool)> {
+    let lower = word.to_lowercase();
+    SYNONYMS.iter().enumerate().find_map(|(i, (a, b))| {
+        if lower == *a {
+            Some((i, false))
+        } else if lower == *b {
+            Some((i, true))
+        } else {
+            None
+        }
+    })
+}
+
+// Slot context for a word: its base form if it is a synonym slot.
+fn context_word(word: &str) -> String {
+    match synonym_of(word) {
+        Some((idx, _)) => SYNONYMS[idx].0.to_string(),
+        None => word.to_lowercase(),
+    }
+}
+
+fn match_case(template: &str, word: &str) -> String {
+    let mut chars = template.chars();
+    match chars.next() {
+        Some(first) if first.is_uppercase() => {
+            if template.chars().filter(|c| c.is_alphabetic()).count() > 1
+                && template
+                    .chars()
+                    .filter(|c| c.is_alphabetic())
+                    .all(char::is_uppercase)
+            {
+                word.to_uppercase()
+            } else {
+                let mut w = word.chars();
+                w.next()
+                    .map(|f| f.to_uppercase().chain(w).collect())
+                    .unwrap_or_default()
+            }
+        }
+        _ => word.to_string(),
+    }
+}
+
+// Split into alternating word / separator runs.
+fn runs(text: &str) -> Vec<(bool, &str)> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut current: Option<bool> = None;
+    for (i, c) in text.char_indices() {
+        let w = is_word_char(c);
+        match current {
+            Some(prev) if prev == w => {}
+            Some(prev) => {
+                out.push((prev, &text[start..i]));
+                start = i;
+                current = Some(w);
+            }
+            None => current = Some(w),
+        }
+    }
+    if let Some(w) = current {
+        out.push((w, &text[start..]));
+    }
+    out
+}
+
+// Separator slot: which kind, and the variant bit it currently carries.
+fn separator_slot(sep: &str) -> Option<(SlotKind, bool)> {
+    if let Some(rest) = sep.strip_prefix("...") {
+        (rest.is_empty() || rest.starts_with(' ')).then_some((SlotKind::Ellipsis, false))
+    } else if let Some(rest) = sep.strip_prefix('…') {
+        (rest.is_empty() || rest.starts_with(' ')).then_some((SlotKind::Ellipsis, true))
+    } else if sep == ". " {
+        Some((SlotKind::SentenceSpace, false))
+    } else if sep == ".  " {
+        Some((SlotKind::SentenceSpace, true))
+    } else {
+        None
+    }
+}
+
+fn write_separator(kind: SlotKind, sep: &str, bit: bool) -> String {
+    match kind {
+        SlotKind::Ellipsis => {
+            let rest = sep
+                .strip_prefix("...")
+                .or_else(|| sep.strip_prefix('…'))
+                .unwrap_or("");
+            format!("{}{}", if bit { "…" } else { "..." }, rest)
+        }
+        SlotKind::SentenceSpace => if bit { ".  " } else { ". " }.to_string(),
+        SlotKind::Synonym(_) => sep.to_string(),
+    }
+}
+
+/// Streaming embed state: context carried across chunk boundaries.
+#[derive(Debug, Default, Clone)]
+pub struct EmbedState {
+    prev_word: String,
+    partial: String, // word fragment at the end of the last chunk
+}
+
+pub struct RobustWatermark {
+    key: Vec<u8>,
+}
+
+impl RobustWatermark {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    /// Raw key file contents.
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::new(std::fs::read(path).with_context(|| {
+            format!("reading watermark key {}", path.display())
+        })?))
+    }
+
+    fn prf(&self, label: &str, data: &str) -> u64 {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key");
+        mac.update(label.as_bytes());
+        mac.update(b":");
+        mac.update(data.as_bytes());
+        let out = mac.finalize().into_bytes();
+        u64::from_le_bytes(out[..8].try_into().expect("8 bytes"))
+    }
+
+    /// The account's 64 payload bits.
+    pub fn payload(&self, account_id: &str) -> [bool; PAYLOAD_BITS] {
+        let v = self.prf("payload", account_id);
+        std::array::from_fn(|i| (v >> i) & 1 == 1)
+    }
+
+    fn codeword(&self, account_id: &str) -> [bool; CODE_BITS] {
+        let data = self.payload(account_id);
+        let mut code = [false; CODE_BITS];
+        for (block, d) in data.chunks(4).enumerate() {
+            code[block * 7..block * 7 + 7]
+                .copy_from_slice(&hamming_encode([d[0], d[1], d[2], d[3]]));
+        }
+        code
+    }
+
+    // (codeword position, mask) for a slot context.
+    fn slot(&self, prev_word: &str, kind: SlotKind) -> (usize, bool) {
+        let ctx = format!("{}|{}", prev_word, kind.tag());
+        (
+            (self.prf("pos", &ctx) % CODE_BITS as u64) as usize,
+            self.prf("mask", &ctx) & 1 == 1,
+        )
+    }
+
+    /// Watermark a complete text for `account_id`.
+    pub fn embed(&self, text: &str, account_id: &str) -> String {
+        self.embed_chunk(text, account_id, &mut EmbedState::default())
+    }
+
+    /// Watermark one chunk of a streamed text.  A word split across chunks is
+    /// left unchanged (its slot reads as noise) but still serves as context.
+    pub fn embed_chunk(&self, text: &str, account_id: &str, st: &mut EmbedState) -> String {
+        let code = self.codeword(account_id);
+        let runs = runs(text);
+        let mut out = String::with_capacity(text.len() + 8);
+        for (i, (is_word, run)) in runs.iter().enumerate() {
+            let first = i == 0;
+            let last = i + 1 == runs.len();
+            if *is_word {
+                let continues_partial = first && !st.partial.is_empty();
+                let word = if continues_partial {
+                    format!("{}{}", st.partial, run)
+                } else {
+                    run.to_string()
+                };
+                if last {
+                    // May continue in the next chunk: leave it, carry it.
+                    out.push_str(run);
+                    st.partial = word;
+                    continue;
+                }
+                st.partial.clear();
+                match synonym_of(&word).filter(|_| !continues_partial) {
+                    Some((idx, _)) => {
+                        let (pos, mask) = self.slot(&st.prev_word, SlotKind::Synonym(idx));
+                        let bit = code[pos] ^ mask;
+                        let (a, b) = SYNONYMS[idx];
+                        out.push_str(&match_case(&word, if bit { b } else { a }));
+                    }
+                    None => out.push_str(run),
+                }
+                st.prev_word = context_word(&word);
+            } else {
+                if !st.partial.is_empty() {
+                    st.prev_word = context_word(&st.partial);
+                    st.partial.clear();
+                }
+                match separator_slot(run) {
+                    Some((kind, _)) if !last => {
+                        let (pos, mask) = self.slot(&st.prev_word, kind);
+                        out.push_str(&write_separator(kind, run, code[pos] ^ mask));
+                    }
+                    _ => out.push_str(run),
+                }
+            }
+        }
+        out
+    }
+
+    /// Add one text's slot votes to `votes` (per position: ones − zeros).
+    pub fn vote(&self, text: &str, votes: &mut Votes) {
+        let runs = runs(text);
+        let mut prev = String::new();
+        for (i, (is_word, run)) in runs.iter().enumerate() {
+            let slot = if *is_word {
+                synonym_of(run).map(|(idx, bit)| (SlotKind::Synonym(idx), bit))
+            } else if i + 1 < runs.len() {
+                separator_slot(run)
+            } else {
+                None
+            };
+            if let Some((kind, bit)) = slot {
+                let (pos, mask) = self.slot(&prev, kind);
+                votes.tally[pos] += if bit ^ mask { 1 } else { -1 };
+                votes.slots += 1;
+            }
+            if *is_word {
+                prev = context_word(run);
+            }
+        }
+    }
+
+    /// Candidate accounts ranked by p-value against the decoded payload.
+    pub fn attribute(&self, votes: &Votes, candidates: &[String]) -> Vec<Attribution> {
+        let decoded = votes.decode();
+        let k = decoded.iter().filter(|b| b.is_some()).count();
+        let n = candidates.len().max(1) as f64;
+        let mut out: Vec<Attribution> = candidates
+            .iter()
+            .map(|account| {
+                let expected = self.payload(account);
+                let matches = decoded
+                    .iter()
+                    .zip(expected.iter())
+                    .filter(|(d, e)| **d == Some(**e))
+                    .count();
+                let p_value = binomial_tail(k, matches);
+                Attribution {
+                    account_id: account.clone(),
+                    recovered_bits: k,
+                    matching_bits: matches,
+                    p_value,
+                    p_value_bonferroni: (p_value * n).min(1.0),
+                }
+            })
+            .collect();
+        out.sort_by(|a, b| a.p_value.total_cmp(&b.p_value));
+        out
+    }
+}
+
+/// Per-position votes accumulated over a corpus.
+#[derive(Debug, Clone)]
+pub struct Votes {
+    pub tally: [i64; CODE_BITS],
+    pub slots: u64,
+}
+
+impl Default for Votes {
+    fn default() -> Self {
+        Self {
+            tally: [0; CODE_BITS],
+            slots: 0,
+        }
+    }
+}
+
+impl Votes {
+    /// Majority-decode each position, then Hamming-correct each block.
+    /// Blocks with an unvoted position yield only their voted data bits.
+    pub fn decode(&self) -> Vec<Option<bool>> {
+        let bits: Vec<Option<bool>> = self
+            .tally
+            .iter()
+            .map(|&t| (t != 0).then_some(t > 0))
+            .collect();
+        let mut out = Vec::with_capacity(PAYLOAD_BITS);
+        for block in bits.chunks(7) {
+            if block.iter().all(Option::is_some) {
+                let b: [bool; 7] = std::array::from_fn(|i| block[i].unwrap());
+                out.extend(hamming_decode(b).map(Some));
+            } else {
+                // Data bits sit at codeword positions 2, 4, 5, 6.
+                out.extend([block[2], block[4], block[5], block[6]]);
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Attribution {
+    pub account_id: String,
+    pub recovered_bits: usize,
+    pub matching_bits: usize,
+    pub p_value: f64,
+    pub p_value_bonferroni: f64,
+}
+
+// ── Hamming(7,4) ──────────────────────────────────────────────────────────────
+// Codeword layout p1 p2 d1 p3 d2 d3 d4 (positions 1..7).
+
+fn hamming_encode(d: [bool; 4]) -> [bool; 7] {
+    let p1 = d[0] ^ d[1] ^ d[3];
+    let p2 = d[0] ^ d[2] ^ d[3];
+    let p3 = d[1] ^ d[2] ^ d[3];
+    [p1, p2, d[0], p3, d[1], d[2], d[3]]
+}
+
+fn hamming_decode(mut c: [bool; 7]) -> [bool; 4] {
+    let s1 = c[0] ^ c[2] ^ c[4] ^ c[6];
+    let s2 = c[1] ^ c[2] ^ c[5] ^ c[6];
+    let s3 = c[3] ^ c[4] ^ c[5] ^ c[6];
+    let syndrome = (s1 as usize) | (s2 as usize) << 1 | (s3 as usize) << 2;
+    if syndrome != 0 {
+        c[syndrome - 1] = !c[syndrome - 1];
+    }
+    [c[2], c[4], c[5], c[6]]
+}
+
+/// P(Binomial(n, ½) ≥ k), exact.
+pub fn binomial_tail(n: usize, k: usize) -> f64 {
+    if k == 0 {
+        return 1.0;
+    }
+    if k > n {
+        return 0.0;
+    }
+    // log C(n, i) incrementally; sum the tail in log space.
+    let ln2 = std::f64::consts::LN_2;
+    let mut log_c = vec![0.0f64; n + 1];
+    for i in 1..=n {
+        log_c[i] = log_c[i - 1] + ((n - i + 1) as f64).ln() - (i as f64).ln();
+    }
+    let terms: Vec<f64> = (k..=n).map(|i| log_c[i] - n as f64 * ln2).collect();
+    let max = terms.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    (max + terms.iter().map(|t| (t - max).exp()).sum::<f64>().ln())
+        .exp()
+        .min(1.0)
+}
+
+// ── Corpus scanning ───────────────────────────────────────────────────────────
+
+/// Text fields read from JSONL rows; non-JSON lines are scanned whole.
+pub const TEXT_KEYS: &[&str] = &["text", "content", "completion", "response", "output"];
+
+/// Collect the text to scan from one corpus line.
+pub fn line_texts(line: &str) -> Vec<String> {
+    fn walk(v: &serde_json::Value, out: &mut Vec<String>) {
+        match v {
+            serde_json::Value::Object(m) => {
+                for (k, child) in m {
+                    match child {
+                        serde_json::Value::String(s) if TEXT_KEYS.contains(&k.as_str()) => {
+                            out.push(s.clone())
+                        }
+                        _ => walk(child, out),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => items.iter().for_each(|i| walk(i, out)),
+            _ => {}
+        }
+    }
+    match serde_json::from_str::<serde_json::Value>(line) {
+        Ok(v) if v.is_object() || v.is_array() => {
+            let mut out = Vec::new();
+            walk(&v, &mut out);
+            out
+        }
+        _ => vec![line.to_string()],
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DetectReport {
+    pub rows: u64,
+    pub slots: u64,
+    pub recovered_bits: usize,
+    pub attributions: Vec<Attribution>,
+}
+
+/// Offline detection over a dataset file (JSONL or plain text).
+pub fn detect_file(
+    wm: &RobustWatermark,
+    path: &Path,
+    candidates: &[String],
+) -> Result<DetectReport> {
+    use std::io::BufRead;
+    let file = std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut votes = Votes::default();
+    let mut rows = 0u64;
+    for line in std::io::BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        rows += 1;
+        for text in line_texts(&line) {
+            wm.vote(&text, &mut votes);
+        }
+    }
+    let attributions = wm.attribute(&votes, candidates);
+    Ok(DetectReport {
+        rows,
+        slots: votes.slots,
+        recovered_bits: votes.decode().iter().filter(|b| b.is_some()).count(),
+        attributions,
+    })
+}
+
+/// Candidate account ids, one per line (blank lines and '#' comments skipped).
+pub fn load_accounts(path: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading accounts {}", path.display()))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wm() -> RobustWatermark {
+        RobustWatermark::new(b"test-key".to_vec())
+    }
+
+    fn candidates() -> Vec<String> {
+        (0..50).map(|i| format!("acct_{i}")).collect()
+    }
+
+    // Sentences with synonym slots next to each other, an ellipsis and
+    // sentence spacing, each in its own context.
+    fn corpus(sentences: usize) -> Vec<String> {
+        (0..sentences)
+            .map(|i| {
+                format!(
+                    "Item{i} maybe often works... Case{i} usually mostly fails. \
+                     Run{i} also quickly ends, and almost completely stops. "
+                )
+            })
+            .collect()
+    }
+
+    fn attribute(wm: &RobustWatermark, texts: &[String]) -> Vec<Attribution> {
+        let mut votes = Votes::default();
+        for t in texts {
+            wm.vote(t, &mut votes);
+        }
+        wm.attribute(&votes, &candidates())
+    }
+
+    #[test]
+    fn every_slot_votes_for_the_codeword() {
+        let wm = wm();
+        let text = corpus(40).concat();
+        let marked = wm.embed(&text, "acct_7");
+        assert_ne!(marked, text);
+
+        let mut votes = Votes::default();
+        wm.vote(&marked, &mut votes);
+        let code = wm.codeword("acct_7");
+        // Embedder and detector agree on every context: no vote cancels.
+        let total: i64 = votes.tally.iter().map(|t| t.abs()).sum();
+        assert_eq!(total, votes.slots as i64);
+        for (pos, &t) in votes.tally.iter().enumerate() {
+            if t != 0 {
+                assert_eq!(t > 0, code[pos], "position {pos}");
+            }
+        }
+
+        // Chunked embedding (stream) keeps the same contexts.
+        let mut st = EmbedState::default();
+        let streamed: String = text
+            .as_bytes()
+            .chunks(37)
+            .map(|c| String::from_utf8_lossy(c).into_owned())
+            .map(|c| wm.embed_chunk(&c, "acct_7", &mut st))
+            .collect();
+        let mut votes = Votes::default();
+        wm.vote(&streamed, &mut votes);
+        let agreeing: i64 = votes
+            .tally
+            .iter()
+            .enumerate()
+            .map(|(pos, &t)| if code[pos] { t } else { -t })
+            .sum();
+        assert!(agreeing as f64 > 0.8 * votes.slots as f64);
+    }
+
+    #[test]
+    fn round_trip_attributes_the_right_account() {
+        let wm = wm();
+        let marked: Vec<String> = corpus(300).iter().map(|t| wm.embed(t, "acct_7")).collect();
+        let ranked = attribute(&wm, &marked);
+        assert_eq!(ranked[0].account_id, "acct_7");
+        assert!(ranked[0].p_value_bonferroni < 1e-9);
+        assert!(ranked[1].p_value_bonferroni > 1e-3);
+
+        // Unmarked text attributes to no one.
+        let ranked = attribute(&wm, &corpus(300));
+        assert!(ranked[0].p_value_bonferroni > 1e-3);
+    }
+
+    #[test]
+    fn survives_normalization() {
+        let wm = wm();
+        // NFKC folds "…" to "...", whitespace is collapsed, zero-width
+        // characters are stripped and the text lowercased.
+        let normalized: Vec<String> = corpus(300)
+            .iter()
+            .map(|t| {
+                let t = crate::workers::watermark::embed(&wm.embed(t, "acct_7"), "acct_7");
+                t.replace('…', "...")
+                    .replace(['\u{200D}', '\u{200C}'], "")
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .to_lowercase()
+            })
+            .collect();
+        let ranked = attribute(&wm, &normalized);
+        assert_eq!(ranked[0].account_id, "acct_7");
+        assert!(ranked[0].p_value_bonferroni < 1e-9);
+    }
+
+    #[test]
+    fn survives_light_paraphrase() {
+        let wm = wm();
+        let marked: Vec<String> = corpus(300).iter().map(|t| wm.embed(t, "acct_7")).collect();
+        // Drop a third of the samples, reorder the rest and reword the verbs
+        // around the slots.
+        let mut paraphrased: Vec<String> = marked
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i % 3 != 0)
+            .map(|(_, t)| {
+                t.replace("works", "succeeds")
+                    .replace("fails", "breaks")
+                    .replace(", and", ";")
+            })
+            .collect();
+        paraphrased.reverse();
+        let ranked = attribute(&wm, &paraphrased);
+        assert_eq!(ranked[0].account_id, "acct_7");
+        assert!(ranked[0].p_value_bonferroni < 1e-6);
+    }
+}
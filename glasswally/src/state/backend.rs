@@ -0,0 +1,617 @@
+// glasswally/src/state/backend.rs
+//
+// State backends — where detection state lives.
+//
+// Workers, fusion, the dispatcher, canary scanning and analyst feedback query
+// state through the StateBackend trait instead of StateStore directly, so the
+// pipeline can run on either backend:
+//
+//   memory   StateStore itself.  Every account window stays resident in the
+//            DashMap; idle windows over the memory budget are dropped
+//            (default).
+//   disk     DiskBackend.  The same StateStore holds indexes, graph, entity
+//            windows, registries and hot windows; idle windows over the
+//            budget are spilled to an embedded on-disk store instead of
+//            dropped, and faulted back in on the account's next event or
+//            query (`--state-dir`).
+//
+// Only account windows spill — they are the bulk of the memory
+// (WindowLimits).  The reverse indexes and graph stay resident on every
+// backend, so cross-account queries never touch the disk.
+//
+// On-disk layout: one JSON file per spilled window, named by the hex-encoded
+// account id, under 256 shard directories (low byte of the id hash), written
+// via temp file + rename.  A window lives in exactly one place: faulting it
+// in deletes its file, so the resident copy is always authoritative.  Files
+// older than the 24 h window are removed by housekeeping and at open.
+//
+// Moving a window between memory and disk is serialized per account by a
+// striped lock, held across spill (remove, then write), fault-in (read, then
+// restore) and ingest, so concurrent events for a spilled account see one
+// history, never a fresh window next to the faulted-in one.  No file I/O
+// happens under a DashMap shard lock, and on the multi-threaded runtime the
+// disk reads and writes run under `block_in_place` so the ingest path's
+// worker thread hands its other tasks off while it waits.
+//
+// The backend tests run one suite against every backend.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use parking_lot::{Mutex, MutexGuard, RwLock};
+use tracing::{info, warn};
+
+use super::entity::{EntityKind, EntityWindow};
+use super::rollup::Rollup;
+use super::window::{value_hash, AccountWindow, IndexUpdate, StateStore, W_24HR};
+use crate::events::{ApiEvent, CanaryHit, CanaryToken, ClusterEvent, RateLimitCommand};
+use crate::net::SubnetPrefixes;
+
+const HOUSEKEEPING_SECS: u64 = 300;
+const MAX_ACCOUNT_ID_BYTES: usize = 120; // hex file name stays under 255 bytes
+const LOCK_STRIPES: usize = 1024;
+
+// ── Backend trait ─────────────────────────────────────────────────────────────
+
+/// Detection-state queries.  Everything but account windows is served by the
+/// resident StateStore; a backend decides where windows live.
+pub trait StateBackend: Send + Sync + 'static {
+    /// Indexes, graph, entity windows, registries and resident windows.
+    fn resident(&self) -> &StateStore;
+
+    // ── Account windows ───────────────────────────────────────────────────────
+
+    fn ingest(&self, event: &ApiEvent) -> IndexUpdate {
+        self.resident().ingest(event)
+    }
+
+    fn get_window(&self, account_id: &str) -> Option<Arc<RwLock<AccountWindow>>> {
+        self.resident().get_window(account_id)
+    }
+
+    fn set_suspended(&self, account_id: &str, suspended: bool) {
+        self.resident().set_suspended(account_id, suspended)
+    }
+
+    fn mark_watermarked(&self, account_id: &str) {
+        self.resident().mark_watermarked(account_id)
+    }
+
+    fn set_rate_limit(&self, account_id: &str, command: &RateLimitCommand) {
+        self.resident().set_rate_limit(account_id, command)
+    }
+
+    /// Account windows held, resident or not.
+    fn n_accounts(&self) -> usize {
+        self.resident().n_accounts()
+    }
+
+    /// One housekeeping pass (StateStore::housekeep_with).
+    fn housekeep(&self) {
+        let store = self.resident();
+        store.housekeep_with(|account| store.take_window(account).is_some());
+    }
+
+    // ── Infrastructure and graph ──────────────────────────────────────────────
+
+    fn entity_keys(&self, event: &ApiEvent) -> Vec<(EntityKind, String)> {
+        self.resident().entity_keys(event)
+    }
+
+    fn get_entity(&self, kind: EntityKind, key: &str) -> Option<Arc<RwLock<EntityWindow>>> {
+        self.resident().get_entity(kind, key)
+    }
+
+    fn subnet_prefixes(&self) -> SubnetPrefixes {
+        self.resident().subnet_prefixes()
+    }
+
+    fn get_cluster(&self, account_id: &str) -> Option<u32> {
+        self.resident().get_cluster(account_id)
+    }
+
+    fn cluster_members(&self, cluster_id: u32) -> HashSet<String> {
+        self.resident().cluster_members(cluster_id)
+    }
+
+    fn n_clusters(&self) -> usize {
+        self.resident().n_clusters()
+    }
+
+    fn drain_cluster_events(&self) -> Vec<ClusterEvent> {
+        self.resident().drain_cluster_events()
+    }
+
+    fn link_accounts(&self, a: &str, b: &str) -> Option<u32> {
+        self.resident().link_accounts(a, b)
+    }
+
+    fn model_switches(&self, account_id: &str) -> Vec<(DateTime<Utc>, String, String)> {
+        self.resident().model_switches(account_id)
+    }
+
+    fn accounts_with_ja3(&self, ja3: &str) -> HashSet<String> {
+        self.resident().accounts_with_ja3(ja3)
+    }
+
+    fn accounts_with_ja3s(&self, ja3s: &str) -> HashSet<String> {
+        self.resident().accounts_with_ja3s(ja3s)
+    }
+
+    fn accounts_with_header_hash(&self, hash: &str) -> HashSet<String> {
+        self.resident().accounts_with_header_hash(hash)
+    }
+
+    fn accounts_with_preamble_hash(&self, hash: &str) -> usize {
+        self.resident().accounts_with_preamble_hash(hash)
+    }
+
+    fn accounts_in_bucket(&self, bucket: u64) -> usize {
+        self.resident().accounts_in_bucket(bucket)
+    }
+
+    fn with_account_rollup<T>(&self, account_id: &str, f: impl FnOnce(&Rollup) -> T) -> Option<T> {
+        self.resident().with_account_rollup(account_id, f)
+    }
+
+    fn cluster_rollup(&self, cluster_id: u32) -> Rollup {
+        self.resident().cluster_rollup(cluster_id)
+    }
+
+    // ── Watermarks and canaries ───────────────────────────────────────────────
+
+    fn is_watermarked(&self, account_id: &str) -> bool {
+        self.resident().is_watermarked(account_id)
+    }
+
+    fn watermarked_accounts(&self) -> Vec<String> {
+        self.resident().watermarked_accounts()
+    }
+
+    fn register_canary(&self, token: CanaryToken) {
+        self.resident().register_canary(token)
+    }
+
+    fn lookup_canary(&self, token: &str) -> Option<CanaryToken> {
+        self.resident().lookup_canary(token)
+    }
+
+    fn trigger_canary(&self, token: &str) {
+        self.resident().trigger_canary(token)
+    }
+
+    fn record_canary_hit(&self, hit: CanaryHit) {
+        self.resident().record_canary_hit(hit)
+    }
+
+    fn canary_hits(&self, account_id: &str) -> Vec<CanaryHit> {
+        self.resident().canary_hits(account_id)
+    }
+
+    fn triggered_canaries_for_cluster(&self, cluster_id: u32) -> Vec<String> {
+        self.resident().triggered_canaries_for_cluster(cluster_id)
+    }
+}
+
+/// In-memory backend: the DashMap store itself.
+impl StateBackend for StateStore {
+    fn resident(&self) -> &StateStore {
+        self
+    }
+}
+
+/// Housekeeping every 5 minutes, for any backend, on the blocking pool.
+pub async fn housekeeping_loop<S: StateBackend>(state: Arc<S>) {
+    loop {
+        tokio::time::sleep(tokio::time::Duration::from_secs(HOUSEKEEPING_SECS)).await;
+        let state = Arc::clone(&state);
+        if let Err(e) = tokio::task::spawn_blocking(move || state.housekeep()).await {
+            warn!("Housekeeping pass failed: {}", e);
+        }
+    }
+}
+
+// Run blocking file I/O from code that may sit on a runtime worker thread.
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    use tokio::runtime::{Handle, RuntimeFlavor};
+    match Handle::try_current() {
+        Ok(h) if h.runtime_flavor() == RuntimeFlavor::MultiThread => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+// ── On-disk window store ──────────────────────────────────────────────────────
+
+/// Spilled account windows, one JSON file each.
+pub struct WindowDb {
+    dir: PathBuf,
+    index: DashMap<String, ()>, // account ids with a file on disk
+    pub spilled: AtomicU64,
+    pub faulted: AtomicU64,
+    pub expired: AtomicU64,
+}
+
+impl WindowDb {
+    /// Open (or create) the store under `dir`, dropping files older than the
+    /// 24 h window and leftovers of interrupted writes.
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("creating state dir {}", dir.display()))?;
+        let db = Self {
+            dir: dir.to_path_buf(),
+            index: DashMap::new(),
+            spilled: AtomicU64::new(0),
+            faulted: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+        };
+        let cutoff = Utc::now() - Duration::seconds(W_24HR);
+        for shard in std::fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+            for file in std::fs::read_dir(&shard)? {
+                let path = file?.path();
+                let account = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(|n| n.strip_suffix(".json"))
+                    .and_then(|n| hex::decode(n).ok())
+                    .and_then(|b| String::from_utf8(b).ok());
+                match account {
+                    Some(account) if !is_stale(&path, cutoff) => {
+                        db.index.insert(account, ());
+                    }
+                    _ => {
+                        std::fs::remove_file(&path).ok();
+                    }
+                }
+            }
+        }
+        Ok(db)
+    }
+
+    fn path(&self, account_id: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:02x}", value_hash(account_id) & 0xff))
+            .join(format!("{}.json", hex::encode(account_id)))
+    }
+
+    pub fn contains(&self, account_id: &str) -> bool {
+        self.index.contains_key(account_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Write a window (temp file, then rename).
+    pub fn put(&self, window: &AccountWindow) -> Result<()> {
+        if window.account_id.len() > MAX_ACCOUNT_ID_BYTES {
+            bail!(
+                "account id of {} bytes too long to spill",
+                window.account_id.len()
+            );
+        }
+        let path = self.path(&window.account_id);
+        if let Some(shard) = path.parent() {
+            std::fs::create_dir_all(shard)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        let body = serde_json::to_vec(window)?;
+        std::fs::write(&tmp, body).with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("replacing {}", path.display()))?;
+        self.index.insert(window.account_id.clone(), ());
+        self.spilled.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Read and delete a spilled window.
+    pub fn take(&self, account_id: &str) -> Result<Option<AccountWindow>> {
+        if self.index.remove(account_id).is_none() {
+            return Ok(None);
+        }
+        let path = self.path(account_id);
+        let body = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        std::fs::remove_file(&path).ok();
+        let window: AccountWindow = serde_json::from_slice(&body)
+            .with_context(|| format!("parsing spilled window {}", path.display()))?;
+        self.faulted.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(window))
+    }
+
+    /// Delete windows spilled before `cutoff`.  Returns the number removed.
+    pub fn expire(&self, cutoff: DateTime<Utc>) -> usize {
+        let stale: Vec<String> = self
+            .index
+            .iter()
+            .map(|e| e.key().clone())
+            .filter(|a| is_stale(&self.path(a), cutoff))
+            .collect();
+        for account in &stale {
+            self.index.remove(account);
+            std::fs::remove_file(self.path(account)).ok();
+        }
+        self.expired
+            .fetch_add(stale.len() as u64, Ordering::Relaxed);
+        stale.len()
+    }
+}
+
+fn is_stale(path: &Path, cutoff: DateTime<Utc>) -> bool {
+    match std::fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(modified) => DateTime::<Utc>::from(modified) < cutoff,
+        Err(_) => true,
+    }
+}
+
+// ── Disk backend ──────────────────────────────────────────────────────────────
+
+pub struct DiskBackend {
+    store: StateStore,
+    db: WindowDb,
+    stripes: Box<[Mutex<()>]>, // per-account residency moves
+}
+
+impl DiskBackend {
+    pub fn open(store: StateStore, dir: &Path) -> Result<Self> {
+        let db = WindowDb::open(dir)?;
+        info!(
+            "Disk state backend at {} ({} spilled windows)",
+            dir.display(),
+            db.len()
+        );
+        Ok(Self {
+            store,
+            db,
+            stripes: (0..LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+        })
+    }
+
+    pub fn db(&self) -> &WindowDb {
+        &self.db
+    }
+
+    fn stripe(&self, account_id: &str) -> MutexGuard<'_, ()> {
+        self.stripes[(value_hash(account_id) >> 8) as usize % LOCK_STRIPES].lock()
+    }
+
+    /// Load a spilled window back into the resident store.  The caller holds
+    /// the account's stripe.
+    fn fault_in(&self, account_id: &str) -> Option<Arc<RwLock<AccountWindow>>> {
+        if !self.db.contains(account_id) {
+            return None;
+        }
+        match blocking(|| self.db.take(account_id)) {
+            Ok(Some(mut window)) => {
+                window.expire_old();
+                Some(self.store.restore_window(window))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Faulting in window {} failed: {}", account_id, e);
+                None
+            }
+        }
+    }
+
+    /// Fault the window in unless resident.  The caller holds the stripe.
+    fn make_resident(&self, account_id: &str) {
+        if self.store.get_window(account_id).is_none() {
+            self.fault_in(account_id);
+        }
+    }
+
+    /// Move a resident window to disk.  The window leaves the map before the
+    /// write, so no shard lock is held during I/O; the stripe keeps the
+    /// account's ingest and fault-in waiting until the file is complete.
+    fn spill(&self, account_id: &str) -> bool {
+        let _stripe = self.stripe(account_id);
+        let Some(window) = self.store.take_window(account_id) else {
+            return false;
+        };
+        let written = blocking(|| self.db.put(&window.read()));
+        match written {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Spilling window {} failed: {}", account_id, e);
+                self.store.reinsert_window(account_id, window);
+                false
+            }
+        }
+    }
+}
+
+impl StateBackend for DiskBackend {
+    fn resident(&self) -> &StateStore {
+        &self.store
+    }
+
+    fn ingest(&self, event: &ApiEvent) -> IndexUpdate {
+        // Held through the ingest: a spill between the residency check and
+        // the append would otherwise start a fresh window.
+        let _stripe = self.stripe(&event.account_id);
+        self.make_resident(&event.account_id);
+        self.store.ingest(event)
+    }
+
+    fn get_window(&self, account_id: &str) -> Option<Arc<RwLock<AccountWindow>>> {
+        if let Some(w) = self.store.get_window(account_id) {
+            return Some(w);
+        }
+        let _stripe = self.stripe(account_id);
+        self.store
+            .get_window(account_id)
+            .or_else(|| self.fault_in(account_id))
+    }
+
+    fn set_suspended(&self, account_id: &str, suspended: bool) {
+        let _stripe = self.stripe(account_id);
+        self.make_resident(account_id);
+        self.store.set_suspended(account_id, suspended)
+    }
+
+    fn mark_watermarked(&self, account_id: &str) {
+        let _stripe = self.stripe(account_id);
+        self.make_resident(account_id);
+        self.store.mark_watermarked(account_id)
+    }
+
+    fn set_rate_limit(&self, account_id: &str, command: &RateLimitCommand) {
+        let _stripe = self.stripe(account_id);
+        self.make_resident(account_id);
+        self.store.set_rate_limit(account_id, command)
+    }
+
+    fn n_accounts(&self) -> usize {
+        self.store.n_accounts() + self.db.len()
+    }
+
+    fn housekeep(&self) {
+        self.store.housekeep_with(|account| self.spill(account));
+        let expired = blocking(|| self.db.expire(Utc::now() - Duration::seconds(W_24HR)));
+        if expired > 0 {
+            info!("Expired {} spilled account windows", expired);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fusion::FusionEngine;
+    use crate::state::window::WindowLimits;
+
+    fn event(account: &str, ts: DateTime<Utc>, payment: Option<&str>) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": ts,
+            "ip_address": "203.0.113.7", "user_agent": "ua", "model": "m",
+            "prompt": "explain step by step", "token_count": 100,
+            "payment_method_hash": payment, "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": "preamble",
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    /// The behaviour every backend must share.
+    async fn suite<S: StateBackend>(state: &S) {
+        let t0 = Utc::now() - Duration::minutes(30);
+        let accounts = ["ring_0", "ring_1", "ring_2", "loner"];
+        for k in 0..5 {
+            for a in accounts {
+                let payment = a.starts_with("ring").then_some("pm_ring");
+                state.ingest(&event(a, t0 + Duration::seconds(k * 60), payment));
+            }
+        }
+        state.housekeep();
+
+        assert_eq!(state.n_accounts(), 4);
+        for a in accounts {
+            let w = state.get_window(a).expect("window kept");
+            assert_eq!(w.read().events.len(), 5);
+        }
+        assert!(state.get_window("unknown").is_none());
+
+        let cid = state.get_cluster("ring_0").expect("payment ring clustered");
+        assert_eq!(state.cluster_members(cid).len(), 3);
+        assert!(state.get_cluster("loner").is_none());
+        assert_eq!(state.accounts_with_preamble_hash("preamble"), 4);
+        assert_eq!(state.accounts_in_bucket(t0.timestamp() as u64), 4);
+
+        // Writes through the backend land on the window
+        state.set_suspended("loner", true);
+        assert!(state.get_window("loner").unwrap().read().suspended);
+
+        // Workers and fusion run unchanged
+        let e = event("ring_0", t0 + Duration::seconds(600), Some("pm_ring"));
+        state.ingest(&e);
+        let signals = crate::workers::run_all(&e, state).await;
+        FusionEngine::new().fuse(&e, state, &signals);
+        assert_eq!(state.get_window("ring_0").unwrap().read().events.len(), 6);
+        assert_eq!(state.n_accounts(), 4);
+    }
+
+    #[tokio::test]
+    async fn memory_backend() {
+        suite(&StateStore::new()).await;
+    }
+
+    // Multi-threaded, so disk I/O goes through block_in_place.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn disk_backend() {
+        let dir = std::env::temp_dir().join(format!("glasswally_backend_{}", std::process::id()));
+        let limits = WindowLimits {
+            max_accounts: 1,
+            ..WindowLimits::default()
+        };
+        let disk = DiskBackend::open(StateStore::new().with_window_limits(limits), &dir).unwrap();
+        suite(&disk).await;
+        assert!(disk.db().spilled.load(Ordering::Relaxed) >= 3);
+        assert!(disk.db().faulted.load(Ordering::Relaxed) >= 3);
+
+        // Spilled windows survive a reopen
+        disk.housekeep();
+        let spilled = disk.db().len();
+        assert!(spilled >= 3);
+        let reopened = DiskBackend::open(StateStore::new(), &dir).unwrap();
+        assert_eq!(reopened.db().len(), spilled);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn concurrent_ingest_and_spill_keep_history() {
+        let dir = std::env::temp_dir().join(format!("glasswally_race_{}", std::process::id()));
+        let limits = WindowLimits {
+            max_accounts: 1,
+            ..WindowLimits::default()
+        };
+        let disk = DiskBackend::open(StateStore::new().with_window_limits(limits), &dir).unwrap();
+        let t0 = Utc::now() - Duration::minutes(30);
+        let accounts = ["a", "b", "c", "d"];
+        for a in accounts {
+            disk.ingest(&event(a, t0, None));
+        }
+        disk.housekeep();
+        assert!(disk.db().len() >= 3);
+
+        // Every account's events arrive on several threads at once while
+        // housekeeping keeps spilling them.
+        std::thread::scope(|s| {
+            for thread in 0..4 {
+                let disk = &disk;
+                s.spawn(move || {
+                    for k in 1..=25 {
+                        let ts = t0 + Duration::seconds(thread * 100 + k);
+                        for a in accounts {
+                            disk.ingest(&event(a, ts, None));
+                        }
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..20 {
+                    disk.housekeep();
+                }
+            });
+        });
+
+        for a in accounts {
+            let w = disk.get_window(a).expect("window kept");
+            assert_eq!(w.read().events.len(), 101, "account {a}");
+        }
+        assert_eq!(disk.n_accounts(), 4);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
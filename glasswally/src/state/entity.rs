@@ -0,0 +1,202 @@
+// glasswally/src/state/entity.rs
+//
+// Entity windows — org, payment method and subnet behaviour.
+//
+// Campaigns rotate API keys within one org or one payment method: 50 keys at
+// 10 requests per hour each look benign, one org at 500 per hour does not.
+// The reverse indexes in state/window.rs only say which accounts share a
+// value; an entity window keeps the same compact event summaries as an
+// account window, for every event carrying the value:
+//
+//   org       org_id
+//   payment   payment_method_hash
+//   subnet    client address aggregated to the graph's subnet prefixes
+//
+// Entity windows implement EventWindow, so workers get the same velocity /
+// interarrival / token aggregates as for accounts (workers/velocity.rs), plus
+// the set of accounts seen on the entity.  They expire with the 24 h window
+// and are capped per entity and in number (WindowLimits).
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::window::{expire_summaries, EventSummary, EventWindow, RecentSet, WindowLimits};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Org,
+    Payment,
+    Subnet,
+}
+
+impl std::fmt::Display for EntityKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Org => write!(f, "org"),
+            Self::Payment => write!(f, "payment"),
+            Self::Subnet => write!(f, "subnet"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntityWindow {
+    pub kind: EntityKind,
+    pub key: String,
+    pub events: VecDeque<EventSummary>,
+    pub accounts: RecentSet, // accounts seen on the entity (LRU-capped)
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl EntityWindow {
+    pub fn new(kind: EntityKind, key: &str, now: DateTime<Utc>) -> Self {
+        Self {
+            kind,
+            key: key.to_string(),
+            events: VecDeque::new(),
+            accounts: RecentSet::default(),
+            first_seen: now,
+            last_seen: now,
+        }
+    }
+
+    pub fn ingest(&mut self, account_id: &str, summary: EventSummary, limits: &WindowLimits) {
+        self.last_seen = self.last_seen.max(summary.timestamp);
+        self.accounts
+            .insert(account_id, summary.timestamp, limits.max_entity_accounts);
+        self.events.push_back(summary);
+        while self.events.len() > limits.max_entity_events.max(1) {
+            self.events.pop_front();
+        }
+    }
+
+    /// Distinct accounts seen on the entity since `since`.
+    pub fn accounts_since(&self, since: DateTime<Utc>) -> usize {
+        self.accounts.count_since(since)
+    }
+
+    pub fn expire_old(&mut self, cutoff: DateTime<Utc>) {
+        expire_summaries(&mut self.events, cutoff);
+    }
+
+    pub fn approx_bytes(&self) -> usize {
+        size_of::<Self>()
+            + self.key.capacity()
+            + self.events.capacity() * size_of::<EventSummary>()
+            + self.accounts.heap_bytes()
+    }
+}
+
+impl EventWindow for EntityWindow {
+    fn summaries(&self) -> &VecDeque<EventSummary> {
+        &self.events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ApiEvent;
+    use crate::state::window::{StateStore, W_1HR};
+    use crate::workers::velocity;
+    use chrono::Duration;
+
+    fn event(account: &str, ip: &str, at: DateTime<Utc>, tokens: u32) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": at,
+            "ip_address": ip, "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": tokens,
+            "payment_method_hash": null, "org_id": "org_1", "country_code": "US",
+            "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    // 12 keys in one org, 4 requests each, round robin from their own /24s.
+    fn rotated_keys(gap: impl Fn(usize) -> i64, tokens: impl Fn(usize) -> u32) -> StateStore {
+        let store = StateStore::new();
+        let mut at = Utc::now() - Duration::minutes(40);
+        for i in 0..48 {
+            let account = format!("key_{}", i % 12);
+            let ip = format!("203.0.{}.7", i % 12);
+            store.ingest(&event(&account, &ip, at, tokens(i)));
+            at += Duration::seconds(gap(i));
+        }
+        store
+    }
+
+    #[test]
+    fn entity_window_is_capped() {
+        let limits = WindowLimits {
+            max_entity_events: 3,
+            max_entity_accounts: 2,
+            ..WindowLimits::default()
+        };
+        let t0 = Utc::now() - Duration::minutes(10);
+        let mut w = EntityWindow::new(EntityKind::Org, "org_1", t0);
+        for i in 0..5 {
+            let at = t0 + Duration::seconds(i * 10);
+            let e = event(&format!("key_{i}"), "203.0.113.7", at, 100);
+            w.ingest(&e.account_id, EventSummary::of(&e), &limits);
+        }
+        assert_eq!(w.events.len(), 3);
+        assert_eq!(w.accounts_since(t0), 2);
+        assert_eq!(w.last_seen, t0 + Duration::seconds(40));
+        assert!((w.rate_per_hour(W_1HR) - 3.0 / 20.0 * 3600.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn org_window_aggregates_across_keys() {
+        let store = rotated_keys(|_| 15, |_| 500);
+        let org = store.get_entity(EntityKind::Org, "org_1").unwrap();
+        let org = org.read();
+        assert_eq!(
+            org.accounts_since(Utc::now() - Duration::seconds(W_1HR)),
+            12
+        );
+        assert_eq!(org.events_in(W_1HR).len(), 48);
+        assert!(org.rate_per_hour(W_1HR) > 200.0);
+        // Each /24 only saw one key.
+        let subnet = store
+            .get_entity(EntityKind::Subnet, "203.0.3.0/24")
+            .unwrap();
+        assert_eq!(subnet.read().events.len(), 4);
+        assert_eq!(store.n_entities(), 13);
+    }
+
+    #[tokio::test]
+    async fn scripted_org_scores_while_each_key_is_quiet() {
+        let store = rotated_keys(|_| 15, |_| 500);
+        let probe = event("key_0", "203.0.0.7", Utc::now(), 500);
+        let signal = velocity::analyze(&probe, &store).await.unwrap();
+        assert!(signal.score >= 0.5, "{signal:?}");
+        assert!(signal
+            .evidence
+            .iter()
+            .any(|e| e.starts_with("org_scripted_timing")));
+        assert!(signal.evidence.iter().any(|e| e == "org_uniform_tokens"));
+        assert!(signal
+            .evidence
+            .iter()
+            .any(|e| e.starts_with("org_velocity") && e.ends_with("over_12_accounts")));
+    }
+
+    #[tokio::test]
+    async fn busy_but_irregular_org_does_not_score() {
+        let store = rotated_keys(
+            |i| if i % 4 == 3 { 150 } else { 1 },
+            |i| 100 * (i as u32 % 7 + 1),
+        );
+        let probe = event("key_0", "203.0.0.7", Utc::now(), 100);
+        let signal = velocity::analyze(&probe, &store).await.unwrap();
+        assert_eq!(signal.score, 0.0);
+        assert_eq!(signal.evidence, ["insufficient_data"]);
+    }
+}
@@ -0,0 +1,511 @@
+// glasswally/src/state/export.rs
+//
+// Graph export — the account / infrastructure graph for investigators.
+//
+// Nodes:
+//
+//   account    every account in the relationship graph or an index
+//              (attribute: cluster id)
+//   payment    payment method hash   ┐
+//   subnet     aggregated subnet     │ values from the StateStore reverse
+//   ja3        TLS client hash       │ indexes, still within their TTL
+//   preamble   role preamble hash    ┘
+//
+// Edges (typed, weighted, timestamped):
+//
+//   shared_payment / shared_org / shared_subnet / shared_ja3 / shared_canary
+//              account ↔ account, one per link kind of a graph edge
+//              (state/graph.rs); weight = kind weight × supernode scale,
+//              first / last seen of the link
+//   uses       account → infrastructure value; weight 1, last seen (the
+//              indexes do not keep a first sighting)
+//
+// Scope is the whole store or one cluster: its members, the links between
+// them and the values they used.  Formats:
+//
+//   graphml    GraphML with typed <key> attributes (yEd, Gephi, NetworkX)
+//   gexf       GEXF 1.2, dynamic edges spanning first → last seen (Gephi
+//              timeline)
+//   neo4j_csv  nodes.csv + relationships.csv in neo4j-admin import headers
+//
+// Available as the `export-graph` subcommand (replays an event log) and the
+// query API's export request (grpc_api.rs).  The API builds with an edge cap
+// (`build_limited`) so one request cannot hold the graph lock or memory for a
+// whole-store dump; the subcommand is uncapped.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::window::StateStore;
+use crate::events::LinkKind;
+
+// ── Model ─────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Account,
+    Payment,
+    Subnet,
+    Ja3,
+    Preamble,
+}
+
+impl NodeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Account => "account",
+            Self::Payment => "payment",
+            Self::Subnet => "subnet",
+            Self::Ja3 => "ja3",
+            Self::Preamble => "preamble",
+        }
+    }
+
+    /// Neo4j node label.
+    fn label(self) -> &'static str {
+        match self {
+            Self::Account => "Account",
+            Self::Payment => "Payment",
+            Self::Subnet => "Subnet",
+            Self::Ja3 => "Ja3",
+            Self::Preamble => "Preamble",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    Shared(LinkKind),
+    Uses,
+}
+
+impl EdgeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Shared(LinkKind::Payment) => "shared_payment",
+            Self::Shared(LinkKind::Org) => "shared_org",
+            Self::Shared(LinkKind::Subnet) => "shared_subnet",
+            Self::Shared(LinkKind::Ja3) => "shared_ja3",
+            Self::Shared(LinkKind::Canary) => "shared_canary",
+            Self::Uses => "uses",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub id: String, // "<kind>:<value>"
+    pub kind: NodeKind,
+    pub key: String,
+    pub cluster: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    pub kind: EdgeKind,
+    pub weight: f32,
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphFormat {
+    Graphml,
+    Gexf,
+    Neo4jCsv,
+}
+
+impl std::str::FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "graphml" => Ok(Self::Graphml),
+            "gexf" => Ok(Self::Gexf),
+            "neo4j-csv" | "neo4j_csv" => Ok(Self::Neo4jCsv),
+            other => Err(format!(
+                "unknown graph format {other:?} (graphml, gexf, neo4j-csv)"
+            )),
+        }
+    }
+}
+
+fn node_id(kind: NodeKind, key: &str) -> String {
+    format!("{}:{}", kind.as_str(), key)
+}
+
+// ── Export ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GraphExport {
+    pub cluster: Option<u32>,
+    pub nodes: Vec<GraphNode>, // sorted by id
+    pub edges: Vec<GraphEdge>, // sorted by source, target, kind
+}
+
+impl GraphExport {
+    /// Snapshot the graph of one cluster, or of the whole store.
+    pub fn build(store: &StateStore, cluster: Option<u32>) -> Result<Self> {
+        Self::build_limited(store, cluster, usize::MAX)
+    }
+
+    /// `build`, failing as soon as the export passes `max_edges` edges.
+    pub fn build_limited(
+        store: &StateStore,
+        cluster: Option<u32>,
+        max_edges: usize,
+    ) -> Result<Self> {
+        let too_large = || {
+            anyhow::anyhow!(
+                "graph export exceeds {} edges; export one cluster or use the export-graph subcommand",
+                max_edges
+            )
+        };
+        let members: Option<HashSet<String>> = match cluster {
+            Some(cid) => {
+                let m = store.cluster_members(cid);
+                if m.is_empty() {
+                    bail!("no cluster {}", cid);
+                }
+                Some(m)
+            }
+            None => None,
+        };
+        let keep = |a: &str| members.as_ref().is_none_or(|m| m.contains(a));
+
+        let mut nodes: BTreeMap<String, GraphNode> = BTreeMap::new();
+        let mut edges = Vec::new();
+        let mut accounts: HashSet<String> = HashSet::new();
+
+        store.with_graph(|graph| {
+            let weights = &graph.config().weights;
+            for (a, b, edge) in graph.edges() {
+                if !keep(a) || !keep(b) {
+                    continue;
+                }
+                accounts.insert(a.to_string());
+                accounts.insert(b.to_string());
+                let (a, b) = if a <= b { (a, b) } else { (b, a) };
+                for (kind, seen) in &edge.links {
+                    edges.push(GraphEdge {
+                        source: node_id(NodeKind::Account, a),
+                        target: node_id(NodeKind::Account, b),
+                        kind: EdgeKind::Shared(*kind),
+                        weight: weights.of(*kind) * seen.scale,
+                        first_seen: Some(seen.first_seen),
+                        last_seen: seen.last_seen,
+                    });
+                }
+                if edges.len() > max_edges {
+                    return Err(too_large());
+                }
+            }
+            Ok(())
+        })?;
+
+        for (kind, index) in store.infra_indexes() {
+            for (value, account, last_seen) in index.entries() {
+                if !keep(&account) {
+                    continue;
+                }
+                let target = node_id(kind, &value);
+                nodes.entry(target.clone()).or_insert_with(|| GraphNode {
+                    id: target.clone(),
+                    kind,
+                    key: value,
+                    cluster: None,
+                });
+                edges.push(GraphEdge {
+                    source: node_id(NodeKind::Account, &account),
+                    target,
+                    kind: EdgeKind::Uses,
+                    weight: 1.0,
+                    first_seen: None,
+                    last_seen,
+                });
+                if edges.len() > max_edges {
+                    return Err(too_large());
+                }
+                accounts.insert(account);
+            }
+        }
+
+        for account in accounts {
+            let id = node_id(NodeKind::Account, &account);
+            nodes.insert(
+                id.clone(),
+                GraphNode {
+                    id,
+                    kind: NodeKind::Account,
+                    cluster: store.get_cluster(&account),
+                    key: account,
+                },
+            );
+        }
+        edges.sort_by(|a, b| (&a.source, &a.target, a.kind).cmp(&(&b.source, &b.target, b.kind)));
+
+        Ok(Self {
+            cluster,
+            nodes: nodes.into_values().collect(),
+            edges,
+        })
+    }
+
+    /// Rendered files as (file name, contents).
+    pub fn render(&self, format: GraphFormat) -> Vec<(&'static str, String)> {
+        match format {
+            GraphFormat::Graphml => vec![("graph.graphml", self.to_graphml())],
+            GraphFormat::Gexf => vec![("graph.gexf", self.to_gexf())],
+            GraphFormat::Neo4jCsv => {
+                let (nodes, rels) = self.to_neo4j_csv();
+                vec![("nodes.csv", nodes), ("relationships.csv", rels)]
+            }
+        }
+    }
+
+    /// Write the rendered files into `dir`.  Returns their paths.
+    pub fn write(&self, format: GraphFormat, dir: &Path) -> Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        let mut paths = Vec::new();
+        for (name, body) in self.render(format) {
+            let path = dir.join(name);
+            std::fs::write(&path, body).with_context(|| format!("writing {}", path.display()))?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    // ── GraphML ───────────────────────────────────────────────────────────────
+
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"kind\" for=\"node\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+            "  <key id=\"key\" for=\"node\" attr.name=\"key\" attr.type=\"string\"/>\n",
+            "  <key id=\"cluster\" for=\"node\" attr.name=\"cluster\" attr.type=\"long\"/>\n",
+            "  <key id=\"type\" for=\"edge\" attr.name=\"type\" attr.type=\"string\"/>\n",
+            "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"double\"/>\n",
+            "  <key id=\"first_seen\" for=\"edge\" attr.name=\"first_seen\" attr.type=\"string\"/>\n",
+            "  <key id=\"last_seen\" for=\"edge\" attr.name=\"last_seen\" attr.type=\"string\"/>\n",
+            "  <graph id=\"glasswally\" edgedefault=\"undirected\">\n",
+        ));
+        for n in &self.nodes {
+            out += &format!("    <node id=\"{}\">\n", xml_escape(&n.id));
+            out += &format!("      <data key=\"kind\">{}</data>\n", n.kind.as_str());
+            out += &format!("      <data key=\"key\">{}</data>\n", xml_escape(&n.key));
+            if let Some(c) = n.cluster {
+                out += &format!("      <data key=\"cluster\">{}</data>\n", c);
+            }
+            out += "    </node>\n";
+        }
+        for (i, e) in self.edges.iter().enumerate() {
+            out += &format!(
+                "    <edge id=\"e{}\" source=\"{}\" target=\"{}\">\n",
+                i,
+                xml_escape(&e.source),
+                xml_escape(&e.target)
+            );
+            out += &format!("      <data key=\"type\">{}</data>\n", e.kind.as_str());
+            out += &format!("      <data key=\"weight\">{}</data>\n", e.weight);
+            if let Some(t) = e.first_seen {
+                out += &format!("      <data key=\"first_seen\">{}</data>\n", rfc3339(t));
+            }
+            out += &format!(
+                "      <data key=\"last_seen\">{}</data>\n",
+                rfc3339(e.last_seen)
+            );
+            out += "    </edge>\n";
+        }
+        out += "  </graph>\n</graphml>\n";
+        out
+    }
+
+    // ── GEXF ──────────────────────────────────────────────────────────────────
+
+    pub fn to_gexf(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<gexf xmlns=\"http://www.gexf.net/1.2draft\" version=\"1.2\">\n",
+            "  <meta>\n    <creator>glasswally</creator>\n  </meta>\n",
+            "  <graph mode=\"dynamic\" defaultedgetype=\"undirected\" timeformat=\"dateTime\">\n",
+            "    <attributes class=\"node\">\n",
+            "      <attribute id=\"0\" title=\"kind\" type=\"string\"/>\n",
+            "      <attribute id=\"1\" title=\"cluster\" type=\"long\"/>\n",
+            "    </attributes>\n",
+            "    <attributes class=\"edge\">\n",
+            "      <attribute id=\"0\" title=\"type\" type=\"string\"/>\n",
+            "    </attributes>\n",
+            "    <nodes>\n",
+        ));
+        for n in &self.nodes {
+            out += &format!(
+                "      <node id=\"{}\" label=\"{}\">\n        <attvalues>\n",
+                xml_escape(&n.id),
+                xml_escape(&n.key)
+            );
+            out += &format!(
+                "          <attvalue for=\"0\" value=\"{}\"/>\n",
+                n.kind.as_str()
+            );
+            if let Some(c) = n.cluster {
+                out += &format!("          <attvalue for=\"1\" value=\"{}\"/>\n", c);
+            }
+            out += "        </attvalues>\n      </node>\n";
+        }
+        out += "    </nodes>\n    <edges>\n";
+        for (i, e) in self.edges.iter().enumerate() {
+            let start = e
+                .first_seen
+                .map(|t| format!(" start=\"{}\"", rfc3339(t)))
+                .unwrap_or_default();
+            out += &format!(
+                "      <edge id=\"{}\" source=\"{}\" target=\"{}\" label=\"{}\" weight=\"{}\"{} end=\"{}\">\n",
+                i,
+                xml_escape(&e.source),
+                xml_escape(&e.target),
+                e.kind.as_str(),
+                e.weight,
+                start,
+                rfc3339(e.last_seen)
+            );
+            out += &format!(
+                "        <attvalues>\n          <attvalue for=\"0\" value=\"{}\"/>\n        </attvalues>\n      </edge>\n",
+                e.kind.as_str()
+            );
+        }
+        out += "    </edges>\n  </graph>\n</gexf>\n";
+        out
+    }
+
+    // ── Neo4j CSV ─────────────────────────────────────────────────────────────
+
+    /// (nodes.csv, relationships.csv) for `neo4j-admin database import`.
+    pub fn to_neo4j_csv(&self) -> (String, String) {
+        let mut nodes = String::from("id:ID,:LABEL,key,cluster:long\n");
+        for n in &self.nodes {
+            nodes += &format!(
+                "{},{},{},{}\n",
+                csv_field(&n.id),
+                n.kind.label(),
+                csv_field(&n.key),
+                n.cluster.map(|c| c.to_string()).unwrap_or_default()
+            );
+        }
+        let mut rels = String::from(
+            ":START_ID,:END_ID,:TYPE,weight:double,first_seen:datetime,last_seen:datetime\n",
+        );
+        for e in &self.edges {
+            rels += &format!(
+                "{},{},{},{},{},{}\n",
+                csv_field(&e.source),
+                csv_field(&e.target),
+                e.kind.as_str().to_uppercase(),
+                e.weight,
+                e.first_seen.map(rfc3339).unwrap_or_default(),
+                rfc3339(e.last_seen)
+            );
+        }
+        (nodes, rels)
+    }
+}
+
+fn rfc3339(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::events::ApiEvent;
+
+    fn event(account: &str, ts: DateTime<Utc>, ip: &str, payment: &str) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": ts,
+            "ip_address": ip, "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": 100,
+            "payment_method_hash": payment, "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": format!("ja3_{account}"), "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": "pre<&>",
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn exports_cluster_subgraph_in_every_format() {
+        let t0: DateTime<Utc> = "2026-03-01T00:00:00Z".parse().unwrap();
+        let store = StateStore::new();
+        for k in 0..2 {
+            let ts = t0 + Duration::minutes(k);
+            store.ingest(&event("a", ts, "198.51.100.1", "pm_ring"));
+            store.ingest(&event("b", ts, "198.51.100.2", "pm_ring"));
+            store.ingest(&event("c", ts, "203.0.113.9", "pm_other"));
+        }
+        let cid = store.get_cluster("a").expect("payment ring clustered");
+
+        let all = GraphExport::build(&store, None).unwrap();
+        let sub = GraphExport::build(&store, Some(cid)).unwrap();
+        assert!(GraphExport::build(&store, Some(cid + 100)).is_err());
+        assert!(all.nodes.iter().any(|n| n.id == "account:c"));
+        assert!(!sub.nodes.iter().any(|n| n.id == "account:c"));
+
+        // a ↔ b share a payment method and a /24
+        let shared: Vec<&str> = sub
+            .edges
+            .iter()
+            .filter(|e| e.source == "account:a" && e.target == "account:b")
+            .map(|e| e.kind.as_str())
+            .collect();
+        assert_eq!(shared, ["shared_payment", "shared_subnet"]);
+        let kinds: HashSet<NodeKind> = sub.nodes.iter().map(|n| n.kind).collect();
+        assert_eq!(kinds.len(), 5);
+        let a = sub.nodes.iter().find(|n| n.id == "account:a").unwrap();
+        assert_eq!(a.cluster, Some(cid));
+
+        let graphml = sub.to_graphml();
+        assert!(graphml.contains("<node id=\"preamble:pre&lt;&amp;&gt;\">"));
+        assert_eq!(graphml.matches("<edge ").count(), sub.edges.len());
+        let gexf = sub.to_gexf();
+        assert!(gexf.contains("start=\"2026-03-01T00:00:00Z\" end=\"2026-03-01T00:01:00Z\""));
+        let (nodes, rels) = sub.to_neo4j_csv();
+        assert_eq!(nodes.lines().count(), sub.nodes.len() + 1);
+        assert!(rels.contains("account:a,account:b,SHARED_PAYMENT,4,"));
+    }
+}
@@ -0,0 +1,778 @@
+// glasswally/src/state/graph.rs
+//
+// Weighted account relationship graph and cluster membership.
+//
+// Nodes are accounts; an edge carries every kind of infrastructure the two
+// accounts share, each with first/last-seen timestamps.  Edge weight is the
+// sum of the per-kind weights (one contribution per kind, however many values
+// are shared):
+//
+//   payment 4   shared payment method — near-certain common operator
+//   org     3
+//   subnet  2   same /24 (IPv4) or /64 (IPv6) — `subnets` in the config
+//   ja3     2   same TLS client library build
+//   canary  4   one account replayed the other's canary / watermark
+//
+// Values shared by very many accounts scale their links down or drop them
+// (state/supernode.rs); the scale is stored per link, so an edge's weight is
+// Σ kind weight × scale.
+//
+// Clusters are the connected components of the subgraph of edges whose weight
+// reaches `min_edge_weight` (default 4): a shared card links on its own, a
+// shared subnet needs a second signal (subnet + JA3, subnet + org, ...).
+//
+// Components are tracked with union-find (union by size), so a bridging edge
+// merges two clusters in one step: the larger cluster keeps its id, the
+// smaller one's members move over and its entry is removed.  Every formation,
+// join and merge is queued as a ClusterEvent for the audit stream.
+//
+// Links age out.  Each kind has a TTL measured from its last sighting
+// (payment / org 30 days, subnet 3 days, JA3 7 days, canary 90 days).
+// `recompute` (StateStore housekeeping, every 5 minutes) drops expired links,
+// edges left with none, and accounts left with no edges, then rebuilds the
+// components from scratch.  Union-find cannot un-merge, so this is the only
+// way clusters shrink.  Before it runs, `rescale` re-applies the current
+// supernode scale to existing links (a scale of 0 drops the link), so values
+// that became supernodes after linking stop holding clusters together.  Ids
+// are carried over by overlap: each old cluster's id
+// goes to the new component holding most of its members.  Other pieces get
+// fresh ids and a Split event naming the parent, accounts that fell out a
+// Shrunk event, and a cluster with nothing left a Dissolved event.
+// `parents(id)` keeps the lineage of every live cluster.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use petgraph::stable_graph::{EdgeIndex, NodeIndex, StableUnGraph};
+use petgraph::visit::{EdgeRef, IntoEdgeReferences, NodeIndexable};
+use serde::{Deserialize, Serialize};
+
+use super::supernode::SupernodeConfig;
+use crate::events::{ClusterEvent, ClusterEventKind, LinkKind};
+use crate::net::SubnetPrefixes;
+
+// ── Config ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkWeights {
+    pub payment: f32,
+    pub org: f32,
+    pub subnet: f32,
+    pub ja3: f32,
+    pub canary: f32,
+}
+
+impl Default for LinkWeights {
+    fn default() -> Self {
+        Self {
+            payment: 4.0,
+            org: 3.0,
+            subnet: 2.0,
+            ja3: 2.0,
+            canary: 4.0,
+        }
+    }
+}
+
+impl LinkWeights {
+    pub fn of(&self, kind: LinkKind) -> f32 {
+        match kind {
+            LinkKind::Payment => self.payment,
+            LinkKind::Org => self.org,
+            LinkKind::Subnet => self.subnet,
+            LinkKind::Ja3 => self.ja3,
+            LinkKind::Canary => self.canary,
+        }
+    }
+}
+
+/// Seconds after its last sighting that a link expires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkTtl {
+    pub payment: i64,
+    pub org: i64,
+    pub subnet: i64,
+    pub ja3: i64,
+    pub canary: i64,
+}
+
+impl Default for LinkTtl {
+    fn default() -> Self {
+        const DAY: i64 = 24 * 3600;
+        Self {
+            payment: 30 * DAY,
+            org: 30 * DAY,
+            subnet: 3 * DAY,
+            ja3: 7 * DAY,
+            canary: 90 * DAY,
+        }
+    }
+}
+
+impl LinkTtl {
+    pub fn of(&self, kind: LinkKind) -> Duration {
+        Duration::seconds(match kind {
+            LinkKind::Payment => self.payment,
+            LinkKind::Org => self.org,
+            LinkKind::Subnet => self.subnet,
+            LinkKind::Ja3 => self.ja3,
+            LinkKind::Canary => self.canary,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphConfig {
+    pub weights: LinkWeights,
+    pub min_edge_weight: f32,
+    pub ttl: LinkTtl,
+    pub subnets: SubnetPrefixes,
+    pub supernode: SupernodeConfig,
+    pub max_pending_events: usize,
+    pub index_max_keys: usize, // per infrastructure index (StateStore)
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        Self {
+            weights: LinkWeights::default(),
+            min_edge_weight: 4.0,
+            ttl: LinkTtl::default(),
+            subnets: SubnetPrefixes::default(),
+            supernode: SupernodeConfig::default(),
+            max_pending_events: 10_000,
+            index_max_keys: 1_000_000,
+        }
+    }
+}
+
+impl GraphConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading graph config {}", path.display()))?;
+        let cfg: Self = serde_json::from_str(&content)
+            .with_context(|| format!("parsing graph config {}", path.display()))?;
+        cfg.subnets
+            .validate()
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("invalid graph config {}", path.display()))?;
+        Ok(cfg)
+    }
+}
+
+// ── Edges ─────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy)]
+pub struct LinkSeen {
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub scale: f32, // supernode down-weighting at last sighting
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Edge {
+    pub links: BTreeMap<LinkKind, LinkSeen>,
+    pub weight: f32,
+}
+
+impl Edge {
+    fn reweigh(&mut self, weights: &LinkWeights) {
+        self.weight = self
+            .links
+            .iter()
+            .map(|(k, seen)| weights.of(*k) * seen.scale)
+            .sum();
+    }
+}
+
+// ── Union-find ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default)]
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn ensure(&mut self, i: usize) {
+        while self.parent.len() <= i {
+            self.parent.push(self.parent.len());
+            self.size.push(1);
+        }
+    }
+
+    // Read-only find: union by size keeps paths O(log n).
+    fn root(&self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    /// Union two roots; returns (new root, absorbed root).
+    fn union_roots(&mut self, a: usize, b: usize) -> (usize, usize) {
+        let (big, small) = if self.size[a] >= self.size[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parent[small] = big;
+        self.size[big] += self.size[small];
+        (big, small)
+    }
+}
+
+// ── Graph ─────────────────────────────────────────────────────────────────────
+
+pub struct AccountGraph {
+    cfg: GraphConfig,
+    graph: StableUnGraph<String, Edge>,
+    nodes: HashMap<String, NodeIndex>,
+    uf: UnionFind,
+    root_cluster: HashMap<usize, u32>, // component root → cluster id
+    clusters: HashMap<u32, HashSet<String>>,
+    parents: HashMap<u32, Vec<u32>>, // live cluster → clusters it came from
+    next_cluster: u32,
+    events: VecDeque<ClusterEvent>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RecomputeStats {
+    pub expired_links: usize,
+    pub rescaled_links: usize, // set by StateStore::recompute_clusters
+    pub removed_edges: usize,
+    pub removed_accounts: usize,
+    pub clusters: usize,
+    pub splits: usize,
+    pub dissolved: usize,
+}
+
+impl AccountGraph {
+    pub fn new(cfg: GraphConfig) -> Self {
+        Self {
+            cfg,
+            graph: StableUnGraph::default(),
+            nodes: HashMap::new(),
+            uf: UnionFind::default(),
+            root_cluster: HashMap::new(),
+            clusters: HashMap::new(),
+            parents: HashMap::new(),
+            next_cluster: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> &GraphConfig {
+        &self.cfg
+    }
+
+    fn node(&mut self, account_id: &str) -> NodeIndex {
+        if let Some(&n) = self.nodes.get(account_id) {
+            return n;
+        }
+        let n = self.graph.add_node(account_id.to_string());
+        self.nodes.insert(account_id.to_string(), n);
+        self.uf.ensure(n.index());
+        n
+    }
+
+    /// Record that `a` and `b` share `kind` infrastructure at `now`, scaled by
+    /// `scale` (1 unless the value is a supernode).  Returns the cluster both
+    /// are in when the edge is (or already was) strong enough.
+    pub fn link(
+        &mut self,
+        a: &str,
+        b: &str,
+        kind: LinkKind,
+        scale: f32,
+        now: DateTime<Utc>,
+    ) -> Option<u32> {
+        if a == b {
+            return None;
+        }
+        let (na, nb) = (self.node(a), self.node(b));
+        let edge_ix = match self.graph.find_edge(na, nb) {
+            Some(e) => e,
+            None => self.graph.add_edge(na, nb, Edge::default()),
+        };
+        let edge = &mut self.graph[edge_ix];
+        edge.links
+            .entry(kind)
+            .and_modify(|s| {
+                s.last_seen = s.last_seen.max(now);
+                s.scale = scale;
+            })
+            .or_insert(LinkSeen {
+                first_seen: now,
+                last_seen: now,
+                scale,
+            });
+        edge.reweigh(&self.cfg.weights);
+        if edge.weight < self.cfg.min_edge_weight {
+            return None;
+        }
+        let (weight, links) = (edge.weight, edge.links.keys().copied().collect());
+        Some(self.union(na, nb, weight, links, now))
+    }
+
+    fn union(
+        &mut self,
+        na: NodeIndex,
+        nb: NodeIndex,
+        weight: f32,
+        links: Vec<LinkKind>,
+        now: DateTime<Utc>,
+    ) -> u32 {
+        let (ra, rb) = (self.uf.find(na.index()), self.uf.find(nb.index()));
+        if ra == rb {
+            if let Some(&id) = self.root_cluster.get(&ra) {
+                return id;
+            }
+        }
+        let (ca, cb) = (self.root_cluster.remove(&ra), self.root_cluster.remove(&rb));
+        let root = if ra == rb {
+            ra
+        } else {
+            self.uf.union_roots(ra, rb).0
+        };
+
+        let (kind, cluster_id, parents, accounts) = match (ca, cb) {
+            (None, None) => {
+                let id = self.new_cluster_id();
+                let members: HashSet<String> =
+                    [self.graph[na].clone(), self.graph[nb].clone()].into();
+                self.clusters.insert(id, members.clone());
+                (ClusterEventKind::Formed, id, Vec::new(), members)
+            }
+            (Some(id), None) | (None, Some(id)) => {
+                let joiner = if ca.is_some() { nb } else { na };
+                let account = self.graph[joiner].clone();
+                self.clusters.entry(id).or_default().insert(account.clone());
+                (ClusterEventKind::Joined, id, Vec::new(), [account].into())
+            }
+            (Some(x), Some(y)) => {
+                // Larger cluster keeps its id; ties go to the older (lower) id.
+                let (lx, ly) = (self.cluster_size(x), self.cluster_size(y));
+                let (keep, absorb) = if lx > ly || (lx == ly && x < y) {
+                    (x, y)
+                } else {
+                    (y, x)
+                };
+                let moved = self.clusters.remove(&absorb).unwrap_or_default();
+                self.parents.remove(&absorb);
+                self.parents.entry(keep).or_default().push(absorb);
+                self.clusters
+                    .entry(keep)
+                    .or_default()
+                    .extend(moved.iter().cloned());
+                (ClusterEventKind::Merged, keep, vec![absorb], moved)
+            }
+        };
+        self.root_cluster.insert(root, cluster_id);
+
+        let mut accounts: Vec<String> = accounts.into_iter().collect();
+        accounts.sort();
+        self.push_event(ClusterEvent {
+            kind,
+            cluster_id,
+            parents,
+            accounts,
+            size: self.cluster_size(cluster_id),
+            edge: Some((self.graph[na].clone(), self.graph[nb].clone())),
+            links,
+            weight,
+            timestamp: now,
+        });
+        cluster_id
+    }
+
+    fn new_cluster_id(&mut self) -> u32 {
+        let id = self.next_cluster;
+        self.next_cluster += 1;
+        id
+    }
+
+    /// Re-apply supernode scaling to existing `kind` links: `scale(a, b)` is
+    /// the pair's current scale (None leaves the link as it is); links scaled
+    /// to 0 are dropped and their empty edges removed by `recompute`.  Returns
+    /// the number of links changed.
+    pub fn rescale(
+        &mut self,
+        kind: LinkKind,
+        mut scale: impl FnMut(&str, &str) -> Option<f32>,
+    ) -> usize {
+        let mut changed = 0;
+        let edges: Vec<EdgeIndex> = self.graph.edge_indices().collect();
+        for e in edges {
+            let Some(current) = self.graph[e].links.get(&kind).map(|s| s.scale) else {
+                continue;
+            };
+            let Some((na, nb)) = self.graph.edge_endpoints(e) else {
+                continue;
+            };
+            let Some(s) = scale(&self.graph[na], &self.graph[nb]) else {
+                continue;
+            };
+            if s == current {
+                continue;
+            }
+            let edge = &mut self.graph[e];
+            if s <= 0.0 {
+                edge.links.remove(&kind);
+            } else if let Some(seen) = edge.links.get_mut(&kind) {
+                seen.scale = s;
+            }
+            edge.reweigh(&self.cfg.weights);
+            changed += 1;
+        }
+        changed
+    }
+
+    /// Expire links older than their TTL and rebuild clusters from the
+    /// remaining strong edges, carrying ids over by overlap.
+    pub fn recompute(&mut self, now: DateTime<Utc>) -> RecomputeStats {
+        let mut stats = RecomputeStats::default();
+
+        // 1. Expire links; drop empty edges and isolated accounts.
+        let edges: Vec<EdgeIndex> = self.graph.edge_indices().collect();
+        for e in edges {
+            let edge = &mut self.graph[e];
+            let before = edge.links.len();
+            edge.links
+                .retain(|kind, seen| now - seen.last_seen <= self.cfg.ttl.of(*kind));
+            stats.expired_links += before - edge.links.len();
+            if edge.links.is_empty() {
+                self.graph.remove_edge(e);
+                stats.removed_edges += 1;
+            } else {
+                edge.reweigh(&self.cfg.weights);
+            }
+        }
+        let isolated: Vec<NodeIndex> = self
+            .graph
+            .node_indices()
+            .filter(|n| self.graph.neighbors(*n).next().is_none())
+            .collect();
+        for n in isolated {
+            if let Some(account) = self.graph.remove_node(n) {
+                self.nodes.remove(&account);
+                stats.removed_accounts += 1;
+            }
+        }
+
+        // 2. Components of the strong subgraph.
+        let mut uf = UnionFind::default();
+        uf.ensure(self.graph.node_bound());
+        for e in (&self.graph).edge_references() {
+            if e.weight().weight >= self.cfg.min_edge_weight {
+                let (ra, rb) = (uf.find(e.source().index()), uf.find(e.target().index()));
+                if ra != rb {
+                    uf.union_roots(ra, rb);
+                }
+            }
+        }
+        let mut components: HashMap<usize, HashSet<String>> = HashMap::new();
+        for n in self.graph.node_indices() {
+            components
+                .entry(uf.find(n.index()))
+                .or_default()
+                .insert(self.graph[n].clone());
+        }
+        let mut components: Vec<(usize, HashSet<String>)> = components
+            .into_iter()
+            .filter(|(_, m)| m.len() >= 2)
+            .collect();
+        // Largest first, so the biggest piece of a split keeps the old id.
+        components.sort_by(|a, b| {
+            b.1.len()
+                .cmp(&a.1.len())
+                .then_with(|| a.1.iter().min().cmp(&b.1.iter().min()))
+        });
+
+        // 3. Carry ids over by overlap.
+        let old_clusters = std::mem::take(&mut self.clusters);
+        let old_of: HashMap<&str, u32> = old_clusters
+            .iter()
+            .flat_map(|(id, m)| m.iter().map(move |a| (a.as_str(), *id)))
+            .collect();
+        let mut root_cluster = HashMap::new();
+        let mut taken: HashSet<u32> = HashSet::new();
+        for (root, members) in components {
+            let mut overlap: HashMap<u32, usize> = HashMap::new();
+            for a in &members {
+                if let Some(id) = old_of.get(a.as_str()) {
+                    *overlap.entry(*id).or_default() += 1;
+                }
+            }
+            let mut ranked: Vec<(u32, usize)> = overlap.into_iter().collect();
+            ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            let origins: Vec<u32> = ranked.iter().map(|(id, _)| *id).collect();
+
+            let id = match origins.iter().find(|id| !taken.contains(id)) {
+                Some(&id) => id,
+                None => {
+                    let id = self.new_cluster_id();
+                    let kind = if origins.is_empty() {
+                        ClusterEventKind::Formed
+                    } else {
+                        stats.splits += 1;
+                        ClusterEventKind::Split
+                    };
+                    let mut accounts: Vec<String> = members.iter().cloned().collect();
+                    accounts.sort();
+                    self.parents.insert(id, origins.clone());
+                    self.push_event(recompute_event(
+                        kind,
+                        id,
+                        origins,
+                        accounts,
+                        members.len(),
+                        now,
+                    ));
+                    id
+                }
+            };
+            taken.insert(id);
+            root_cluster.insert(root, id);
+            self.clusters.insert(id, members);
+        }
+
+        // 4. Shrunk / dissolved clusters.
+        let mut old_ids: Vec<&u32> = old_clusters.keys().collect();
+        old_ids.sort();
+        for id in old_ids {
+            let old = &old_clusters[id];
+            let (kind, gone) = match self.clusters.get(id) {
+                Some(new) => (
+                    ClusterEventKind::Shrunk,
+                    old.difference(new).cloned().collect(),
+                ),
+                None => {
+                    stats.dissolved += 1;
+                    self.parents.remove(id);
+                    (ClusterEventKind::Dissolved, old.iter().cloned().collect())
+                }
+            };
+            let mut gone: Vec<String> = gone;
+            if gone.is_empty() {
+                continue;
+            }
+            gone.sort();
+            let size = self.cluster_size(*id);
+            self.push_event(recompute_event(kind, *id, Vec::new(), gone, size, now));
+        }
+
+        self.uf = uf;
+        self.root_cluster = root_cluster;
+        stats.clusters = self.clusters.len();
+        stats
+    }
+
+    fn push_event(&mut self, event: ClusterEvent) {
+        if self.events.len() >= self.cfg.max_pending_events {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Take queued cluster events (oldest first).
+    pub fn drain_events(&mut self) -> Vec<ClusterEvent> {
+        self.events.drain(..).collect()
+    }
+
+    // ── Queries ───────────────────────────────────────────────────────────────
+
+    pub fn cluster_of(&self, account_id: &str) -> Option<u32> {
+        let n = self.nodes.get(account_id)?;
+        self.root_cluster.get(&self.uf.root(n.index())).copied()
+    }
+
+    pub fn members(&self, cluster_id: u32) -> HashSet<String> {
+        self.clusters.get(&cluster_id).cloned().unwrap_or_default()
+    }
+
+    pub fn cluster_size(&self, cluster_id: u32) -> usize {
+        self.clusters.get(&cluster_id).map_or(0, |m| m.len())
+    }
+
+    /// Clusters this one was merged from or split off (most recent last).
+    pub fn parents(&self, cluster_id: u32) -> Vec<u32> {
+        self.parents.get(&cluster_id).cloned().unwrap_or_default()
+    }
+
+    pub fn n_clusters(&self) -> usize {
+        self.clusters.len()
+    }
+
+    pub fn n_nodes(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    pub fn n_edges(&self) -> usize {
+        self.graph.edge_count()
+    }
+
+    /// Edge between two accounts, if any.
+    pub fn edge(&self, a: &str, b: &str) -> Option<&Edge> {
+        let (na, nb) = (self.nodes.get(a)?, self.nodes.get(b)?);
+        self.graph.find_edge(*na, *nb).map(|e| &self.graph[e])
+    }
+
+    /// Every edge with its two accounts (graph export, state/export.rs).
+    pub fn edges(&self) -> impl Iterator<Item = (&str, &str, &Edge)> + '_ {
+        (&self.graph).edge_references().map(|e| {
+            (
+                self.graph[e.source()].as_str(),
+                self.graph[e.target()].as_str(),
+                e.weight(),
+            )
+        })
+    }
+}
+
+fn recompute_event(
+    kind: ClusterEventKind,
+    cluster_id: u32,
+    parents: Vec<u32>,
+    accounts: Vec<String>,
+    size: usize,
+    now: DateTime<Utc>,
+) -> ClusterEvent {
+    ClusterEvent {
+        kind,
+        cluster_id,
+        parents,
+        accounts,
+        size,
+        edge: None,
+        links: Vec::new(),
+        weight: 0.0,
+        timestamp: now,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_links_form_and_merge_clusters_without_leaking() {
+        let mut g = AccountGraph::new(GraphConfig::default());
+        let now = Utc::now();
+
+        // A shared subnet alone is below threshold; adding JA3 crosses it.
+        assert_eq!(g.link("a", "b", LinkKind::Subnet, 1.0, now), None);
+        assert_eq!(g.n_clusters(), 0);
+        let c1 = g.link("a", "b", LinkKind::Ja3, 1.0, now).unwrap();
+        assert_eq!(g.edge("a", "b").unwrap().weight, 4.0);
+
+        // Shared payment links on its own; c grows c1.
+        assert_eq!(g.link("b", "c", LinkKind::Payment, 1.0, now), Some(c1));
+
+        // A second, smaller cluster.
+        let c2 = g.link("x", "y", LinkKind::Payment, 1.0, now).unwrap();
+        assert_ne!(c1, c2);
+
+        // A bridging edge merges them: the larger keeps its id, the smaller's
+        // members move and its entry is gone.
+        assert_eq!(g.link("c", "x", LinkKind::Payment, 1.0, now), Some(c1));
+        assert_eq!(g.n_clusters(), 1);
+        for acct in ["a", "b", "c", "x", "y"] {
+            assert_eq!(g.cluster_of(acct), Some(c1));
+        }
+        assert_eq!(g.cluster_size(c1), 5);
+        assert!(g.members(c2).is_empty());
+
+        let events = g.drain_events();
+        let kinds: Vec<ClusterEventKind> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ClusterEventKind::Formed,
+                ClusterEventKind::Joined,
+                ClusterEventKind::Formed,
+                ClusterEventKind::Merged
+            ]
+        );
+        let merge = events.last().unwrap();
+        assert_eq!(merge.parents, vec![c2]);
+        assert_eq!(merge.accounts, vec!["x".to_string(), "y".to_string()]);
+        assert_eq!(merge.size, 5);
+        assert!(g.drain_events().is_empty());
+    }
+
+    #[test]
+    fn expired_links_split_clusters_with_lineage() {
+        let mut g = AccountGraph::new(GraphConfig::default());
+        let t0 = Utc::now() - Duration::days(10);
+        let now = Utc::now();
+
+        // a–b by card (fresh), b–c by an old shared subnet + JA3, c–d by card.
+        g.link("a", "b", LinkKind::Payment, 1.0, now);
+        g.link("b", "c", LinkKind::Subnet, 1.0, t0);
+        g.link("b", "c", LinkKind::Ja3, 1.0, t0);
+        g.link("c", "d", LinkKind::Payment, 1.0, now);
+        g.link("d", "e", LinkKind::Payment, 1.0, now);
+        let parent = g.cluster_of("a").unwrap();
+        assert_eq!(g.cluster_size(parent), 5);
+        g.drain_events();
+
+        // Subnet (3d) and JA3 (7d) links expire: the edge goes, the cluster splits.
+        let stats = g.recompute(now);
+        assert_eq!(stats.expired_links, 2);
+        assert_eq!(stats.removed_edges, 1);
+        assert_eq!(stats.splits, 1);
+
+        // The larger piece keeps the id; the other is a child of it.
+        assert_eq!(g.cluster_of("c"), Some(parent));
+        let child = g.cluster_of("a").unwrap();
+        assert_ne!(child, parent);
+        assert_eq!(g.members(parent).len(), 3);
+        assert_eq!(g.parents(child), vec![parent]);
+
+        let events = g.drain_events();
+        let split = events
+            .iter()
+            .find(|e| e.kind == ClusterEventKind::Split)
+            .unwrap();
+        assert_eq!(split.parents, vec![parent]);
+        assert_eq!(split.accounts, vec!["a".to_string(), "b".to_string()]);
+        let shrunk = events
+            .iter()
+            .find(|e| e.kind == ClusterEventKind::Shrunk)
+            .unwrap();
+        assert_eq!(shrunk.cluster_id, parent);
+    }
+
+    #[test]
+    fn config_load_rejects_bad_subnet_prefixes() {
+        let path =
+            std::env::temp_dir().join(format!("glasswally_graph_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{ "subnets": { "v4": 16, "v6": 48 } }"#).unwrap();
+        let cfg = GraphConfig::load(&path).unwrap();
+        assert_eq!(cfg.subnets, SubnetPrefixes { v4: 16, v6: 48 });
+
+        for bad in [
+            r#"{ "subnets": { "v4": 40 } }"#,
+            r#"{ "subnets": { "v6": 200 } }"#,
+        ] {
+            std::fs::write(&path, bad).unwrap();
+            let err = GraphConfig::load(&path).unwrap_err();
+            assert!(format!("{err:#}").contains("outside"), "{err:#}");
+        }
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -1 +1,9 @@
+pub mod backend;
+pub mod entity;
+pub mod export;
+pub mod graph;
+pub mod partition;
+pub mod risk;
+pub mod rollup;
+pub mod supernode;
 pub mod window;
@@ -0,0 +1,556 @@
+// glasswally/src/state/partition.rs
+//
+// Partitioned deployment — per-account state sharded across instances.
+//
+// One process cannot hold provider-scale traffic, but cross-account signals
+// (Hydra, TimingCluster, preamble collisions, entity velocity) need a global
+// view.  StateStore::ingest is split in two halves:
+//
+//   local    account window, model pivots, rollups — kept only by the owner
+//   global   IndexUpdate: entity windows, reverse indexes (payment, org, JA3,
+//            JA3S, subnet, preamble), timing buckets, graph links — applied
+//            by every instance
+//
+// An account is owned by instance `value_hash(account_id) % instances`.
+// Each instance processes only the events of accounts it owns (events for
+// other accounts are dropped, so every instance can tail the same log or sit
+// behind an account-hash load balancer) and broadcasts the IndexUpdate of
+// each event to its peers, which apply it with StateStore::apply_update.
+// Every instance therefore holds the full graph and indexes, and the same
+// clusters as a single instance would.
+//
+// Exchange protocol: newline-delimited JSON IndexUpdates over TCP.  Each
+// instance listens on `listen` and keeps one connection per peer,
+// reconnecting on failure; updates queued for an unreachable peer beyond
+// QUEUE_DEPTH are dropped and counted.
+//
+// The listener only accepts connections from the IP addresses in `peers`.
+// With `secret_file` set (the same file on every instance) each connection
+// also answers a challenge first: the listener sends a random nonce line, the
+// connecting peer replies with hex HMAC-SHA256(secret, "gw_exchange_v1:" ||
+// nonce).  Anything else is closed before an update is read.  Without a
+// secret, anyone able to connect from a peer address can inject updates —
+// set one unless the exchange network is otherwise isolated.
+//
+// Not replicated: account windows, risk, canary registry and audit streams.
+// Cluster scoring (engine/cluster_fusion.rs) reads member windows, so each
+// instance scores a cluster from the members it owns; cluster lifecycle
+// events are recorded by instance 0 only.
+//
+// LocalCluster runs N instances in one process with synchronous delivery —
+// the harness the partition tests use to compare against a single store.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use super::backend::StateBackend;
+use super::graph::GraphConfig;
+use super::window::{value_hash, IndexUpdate, StateStore};
+use crate::events::ApiEvent;
+
+const QUEUE_DEPTH: usize = 65_536;
+const RECONNECT_SECS: u64 = 1;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// ── Configuration ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PartitionConfig {
+    pub instance: usize,
+    pub instances: usize,
+    pub listen: Option<SocketAddr>,   // exchange listener
+    pub peers: Vec<SocketAddr>,       // every other instance's listener
+    pub secret_file: Option<PathBuf>, // shared exchange secret (raw bytes)
+    #[serde(skip)]
+    pub secret: Option<Vec<u8>>,
+}
+
+impl Default for PartitionConfig {
+    fn default() -> Self {
+        Self {
+            instance: 0,
+            instances: 1,
+            listen: None,
+            peers: Vec::new(),
+            secret_file: None,
+            secret: None,
+        }
+    }
+}
+
+impl PartitionConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading partition config {}", path.display()))?;
+        let mut cfg: Self = serde_json::from_str(&content)
+            .with_context(|| format!("parsing partition config {}", path.display()))?;
+        if let Some(file) = &cfg.secret_file {
+            let secret = std::fs::read(file)
+                .with_context(|| format!("reading exchange secret {}", file.display()))?;
+            if secret.is_empty() {
+                bail!("exchange secret {} is empty", file.display());
+            }
+            cfg.secret = Some(secret);
+        }
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.instances == 0 || self.instance >= self.instances {
+            bail!(
+                "partition instance {} out of range for {} instances",
+                self.instance,
+                self.instances
+            );
+        }
+        if self.instances > 1 && (self.listen.is_none() || self.peers.len() + 1 != self.instances) {
+            bail!(
+                "partitioned mode needs `listen` and {} peers",
+                self.instances - 1
+            );
+        }
+        Ok(())
+    }
+
+    pub fn owns(&self, account_id: &str) -> bool {
+        owner_of(account_id, self.instances) == self.instance
+    }
+
+    /// Cluster lifecycle events are recorded once, by instance 0.
+    pub fn records_cluster_events(&self) -> bool {
+        self.instance == 0
+    }
+}
+
+/// Instance owning an account's state.
+pub fn owner_of(account_id: &str, instances: usize) -> usize {
+    (value_hash(account_id) % instances.max(1) as u64) as usize
+}
+
+// ── TCP exchange ──────────────────────────────────────────────────────────────
+
+#[derive(Default)]
+pub struct ExchangeStats {
+    pub sent: AtomicU64,
+    pub received: AtomicU64,
+    pub dropped: AtomicU64,
+    pub rejected: AtomicU64, // connections from non-peers or failing the challenge
+}
+
+pub struct Exchange {
+    peers: Vec<mpsc::Sender<Arc<str>>>,
+    pub stats: Arc<ExchangeStats>,
+}
+
+impl Exchange {
+    /// Start the listener (updates applied to `store`) and peer senders.
+    pub async fn start<S: StateBackend>(cfg: &PartitionConfig, store: Arc<S>) -> Result<Arc<Self>> {
+        let stats = Arc::new(ExchangeStats::default());
+        if let Some(addr) = cfg.listen {
+            let listener = TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("binding exchange listener {}", addr))?;
+            info!(
+                "Partition {}/{} exchange listening on {}",
+                cfg.instance, cfg.instances, addr
+            );
+            if cfg.secret.is_none() {
+                warn!("Exchange has no secret_file: peers are trusted by address only");
+            }
+            let allowed: Vec<_> = cfg.peers.iter().map(SocketAddr::ip).collect();
+            let secret = cfg.secret.clone().map(Arc::<[u8]>::from);
+            let stats = Arc::clone(&stats);
+            tokio::spawn(async move {
+                loop {
+                    let Ok((stream, peer)) = listener.accept().await else {
+                        continue;
+                    };
+                    if !allowed.contains(&peer.ip()) {
+                        warn!("Exchange connection from non-peer {} rejected", peer);
+                        stats.rejected.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                    let store = Arc::clone(&store);
+                    let stats = Arc::clone(&stats);
+                    let secret = secret.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) =
+                            receive(stream, secret.as_deref(), store.resident(), &stats).await
+                        {
+                            warn!("Exchange connection from {} closed: {}", peer, e);
+                        }
+                    });
+                }
+            });
+        }
+        let secret = cfg.secret.clone().map(Arc::<[u8]>::from);
+        let peers = cfg
+            .peers
+            .iter()
+            .map(|addr| {
+                let (tx, rx) = mpsc::channel(QUEUE_DEPTH);
+                tokio::spawn(send_loop(*addr, secret.clone(), rx, Arc::clone(&stats)));
+                tx
+            })
+            .collect();
+        Ok(Arc::new(Self { peers, stats }))
+    }
+
+    /// Queue an update for every peer.
+    pub fn publish(&self, update: &IndexUpdate) {
+        let Ok(line) = serde_json::to_string(update) else {
+            return;
+        };
+        let line: Arc<str> = Arc::from(line);
+        for peer in &self.peers {
+            if peer.try_send(Arc::clone(&line)).is_err() {
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+// hex HMAC-SHA256(secret, "gw_exchange_v1:" || nonce).
+fn challenge_mac(secret: &[u8], nonce: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key");
+    mac.update(b"gw_exchange_v1:");
+    mac.update(nonce.as_bytes());
+    mac
+}
+
+async fn receive(
+    stream: TcpStream,
+    secret: Option<&[u8]>,
+    store: &StateStore,
+    stats: &ExchangeStats,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    if let Some(secret) = secret {
+        let mut nonce = [0u8; 16];
+        tokio_rustls::rustls::crypto::ring::default_provider()
+            .secure_random
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("no randomness for exchange nonce"))?;
+        let nonce = hex::encode(nonce);
+        reader
+            .get_mut()
+            .write_all(format!("{nonce}\n").as_bytes())
+            .await?;
+        let mut reply = String::new();
+        timeout(HANDSHAKE_TIMEOUT, reader.read_line(&mut reply))
+            .await
+            .context("exchange challenge timed out")??;
+        let ok = hex::decode(reply.trim())
+            .is_ok_and(|tag| challenge_mac(secret, &nonce).verify_slice(&tag).is_ok());
+        if !ok {
+            stats.rejected.fetch_add(1, Ordering::Relaxed);
+            bail!("exchange challenge failed");
+        }
+    }
+    let mut lines = reader.lines();
+    while let Some(line) = lines.next_line().await? {
+        let update: IndexUpdate = serde_json::from_str(&line).context("bad index update")?;
+        store.apply_update(&update);
+        stats.received.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+// Answer the listener's nonce line.
+async fn answer_challenge(stream: &mut TcpStream, secret: &[u8]) -> Result<()> {
+    let mut nonce = String::new();
+    timeout(
+        HANDSHAKE_TIMEOUT,
+        BufReader::new(&mut *stream).read_line(&mut nonce),
+    )
+    .await
+    .context("exchange challenge timed out")??;
+    let tag = challenge_mac(secret, nonce.trim()).finalize().into_bytes();
+    stream
+        .write_all(format!("{}\n", hex::encode(tag)).as_bytes())
+        .await?;
+    Ok(())
+}
+
+async fn send_loop(
+    addr: SocketAddr,
+    secret: Option<Arc<[u8]>>,
+    mut rx: mpsc::Receiver<Arc<str>>,
+    stats: Arc<ExchangeStats>,
+) {
+    let mut pending: Option<Arc<str>> = None;
+    loop {
+        let connected = async {
+            let mut stream = TcpStream::connect(addr).await?;
+            if let Some(secret) = &secret {
+                answer_challenge(&mut stream, secret).await?;
+            }
+            anyhow::Ok(stream)
+        };
+        let mut stream = match connected.await {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("Exchange peer {} unreachable: {}", addr, e);
+                tokio::time::sleep(Duration::from_secs(RECONNECT_SECS)).await;
+                continue;
+            }
+        };
+        info!("Exchange connected to peer {}", addr);
+        loop {
+            let line = match pending.take() {
+                Some(l) => l,
+                None => match rx.recv().await {
+                    Some(l) => l,
+                    None => return,
+                },
+            };
+            let write = async {
+                stream.write_all(line.as_bytes()).await?;
+                stream.write_all(b"\n").await
+            };
+            if let Err(e) = write.await {
+                warn!("Exchange peer {} write failed: {}", addr, e);
+                pending = Some(line);
+                break;
+            }
+            stats.sent.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// ── In-process harness ────────────────────────────────────────────────────────
+
+/// N partitioned instances in one process, with updates delivered
+/// synchronously in ingest order.
+pub struct LocalCluster {
+    pub instances: Vec<StateStore>,
+}
+
+impl LocalCluster {
+    pub fn new(n: usize, graph: GraphConfig) -> Self {
+        Self {
+            instances: (0..n.max(1))
+                .map(|_| StateStore::with_graph_config(graph.clone()))
+                .collect(),
+        }
+    }
+
+    /// Ingest on the owning instance and apply its update on the others.
+    /// Returns the owner.
+    pub fn ingest(&self, event: &ApiEvent) -> usize {
+        let owner = owner_of(&event.account_id, self.instances.len());
+        let update = self.instances[owner].ingest(event);
+        for (i, store) in self.instances.iter().enumerate() {
+            if i != owner {
+                store.apply_update(&update);
+            }
+        }
+        owner
+    }
+
+    pub fn recompute_clusters(&self) {
+        for store in &self.instances {
+            store.recompute_clusters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use chrono::{DateTime, Duration, Utc};
+
+    use super::*;
+
+    fn event(
+        account: &str,
+        ts: DateTime<Utc>,
+        ip: &str,
+        ja3: &str,
+        payment: Option<&str>,
+    ) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": ts,
+            "ip_address": ip, "user_agent": "ua", "model": "m",
+            "prompt": "explain step by step", "token_count": 100,
+            "payment_method_hash": payment, "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": ja3, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    /// Clusters as sorted member sets, over the given accounts.
+    fn clusters(store: &StateStore, accounts: &[String]) -> BTreeSet<Vec<String>> {
+        accounts
+            .iter()
+            .filter_map(|a| store.get_cluster(a))
+            .map(|cid| {
+                let mut m: Vec<String> = store.cluster_members(cid).into_iter().collect();
+                m.sort();
+                m
+            })
+            .collect()
+    }
+
+    #[test]
+    fn partitioned_instances_match_single_instance() {
+        let t0: DateTime<Utc> = "2026-03-01T00:00:00Z".parse().unwrap();
+        let mut events = Vec::new();
+        let mut accounts = Vec::new();
+        for i in 0..60 {
+            let account = format!("acct_{i}");
+            // Payment rings of 4, subnet + JA3 pairs, and loners.
+            let payment = (i < 24).then(|| format!("pm_{}", i / 4));
+            let (ip, ja3) = if (24..44).contains(&i) {
+                (
+                    format!("198.51.{}.{}", i / 2, i),
+                    format!("ja3_pair_{}", i / 2),
+                )
+            } else {
+                (format!("203.0.{}.1", i), format!("ja3_{}", i))
+            };
+            for k in 0..3 {
+                let ts = t0 + Duration::seconds(i * 7 + k * 600);
+                events.push(event(&account, ts, &ip, &ja3, payment.as_deref()));
+            }
+            accounts.push(account);
+        }
+        events.sort_by_key(|e| e.timestamp);
+
+        let single = StateStore::new();
+        let cluster = LocalCluster::new(3, GraphConfig::default());
+        for e in &events {
+            single.ingest(e);
+            cluster.ingest(e);
+        }
+
+        let expected = clusters(&single, &accounts);
+        assert_eq!(expected.len(), 16); // 6 payment rings + 10 subnet pairs
+        for store in &cluster.instances {
+            assert_eq!(clusters(store, &accounts), expected);
+        }
+
+        // Account windows are sharded: each account lives on its owner only.
+        let total: usize = cluster.instances.iter().map(|s| s.n_accounts()).sum();
+        assert_eq!(total, single.n_accounts());
+        for a in &accounts {
+            let owner = owner_of(a, 3);
+            for (i, store) in cluster.instances.iter().enumerate() {
+                assert_eq!(store.get_window(a).is_some(), i == owner);
+            }
+        }
+
+        // Global indexes are complete everywhere.
+        for store in &cluster.instances {
+            assert_eq!(store.accounts_with_ja3("ja3_pair_12").len(), 2);
+            assert_eq!(store.timing_bucket_count(), single.timing_bucket_count());
+        }
+
+        // Expiry and recompute keep the instances in agreement.
+        single.recompute_clusters();
+        cluster.recompute_clusters();
+        for store in &cluster.instances {
+            assert_eq!(clusters(store, &accounts), clusters(&single, &accounts));
+        }
+    }
+
+    fn free_addr() -> SocketAddr {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    async fn until(cond: impl Fn() -> bool) -> bool {
+        for _ in 0..200 {
+            if cond() {
+                return true;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    fn update(account: &str, ja3: &str) -> IndexUpdate {
+        let t0: DateTime<Utc> = "2026-03-01T00:00:00Z".parse().unwrap();
+        StateStore::new().ingest(&event(account, t0, "203.0.113.7", ja3, None))
+    }
+
+    #[tokio::test]
+    async fn exchange_authenticates_peers() {
+        let (a_addr, b_addr) = (free_addr(), free_addr());
+        let cfg = |instance, listen, peer| PartitionConfig {
+            instance,
+            instances: 2,
+            listen: Some(listen),
+            peers: vec![peer],
+            secret: Some(b"shared exchange secret".to_vec()),
+            ..PartitionConfig::default()
+        };
+        let a_store = Arc::new(StateStore::new());
+        let a = Exchange::start(&cfg(0, a_addr, b_addr), Arc::clone(&a_store))
+            .await
+            .unwrap();
+        let b = Exchange::start(&cfg(1, b_addr, a_addr), Arc::new(StateStore::new()))
+            .await
+            .unwrap();
+
+        b.publish(&update("acct_b", "ja3_from_peer"));
+        assert!(until(|| a_store.accounts_with_ja3("ja3_from_peer").len() == 1).await);
+
+        // Right address, no answer to the challenge.
+        let mut intruder = TcpStream::connect(a_addr).await.unwrap();
+        let line = serde_json::to_string(&update("acct_x", "ja3_injected")).unwrap();
+        intruder
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .ok();
+        assert!(until(|| a.stats.rejected.load(Ordering::Relaxed) == 1).await);
+        assert!(a_store.accounts_with_ja3("ja3_injected").is_empty());
+        assert_eq!(a.stats.received.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn exchange_rejects_non_peer_addresses() {
+        let listen = free_addr();
+        let cfg = PartitionConfig {
+            instance: 0,
+            instances: 2,
+            listen: Some(listen),
+            peers: vec!["10.0.0.2:7400".parse().unwrap()],
+            ..PartitionConfig::default()
+        };
+        let store = Arc::new(StateStore::new());
+        let ex = Exchange::start(&cfg, Arc::clone(&store)).await.unwrap();
+
+        let mut stranger = TcpStream::connect(listen).await.unwrap();
+        let line = serde_json::to_string(&update("acct_x", "ja3_injected")).unwrap();
+        stranger
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .ok();
+        assert!(until(|| ex.stats.rejected.load(Ordering::Relaxed) == 1).await);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(store.accounts_with_ja3("ja3_injected").is_empty());
+        assert_eq!(ex.stats.received.load(Ordering::Relaxed), 0);
+    }
+}
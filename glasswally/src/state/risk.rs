@@ -0,0 +1,199 @@
+// glasswally/src/state/risk.rs
+//
+// Persistent per-account risk with exponential temporal decay.
+//
+// Per-event composites are noisy: a campaign that spreads its behaviour across
+// hundreds of mildly-scored events never crosses a tier threshold on any single
+// event.  AccountRisk accumulates event composites into a single score that
+// decays between events:
+//
+//   decayed  = score × 0.5^(Δt / half_life)
+//   score'   = max(event, decayed + gain × event × (1 − decayed))
+//
+// Properties:
+//   - A single strong event is never delayed (max with the event composite).
+//   - Repeated mild events (≥ floor) ratchet the score up, saturating at 1.0.
+//   - Quiet accounts decay back toward zero.
+//   - Once an account reaches High or Critical it decays on the slower
+//     `escalated_half_life_secs`, and its peak tier is held for `hold_secs`
+//     (monotonic escalation — a paused campaign does not immediately de-tier).
+//
+// AccountRisk lives on AccountWindow, so it is persisted with every window
+// snapshot (see redis_state.rs) and survives restarts.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::events::{RiskTier, WorkerKind};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecayConfig {
+    pub half_life_secs: i64,
+    pub escalated_half_life_secs: i64,
+    pub gain: f32,      // fraction of remaining headroom one event can claim
+    pub floor: f32,     // events below this composite do not accumulate
+    pub hold_secs: i64, // peak tier held for this long after it was reached
+}
+
+impl Default for DecayConfig {
+    fn default() -> Self {
+        Self {
+            half_life_secs: 6 * 3600,
+            escalated_half_life_secs: 48 * 3600,
+            gain: 0.05,
+            floor: 0.20,
+            hold_secs: 24 * 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountRisk {
+    pub score: f32,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub peak_tier: Option<RiskTier>,
+    pub peak_at: Option<DateTime<Utc>>,
+    pub events_scored: u64,
+    pub last_evidence: Vec<String>, // top evidence from the last tiered decision
+    #[serde(default)]
+    pub last_drivers: Vec<WorkerKind>, // workers that drove the last tiered decision
+    #[serde(default)]
+    pub coordination: HashMap<WorkerKind, f32>, // latest coordinated-behaviour signal scores
+}
+
+impl AccountRisk {
+    fn half_life(&self, cfg: &DecayConfig) -> i64 {
+        match self.peak_tier {
+            Some(t) if t >= RiskTier::High => cfg.escalated_half_life_secs,
+            _ => cfg.half_life_secs,
+        }
+        .max(1)
+    }
+
+    /// Score decayed to `now` (does not mutate).
+    pub fn decayed(&self, cfg: &DecayConfig, now: DateTime<Utc>) -> f32 {
+        let Some(updated) = self.updated_at else {
+            return 0.0;
+        };
+        let dt = (now - updated).num_seconds().max(0) as f64;
+        let factor = 0.5f64.powf(dt / self.half_life(cfg) as f64) as f32;
+        self.score * factor
+    }
+
+    /// Fold one event composite into the accumulated score; returns the new score.
+    pub fn update(&mut self, event_score: f32, cfg: &DecayConfig, now: DateTime<Utc>) -> f32 {
+        let now = self.updated_at.map(|u| u.max(now)).unwrap_or(now);
+        let decayed = self.decayed(cfg, now);
+        let accumulated = if event_score >= cfg.floor {
+            decayed + cfg.gain * event_score * (1.0 - decayed)
+        } else {
+            decayed
+        };
+        self.score = ((accumulated.max(event_score).min(1.0)) * 10000.0).round() / 10000.0;
+        self.updated_at = Some(now);
+        self.events_scored += 1;
+        self.score
+    }
+
+    /// Record that `tier` was assigned at `now`; refreshes the held peak unless
+    /// a higher tier is still being held.
+    pub fn record_tier(&mut self, tier: RiskTier, cfg: &DecayConfig, now: DateTime<Utc>) {
+        if self.held_tier(cfg, now).is_none_or(|held| tier >= held) {
+            self.peak_tier = Some(tier);
+            self.peak_at = Some(now);
+        }
+    }
+
+    /// Peak tier if it is still inside the hold period.
+    pub fn held_tier(&self, cfg: &DecayConfig, now: DateTime<Utc>) -> Option<RiskTier> {
+        match (self.peak_tier, self.peak_at) {
+            (Some(t), Some(at)) if (now - at).num_seconds() < cfg.hold_secs => Some(t),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn cfg() -> DecayConfig {
+        DecayConfig {
+            half_life_secs: 3600,
+            escalated_half_life_secs: 4 * 3600,
+            gain: 0.1,
+            floor: 0.2,
+            hold_secs: 1800,
+        }
+    }
+
+    #[test]
+    fn decays_by_half_life() {
+        let cfg = cfg();
+        let t0: DateTime<Utc> = "2026-03-01T00:00:00Z".parse().unwrap();
+        let mut r = AccountRisk::default();
+        assert_eq!(r.decayed(&cfg, t0), 0.0);
+
+        assert_eq!(r.update(0.8, &cfg, t0), 0.8);
+        assert!((r.decayed(&cfg, t0 + Duration::hours(1)) - 0.4).abs() < 1e-4);
+        assert!((r.decayed(&cfg, t0 + Duration::hours(2)) - 0.2).abs() < 1e-4);
+        // Clock skew never inflates the score
+        assert_eq!(r.decayed(&cfg, t0 - Duration::hours(1)), 0.8);
+
+        // Escalated accounts decay on the slower half-life
+        r.record_tier(RiskTier::High, &cfg, t0);
+        assert!((r.decayed(&cfg, t0 + Duration::hours(4)) - 0.4).abs() < 1e-4);
+    }
+
+    #[test]
+    fn mild_events_accumulate_above_floor_only() {
+        let cfg = cfg();
+        let t0: DateTime<Utc> = "2026-03-01T00:00:00Z".parse().unwrap();
+
+        let mut mild = AccountRisk::default();
+        let mut noise = AccountRisk::default();
+        for k in 0..40 {
+            let ts = t0 + Duration::seconds(k * 10);
+            mild.update(0.3, &cfg, ts);
+            noise.update(0.1, &cfg, ts);
+        }
+        assert_eq!(mild.events_scored, 40);
+        assert!(mild.score > 0.55, "mild events ratchet up: {}", mild.score);
+        assert!(mild.score <= 1.0);
+        assert_eq!(noise.score, 0.1, "below-floor events never accumulate");
+
+        // A single strong event is never delayed
+        let mut strong = AccountRisk::default();
+        assert_eq!(strong.update(0.9, &cfg, t0), 0.9);
+        // Out-of-order events fold in at the latest timestamp seen
+        strong.update(0.0, &cfg, t0 - Duration::hours(5));
+        assert_eq!(strong.updated_at, Some(t0));
+    }
+
+    #[test]
+    fn peak_tier_is_held_then_released() {
+        let cfg = cfg();
+        let t0: DateTime<Utc> = "2026-03-01T00:00:00Z".parse().unwrap();
+        let mut r = AccountRisk::default();
+        assert_eq!(r.held_tier(&cfg, t0), None);
+
+        r.record_tier(RiskTier::Critical, &cfg, t0);
+        let later = t0 + Duration::minutes(10);
+        assert_eq!(r.held_tier(&cfg, later), Some(RiskTier::Critical));
+
+        // A lower tier inside the hold does not replace the held peak
+        r.record_tier(RiskTier::Medium, &cfg, later);
+        assert_eq!(r.held_tier(&cfg, later), Some(RiskTier::Critical));
+        assert_eq!(r.peak_at, Some(t0));
+        assert_eq!(r.held_tier(&cfg, t0 + Duration::seconds(1800)), None);
+
+        // Once the hold lapses a lower tier becomes the new peak
+        let after = t0 + Duration::hours(1);
+        r.record_tier(RiskTier::Medium, &cfg, after);
+        assert_eq!(r.held_tier(&cfg, after), Some(RiskTier::Medium));
+    }
+}
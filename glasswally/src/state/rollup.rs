@@ -0,0 +1,443 @@
+// glasswally/src/state/rollup.rs
+//
+// Multi-resolution rollups — long-horizon account and cluster history.
+//
+// Account windows (state/window.rs) keep 24 hours of events, so slow-and-low
+// campaigns that pace extraction over weeks never fill a window.  Every
+// event is also folded into sparse per-account buckets:
+//
+//   hourly   retained `hourly_days` (default 30)
+//   daily    retained `daily_days`  (default 90)
+//
+// A bucket is ~120 bytes: request and token counts, a topic histogram
+// (workers/sequence_model.rs tagger) and distinct-value sketches for IPs,
+// JA3s, payment methods, orgs and user agents.  A sketch is a 64-bit
+// linear-counting bitmap: mergeable by OR (hour → day, account → cluster),
+// accurate to a few dozen values and saturating around 250.  Only hours and
+// days with activity are stored.
+//
+// Cluster rollups are merged on demand from the current members' rollups,
+// so a cluster's history includes what its members did before they were
+// linked.  Account rollups outlive window eviction and are snapshotted to
+// <output>/rollups.jsonl (restored at startup).
+
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use super::window::{value_hash, EventSummary};
+use crate::events::ApiEvent;
+use crate::workers::sequence_model::Topic;
+
+// ── Configuration ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RollupConfig {
+    pub hourly_days: i64,
+    pub daily_days: i64,
+}
+
+impl Default for RollupConfig {
+    fn default() -> Self {
+        Self {
+            hourly_days: 30,
+            daily_days: 90,
+        }
+    }
+}
+
+// ── Distinct-value sketch ─────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Sketch(u64);
+
+impl Sketch {
+    pub fn insert(&mut self, value: &str) {
+        self.0 |= 1 << (value_hash(value) % 64);
+    }
+
+    pub fn merge(&mut self, other: Sketch) {
+        self.0 |= other.0;
+    }
+
+    /// Linear-counting estimate of the number of distinct values.
+    pub fn estimate(&self) -> f64 {
+        let zeros = self.0.count_zeros() as f64;
+        if zeros == 0.0 {
+            return 64.0 * 64f64.ln(); // saturated
+        }
+        -64.0 * (zeros / 64.0).ln()
+    }
+}
+
+// ── Buckets ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bucket {
+    pub start: DateTime<Utc>,
+    pub requests: u32,
+    pub tokens: u64,
+    pub topics: [u32; Topic::N],
+    pub ips: Sketch,
+    pub ja3s: Sketch,
+    pub payments: Sketch,
+    pub orgs: Sketch,
+    pub user_agents: Sketch,
+}
+
+impl Bucket {
+    fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            start,
+            requests: 0,
+            tokens: 0,
+            topics: [0; Topic::N],
+            ips: Sketch::default(),
+            ja3s: Sketch::default(),
+            payments: Sketch::default(),
+            orgs: Sketch::default(),
+            user_agents: Sketch::default(),
+        }
+    }
+
+    fn record(&mut self, event: &ApiEvent, summary: &EventSummary) {
+        self.requests = self.requests.saturating_add(1);
+        self.tokens += summary.token_count as u64;
+        self.topics[summary.topic.index()] += 1;
+        self.ips.insert(&event.ip_address.to_string());
+        self.user_agents.insert(&event.user_agent);
+        if let Some(ja3) = &event.ja3_hash {
+            self.ja3s.insert(ja3);
+        }
+        if let Some(pm) = &event.payment_method_hash {
+            self.payments.insert(pm);
+        }
+        if let Some(org) = &event.org_id {
+            self.orgs.insert(org);
+        }
+    }
+
+    fn merge(&mut self, other: &Bucket) {
+        self.requests = self.requests.saturating_add(other.requests);
+        self.tokens += other.tokens;
+        for (a, b) in self.topics.iter_mut().zip(other.topics) {
+            *a += b;
+        }
+        self.ips.merge(other.ips);
+        self.ja3s.merge(other.ja3s);
+        self.payments.merge(other.payments);
+        self.orgs.merge(other.orgs);
+        self.user_agents.merge(other.user_agents);
+    }
+}
+
+/// Add `bucket` into a start-ordered series, merging with an equal start.
+fn merge_into(series: &mut VecDeque<Bucket>, bucket: &Bucket) {
+    match series.binary_search_by_key(&bucket.start, |b| b.start) {
+        Ok(i) => series[i].merge(bucket),
+        Err(i) => series.insert(i, bucket.clone()),
+    }
+}
+
+fn bucket_for(series: &mut VecDeque<Bucket>, start: DateTime<Utc>) -> &mut Bucket {
+    let i = match series.binary_search_by_key(&start, |b| b.start) {
+        Ok(i) => i,
+        Err(i) => {
+            series.insert(i, Bucket::new(start));
+            i
+        }
+    };
+    &mut series[i]
+}
+
+// ── Rollup ────────────────────────────────────────────────────────────────────
+
+/// Hourly and daily buckets for one account (or a merged cluster), oldest
+/// first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rollup {
+    pub hourly: VecDeque<Bucket>,
+    pub daily: VecDeque<Bucket>,
+}
+
+impl Rollup {
+    pub fn record(&mut self, event: &ApiEvent, summary: &EventSummary) {
+        let ts = summary.timestamp;
+        let hour = ts.duration_trunc(Duration::hours(1)).unwrap_or(ts);
+        let day = ts.duration_trunc(Duration::days(1)).unwrap_or(ts);
+        bucket_for(&mut self.hourly, hour).record(event, summary);
+        bucket_for(&mut self.daily, day).record(event, summary);
+    }
+
+    pub fn merge(&mut self, other: &Rollup) {
+        for b in &other.hourly {
+            merge_into(&mut self.hourly, b);
+        }
+        for b in &other.daily {
+            merge_into(&mut self.daily, b);
+        }
+    }
+
+    /// Drop buckets older than the retention.  Returns true when empty.
+    fn expire(&mut self, cfg: &RollupConfig, now: DateTime<Utc>) -> bool {
+        let hourly_cutoff = now - Duration::days(cfg.hourly_days);
+        let daily_cutoff = now - Duration::days(cfg.daily_days);
+        while self.hourly.front().is_some_and(|b| b.start < hourly_cutoff) {
+            self.hourly.pop_front();
+        }
+        while self.daily.front().is_some_and(|b| b.start < daily_cutoff) {
+            self.daily.pop_front();
+        }
+        self.hourly.is_empty() && self.daily.is_empty()
+    }
+
+    /// Daily buckets starting at or after `since`.
+    pub fn days_since(&self, since: DateTime<Utc>) -> impl Iterator<Item = &Bucket> {
+        self.daily.iter().filter(move |b| b.start >= since)
+    }
+
+    pub fn approx_bytes(&self) -> usize {
+        (self.hourly.capacity() + self.daily.capacity()) * size_of::<Bucket>()
+    }
+}
+
+// ── Store ─────────────────────────────────────────────────────────────────────
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotEntry {
+    account_id: String,
+    #[serde(flatten)]
+    rollup: Rollup,
+}
+
+#[derive(Default)]
+pub struct RollupStore {
+    cfg: RollupConfig,
+    accounts: DashMap<String, Rollup>,
+}
+
+impl RollupStore {
+    pub fn new(cfg: RollupConfig) -> Self {
+        Self {
+            cfg,
+            accounts: DashMap::new(),
+        }
+    }
+
+    pub fn record(&self, event: &ApiEvent, summary: &EventSummary) {
+        self.accounts
+            .entry(event.account_id.clone())
+            .or_default()
+            .record(event, summary);
+    }
+
+    pub fn with_account<T>(&self, account_id: &str, f: impl FnOnce(&Rollup) -> T) -> Option<T> {
+        self.accounts.get(account_id).map(|r| f(&r))
+    }
+
+    /// Merged rollup of `members`.
+    pub fn merged<'a>(&self, members: impl IntoIterator<Item = &'a String>) -> Rollup {
+        let mut out = Rollup::default();
+        for m in members {
+            if let Some(r) = self.accounts.get(m) {
+                out.merge(&r);
+            }
+        }
+        out
+    }
+
+    /// Expire buckets past retention; accounts left empty are dropped.
+    pub fn expire(&self, now: DateTime<Utc>) {
+        self.accounts.retain(|_, r| !r.expire(&self.cfg, now));
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn approx_bytes(&self) -> u64 {
+        self.accounts
+            .iter()
+            .map(|r| (r.key().capacity() + r.approx_bytes()) as u64)
+            .sum()
+    }
+
+    /// Write every account rollup as JSONL (temp file, then rename).
+    pub fn write_snapshot(&self, path: &Path) -> Result<usize> {
+        let tmp = path.with_extension("jsonl.tmp");
+        let mut out = std::io::BufWriter::new(
+            std::fs::File::create(&tmp).with_context(|| format!("creating {}", tmp.display()))?,
+        );
+        let mut n = 0;
+        for r in self.accounts.iter() {
+            let entry = SnapshotEntry {
+                account_id: r.key().clone(),
+                rollup: r.value().clone(),
+            };
+            serde_json::to_writer(&mut out, &entry)?;
+            out.write_all(b"\n")?;
+            n += 1;
+        }
+        out.flush()?;
+        drop(out);
+        std::fs::rename(&tmp, path).with_context(|| format!("replacing {}", path.display()))?;
+        Ok(n)
+    }
+
+    /// Merge a snapshot written by `write_snapshot` into the store.
+    pub fn restore(&self, path: &Path) -> Result<usize> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("opening rollup snapshot {}", path.display()))?;
+        let mut n = 0;
+        for (i, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: SnapshotEntry = serde_json::from_str(&line)
+                .with_context(|| format!("{}:{}: bad rollup entry", path.display(), i + 1))?;
+            self.accounts
+                .entry(entry.account_id)
+                .or_default()
+                .merge(&entry.rollup);
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(account: &str, ip: &str, ts: DateTime<Utc>) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": ts,
+            "ip_address": ip, "user_agent": "ua", "model": "m",
+            "prompt": "explain the proof", "token_count": 100,
+            "payment_method_hash": "pm", "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    fn record(store: &RollupStore, account: &str, ip: &str, ts: DateTime<Utc>) {
+        let e = event(account, ip, ts);
+        store.record(&e, &EventSummary::of(&e));
+    }
+
+    #[test]
+    fn sketch_estimates_small_counts_and_saturates() {
+        for n in [1usize, 5, 20, 40] {
+            let mut s = Sketch::default();
+            for i in 0..n {
+                s.insert(&format!("198.51.100.{i}"));
+                s.insert(&format!("198.51.100.{i}")); // duplicates are free
+            }
+            let est = s.estimate();
+            let tolerance = 0.5 + 0.3 * n as f64;
+            assert!((est - n as f64).abs() <= tolerance, "n={n} est={est:.1}");
+        }
+
+        // OR-merge of two halves equals the sketch of the whole.
+        let (mut a, mut b, mut all) = (Sketch::default(), Sketch::default(), Sketch::default());
+        for i in 0..30 {
+            let v = format!("ja3_{i}");
+            if i % 2 == 0 {
+                a.insert(&v)
+            } else {
+                b.insert(&v)
+            }
+            all.insert(&v);
+        }
+        a.merge(b);
+        assert_eq!(a, all);
+
+        // Past a few hundred values every bit is set and the estimate pins.
+        let mut s = Sketch::default();
+        for i in 0..2000 {
+            s.insert(&i.to_string());
+        }
+        assert_eq!(s.estimate(), 64.0 * 64f64.ln());
+        assert!(s.estimate() < 300.0);
+    }
+
+    #[test]
+    fn hourly_and_daily_buckets_expire_separately() {
+        let store = RollupStore::new(RollupConfig {
+            hourly_days: 2,
+            daily_days: 5,
+        });
+        let now = Utc::now();
+        for days in [1, 3, 6] {
+            record(&store, "acct", "198.51.100.1", now - Duration::days(days));
+        }
+        record(&store, "acct", "198.51.100.2", now - Duration::days(1));
+
+        store.expire(now);
+        let (hourly, daily) = store
+            .with_account("acct", |r| (r.hourly.len(), r.daily.len()))
+            .unwrap();
+        assert_eq!(hourly, 1); // only yesterday's hour is inside 2 days
+        assert_eq!(daily, 2); // 1 and 3 days ago are inside 5 days
+        let hour = store.with_account("acct", |r| r.hourly[0].clone()).unwrap();
+        assert_eq!(hour.requests, 2);
+        assert!((hour.ips.estimate() - 2.0).abs() < 0.5);
+
+        // Everything past retention: the account itself is dropped.
+        store.expire(now + Duration::days(10));
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn snapshot_restores_every_account() {
+        let store = RollupStore::new(RollupConfig::default());
+        let now = Utc::now();
+        for h in 0..30 {
+            record(
+                &store,
+                "a",
+                &format!("198.51.100.{h}"),
+                now - Duration::hours(h),
+            );
+        }
+        record(&store, "b", "203.0.113.1", now - Duration::days(20));
+
+        let path =
+            std::env::temp_dir().join(format!("glasswally_rollups_{}.jsonl", std::process::id()));
+        assert_eq!(store.write_snapshot(&path).unwrap(), 2);
+        let restored = RollupStore::new(RollupConfig::default());
+        assert_eq!(restored.restore(&path).unwrap(), 2);
+        std::fs::remove_file(&path).ok();
+
+        for acct in ["a", "b"] {
+            let json = |s: &RollupStore| {
+                s.with_account(acct, |r| serde_json::to_string(r).unwrap())
+                    .unwrap()
+            };
+            assert_eq!(json(&store), json(&restored));
+        }
+        let ips = restored
+            .merged(&["a".to_string(), "b".to_string()])
+            .days_since(now - Duration::days(30))
+            .fold(Sketch::default(), |mut s, b| {
+                s.merge(b.ips);
+                s
+            });
+        assert!(ips.estimate() > 20.0);
+    }
+}
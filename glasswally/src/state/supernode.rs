@@ -0,0 +1,329 @@
+// glasswally/src/state/supernode.rs
+//
+// Supernode suppression for shared infrastructure.
+//
+// Some infrastructure values are shared by thousands of unrelated accounts:
+// the python-requests JA3, a mobile carrier's CGNAT /24, a corporate proxy, a
+// big customer's org id.  Linking on them glues everyone into one cluster.
+//
+// Each link is scaled by how many accounts currently share the value
+// (fresh entries in the StateStore index), IDF-style:
+//
+//   n ≤ soft             scale 1
+//   soft < n < hard      scale ln(hard / n) / ln(hard / soft)   (1 → 0)
+//   n ≥ hard             suppressed — no link at all
+//
+// Values on the operator's known-shared list are always suppressed; a subnet
+// entry is a CIDR and covers every subnet inside it (100.64.0.0/10 for
+// CGNAT).  Every down-weighted or suppressed value is tracked (kind, value,
+// account count, reason, first/last seen) and exported as Prometheus metrics,
+// so analysts can see which values were ignored and promote recurring ones to
+// the list.
+//
+// Scales are applied when a link is made and re-applied to every existing
+// link at graph recompute (StateStore::recompute_clusters), so a value that
+// becomes a supernode stops holding clusters together within one housekeeping
+// pass, not only once its old links reach their TTL.
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::events::LinkKind;
+use crate::net::IpCidr;
+
+const MAX_TRACKED: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DegreeLimits {
+    pub soft: usize,
+    pub hard: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SupernodeConfig {
+    pub limits: BTreeMap<LinkKind, DegreeLimits>, // kinds left out are never scaled
+    pub known_shared: BTreeMap<LinkKind, HashSet<String>>,
+}
+
+impl Default for SupernodeConfig {
+    fn default() -> Self {
+        let limits = [
+            (LinkKind::Payment, 10, 100),
+            (LinkKind::Org, 25, 250),
+            (LinkKind::Subnet, 16, 128),
+            (LinkKind::Ja3, 10, 100),
+        ]
+        .into_iter()
+        .map(|(k, soft, hard)| (k, DegreeLimits { soft, hard }))
+        .collect();
+        Self {
+            limits,
+            known_shared: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuppressionReason {
+    DownWeighted,
+    DegreeCap,
+    KnownShared,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DownWeighted => "down_weighted",
+            Self::DegreeCap => "degree_cap",
+            Self::KnownShared => "known_shared",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuppressedValue {
+    pub kind: LinkKind,
+    pub value: String,
+    pub accounts: usize,
+    pub scale: f32,
+    pub reason: SuppressionReason,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl SupernodeConfig {
+    fn is_known_shared(&self, kind: LinkKind, value: &str) -> bool {
+        let Some(list) = self.known_shared.get(&kind) else {
+            return false;
+        };
+        if list.contains(value) {
+            return true;
+        }
+        let Ok(subnet) = value.parse::<IpCidr>() else {
+            return false;
+        };
+        kind == LinkKind::Subnet
+            && list
+                .iter()
+                .filter_map(|v| v.parse::<IpCidr>().ok())
+                .any(|shared| shared.prefix() <= subnet.prefix() && shared.contains(&subnet.addr()))
+    }
+
+    /// Link scale for a value shared by `accounts` accounts (0 = suppressed).
+    pub fn scale(
+        &self,
+        kind: LinkKind,
+        value: &str,
+        accounts: usize,
+    ) -> (f32, Option<SuppressionReason>) {
+        if self.is_known_shared(kind, value) {
+            return (0.0, Some(SuppressionReason::KnownShared));
+        }
+        let Some(l) = self.limits.get(&kind) else {
+            return (1.0, None);
+        };
+        if accounts <= l.soft {
+            (1.0, None)
+        } else if accounts >= l.hard || l.hard <= l.soft {
+            (0.0, Some(SuppressionReason::DegreeCap))
+        } else {
+            let s = (l.hard as f32 / accounts as f32).ln() / (l.hard as f32 / l.soft as f32).ln();
+            (s, Some(SuppressionReason::DownWeighted))
+        }
+    }
+}
+
+/// Values currently down-weighted or suppressed.
+#[derive(Default)]
+pub struct SupernodeTracker {
+    values: DashMap<(LinkKind, String), SuppressedValue>,
+    values_suppressed: DashMap<LinkKind, u64>,
+}
+
+impl SupernodeTracker {
+    pub fn record(
+        &self,
+        kind: LinkKind,
+        value: &str,
+        accounts: usize,
+        scale: f32,
+        reason: SuppressionReason,
+        now: DateTime<Utc>,
+    ) {
+        let suppressed = reason != SuppressionReason::DownWeighted;
+        let key = (kind, value.to_string());
+        if let Some(mut v) = self.values.get_mut(&key) {
+            if suppressed && v.reason == SuppressionReason::DownWeighted {
+                *self.values_suppressed.entry(kind).or_default() += 1;
+            }
+            v.accounts = accounts;
+            v.scale = scale;
+            v.reason = reason;
+            v.last_seen = v.last_seen.max(now);
+            return;
+        }
+        if self.values.len() >= MAX_TRACKED {
+            return;
+        }
+        if suppressed {
+            *self.values_suppressed.entry(kind).or_default() += 1;
+        }
+        self.values.insert(
+            key,
+            SuppressedValue {
+                kind,
+                value: value.to_string(),
+                accounts,
+                scale,
+                reason,
+                first_seen: now,
+                last_seen: now,
+            },
+        );
+    }
+
+    /// Forget values not seen since `cutoff`.
+    pub fn prune(&self, cutoff: DateTime<Utc>) {
+        self.values.retain(|_, v| v.last_seen >= cutoff);
+    }
+
+    /// Tracked values, most shared first.
+    pub fn values(&self) -> Vec<SuppressedValue> {
+        let mut out: Vec<SuppressedValue> = self.values.iter().map(|v| v.value().clone()).collect();
+        out.sort_by(|a, b| b.accounts.cmp(&a.accounts).then(a.value.cmp(&b.value)));
+        out
+    }
+
+    /// Values that went from linking to suppressed, per kind (each counted
+    /// once per episode: again only if it was pruned or down-weighted since).
+    pub fn values_suppressed(&self) -> BTreeMap<LinkKind, u64> {
+        self.values_suppressed
+            .iter()
+            .map(|e| (*e.key(), *e.value()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ApiEvent;
+    use crate::state::graph::GraphConfig;
+    use crate::state::window::StateStore;
+
+    fn event(account: &str, payment: &str, ip: &str) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": Utc::now(),
+            "ip_address": ip, "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": 100,
+            "payment_method_hash": payment, "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn scale_falls_from_soft_to_hard_limit() {
+        let cfg = SupernodeConfig::default(); // payment 10 / 100
+        assert_eq!(cfg.scale(LinkKind::Payment, "pm", 2), (1.0, None));
+        assert_eq!(cfg.scale(LinkKind::Payment, "pm", 10), (1.0, None));
+
+        let (s, reason) = cfg.scale(LinkKind::Payment, "pm", 11);
+        assert!(s < 1.0 && s > 0.9, "{s}");
+        assert_eq!(reason, Some(SuppressionReason::DownWeighted));
+        let (mid, _) = cfg.scale(LinkKind::Payment, "pm", 32);
+        assert!((mid - 0.5).abs() < 0.01, "{mid}"); // √(soft·hard)
+        let (s, _) = cfg.scale(LinkKind::Payment, "pm", 99);
+        assert!(s > 0.0 && s < 0.01, "{s}");
+
+        assert_eq!(
+            cfg.scale(LinkKind::Payment, "pm", 100),
+            (0.0, Some(SuppressionReason::DegreeCap))
+        );
+        assert_eq!(
+            cfg.scale(LinkKind::Payment, "pm", 5000),
+            (0.0, Some(SuppressionReason::DegreeCap))
+        );
+        // Kinds without limits are never scaled.
+        assert_eq!(cfg.scale(LinkKind::Canary, "c", 5000), (1.0, None));
+    }
+
+    #[test]
+    fn known_shared_cidr_covers_subnets_inside_it() {
+        let mut cfg = SupernodeConfig::default();
+        cfg.known_shared.insert(
+            LinkKind::Subnet,
+            ["100.64.0.0/10".to_string()].into_iter().collect(),
+        );
+        cfg.known_shared.insert(
+            LinkKind::Ja3,
+            ["python_requests".to_string()].into_iter().collect(),
+        );
+        let shared = (0.0, Some(SuppressionReason::KnownShared));
+
+        // Suppressed however few accounts share the value.
+        assert_eq!(cfg.scale(LinkKind::Subnet, "100.64.7.0/24", 2), shared);
+        assert_eq!(cfg.scale(LinkKind::Subnet, "100.127.255.0/24", 2), shared);
+        assert_eq!(cfg.scale(LinkKind::Ja3, "python_requests", 2), shared);
+
+        // Outside the CIDR, wider than it, or another kind: normal scaling.
+        assert_eq!(
+            cfg.scale(LinkKind::Subnet, "100.128.0.0/24", 2),
+            (1.0, None)
+        );
+        assert_eq!(cfg.scale(LinkKind::Subnet, "100.0.0.0/8", 2), (1.0, None));
+        assert_eq!(cfg.scale(LinkKind::Org, "100.64.7.0/24", 2), (1.0, None));
+    }
+
+    #[test]
+    fn supernode_stops_holding_clusters_together() {
+        let mut cfg = GraphConfig::default();
+        cfg.supernode
+            .limits
+            .insert(LinkKind::Payment, DegreeLimits { soft: 2, hard: 4 });
+        let store = StateStore::with_graph_config(cfg);
+
+        // Two clusters, each on its own card, on different subnets.
+        store.ingest(&event("a1", "pm_a", "198.51.100.1"));
+        store.ingest(&event("a2", "pm_a", "198.51.100.2"));
+        store.ingest(&event("b1", "pm_b", "203.0.113.1"));
+        store.ingest(&event("b2", "pm_b", "203.0.113.2"));
+        let (ca, cb) = (store.get_cluster("a1"), store.get_cluster("b1"));
+        assert!(ca.is_some() && ca != cb);
+
+        // a1 and b1 share a card while only they use it: the clusters merge.
+        store.ingest(&event("a1", "pm_hub", "198.51.100.1"));
+        store.ingest(&event("b1", "pm_hub", "203.0.113.1"));
+        assert_eq!(store.get_cluster("a1"), store.get_cluster("b1"));
+
+        // The card turns out to be everyone's: new users get no links ...
+        for x in ["x1", "x2", "x3"] {
+            store.ingest(&event(x, "pm_hub", "192.0.2.9"));
+        }
+        assert_eq!(store.get_cluster("x3"), None);
+
+        // ... and recompute drops the old a1–b1 link, splitting the clusters.
+        let stats = store.recompute_clusters();
+        assert!(stats.rescaled_links >= 1);
+        assert_eq!(stats.splits, 1);
+        let (ca, cb) = (store.get_cluster("a1"), store.get_cluster("b1"));
+        assert!(ca.is_some() && cb.is_some() && ca != cb);
+        assert_eq!(store.get_cluster("a2"), ca);
+        assert_eq!(store.get_cluster("b2"), cb);
+
+        // Counted once, however many events hit the suppressed value.
+        assert_eq!(store.suppressed_value_count()[&LinkKind::Payment], 1);
+        let hub = store.suppressed_values();
+        assert_eq!(hub[0].value, "pm_hub");
+        assert_eq!(hub[0].accounts, 5);
+        assert_eq!(hub[0].reason, SuppressionReason::DegreeCap);
+    }
+}
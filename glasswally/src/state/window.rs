@@ -4,28 +4,49 @@
 // DashMap = sharded concurrent HashMap — safe across tokio tasks with no mutex.
 //
 // Design:
-//   - Per-account event ring buffer (VecDeque, auto-expiring)
+//   - Per-account ring of compact event summaries (VecDeque, auto-expiring)
+//     plus a small ring of full recent events; prompt text is only kept there,
+//     so the prompt-text workers (CoT, biometric, refusal probe) score the
+//     last `max_prompts` prompts, not the whole 24 h window
+//   - Per-account caps (events, distinct values) and LRU eviction of idle
+//     accounts under a global memory budget (WindowLimits); the disk backend
+//     spills evicted windows instead of dropping them (state/backend.rs)
 //   - Infrastructure reverse indexes: payment → accounts, subnet → accounts
-//   - Relationship graph: accounts as nodes, shared infra as edges
-//   - Cluster membership: connected components with 3+ accounts
+//   - Relationship graph: accounts as nodes, weighted shared-infra edges
+//     (state/graph.rs)
+//   - Cluster membership: union-find components of edges above threshold
+//   - Org / payment / subnet entity windows with the same aggregates
+//     (state/entity.rs)
+//   - Hourly / daily rollups per account for 30–90 days (state/rollup.rs)
 //   - Timing buckets: second-resolution global burst detection
 //   - Canary registry: per-account watermark + canary token tracking
 //
 // This is the in-memory equivalent of:
 //   Redis     → per-account state
-//   Neo4j     → relationship graph
+//   Neo4j     → relationship graph (exported by state/export.rs)
 //   ClickHouse → analytics aggregates
 
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::path::Path;
 use std::sync::Arc;
 
+use anyhow::{Context, Result};
 use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
-
-use crate::events::{ApiEvent, CanaryToken};
+use tracing::{debug, info};
+
+use super::entity::{EntityKind, EntityWindow};
+use super::export::NodeKind;
+use super::graph::{AccountGraph, GraphConfig, RecomputeStats};
+use super::risk::AccountRisk;
+use super::rollup::{Rollup, RollupConfig, RollupStore};
+use super::supernode::{SupernodeTracker, SuppressedValue};
+use crate::events::{ApiEvent, CanaryHit, CanaryToken, ClusterEvent, LinkKind, RateLimitCommand};
+use crate::net::{IpCidr, SubnetPrefixes};
+use crate::workers::sequence_model::{classify_topic, Topic};
 
 // ── Window durations ──────────────────────────────────────────────────────────
 
@@ -33,27 +54,247 @@ pub const W_5MIN: i64 = 5 * 60;
 pub const W_1HR: i64 = 60 * 60;
 pub const W_24HR: i64 = 24 * 60 * 60;
 
+const MAX_CANARY_HITS: usize = 100;
+
+// ── Memory limits ─────────────────────────────────────────────────────────────
+
+/// Per-account caps and the global memory budget for account windows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowLimits {
+    pub max_events: usize,          // event summaries per account (oldest dropped)
+    pub max_prompts: usize,         // full events in the recent-prompt ring (prompt-text workers)
+    pub max_prompt_bytes: usize,    // prompt / system prompt truncation in the ring
+    pub max_values: usize,          // distinct values per infrastructure set (LRU)
+    pub max_accounts: usize,        // account windows before idle ones are evicted
+    pub memory_budget_bytes: u64,   // estimated window bytes before idle ones are evicted
+    pub max_entity_events: usize,   // event summaries per org / payment / subnet window
+    pub max_entity_accounts: usize, // accounts tracked per entity window (LRU)
+    pub max_entities: usize,        // entity windows before idle ones are evicted
+    pub rollup: RollupConfig,       // hourly / daily rollup retention (state/rollup.rs)
+}
+
+impl Default for WindowLimits {
+    fn default() -> Self {
+        Self {
+            max_events: 20_000,
+            max_prompts: 64, // ≥ 50: biometric reaches full confidence at 50 prompts
+            max_prompt_bytes: 8 * 1024,
+            max_values: 64,
+            max_accounts: 2_000_000,
+            memory_budget_bytes: 8 << 30,
+            max_entity_events: 50_000,
+            max_entity_accounts: 1024,
+            max_entities: 500_000,
+            rollup: RollupConfig::default(),
+        }
+    }
+}
+
+impl WindowLimits {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading window limits {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("parsing window limits {}", path.display()))
+    }
+}
+
+/// Stable 64-bit hash of a string (SHA-256 prefix), for compact event fields.
+pub fn value_hash(value: &str) -> u64 {
+    use sha2::{Digest, Sha256};
+    let digest = Sha256::digest(value.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
+fn truncate_utf8(s: &mut String, max: usize) {
+    if s.len() > max {
+        let mut end = max;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+    }
+}
+
+// ── Compact event record ──────────────────────────────────────────────────────
+
+/// What the window keeps for every event: enough for velocity, token budget,
+/// session gap, topic sequence and preamble reuse, without the prompt text.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct EventSummary {
+    pub timestamp: DateTime<Utc>,
+    pub token_count: u32,
+    pub max_tokens: Option<u32>,
+    pub prompt_len: u32,
+    pub prompt_hash: u64,
+    pub system_prompt_hash: Option<u64>, // value_hash of the event's preamble hash
+    pub topic: Topic,
+    pub asn_number: Option<u32>,
+}
+
+impl EventSummary {
+    pub fn of(event: &ApiEvent) -> Self {
+        Self {
+            timestamp: event.timestamp,
+            token_count: event.token_count,
+            max_tokens: event.max_tokens,
+            prompt_len: event.prompt.len().min(u32::MAX as usize) as u32,
+            prompt_hash: value_hash(&event.prompt),
+            system_prompt_hash: event.system_prompt_hash.as_deref().map(value_hash),
+            topic: classify_topic(&event.prompt),
+            asn_number: event.asn_number,
+        }
+    }
+}
+
+/// Distinct values with their last-seen time.  At the cap, inserting a new
+/// value evicts the least recently seen one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RecentSet(HashMap<String, DateTime<Utc>>);
+
+impl RecentSet {
+    pub fn insert(&mut self, value: &str, ts: DateTime<Utc>, cap: usize) {
+        if let Some(seen) = self.0.get_mut(value) {
+            *seen = (*seen).max(ts);
+            return;
+        }
+        if self.0.len() >= cap.max(1) {
+            if let Some(oldest) = self
+                .0
+                .iter()
+                .min_by_key(|(_, t)| **t)
+                .map(|(v, _)| v.clone())
+            {
+                self.0.remove(&oldest);
+            }
+        }
+        self.0.insert(value.to_string(), ts);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.0.keys()
+    }
+
+    pub fn contains(&self, value: &str) -> bool {
+        self.0.contains_key(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Values last seen at or after `since`.
+    pub fn count_since(&self, since: DateTime<Utc>) -> usize {
+        self.0.values().filter(|t| **t >= since).count()
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.0
+            .keys()
+            .map(|v| v.capacity() + size_of::<(String, DateTime<Utc>)>() + 8)
+            .sum()
+    }
+}
+
+impl<'a> IntoIterator for &'a RecentSet {
+    type Item = &'a String;
+    type IntoIter = std::collections::hash_map::Keys<'a, String, DateTime<Utc>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.keys()
+    }
+}
+
+// ── Windowed aggregates ───────────────────────────────────────────────────────
+
+/// Velocity / token aggregates over a ring of event summaries, shared by
+/// account windows and entity windows (state/entity.rs).
+pub trait EventWindow {
+    fn summaries(&self) -> &VecDeque<EventSummary>;
+
+    fn events_in(&self, seconds: i64) -> Vec<&EventSummary> {
+        let cutoff = Utc::now() - Duration::seconds(seconds);
+        self.summaries()
+            .iter()
+            .filter(|e| e.timestamp >= cutoff)
+            .collect()
+    }
+
+    fn rate_per_hour(&self, seconds: i64) -> f64 {
+        let evs = self.events_in(seconds);
+        if evs.len() < 2 {
+            return 0.0;
+        }
+        let span = (evs.last().unwrap().timestamp - evs.first().unwrap().timestamp)
+            .num_seconds()
+            .max(1) as f64;
+        (evs.len() as f64 / span) * 3600.0
+    }
+
+    fn interarrivals(&self, seconds: i64) -> Vec<f64> {
+        let evs = self.events_in(seconds);
+        if evs.len() < 2 {
+            return vec![];
+        }
+        evs.windows(2)
+            .map(|w| (w[1].timestamp - w[0].timestamp).num_milliseconds() as f64 / 1000.0)
+            .filter(|&d| d > 0.0)
+            .collect()
+    }
+
+    /// Tokens per minute over the window, 0 below two events.
+    fn tokens_per_minute(&self, seconds: i64) -> f64 {
+        let evs = self.events_in(seconds);
+        if evs.len() < 2 {
+            return 0.0;
+        }
+        let span_min = (evs.last().unwrap().timestamp - evs.first().unwrap().timestamp)
+            .num_seconds()
+            .max(1) as f64
+            / 60.0;
+        evs.iter().map(|e| e.token_count as f64).sum::<f64>() / span_min
+    }
+}
+
+/// Pop summaries older than `cutoff` from the front of a time-ordered ring.
+pub(crate) fn expire_summaries(events: &mut VecDeque<EventSummary>, cutoff: DateTime<Utc>) {
+    while events.front().is_some_and(|e| e.timestamp < cutoff) {
+        events.pop_front();
+    }
+}
+
 // ── Per-account window ────────────────────────────────────────────────────────
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AccountWindow {
     pub account_id: String,
-    pub events: VecDeque<ApiEvent>,
+    pub events: VecDeque<EventSummary>, // every event in the last 24 h (capped)
+    pub recent: VecDeque<ApiEvent>,     // last few full events, prompts truncated
     pub first_seen: DateTime<Utc>,
     pub last_seen: DateTime<Utc>,
-    pub ip_addresses: HashSet<String>,
-    pub payment_hashes: HashSet<String>,
-    pub user_agents: HashSet<String>,
-    pub country_codes: HashSet<String>,
-    pub org_ids: HashSet<String>,
-    pub models_seen: Vec<(DateTime<Utc>, String)>,
-    pub header_hashes: HashSet<String>,   // header order hashes
-    pub ja3_hashes: HashSet<String>,      // TLS ClientHello fingerprints
-    pub ja3s_hashes: HashSet<String>,     // TLS ServerHello fingerprints (Tier 1)
-    pub h2_fingerprints: HashSet<String>, // HTTP/2 SETTINGS fingerprints (Tier 2)
+    pub ip_addresses: RecentSet,
+    pub payment_hashes: RecentSet,
+    pub user_agents: RecentSet,
+    pub country_codes: RecentSet,
+    pub org_ids: RecentSet,
+    pub models: RecentSet,
+    pub header_hashes: RecentSet,   // header order hashes
+    pub ja3_hashes: RecentSet,      // TLS ClientHello fingerprints
+    pub ja3s_hashes: RecentSet,     // TLS ServerHello fingerprints (Tier 1)
+    pub h2_fingerprints: RecentSet, // HTTP/2 SETTINGS fingerprints (Tier 2)
     pub suspended: bool,
     pub last_alerted: Option<DateTime<Utc>>,
     pub watermarked_at: Option<DateTime<Utc>>, // when account was first watermarked
+    #[serde(default)]
+    pub risk: AccountRisk, // decayed account-level risk (engine/fusion.rs)
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitCommand>, // last account-scope command (engine/rate_limit.rs)
 }
 
 impl AccountWindow {
@@ -61,44 +302,51 @@ impl AccountWindow {
         Self {
             account_id: account_id.to_string(),
             events: VecDeque::new(),
+            recent: VecDeque::new(),
             first_seen: now,
             last_seen: now,
-            ip_addresses: HashSet::new(),
-            payment_hashes: HashSet::new(),
-            user_agents: HashSet::new(),
-            country_codes: HashSet::new(),
-            org_ids: HashSet::new(),
-            models_seen: Vec::new(),
-            header_hashes: HashSet::new(),
-            ja3_hashes: HashSet::new(),
-            ja3s_hashes: HashSet::new(),
-            h2_fingerprints: HashSet::new(),
+            ip_addresses: RecentSet::default(),
+            payment_hashes: RecentSet::default(),
+            user_agents: RecentSet::default(),
+            country_codes: RecentSet::default(),
+            org_ids: RecentSet::default(),
+            models: RecentSet::default(),
+            header_hashes: RecentSet::default(),
+            ja3_hashes: RecentSet::default(),
+            ja3s_hashes: RecentSet::default(),
+            h2_fingerprints: RecentSet::default(),
             suspended: false,
             last_alerted: None,
             watermarked_at: None,
+            risk: AccountRisk::default(),
+            rate_limit: None,
         }
     }
 
-    pub fn ingest(&mut self, event: &ApiEvent) {
-        self.last_seen = event.timestamp;
-        self.ip_addresses.insert(event.ip_address.to_string());
-        self.user_agents.insert(event.user_agent.clone());
-        self.country_codes.insert(event.country_code.clone());
+    pub fn ingest(&mut self, event: &ApiEvent, limits: &WindowLimits) -> EventSummary {
+        let ts = event.timestamp;
+        let cap = limits.max_values;
+        self.last_seen = ts;
+        self.ip_addresses
+            .insert(&event.ip_address.to_string(), ts, cap);
+        self.user_agents.insert(&event.user_agent, ts, cap);
+        self.country_codes.insert(&event.country_code, ts, cap);
+        self.models.insert(&event.model, ts, cap);
         if let Some(ref pm) = event.payment_method_hash {
-            self.payment_hashes.insert(pm.clone());
+            self.payment_hashes.insert(pm, ts, cap);
         }
         if let Some(ref org) = event.org_id {
-            self.org_ids.insert(org.clone());
+            self.org_ids.insert(org, ts, cap);
         }
         if let Some(ref ja3) = event.ja3_hash {
-            self.ja3_hashes.insert(ja3.clone());
+            self.ja3_hashes.insert(ja3, ts, cap);
         }
         if let Some(ref ja3s) = event.ja3s_hash {
-            self.ja3s_hashes.insert(ja3s.clone());
+            self.ja3s_hashes.insert(ja3s, ts, cap);
         }
         if let Some(ref h2) = event.h2_settings {
             if !h2.fingerprint.is_empty() {
-                self.h2_fingerprints.insert(h2.fingerprint.clone());
+                self.h2_fingerprints.insert(&h2.fingerprint, ts, cap);
             }
         }
         // Header order hash
@@ -107,95 +355,119 @@ impl AccountWindow {
             let joined = event.header_order.join("|");
             let mut h = Sha256::new();
             h.update(joined.as_bytes());
-            self.header_hashes.insert(hex::encode(&h.finalize()[..8]));
+            self.header_hashes
+                .insert(&hex::encode(&h.finalize()[..8]), ts, cap);
+        }
+
+        let summary = EventSummary::of(event);
+        self.events.push_back(summary);
+        while self.events.len() > limits.max_events.max(1) {
+            self.events.pop_front();
+        }
+        let mut full = event.clone();
+        truncate_utf8(&mut full.prompt, limits.max_prompt_bytes);
+        if let Some(sp) = full.system_prompt.as_mut() {
+            truncate_utf8(sp, limits.max_prompt_bytes);
         }
-        self.models_seen
-            .push((event.timestamp, event.model.clone()));
-        self.events.push_back(event.clone());
+        self.recent.push_back(full);
+        while self.recent.len() > limits.max_prompts.max(1) {
+            self.recent.pop_front();
+        }
+        summary
     }
 
-    pub fn events_in(&self, seconds: i64) -> Vec<&ApiEvent> {
+    /// Prompts in the recent-prompt ring within the last `seconds`.
+    pub fn prompts_in(&self, seconds: i64) -> Vec<String> {
         let cutoff = Utc::now() - Duration::seconds(seconds);
-        self.events
+        self.recent
             .iter()
             .filter(|e| e.timestamp >= cutoff)
-            .collect()
-    }
-
-    pub fn prompts_in(&self, seconds: i64) -> Vec<String> {
-        self.events_in(seconds)
-            .into_iter()
             .map(|e| e.prompt.clone())
             .collect()
     }
 
-    pub fn rate_per_hour(&self, seconds: i64) -> f64 {
-        let evs = self.events_in(seconds);
-        if evs.len() < 2 {
-            return 0.0;
-        }
-        let span = (evs.last().unwrap().timestamp - evs.first().unwrap().timestamp)
-            .num_seconds()
-            .max(1) as f64;
-        (evs.len() as f64 / span) * 3600.0
-    }
-
-    pub fn interarrivals(&self, seconds: i64) -> Vec<f64> {
-        let evs = self.events_in(seconds);
-        if evs.len() < 2 {
-            return vec![];
-        }
-        evs.windows(2)
-            .map(|w| (w[1].timestamp - w[0].timestamp).num_milliseconds() as f64 / 1000.0)
-            .filter(|&d| d > 0.0)
-            .collect()
-    }
-
-    pub fn subnets(&self) -> HashSet<String> {
-        self.ip_addresses
-            .iter()
-            .filter_map(|ip| {
-                let p: Vec<&str> = ip.split('.').collect();
-                if p.len() == 4 {
-                    Some(format!("{}.{}.{}", p[0], p[1], p[2]))
-                } else {
-                    None
-                }
-            })
-            .collect()
+    /// The account's addresses aggregated to `prefixes` (both families).
+    pub fn subnets(&self, prefixes: &SubnetPrefixes) -> HashSet<IpCidr> {
+        prefixes.of_all(&self.ip_addresses)
     }
 
     pub fn expire_old(&mut self) {
         let cutoff = Utc::now() - Duration::seconds(W_24HR);
+        expire_summaries(&mut self.events, cutoff);
         while self
-            .events
+            .recent
             .front()
             .map(|e| e.timestamp < cutoff)
             .unwrap_or(false)
         {
-            self.events.pop_front();
+            self.recent.pop_front();
         }
     }
+
+    /// Rough heap footprint of the window, for the global memory budget.
+    pub fn approx_bytes(&self) -> usize {
+        let full: usize = self
+            .recent
+            .iter()
+            .map(|e| {
+                size_of::<ApiEvent>()
+                    + e.prompt.capacity()
+                    + e.system_prompt.as_ref().map_or(0, String::capacity)
+                    + e.user_agent.capacity()
+                    + e.header_order
+                        .iter()
+                        .map(|h| h.capacity() + 24)
+                        .sum::<usize>()
+            })
+            .sum();
+        size_of::<Self>()
+            + self.account_id.capacity()
+            + self.events.capacity() * size_of::<EventSummary>()
+            + full
+            + [
+                &self.ip_addresses,
+                &self.payment_hashes,
+                &self.user_agents,
+                &self.country_codes,
+                &self.org_ids,
+                &self.models,
+                &self.header_hashes,
+                &self.ja3_hashes,
+                &self.ja3s_hashes,
+                &self.h2_fingerprints,
+            ]
+            .iter()
+            .map(|s| s.heap_bytes())
+            .sum::<usize>()
+    }
+}
+
+impl EventWindow for AccountWindow {
+    fn summaries(&self) -> &VecDeque<EventSummary> {
+        &self.events
+    }
 }
 
 // ── Global state store ────────────────────────────────────────────────────────
 
+/// Account → (infrastructure value, current supernode scale) for one link kind.
+type ValueScales = HashMap<String, Vec<(String, f32)>>;
+
 pub struct StateStore {
     // Account windows — the primary per-account state
     pub accounts: DashMap<String, Arc<RwLock<AccountWindow>>>,
 
     // Infrastructure reverse indexes — fast cluster detection
-    payment_idx: DashMap<String, HashSet<String>>, // payment_hash → account_ids
-    subnet_idx: DashMap<String, HashSet<String>>,  // subnet → account_ids
-    org_idx: DashMap<String, HashSet<String>>,     // org_id → account_ids
-    ja3_idx: DashMap<String, HashSet<String>>,     // ja3_hash → account_ids
-    ja3s_idx: DashMap<String, HashSet<String>>,    // ja3s_hash → account_ids
-    hdr_idx: DashMap<String, HashSet<String>>,     // header_hash → account_ids
-
-    // Cluster assignments (updated incrementally on each new edge)
-    pub account_cluster: DashMap<String, u32>,
-    pub clusters: DashMap<u32, HashSet<String>>,
-    next_cluster: parking_lot::Mutex<u32>,
+    payment_idx: InfraIndex, // payment_hash → account_ids
+    subnet_idx: InfraIndex,  // subnet → account_ids
+    org_idx: InfraIndex,     // org_id → account_ids
+    ja3_idx: InfraIndex,     // ja3_hash → account_ids
+    ja3s_idx: InfraIndex,    // ja3s_hash → account_ids
+    hdr_idx: InfraIndex,     // header_hash → account_ids
+
+    // Weighted relationship graph + cluster assignments (updated on each event)
+    graph: RwLock<AccountGraph>,
+    supernodes: SupernodeTracker, // shared-infrastructure values down-weighted / ignored
 
     // Model pivot tracking
     model_switches: DashMap<String, Vec<(DateTime<Utc>, String, String)>>,
@@ -206,10 +478,11 @@ pub struct StateStore {
 
     // Role preamble index (Phase 1 — cross-account preamble collision detection)
     // Key = preamble hash (SHA256[:8] of normalized role preamble), Value = account_ids
-    preamble_idx: DashMap<String, HashSet<String>>,
+    preamble_idx: InfraIndex,
 
     // Canary token registry (Tier 2 — response attribution)
     canary_registry: DashMap<String, CanaryToken>, // token → metadata
+    canary_hits: DashMap<String, Vec<CanaryHit>>,  // replaying account → hits
 
     // Watermark tracking — accounts under active watermark surveillance
     watermarked: DashMap<String, DateTime<Utc>>, // account_id → watermark start time
@@ -217,33 +490,70 @@ pub struct StateStore {
     // Global counters
     pub total_events: std::sync::atomic::AtomicU64,
     pub total_accounts: std::sync::atomic::AtomicU64,
+    clock: std::sync::atomic::AtomicI64, // latest event timestamp (Unix s) — graph expiry clock
+
+    // Org / payment / subnet behaviour windows (state/entity.rs)
+    entities: DashMap<(EntityKind, String), Arc<RwLock<EntityWindow>>>,
+
+    // Long-horizon hourly / daily rollups (state/rollup.rs)
+    rollups: RollupStore,
+
+    // Memory bounds (estimated bytes refreshed by housekeeping)
+    limits: WindowLimits,
+    window_bytes: std::sync::atomic::AtomicU64,
+    pub evicted_accounts: std::sync::atomic::AtomicU64,
 }
 
 impl StateStore {
     pub fn new() -> Self {
+        Self::with_graph_config(GraphConfig::default())
+    }
+
+    pub fn with_graph_config(graph: GraphConfig) -> Self {
         Self {
             accounts: DashMap::new(),
-            payment_idx: DashMap::new(),
-            subnet_idx: DashMap::new(),
-            org_idx: DashMap::new(),
-            ja3_idx: DashMap::new(),
-            ja3s_idx: DashMap::new(),
-            hdr_idx: DashMap::new(),
-            account_cluster: DashMap::new(),
-            clusters: DashMap::new(),
-            next_cluster: parking_lot::Mutex::new(0),
+            payment_idx: InfraIndex::default(),
+            subnet_idx: InfraIndex::default(),
+            org_idx: InfraIndex::default(),
+            ja3_idx: InfraIndex::default(),
+            ja3s_idx: InfraIndex::default(),
+            hdr_idx: InfraIndex::default(),
+            graph: RwLock::new(AccountGraph::new(graph)),
+            supernodes: SupernodeTracker::default(),
             model_switches: DashMap::new(),
             timing_buckets: DashMap::new(),
-            preamble_idx: DashMap::new(),
+            preamble_idx: InfraIndex::default(),
             canary_registry: DashMap::new(),
+            canary_hits: DashMap::new(),
             watermarked: DashMap::new(),
             total_events: std::sync::atomic::AtomicU64::new(0),
             total_accounts: std::sync::atomic::AtomicU64::new(0),
+            clock: std::sync::atomic::AtomicI64::new(0),
+            entities: DashMap::new(),
+            rollups: RollupStore::default(),
+            limits: WindowLimits::default(),
+            window_bytes: std::sync::atomic::AtomicU64::new(0),
+            evicted_accounts: std::sync::atomic::AtomicU64::new(0),
         }
     }
 
+    pub fn with_window_limits(mut self, limits: WindowLimits) -> Self {
+        self.rollups = RollupStore::new(limits.rollup.clone());
+        self.limits = limits;
+        self
+    }
+
     /// Ingest one event. Updates all indexes and triggers cluster detection.
-    pub fn ingest(&self, event: &ApiEvent) {
+    /// Returns the event's global-state update, for peers in a partitioned
+    /// deployment (state/partition.rs).
+    pub fn ingest(&self, event: &ApiEvent) -> IndexUpdate {
+        let update = self.ingest_local(event);
+        self.apply_update(&update);
+        update
+    }
+
+    /// Per-account half of ingest: window, model pivots, rollups.
+    fn ingest_local(&self, event: &ApiEvent) -> IndexUpdate {
         self.total_events
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
@@ -267,7 +577,7 @@ impl StateStore {
         // Detect model pivot before ingesting
         {
             let r = window.read();
-            if let Some(last) = r.events.back() {
+            if let Some(last) = r.recent.back() {
                 if !last.model.is_empty() && last.model != event.model {
                     self.model_switches
                         .entry(event.account_id.clone())
@@ -277,151 +587,214 @@ impl StateStore {
             }
         }
 
-        window.write().ingest(event);
+        let mut w = window.write();
+        let summary = w.ingest(event, &self.limits);
+        self.rollups.record(event, &summary);
+        IndexUpdate {
+            account_id: event.account_id.clone(),
+            timestamp: event.timestamp,
+            ip_address: event.ip_address,
+            payment_method_hash: event.payment_method_hash.clone(),
+            org_id: event.org_id.clone(),
+            ja3_hash: event.ja3_hash.clone(),
+            ja3s_hash: event.ja3s_hash.clone(),
+            system_prompt_hash: event.system_prompt_hash.clone(),
+            summary,
+            meta: w.clone_meta(),
+        }
+    }
+
+    /// Global half of ingest: entity windows, infrastructure indexes, timing
+    /// buckets, graph links.  Applied locally for owned accounts and on every
+    /// peer for the rest.
+    pub fn apply_update(&self, u: &IndexUpdate) {
+        let prefixes = self.subnet_prefixes();
+        let subnet = prefixes.of(u.ip_address).map(|s| s.to_string());
+        let entities = [
+            (EntityKind::Org, u.org_id.as_ref()),
+            (EntityKind::Payment, u.payment_method_hash.as_ref()),
+            (EntityKind::Subnet, subnet.as_ref()),
+        ];
+        for (kind, key) in entities {
+            let Some(key) = key else { continue };
+            self.entities
+                .entry((kind, key.clone()))
+                .or_insert_with(|| Arc::new(RwLock::new(EntityWindow::new(kind, key, u.timestamp))))
+                .write()
+                .ingest(&u.account_id, u.summary, &self.limits);
+        }
 
         // Update all indexes
-        if let Some(ref pm) = event.payment_method_hash {
-            self.payment_idx
-                .entry(pm.clone())
-                .or_default()
-                .insert(event.account_id.clone());
+        if let Some(ref pm) = u.payment_method_hash {
+            self.payment_idx.touch(pm, &u.account_id, u.timestamp);
         }
-        if let Some(ref org) = event.org_id {
-            self.org_idx
-                .entry(org.clone())
-                .or_default()
-                .insert(event.account_id.clone());
+        if let Some(ref org) = u.org_id {
+            self.org_idx.touch(org, &u.account_id, u.timestamp);
         }
-        if let Some(ref ja3) = event.ja3_hash {
-            self.ja3_idx
-                .entry(ja3.clone())
-                .or_default()
-                .insert(event.account_id.clone());
+        if let Some(ref ja3) = u.ja3_hash {
+            self.ja3_idx.touch(ja3, &u.account_id, u.timestamp);
         }
-        if let Some(ref ja3s) = event.ja3s_hash {
-            self.ja3s_idx
-                .entry(ja3s.clone())
-                .or_default()
-                .insert(event.account_id.clone());
+        if let Some(ref ja3s) = u.ja3s_hash {
+            self.ja3s_idx.touch(ja3s, &u.account_id, u.timestamp);
         }
-        for subnet in window.read().subnets() {
-            self.subnet_idx
-                .entry(subnet)
-                .or_default()
-                .insert(event.account_id.clone());
+        if let Some(ref subnet) = subnet {
+            self.subnet_idx.touch(subnet, &u.account_id, u.timestamp);
         }
 
         // Record global timing bucket (for cross-account burst detection)
-        let bucket = event.timestamp.timestamp() as u64;
+        let bucket = u.timestamp.timestamp() as u64;
         self.timing_buckets
             .entry(bucket)
             .or_default()
-            .insert(event.account_id.clone());
+            .insert(u.account_id.clone());
 
         // Record preamble hash (Phase 1 — role preamble collision detection)
-        if let Some(ref ph) = event.system_prompt_hash {
-            self.preamble_idx
-                .entry(ph.clone())
-                .or_default()
-                .insert(event.account_id.clone());
+        if let Some(ref ph) = u.system_prompt_hash {
+            self.preamble_idx.touch(ph, &u.account_id, u.timestamp);
         }
 
+        self.clock.fetch_max(
+            u.timestamp.timestamp(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+
         // Trigger incremental cluster update
-        self.update_clusters(&event.account_id);
+        self.update_clusters(&u.account_id, &u.meta, u.timestamp);
     }
 
-    fn update_clusters(&self, account_id: &str) {
-        // Find all accounts related to this one via shared infrastructure
-        let mut related: HashSet<String> = HashSet::new();
-
-        let window = match self.accounts.get(account_id) {
-            Some(w) => w.read().clone_meta(),
-            None => return,
-        };
-
-        // Shared payment (weight 4 — strongest signal)
-        for pm in &window.payment_hashes {
-            if let Some(accts) = self.payment_idx.get(pm) {
-                related.extend(accts.iter().cloned());
+    fn update_clusters(&self, account_id: &str, window: &AccountMeta, now: DateTime<Utc>) {
+        // Accounts sharing infrastructure with this one, per link kind, with
+        // the link scale.  Only values both accounts used within the link's
+        // TTL count; supernode values are down-weighted or skipped.
+        let cfg = self.graph.read().config().clone();
+        let mut related: Vec<(LinkKind, HashMap<String, f32>)> = Vec::new();
+        let mut collect = |kind: LinkKind, idx: &InfraIndex, keys: &HashSet<String>| {
+            let since = now - cfg.ttl.of(kind);
+            let mut peers: HashMap<String, f32> = HashMap::new();
+            for key in keys {
+                let accts = idx.fresh_peers(key, account_id, since);
+                if accts.is_empty() {
+                    continue;
+                }
+                let (scale, reason) = cfg.supernode.scale(kind, key, accts.len() + 1);
+                if let Some(reason) = reason {
+                    self.supernodes
+                        .record(kind, key, accts.len() + 1, scale, reason, now);
+                }
+                if scale <= 0.0 {
+                    continue;
+                }
+                for a in accts {
+                    let s = peers.entry(a).or_insert(0.0);
+                    *s = s.max(scale);
+                }
             }
-        }
-        // Shared org (weight 3)
-        for org in &window.org_ids {
-            if let Some(accts) = self.org_idx.get(org) {
-                related.extend(accts.iter().cloned());
+            if !peers.is_empty() {
+                related.push((kind, peers));
             }
+        };
+        collect(LinkKind::Payment, &self.payment_idx, &window.payment_hashes);
+        collect(LinkKind::Org, &self.org_idx, &window.org_ids);
+        collect(
+            LinkKind::Subnet,
+            &self.subnet_idx,
+            &window.subnets(&cfg.subnets),
+        );
+        collect(LinkKind::Ja3, &self.ja3_idx, &window.ja3_hashes);
+        if related.is_empty() {
+            return;
         }
-        // Shared subnet (weight 2)
-        for subnet in &window.subnets() {
-            if let Some(accts) = self.subnet_idx.get(subnet) {
-                related.extend(accts.iter().cloned());
+
+        let mut graph = self.graph.write();
+        for (kind, peers) in related {
+            for (other, scale) in peers {
+                graph.link(account_id, &other, kind, scale, now);
             }
         }
-        // Shared JA3 (weight 2 — script clients sharing same library version)
-        for ja3 in &window.ja3_hashes {
-            if let Some(accts) = self.ja3_idx.get(ja3) {
-                related.extend(accts.iter().cloned());
-            }
+        if let Some(cid) = graph.cluster_of(account_id) {
+            debug!(
+                "Cluster {} now has {} members",
+                cid,
+                graph.cluster_size(cid)
+            );
         }
+    }
 
-        related.remove(account_id);
-        if related.len() < 2 {
-            return;
-        }
+    // ── Queries ───────────────────────────────────────────────────────────────
 
-        // Find existing cluster IDs for this account and its neighbors
-        let mut cluster_ids: HashSet<u32> = HashSet::new();
-        if let Some(cid) = self.account_cluster.get(account_id) {
-            cluster_ids.insert(*cid);
-        }
-        for rel in &related {
-            if let Some(cid) = self.account_cluster.get(rel) {
-                cluster_ids.insert(*cid);
-            }
-        }
+    pub fn get_window(&self, account_id: &str) -> Option<Arc<RwLock<AccountWindow>>> {
+        self.accounts.get(account_id).map(|w| w.clone())
+    }
 
-        // Merge clusters (or create new)
-        let cluster_id = if cluster_ids.is_empty() {
-            let mut next = self.next_cluster.lock();
-            let id = *next;
-            *next += 1;
-            id
-        } else {
-            // Use the lowest existing ID (merge others into it)
-            *cluster_ids.iter().min().unwrap()
-        };
+    /// Make a previously evicted window resident again (a window already
+    /// resident for the account wins).
+    pub fn restore_window(&self, window: AccountWindow) -> Arc<RwLock<AccountWindow>> {
+        let account_id = window.account_id.clone();
+        self.reinsert_window(&account_id, Arc::new(RwLock::new(window)))
+    }
+
+    /// `restore_window` for a window taken with `take_window`.
+    pub fn reinsert_window(
+        &self,
+        account_id: &str,
+        window: Arc<RwLock<AccountWindow>>,
+    ) -> Arc<RwLock<AccountWindow>> {
+        self.accounts
+            .entry(account_id.to_string())
+            .or_insert(window)
+            .clone()
+    }
 
-        // Assign all members
-        let mut members = related;
-        members.insert(account_id.to_string());
+    /// Remove a window for eviction; suspended accounts are never evicted.
+    pub fn take_window(&self, account_id: &str) -> Option<Arc<RwLock<AccountWindow>>> {
+        let (_, window) = self
+            .accounts
+            .remove_if(account_id, |_, w| !w.read().suspended)?;
+        self.model_switches.remove(account_id);
+        Some(window)
+    }
 
-        for member in &members {
-            self.account_cluster.insert(member.clone(), cluster_id);
+    /// Org / payment / subnet entities an event belongs to.
+    pub fn entity_keys(&self, event: &ApiEvent) -> Vec<(EntityKind, String)> {
+        let mut keys = Vec::with_capacity(3);
+        if let Some(org) = &event.org_id {
+            keys.push((EntityKind::Org, org.clone()));
+        }
+        if let Some(pm) = &event.payment_method_hash {
+            keys.push((EntityKind::Payment, pm.clone()));
         }
-        self.clusters.entry(cluster_id).or_default().extend(members);
+        if let Some(subnet) = self.subnet_prefixes().of(event.ip_address) {
+            keys.push((EntityKind::Subnet, subnet.to_string()));
+        }
+        keys
+    }
 
-        debug!(
-            "Cluster {} now has {} members",
-            cluster_id,
-            self.clusters.get(&cluster_id).map(|c| c.len()).unwrap_or(0)
-        );
+    pub fn get_entity(&self, kind: EntityKind, key: &str) -> Option<Arc<RwLock<EntityWindow>>> {
+        self.entities
+            .get(&(kind, key.to_string()))
+            .map(|w| w.clone())
     }
 
-    // ── Queries ───────────────────────────────────────────────────────────────
+    pub fn n_entities(&self) -> usize {
+        self.entities.len()
+    }
 
-    pub fn get_window(&self, account_id: &str) -> Option<Arc<RwLock<AccountWindow>>> {
-        self.accounts.get(account_id).map(|w| w.clone())
+    /// Prefix lengths subnets are aggregated to (graph config).
+    pub fn subnet_prefixes(&self) -> SubnetPrefixes {
+        self.graph.read().config().subnets
     }
 
     pub fn get_cluster(&self, account_id: &str) -> Option<u32> {
-        self.account_cluster.get(account_id).map(|c| *c)
+        self.graph.read().cluster_of(account_id)
     }
 
     pub fn cluster_members(&self, cluster_id: u32) -> HashSet<String> {
-        self.clusters
-            .get(&cluster_id)
-            .map(|c| c.clone())
-            .unwrap_or_default()
+        self.graph.read().members(cluster_id)
+    }
+
+    /// Take the formation / join / merge events queued since the last call.
+    pub fn drain_cluster_events(&self) -> Vec<ClusterEvent> {
+        self.graph.write().drain_events()
     }
 
     pub fn model_switches(&self, account_id: &str) -> Vec<(DateTime<Utc>, String, String)> {
@@ -432,33 +805,27 @@ impl StateStore {
     }
 
     pub fn accounts_with_ja3(&self, ja3: &str) -> HashSet<String> {
-        self.ja3_idx.get(ja3).map(|a| a.clone()).unwrap_or_default()
+        self.ja3_idx.accounts(ja3)
     }
 
     pub fn accounts_with_ja3s(&self, ja3s: &str) -> HashSet<String> {
-        self.ja3s_idx
-            .get(ja3s)
-            .map(|a| a.clone())
-            .unwrap_or_default()
+        self.ja3s_idx.accounts(ja3s)
     }
 
     pub fn accounts_with_header_hash(&self, hash: &str) -> HashSet<String> {
-        self.hdr_idx
-            .get(hash)
-            .map(|a| a.clone())
-            .unwrap_or_default()
+        self.hdr_idx.accounts(hash)
     }
 
     /// Number of distinct accounts that share the given preamble hash (Phase 1).
     pub fn accounts_with_preamble_hash(&self, hash: &str) -> usize {
-        self.preamble_idx.get(hash).map(|a| a.len()).unwrap_or(0)
+        self.preamble_idx.count(hash)
     }
 
     pub fn n_accounts(&self) -> usize {
         self.accounts.len()
     }
     pub fn n_clusters(&self) -> usize {
-        self.clusters.len()
+        self.graph.read().n_clusters()
     }
 
     // ── Timing bucket queries (Tier 1 — cross-account burst detection) ────────
@@ -472,6 +839,10 @@ impl StateStore {
     }
 
     /// Count how many distinct accounts fired in a given 1-second bucket.
+    pub fn timing_bucket_count(&self) -> usize {
+        self.timing_buckets.len()
+    }
+
     pub fn accounts_in_bucket(&self, bucket: u64) -> usize {
         self.timing_buckets
             .get(&bucket)
@@ -485,6 +856,10 @@ impl StateStore {
         self.watermarked.contains_key(account_id)
     }
 
+    pub fn watermarked_accounts(&self) -> Vec<String> {
+        self.watermarked.iter().map(|e| e.key().clone()).collect()
+    }
+
     pub fn mark_watermarked(&self, account_id: &str) {
         self.watermarked.insert(account_id.to_string(), Utc::now());
         if let Some(w) = self.accounts.get(account_id) {
@@ -492,6 +867,21 @@ impl StateStore {
         }
     }
 
+    /// Mirror the account's enforcement lifecycle (engine/lifecycle.rs) onto its window.
+    pub fn set_suspended(&self, account_id: &str, suspended: bool) {
+        if let Some(w) = self.accounts.get(account_id) {
+            w.write().suspended = suspended;
+        }
+    }
+
+    /// Remember the account-scope rate-limit command issued for the account,
+    /// so the query API reports the limit the gateway actually applies.
+    pub fn set_rate_limit(&self, account_id: &str, command: &RateLimitCommand) {
+        if let Some(w) = self.accounts.get(account_id) {
+            w.write().rate_limit = Some(command.clone());
+        }
+    }
+
     // ── Canary token registry (Tier 2) ────────────────────────────────────────
 
     pub fn register_canary(&self, token: CanaryToken) {
@@ -502,6 +892,14 @@ impl StateStore {
         self.canary_registry.get(token).map(|t| t.clone())
     }
 
+    /// Copy of the whole registry (for snapshots).
+    pub fn canaries(&self) -> Vec<CanaryToken> {
+        self.canary_registry
+            .iter()
+            .map(|e| e.value().clone())
+            .collect()
+    }
+
     pub fn trigger_canary(&self, token: &str) {
         if let Some(mut entry) = self.canary_registry.get_mut(token) {
             entry.triggered = true;
@@ -509,6 +907,30 @@ impl StateStore {
         }
     }
 
+    /// Keeps the most recent MAX_CANARY_HITS per replaying account.
+    pub fn record_canary_hit(&self, hit: CanaryHit) {
+        let mut hits = self.canary_hits.entry(hit.account_id.clone()).or_default();
+        if hits.len() >= MAX_CANARY_HITS {
+            hits.remove(0);
+        }
+        hits.push(hit);
+    }
+
+    pub fn canary_hits(&self, account_id: &str) -> Vec<CanaryHit> {
+        self.canary_hits
+            .get(account_id)
+            .map(|h| h.clone())
+            .unwrap_or_default()
+    }
+
+    /// Link two accounts by a canary / watermark replay.  Returns their
+    /// cluster, or None if the canary link weight is below the threshold.
+    pub fn link_accounts(&self, a: &str, b: &str) -> Option<u32> {
+        self.graph
+            .write()
+            .link(a, b, LinkKind::Canary, 1.0, Utc::now())
+    }
+
     pub fn triggered_canaries_for_cluster(&self, cluster_id: u32) -> Vec<String> {
         let members = self.cluster_members(cluster_id);
         self.canary_registry
@@ -520,17 +942,257 @@ impl StateStore {
 
     // ── Housekeeping ──────────────────────────────────────────────────────────
 
-    pub async fn housekeeping_loop(self: Arc<Self>) {
-        loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(300)).await;
-            let cutoff_secs = (Utc::now() - chrono::Duration::seconds(W_24HR)).timestamp() as u64;
-            for entry in self.accounts.iter() {
-                entry.value().write().expire_old();
+    /// One housekeeping pass (every 5 minutes, state/backend.rs): expire
+    /// windows, enforce memory limits (`evict` removes each chosen window),
+    /// expire rollups and timing buckets, recompute clusters.
+    pub fn housekeep_with(&self, evict: impl FnMut(&str) -> bool) {
+        let cutoff_secs = (Utc::now() - chrono::Duration::seconds(W_24HR)).timestamp() as u64;
+        for entry in self.accounts.iter() {
+            entry.value().write().expire_old();
+        }
+        let cutoff = Utc::now() - Duration::seconds(W_24HR);
+        self.entities.retain(|_, w| {
+            let mut w = w.write();
+            w.expire_old(cutoff);
+            !w.events.is_empty()
+        });
+        self.enforce_memory_limits(evict);
+        self.rollups.expire(self.clock());
+        // Expire old timing buckets (keep last 10 minutes)
+        self.timing_buckets
+            .retain(|&bucket, _| bucket >= cutoff_secs.saturating_sub(600));
+        self.recompute_clusters();
+    }
+
+    /// Re-estimate window memory and evict the least recently active
+    /// accounts while over `max_accounts` or the memory budget.  Watermarked
+    /// accounts are kept.  Each chosen account is passed to `evict`, with no
+    /// map lock held, which removes it (`take_window`, refusing suspended
+    /// accounts) and returns whether it did — so a backend can spill to disk
+    /// without blocking other accounts.  Returns the number evicted.
+    pub fn enforce_memory_limits(&self, mut evict: impl FnMut(&str) -> bool) -> usize {
+        let mut sizes: Vec<(String, DateTime<Utc>, usize)> = self
+            .accounts
+            .iter()
+            .map(|e| {
+                let w = e.value().read();
+                (e.key().clone(), w.last_seen, w.approx_bytes())
+            })
+            .collect();
+        let entity_bytes = self.enforce_entity_limit();
+        let mut total: u64 = entity_bytes + sizes.iter().map(|(_, _, b)| *b as u64).sum::<u64>();
+        let mut n_accounts = self.accounts.len();
+        let mut evicted = 0;
+        if n_accounts > self.limits.max_accounts || total > self.limits.memory_budget_bytes {
+            sizes.sort_by_key(|(_, t, _)| *t);
+            for (account, _, bytes) in sizes {
+                if n_accounts <= self.limits.max_accounts
+                    && total <= self.limits.memory_budget_bytes
+                {
+                    break;
+                }
+                if self.watermarked.contains_key(&account) {
+                    continue;
+                }
+                if evict(&account) {
+                    total = total.saturating_sub(bytes as u64);
+                    n_accounts -= 1;
+                    evicted += 1;
+                }
+            }
+        }
+        self.window_bytes
+            .store(total, std::sync::atomic::Ordering::Relaxed);
+        if evicted > 0 {
+            self.evicted_accounts
+                .fetch_add(evicted as u64, std::sync::atomic::Ordering::Relaxed);
+            info!(
+                "Evicted {} idle account windows (accounts={} est_bytes={})",
+                evicted, n_accounts, total
+            );
+        }
+        evicted
+    }
+
+    /// Evict the least recently active entity windows beyond `max_entities`.
+    /// Returns the bytes of the entity windows kept.
+    fn enforce_entity_limit(&self) -> u64 {
+        let mut sizes: Vec<((EntityKind, String), DateTime<Utc>, usize)> = self
+            .entities
+            .iter()
+            .map(|e| {
+                let w = e.value().read();
+                (e.key().clone(), w.last_seen, w.approx_bytes())
+            })
+            .collect();
+        let mut total: u64 = sizes.iter().map(|(_, _, b)| *b as u64).sum();
+        if sizes.len() > self.limits.max_entities {
+            sizes.sort_by_key(|(_, t, _)| *t);
+            let excess = sizes.len() - self.limits.max_entities;
+            for (key, _, bytes) in sizes.into_iter().take(excess) {
+                self.entities.remove(&key);
+                total = total.saturating_sub(bytes as u64);
             }
-            // Expire old timing buckets (keep last 10 minutes)
-            self.timing_buckets
-                .retain(|&bucket, _| bucket >= cutoff_secs.saturating_sub(600));
         }
+        total
+    }
+
+    /// Estimated account- and entity-window bytes as of the last housekeeping pass.
+    pub fn window_bytes(&self) -> u64 {
+        self.window_bytes.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Long-horizon rollups (account history beyond the 24 h window).
+    pub fn rollups(&self) -> &RollupStore {
+        &self.rollups
+    }
+
+    /// Run `f` on an account's hourly / daily rollup.
+    pub fn with_account_rollup<T>(
+        &self,
+        account_id: &str,
+        f: impl FnOnce(&Rollup) -> T,
+    ) -> Option<T> {
+        self.rollups.with_account(account_id, f)
+    }
+
+    /// Rollup merged over the cluster's current members.
+    pub fn cluster_rollup(&self, cluster_id: u32) -> Rollup {
+        self.rollups.merged(&self.cluster_members(cluster_id))
+    }
+
+    pub fn window_limits(&self) -> &WindowLimits {
+        &self.limits
+    }
+
+    /// Event-time clock: the latest event timestamp seen (wall clock before
+    /// the first event), so replays of old captures age links consistently.
+    pub fn clock(&self) -> DateTime<Utc> {
+        match self.clock.load(std::sync::atomic::Ordering::Relaxed) {
+            0 => Utc::now(),
+            secs => DateTime::from_timestamp(secs, 0).unwrap_or_else(Utc::now),
+        }
+    }
+
+    /// Prune the infrastructure indexes by link TTL and key cap, then expire
+    /// graph links and recompute clusters (split / shrink / dissolve events
+    /// are queued for `drain_cluster_events`).
+    pub fn recompute_clusters(&self) -> RecomputeStats {
+        let now = self.clock();
+        let cfg = self.graph.read().config().clone();
+        let ja3_since = now - cfg.ttl.of(LinkKind::Ja3);
+        let pruned = self
+            .payment_idx
+            .prune(now - cfg.ttl.of(LinkKind::Payment), cfg.index_max_keys)
+            + self
+                .org_idx
+                .prune(now - cfg.ttl.of(LinkKind::Org), cfg.index_max_keys)
+            + self
+                .subnet_idx
+                .prune(now - cfg.ttl.of(LinkKind::Subnet), cfg.index_max_keys)
+            + self.ja3_idx.prune(ja3_since, cfg.index_max_keys)
+            + self.ja3s_idx.prune(ja3_since, cfg.index_max_keys)
+            + self.hdr_idx.prune(ja3_since, cfg.index_max_keys)
+            + self
+                .preamble_idx
+                .prune(now - Duration::seconds(W_24HR), cfg.index_max_keys);
+        self.supernodes.prune(now - Duration::seconds(W_24HR));
+        let scales = self.supernode_scales(&cfg, now);
+        let mut graph = self.graph.write();
+        let mut rescaled = 0;
+        for (kind, by_account) in &scales {
+            rescaled += graph.rescale(*kind, |a, b| {
+                let (va, vb) = (by_account.get(a)?, by_account.get(b)?);
+                va.iter()
+                    .filter(|(value, _)| vb.iter().any(|(v, _)| v == value))
+                    .map(|(_, s)| *s)
+                    .reduce(f32::max)
+            });
+        }
+        let mut stats = graph.recompute(now);
+        drop(graph);
+        stats.rescaled_links = rescaled;
+        info!(
+            "Graph recompute: pruned_index_entries={} expired_links={} rescaled_links={} removed_edges={} removed_accounts={} clusters={} splits={} dissolved={}",
+            pruned,
+            stats.expired_links,
+            stats.rescaled_links,
+            stats.removed_edges,
+            stats.removed_accounts,
+            stats.clusters,
+            stats.splits,
+            stats.dissolved
+        );
+        stats
+    }
+
+    /// Current supernode scale of every fresh value, per account, for the
+    /// link kinds the supernode config scales at all.
+    fn supernode_scales(
+        &self,
+        cfg: &GraphConfig,
+        now: DateTime<Utc>,
+    ) -> Vec<(LinkKind, ValueScales)> {
+        let indexes = [
+            (LinkKind::Payment, &self.payment_idx),
+            (LinkKind::Org, &self.org_idx),
+            (LinkKind::Subnet, &self.subnet_idx),
+            (LinkKind::Ja3, &self.ja3_idx),
+        ];
+        let sn = &cfg.supernode;
+        let mut out = Vec::new();
+        for (kind, idx) in indexes {
+            if !sn.limits.contains_key(&kind) && !sn.known_shared.contains_key(&kind) {
+                continue;
+            }
+            let mut by_account = ValueScales::new();
+            for (value, accounts) in idx.fresh_values(now - cfg.ttl.of(kind)) {
+                let (scale, reason) = sn.scale(kind, &value, accounts.len());
+                if let Some(reason) = reason {
+                    self.supernodes
+                        .record(kind, &value, accounts.len(), scale, reason, now);
+                }
+                for a in accounts {
+                    by_account
+                        .entry(a)
+                        .or_default()
+                        .push((value.clone(), scale));
+                }
+            }
+            out.push((kind, by_account));
+        }
+        out
+    }
+
+    /// Infrastructure values currently down-weighted or suppressed as
+    /// supernodes, most shared first.
+    pub fn suppressed_values(&self) -> Vec<SuppressedValue> {
+        self.supernodes.values()
+    }
+
+    /// Values that became supernodes and stopped linking, per kind (cumulative).
+    pub fn suppressed_value_count(&self) -> std::collections::BTreeMap<LinkKind, u64> {
+        self.supernodes.values_suppressed()
+    }
+
+    /// Lineage of a live cluster: the clusters it was merged from or split off.
+    pub fn cluster_parents(&self, cluster_id: u32) -> Vec<u32> {
+        self.graph.read().parents(cluster_id)
+    }
+
+    /// Run `f` on the relationship graph (read-locked).
+    pub fn with_graph<T>(&self, f: impl FnOnce(&AccountGraph) -> T) -> T {
+        f(&self.graph.read())
+    }
+
+    /// Infrastructure indexes exported as graph nodes (state/export.rs).
+    pub fn infra_indexes(&self) -> [(NodeKind, &InfraIndex); 4] {
+        [
+            (NodeKind::Payment, &self.payment_idx),
+            (NodeKind::Subnet, &self.subnet_idx),
+            (NodeKind::Ja3, &self.ja3_idx),
+            (NodeKind::Preamble, &self.preamble_idx),
+        ]
     }
 }
 
@@ -540,37 +1202,280 @@ impl Default for StateStore {
     }
 }
 
-// Helper: clone just metadata fields for cluster analysis (avoids cloning all events)
-struct WindowMeta {
-    payment_hashes: HashSet<String>,
-    org_ids: HashSet<String>,
-    ja3_hashes: HashSet<String>,
-    ip_addresses: HashSet<String>,
+// ── Infrastructure index ──────────────────────────────────────────────────────
+
+/// Infrastructure value → (account → last seen).  Entries older than the
+/// link TTL are pruned, and the number of values is capped (least recently
+/// seen dropped first), so the index cannot grow without bound.
+#[derive(Default)]
+pub struct InfraIndex {
+    map: DashMap<String, HashMap<String, DateTime<Utc>>>,
 }
 
-impl WindowMeta {
-    fn subnets(&self) -> HashSet<String> {
-        self.ip_addresses
+impl InfraIndex {
+    pub fn touch(&self, key: &str, account_id: &str, ts: DateTime<Utc>) {
+        let mut accts = self.map.entry(key.to_string()).or_default();
+        let seen = accts.entry(account_id.to_string()).or_insert(ts);
+        *seen = (*seen).max(ts);
+    }
+
+    pub fn accounts(&self, key: &str) -> HashSet<String> {
+        self.map
+            .get(key)
+            .map(|a| a.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn count(&self, key: &str) -> usize {
+        self.map.get(key).map_or(0, |a| a.len())
+    }
+
+    /// Other accounts seen on `key` since `since`, provided `account_id`
+    /// itself was.
+    pub fn fresh_peers(&self, key: &str, account_id: &str, since: DateTime<Utc>) -> Vec<String> {
+        let Some(accts) = self.map.get(key) else {
+            return Vec::new();
+        };
+        if accts.get(account_id).is_none_or(|t| *t < since) {
+            return Vec::new();
+        }
+        accts
+            .iter()
+            .filter(|(a, t)| a.as_str() != account_id && **t >= since)
+            .map(|(a, _)| a.clone())
+            .collect()
+    }
+
+    /// Every value with the accounts seen on it since `since`.
+    pub fn fresh_values(&self, since: DateTime<Utc>) -> Vec<(String, Vec<String>)> {
+        self.map
             .iter()
-            .filter_map(|ip| {
-                let p: Vec<&str> = ip.split('.').collect();
-                if p.len() == 4 {
-                    Some(format!("{}.{}.{}", p[0], p[1], p[2]))
-                } else {
-                    None
+            .filter_map(|e| {
+                let accts: Vec<String> = e
+                    .value()
+                    .iter()
+                    .filter(|(_, t)| **t >= since)
+                    .map(|(a, _)| a.clone())
+                    .collect();
+                (!accts.is_empty()).then(|| (e.key().clone(), accts))
+            })
+            .collect()
+    }
+
+    /// Drop entries last seen before `cutoff`, then the least recently seen
+    /// values beyond `max_keys`.  Returns the number of entries removed.
+    pub fn prune(&self, cutoff: DateTime<Utc>, max_keys: usize) -> usize {
+        let mut removed = 0;
+        self.map.retain(|_, accts| {
+            let before = accts.len();
+            accts.retain(|_, t| *t >= cutoff);
+            removed += before - accts.len();
+            !accts.is_empty()
+        });
+        if self.map.len() > max_keys {
+            let mut newest: Vec<(String, DateTime<Utc>)> = self
+                .map
+                .iter()
+                .filter_map(|e| e.value().values().max().map(|t| (e.key().clone(), *t)))
+                .collect();
+            newest.sort_by_key(|(_, t)| *t);
+            let excess = self.map.len() - max_keys;
+            for (key, _) in newest.into_iter().take(excess) {
+                if let Some((_, accts)) = self.map.remove(&key) {
+                    removed += accts.len();
                 }
+            }
+        }
+        removed
+    }
+
+    /// Every (value, account, last seen) entry.
+    pub fn entries(&self) -> Vec<(String, String, DateTime<Utc>)> {
+        self.map
+            .iter()
+            .flat_map(|e| {
+                let key = e.key().clone();
+                e.value()
+                    .iter()
+                    .map(|(a, t)| (key.clone(), a.clone(), *t))
+                    .collect::<Vec<_>>()
             })
             .collect()
     }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+// ── Global-state update ───────────────────────────────────────────────────────
+
+/// Infrastructure values of an account used for graph linking (the window's
+/// sets, without events).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountMeta {
+    pub payment_hashes: HashSet<String>,
+    pub org_ids: HashSet<String>,
+    pub ja3_hashes: HashSet<String>,
+    pub ip_addresses: HashSet<String>,
+}
+
+impl AccountMeta {
+    fn subnets(&self, prefixes: &SubnetPrefixes) -> HashSet<String> {
+        prefixes
+            .of_all(&self.ip_addresses)
+            .iter()
+            .map(IpCidr::to_string)
+            .collect()
+    }
+}
+
+/// What one event changes in the global (cross-account) state: entity
+/// windows, reverse indexes, timing buckets and the graph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexUpdate {
+    pub account_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub ip_address: IpAddr,
+    pub payment_method_hash: Option<String>,
+    pub org_id: Option<String>,
+    pub ja3_hash: Option<String>,
+    pub ja3s_hash: Option<String>,
+    pub system_prompt_hash: Option<String>,
+    pub summary: EventSummary,
+    pub meta: AccountMeta,
 }
 
 impl AccountWindow {
-    fn clone_meta(&self) -> WindowMeta {
-        WindowMeta {
-            payment_hashes: self.payment_hashes.clone(),
-            org_ids: self.org_ids.clone(),
-            ja3_hashes: self.ja3_hashes.clone(),
-            ip_addresses: self.ip_addresses.clone(),
+    fn clone_meta(&self) -> AccountMeta {
+        AccountMeta {
+            payment_hashes: self.payment_hashes.iter().cloned().collect(),
+            org_ids: self.org_ids.iter().cloned().collect(),
+            ja3_hashes: self.ja3_hashes.iter().cloned().collect(),
+            ip_addresses: self.ip_addresses.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(account: &str, ip: &str, prompt: &str, ts: DateTime<Utc>) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": account, "timestamp": ts,
+            "ip_address": ip, "user_agent": "ua", "model": "m",
+            "prompt": prompt, "token_count": 100,
+            "payment_method_hash": null, "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn recent_set_evicts_least_recently_seen() {
+        let t0 = Utc::now();
+        let mut set = RecentSet::default();
+        set.insert("a", t0, 2);
+        set.insert("b", t0 + Duration::seconds(1), 2);
+        // Seeing "a" again makes "b" the least recent.
+        set.insert("a", t0 + Duration::seconds(2), 2);
+        set.insert("c", t0 + Duration::seconds(3), 2);
+        assert_eq!(set.len(), 2);
+        assert!(set.contains("a") && set.contains("c") && !set.contains("b"));
+        // An out-of-order sighting never moves last-seen backwards.
+        set.insert("c", t0, 2);
+        assert_eq!(set.count_since(t0 + Duration::seconds(2)), 2);
+    }
+
+    #[test]
+    fn window_caps_events_prompts_and_values() {
+        let limits = WindowLimits {
+            max_events: 5,
+            max_prompts: 3,
+            max_prompt_bytes: 10,
+            max_values: 2,
+            ..WindowLimits::default()
+        };
+        let t0 = Utc::now() - Duration::minutes(10);
+        let mut w = AccountWindow::new("acct", t0);
+        for i in 0..8 {
+            let ts = t0 + Duration::seconds(i);
+            let prompt = format!("{i}{}", "é".repeat(7)); // 15 bytes
+            let s = w.ingest(
+                &event("acct", &format!("198.51.100.{i}"), &prompt, ts),
+                &limits,
+            );
+            assert_eq!(s.prompt_len, 15); // summaries keep the full length
         }
+
+        assert_eq!(w.events.len(), 5);
+        assert_eq!(
+            w.events.front().unwrap().timestamp,
+            t0 + Duration::seconds(3)
+        );
+
+        // Only the last three prompts, truncated on a char boundary.
+        let prompts = w.prompts_in(W_1HR);
+        assert_eq!(prompts, vec!["5éééé", "6éééé", "7éééé"]);
+
+        assert_eq!(w.ip_addresses.len(), 2);
+        assert!(w.ip_addresses.contains("198.51.100.7"));
+        assert!(w.ip_addresses.contains("198.51.100.6"));
+    }
+
+    #[test]
+    fn idle_accounts_are_evicted_beyond_the_limits() {
+        let store = StateStore::new().with_window_limits(WindowLimits {
+            max_accounts: 2,
+            ..WindowLimits::default()
+        });
+        let t0 = Utc::now() - Duration::minutes(10);
+        for (i, acct) in ["idle", "marked", "busy", "new"].iter().enumerate() {
+            store.ingest(&event(
+                acct,
+                "198.51.100.1",
+                "p",
+                t0 + Duration::seconds(i as i64),
+            ));
+        }
+        store.mark_watermarked("marked");
+
+        // Least recently active first; watermarked accounts are kept.
+        let evicted = store.enforce_memory_limits(|a| store.take_window(a).is_some());
+        assert_eq!(evicted, 2);
+        assert!(store.get_window("idle").is_none());
+        assert!(store.get_window("busy").is_none());
+        assert!(store.get_window("marked").is_some());
+        assert!(store.get_window("new").is_some());
+        assert_eq!(
+            store
+                .evicted_accounts
+                .load(std::sync::atomic::Ordering::Relaxed),
+            2
+        );
+
+        // A memory budget below one window evicts everything evictable.
+        let store = StateStore::new().with_window_limits(WindowLimits {
+            memory_budget_bytes: 1,
+            ..WindowLimits::default()
+        });
+        store.ingest(&event("a", "198.51.100.1", "p", t0));
+        store.ingest(&event("b", "198.51.100.1", "p", t0));
+        store.set_suspended("b", true);
+        assert_eq!(
+            store.enforce_memory_limits(|a| store.take_window(a).is_some()),
+            1
+        );
+        assert!(store.get_window("a").is_none());
+        assert!(store.get_window("b").is_some());
+        assert!(store.window_bytes() > 1);
     }
 }
@@ -0,0 +1,209 @@
+// glasswally/src/webhook.rs
+//
+// Minimal HTTP/1.1 JSON POST client for webhook-style outputs (enforcement
+// sinks, alert notifiers).
+//
+// Like the query API's framing protocol, this avoids pulling a full HTTP
+// client into the build: one request per connection, `Connection: close`,
+// status line parsed, body ignored.  `https://` URLs (Slack, PagerDuty) are
+// served over rustls with the Mozilla root store (webpki-roots), so no system
+// certificate bundle is needed; `http://` stays available for local
+// receivers and TLS-originating proxies.
+
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl std::str::FromStr for HttpUrl {
+    type Err = anyhow::Error;
+
+    fn from_str(url: &str) -> Result<Self> {
+        let (tls, rest) = match (url.strip_prefix("https://"), url.strip_prefix("http://")) {
+            (Some(r), _) => (true, r),
+            (_, Some(r)) => (false, r),
+            _ => anyhow::bail!("{url}: expected an http:// or https:// URL"),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((h, p)) => (h, p.parse().with_context(|| format!("{url}: port"))?),
+            _ => (authority, if tls { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            anyhow::bail!("{url}: missing host");
+        }
+        Ok(Self {
+            tls,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+/// POST `body` as JSON; returns the response status code.  Non-2xx statuses
+/// are returned as errors so callers can retry.
+pub async fn post_json(
+    url: &HttpUrl,
+    headers: &[(String, String)],
+    body: &[u8],
+    timeout: Duration,
+) -> Result<u16> {
+    tokio::time::timeout(timeout, post_inner(url, headers, body))
+        .await
+        .with_context(|| format!("POST {}:{}{} timed out", url.host, url.port, url.path))?
+}
+
+// One client config per process: building the root store is not free.
+fn tls_connector() -> Result<TlsConnector> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    if let Some(cfg) = CONFIG.get() {
+        return Ok(TlsConnector::from(Arc::clone(cfg)));
+    }
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let cfg =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    let cfg = CONFIG.get_or_init(|| Arc::new(cfg));
+    Ok(TlsConnector::from(Arc::clone(cfg)))
+}
+
+async fn post_inner(url: &HttpUrl, headers: &[(String, String)], body: &[u8]) -> Result<u16> {
+    let tcp = TcpStream::connect((url.host.as_str(), url.port)).await?;
+    if !url.tls {
+        return exchange(tcp, url, headers, body).await;
+    }
+    let name = ServerName::try_from(url.host.clone())
+        .with_context(|| format!("{}: invalid TLS server name", url.host))?;
+    let tls = tls_connector()?
+        .connect(name, tcp)
+        .await
+        .with_context(|| format!("TLS handshake with {}", url.host))?;
+    exchange(tls, url, headers, body).await
+}
+
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    url: &HttpUrl,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> Result<u16> {
+    let mut req = format!(
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\nUser-Agent: glasswally/{}\r\n",
+        url.path,
+        url.host,
+        url.port,
+        body.len(),
+        env!("CARGO_PKG_VERSION")
+    );
+    for (k, v) in headers {
+        req.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+
+    // Only the status line matters; read until it is complete.
+    let mut buf = Vec::with_capacity(256);
+    let mut chunk = [0u8; 256];
+    while !buf.windows(2).any(|w| w == b"\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let line = String::from_utf8_lossy(&buf);
+    let status: u16 = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .with_context(|| format!("malformed HTTP response from {}", url.host))?;
+    if !(200..300).contains(&status) {
+        anyhow::bail!("POST {}{} returned HTTP {}", url.host, url.path, status);
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn parses_http_and_https_urls() {
+        let u: HttpUrl = "https://hooks.slack.com/services/T0/B0/x".parse().unwrap();
+        assert!(u.tls);
+        assert_eq!((u.host.as_str(), u.port), ("hooks.slack.com", 443));
+        assert_eq!(u.path, "/services/T0/B0/x");
+
+        let u: HttpUrl = "http://127.0.0.1:8080".parse().unwrap();
+        assert!(!u.tls);
+        assert_eq!(
+            (u.host.as_str(), u.port, u.path.as_str()),
+            ("127.0.0.1", 8080, "/")
+        );
+
+        assert!("ftp://example.com".parse::<HttpUrl>().is_err());
+        assert!("https://:443/x".parse::<HttpUrl>().is_err());
+        assert!(tls_connector().is_ok());
+    }
+
+    #[tokio::test]
+    async fn posts_and_reports_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let mut seen = Vec::new();
+            for status in ["204 No Content", "503 Service Unavailable"] {
+                let (mut s, _) = listener.accept().await.unwrap();
+                let mut req = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !req.ends_with(b"{}") {
+                    let n = s.read(&mut buf).await.unwrap();
+                    req.extend_from_slice(&buf[..n]);
+                }
+                seen.push(String::from_utf8_lossy(&req).to_string());
+                let resp = format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n");
+                s.write_all(resp.as_bytes()).await.unwrap();
+            }
+            seen
+        });
+        let url: HttpUrl = format!("http://{addr}/hook").parse().unwrap();
+        let headers = [("X-Token".to_string(), "t".to_string())];
+        let timeout = Duration::from_secs(5);
+
+        assert_eq!(
+            post_json(&url, &headers, b"{}", timeout).await.unwrap(),
+            204
+        );
+        assert!(post_json(&url, &headers, b"{}", timeout).await.is_err());
+        let seen = server.await.unwrap();
+        assert!(seen[0].starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(seen[0].contains("X-Token: t\r\n"));
+        assert!(seen[0].ends_with("\r\n\r\n{}"));
+    }
+}
@@ -31,7 +31,7 @@ use std::collections::HashMap;
 use chrono::Utc;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
 
 // ── Known cloud / datacenter ASN org name prefixes (lowercased) ───────────────
 // Tier 1 — major cloud providers (high volume legitimate use too, lower risk weight)
@@ -112,7 +112,7 @@ fn classify_asn_org(org: &str) -> CloudTier {
     CloudTier::None
 }
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let asn_org = event.asn_org.as_deref().unwrap_or("");
     let tier = classify_asn_org(asn_org);
 
@@ -146,7 +146,7 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
             for member in &members {
                 if let Some(w) = store.get_window(member) {
                     let w = w.read();
-                    if let Some(org) = w.events.back().and_then(|e| e.asn_org.as_deref()) {
+                    if let Some(org) = w.recent.back().and_then(|e| e.asn_org.as_deref()) {
                         if classify_asn_org(org) != CloudTier::None {
                             cloud_count += 1;
                             // Aggregate provider name (first word)
@@ -13,7 +13,8 @@
 //   - Regular inter-request timing, high RPH
 //   - Prompt first-word is always the same imperative verb ("Explain", "Implement")
 //
-// We model this with three signals:
+// We model this with three signals, over the last hour of the account's
+// recent-prompt ring (at most WindowLimits::max_prompts prompts, default 64):
 //   1. Shannon entropy over structural prompt hashes
 //   2. Coefficient of variation of prompt lengths
 //   3. Template prefix repetition ratio (same first 30 chars)
@@ -28,7 +29,8 @@ use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::{StateStore, W_1HR};
+use crate::state::backend::StateBackend;
+use crate::state::window::W_1HR;
 
 /// Structural fingerprint of a prompt.
 /// Captures: length bucket (per 100 chars) + first word category + dominant verb.
@@ -87,7 +89,7 @@ fn shannon_entropy(counts: &HashMap<u64, usize>) -> f64 {
         .sum()
 }
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let window = store.get_window(&event.account_id)?;
     let window = window.read();
     let prompts = window.prompts_in(W_1HR);
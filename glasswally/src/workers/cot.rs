@@ -3,6 +3,8 @@
 // Chain-of-thought elicitation detector.
 // Aho-Corasick automaton: O(n) per prompt regardless of pattern count.
 // All 33 patterns from the Anthropic report + domain capability heatmap.
+// Window ratios cover the last hour of the recent-prompt ring (at most
+// WindowLimits::max_prompts prompts), not every prompt in the hour.
 
 use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
 use chrono::Utc;
@@ -10,7 +12,8 @@ use serde_json::json;
 use std::sync::OnceLock;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::{StateStore, W_1HR};
+use crate::state::backend::StateBackend;
+use crate::state::window::W_1HR;
 
 static COT_AC: OnceLock<(AhoCorasick, Vec<&'static str>)> = OnceLock::new();
 static DOMAIN_AC: OnceLock<(AhoCorasick, Vec<&'static str>)> = OnceLock::new();
@@ -103,7 +106,7 @@ fn domain_automaton() -> &'static (AhoCorasick, Vec<&'static str>) {
     })
 }
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let (cot_ac, cot_labels) = cot_automaton();
     let (domain_ac, domain_labels) = domain_automaton();
 
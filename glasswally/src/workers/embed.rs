@@ -28,7 +28,7 @@ use std::collections::HashMap;
 use std::sync::OnceLock;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
 
 const DIM: usize = 512;
 
@@ -212,7 +212,7 @@ fn archetype_vecs() -> &'static Vec<([f32; DIM], &'static str)> {
 
 // ── Detection worker ──────────────────────────────────────────────────────────
 
-pub async fn analyze(event: &ApiEvent, _store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, _store: &impl StateBackend) -> Option<DetectionSignal> {
     if event.prompt.len() < 20 {
         return None;
     }
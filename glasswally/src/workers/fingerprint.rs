@@ -22,7 +22,7 @@ use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
 
 // Known script-client JA3 hashes (TLS ClientHello fingerprints)
 const SCRIPT_JA3: &[&str] = &[
@@ -92,7 +92,7 @@ const PYTHON_REQUESTS_ORDER: &[&str] = &[
     "x-api-key",
 ];
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let mut score = 0.0f32;
     let mut evidence = Vec::new();
 
@@ -25,7 +25,7 @@ use chrono::Utc;
 use serde_json::json;
 
 use crate::events::{ApiEvent, DetectionSignal, H2Settings, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
 
 /// (label, header_table_size, enable_push, initial_window_size, max_frame_size)
 const KNOWN_FINGERPRINTS: &[(&str, u32, u8, u32, u32)] = &[
@@ -66,7 +66,7 @@ fn identify(s: &H2Settings) -> Option<&'static str> {
     None
 }
 
-pub async fn analyze(event: &ApiEvent, _store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, _store: &impl StateBackend) -> Option<DetectionSignal> {
     let h2 = event.h2_settings.as_ref()?;
 
     let mut score = 0.0f32;
@@ -13,9 +13,9 @@ use chrono::Utc;
 use serde_json::json;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let cluster_id = store.get_cluster(&event.account_id)?;
     let members = store.cluster_members(cluster_id);
     let n = members.len();
@@ -47,11 +47,12 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
     let mut all_h2_fps = std::collections::HashSet::new();
     let mut total_requests = 0usize;
 
+    let prefixes = store.subnet_prefixes();
     for member_id in &members {
         if let Some(w) = store.get_window(member_id) {
             let w = w.read();
             all_payments.extend(w.payment_hashes.iter().cloned());
-            shared_subnets.extend(w.subnets());
+            shared_subnets.extend(w.subnets(&prefixes));
             all_countries.extend(w.country_codes.iter().cloned());
             all_h2_fps.extend(w.h2_fingerprints.iter().cloned());
             total_requests += w.events.len();
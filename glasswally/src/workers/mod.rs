@@ -19,11 +19,11 @@ pub mod token_budget;
 pub mod sequence_model;
 
 use crate::events::{ApiEvent, DetectionSignal};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
 
 /// Run all 16 detection workers concurrently and collect their signals.
 /// Workers returning None (insufficient data / no signal) are silently dropped.
-pub async fn run_all(event: &ApiEvent, store: &StateStore) -> Vec<DetectionSignal> {
+pub async fn run_all(event: &ApiEvent, store: &impl StateBackend) -> Vec<DetectionSignal> {
     let (fp, vel, cot_s, hyd, piv, wm, em, tc, h2, bio, asn, role, gap, tok, ref_p, seq) = tokio::join!(
         fingerprint::analyze(event, store),
         velocity::analyze(event, store),
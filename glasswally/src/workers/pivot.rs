@@ -8,12 +8,12 @@ use chrono::{Duration, Utc};
 use serde_json::json;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
 
 const PIVOT_WINDOW_HOURS: i64 = 6;
 const MIN_PIVOT_ACCOUNTS: usize = 5;
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let switches = store.model_switches(&event.account_id);
 
     if switches.is_empty() {
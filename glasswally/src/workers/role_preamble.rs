@@ -26,7 +26,8 @@ use std::collections::HashMap;
 use chrono::Utc;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
+use crate::state::window::value_hash;
 
 // ── Known role preamble archetypes ────────────────────────────────────────────
 // Each entry is a lowercase substring that strongly suggests a systematic
@@ -96,7 +97,7 @@ fn preamble_hash(prompt: &str) -> String {
 
 // ── Main analysis function ────────────────────────────────────────────────────
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     // Need either an explicit system_prompt_hash or a prompt long enough to contain a preamble.
     let preamble_src = if let Some(h) = &event.system_prompt_hash {
         h.clone()
@@ -124,19 +125,17 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
     // ── 2. Within-account template reuse ─────────────────────────────────────
     // Read account preamble hash history from window.
     let window = store.get_window(&event.account_id)?;
-    let preamble_hashes: Vec<String> = {
+    let preamble_hashes: Vec<u64> = {
         let w = window.read();
         w.events
             .iter()
-            .filter_map(|e| e.system_prompt_hash.clone())
+            .filter_map(|e| e.system_prompt_hash)
             .collect()
     };
 
     let reuse_score = if preamble_hashes.len() >= 5 {
-        let same = preamble_hashes
-            .iter()
-            .filter(|h| *h == &preamble_src)
-            .count();
+        let src = value_hash(&preamble_src);
+        let same = preamble_hashes.iter().filter(|h| **h == src).count();
         let frac = same as f32 / preamble_hashes.len() as f32;
         if frac >= 0.80 {
             0.35
@@ -19,21 +19,35 @@
 //   medicine, law, finance, creative, reasoning, language, factual, safety, other).
 //   No ML required; patterns cover the vast majority of extraction prompts.
 //
-// Minimum history required: 15 prompts.
+// Topics are tagged once at ingest and kept on each event summary
+// (state/window.rs), so the chain covers the full 24 h window without
+// retaining prompt text.
+//
+// Multi-week (daily rollups, last 28 days, ≥ 7 active days):
+//   slow_capability_sweep — summed topic histogram entropy ≥ 0.80
+//   daily_topic_rotation  — most days dominated by one topic, ≥ 5 different
+//                           topics across them (a syllabus paced over weeks)
+//
+// Minimum history required: 15 prompts in 24h, or 7 days of rollups.
 
 use std::collections::HashMap;
 
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
+use crate::state::rollup::Rollup;
 
 const MIN_PROMPTS: usize = 15;
+const LONG_LOOKBACK_DAYS: i64 = 28;
+const LONG_MIN_DAYS: usize = 7;
 
 // ── Topic classifier ──────────────────────────────────────────────────────────
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Topic {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
     Code,
     Math,
     Science,
@@ -49,7 +63,7 @@ enum Topic {
 }
 
 impl Topic {
-    fn index(self) -> usize {
+    pub fn index(self) -> usize {
         match self {
             Self::Code => 0,
             Self::Math => 1,
@@ -66,7 +80,7 @@ impl Topic {
         }
     }
 
-    const N: usize = 12;
+    pub const N: usize = 12;
 }
 
 impl std::fmt::Display for Topic {
@@ -92,7 +106,7 @@ impl std::fmt::Display for Topic {
     }
 }
 
-fn classify_topic(prompt: &str) -> Topic {
+pub fn classify_topic(prompt: &str) -> Topic {
     let p = prompt.to_lowercase();
     // Simple priority-ordered keyword matching
     if p.contains("def ")
@@ -219,6 +233,61 @@ fn classify_topic(prompt: &str) -> Topic {
     Topic::Other
 }
 
+/// Shannon entropy of topic counts, normalised to [0,1] by log2(N).
+fn normalized_entropy(counts: &[u32; Topic::N]) -> f64 {
+    let total: u32 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let mut h = 0.0f64;
+    for &c in counts {
+        if c == 0 {
+            continue;
+        }
+        let p = c as f64 / total as f64;
+        h -= p * p.log2();
+    }
+    h / (Topic::N as f64).log2()
+}
+
+// ── Long horizon (state/rollup.rs) ────────────────────────────────────────────
+
+/// Topic coverage over the daily rollups of the last LONG_LOOKBACK_DAYS.
+struct LongHorizon {
+    days: usize,
+    entropy: f64,     // normalised entropy of the summed topic histogram
+    focused: f64,     // fraction of days dominated (≥ 60%) by one topic
+    rotations: usize, // distinct dominant topics across focused days
+}
+
+fn long_horizon(rollup: &Rollup, now: DateTime<Utc>) -> Option<LongHorizon> {
+    let since = now - Duration::days(LONG_LOOKBACK_DAYS);
+    let mut total = [0u32; Topic::N];
+    let mut days = 0usize;
+    let mut focused = 0usize;
+    let mut dominant = [false; Topic::N];
+    for b in rollup.days_since(since) {
+        days += 1;
+        for (t, c) in total.iter_mut().zip(b.topics) {
+            *t += c;
+        }
+        let (top, &n) = b.topics.iter().enumerate().max_by_key(|(_, c)| **c)?;
+        if b.requests > 0 && n as f64 >= 0.60 * b.requests as f64 {
+            focused += 1;
+            dominant[top] = true;
+        }
+    }
+    if days < LONG_MIN_DAYS {
+        return None;
+    }
+    Some(LongHorizon {
+        days,
+        entropy: normalized_entropy(&total),
+        focused: focused as f64 / days as f64,
+        rotations: dominant.iter().filter(|d| **d).count(),
+    })
+}
+
 // ── Markov chain ──────────────────────────────────────────────────────────────
 
 struct MarkovChain {
@@ -247,19 +316,7 @@ impl MarkovChain {
 
     /// Shannon entropy of marginal distribution (normalised by log2(N)).
     fn stationary_entropy(&self) -> f64 {
-        let total: u32 = self.marginal.iter().sum();
-        if total == 0 {
-            return 0.0;
-        }
-        let mut h = 0.0f64;
-        for &c in &self.marginal {
-            if c == 0 {
-                continue;
-            }
-            let p = c as f64 / total as f64;
-            h -= p * p.log2();
-        }
-        h / (Topic::N as f64).log2() // normalise to [0,1]
+        normalized_entropy(&self.marginal)
     }
 
     /// Mean transition entropy (how predictable is the next topic given current).
@@ -296,19 +353,13 @@ impl MarkovChain {
 
 // ── Main analysis ─────────────────────────────────────────────────────────────
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let window = store.get_window(&event.account_id)?;
-    let prompts: Vec<String> = {
+    let topics: Vec<Topic> = {
         let w = window.read();
-        w.events.iter().map(|e| e.prompt.clone()).collect()
+        w.events.iter().map(|e| e.topic).collect()
     };
 
-    if prompts.len() < MIN_PROMPTS {
-        return None;
-    }
-
-    let topics: Vec<Topic> = prompts.iter().map(|p| classify_topic(p)).collect();
-
     let mut chain = MarkovChain::new();
     chain.feed(&topics);
 
@@ -321,44 +372,67 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
     let mut score = 0.0f32;
     let mut evidence = Vec::new();
 
-    // Broad capability coverage
-    if stat_h >= 0.80 {
-        score += 0.40;
-        evidence.push(format!(
-            "broad_topic_coverage:entropy={:.2}_topics={}",
-            stat_h, n_topics
-        ));
-    } else if stat_h >= 0.65 {
-        score += 0.22;
-        evidence.push(format!("moderate_topic_coverage:entropy={:.2}", stat_h));
-    }
+    if topics.len() >= MIN_PROMPTS {
+        // Broad capability coverage
+        if stat_h >= 0.80 {
+            score += 0.40;
+            evidence.push(format!(
+                "broad_topic_coverage:entropy={:.2}_topics={}",
+                stat_h, n_topics
+            ));
+        } else if stat_h >= 0.65 {
+            score += 0.22;
+            evidence.push(format!("moderate_topic_coverage:entropy={:.2}", stat_h));
+        }
+
+        // Predictable topic transitions (systematic drill-down)
+        if trans_h <= 0.25 {
+            score += 0.40;
+            evidence.push(format!(
+                "systematic_topic_drill_down:trans_entropy={:.2}",
+                trans_h
+            ));
+        } else if trans_h <= 0.40 {
+            score += 0.20;
+            evidence.push(format!(
+                "semi_predictable_transitions:trans_entropy={:.2}",
+                trans_h
+            ));
+        }
 
-    // Predictable topic transitions (systematic drill-down)
-    if trans_h <= 0.25 {
-        score += 0.40;
-        evidence.push(format!(
-            "systematic_topic_drill_down:trans_entropy={:.2}",
-            trans_h
-        ));
-    } else if trans_h <= 0.40 {
-        score += 0.20;
-        evidence.push(format!(
-            "semi_predictable_transitions:trans_entropy={:.2}",
-            trans_h
-        ));
+        // Compound: all 12 topics sampled
+        if n_topics >= 10 {
+            score += 0.20;
+            evidence.push(format!("full_capability_sweep:{}_of_12_topics", n_topics));
+        }
     }
 
-    // Compound: all 12 topics sampled
-    if n_topics >= 10 {
-        score += 0.20;
-        evidence.push(format!("full_capability_sweep:{}_of_12_topics", n_topics));
+    // Multi-week coverage: a sweep paced over weeks, often one topic per day
+    let long = store
+        .with_account_rollup(&event.account_id, |r| long_horizon(r, event.timestamp))
+        .flatten();
+    if let Some(l) = &long {
+        if l.entropy >= 0.80 {
+            score += 0.30;
+            evidence.push(format!(
+                "slow_capability_sweep:days={}_entropy={:.2}",
+                l.days, l.entropy
+            ));
+        }
+        if l.focused >= 0.70 && l.rotations >= 5 {
+            score += 0.25;
+            evidence.push(format!(
+                "daily_topic_rotation:{}_topics_over_{}_days",
+                l.rotations, l.days
+            ));
+        }
     }
 
     if score < 0.25 {
         return None;
     }
 
-    let confidence = if stat_h >= 0.80 && trans_h <= 0.25 {
+    let confidence = if topics.len() >= MIN_PROMPTS && stat_h >= 0.80 && trans_h <= 0.25 {
         0.80
     } else if score >= 0.40 {
         0.65
@@ -381,9 +455,17 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
     );
     meta.insert(
         "n_prompts".to_string(),
-        serde_json::Value::Number(serde_json::Number::from(prompts.len() as u64)),
+        serde_json::Value::Number(serde_json::Number::from(topics.len() as u64)),
     );
 
+    if let Some(l) = &long {
+        meta.insert("rollup_days".to_string(), serde_json::json!(l.days));
+        meta.insert(
+            "rollup_entropy".to_string(),
+            serde_json::json!((l.entropy * 1000.0).round() / 1000.0),
+        );
+    }
+
     Some(DetectionSignal {
         worker: WorkerKind::SequenceModel,
         account_id: event.account_id.clone(),
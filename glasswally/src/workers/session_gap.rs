@@ -18,17 +18,28 @@
 //                         combined with cron_regularity → near-certain automation
 //   too_many_sessions   — >20 distinct sessions in 24h → batch job cadence
 //
-// Minimum sessions required: 4
+// Multi-week signals (hourly / daily rollups, state/rollup.rs), over the
+// previous 28 full days with at least 7 active:
+//   daily_cadence       — daily request counts nearly constant (CV < 0.20)
+//   recurring_hours     — the same hours of day active day after day
+//                         (mean Jaccard of consecutive days ≥ 0.80)
+//   no_rest_days        — active ≥ 95% of days over ≥ 21 days
+// These fire on slow-and-low accounts whose 24 h window never fills.
+//
+// Minimum sessions required: 4 (within 24h)
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use chrono::Utc;
+use chrono::{DateTime, Duration, DurationRound, Timelike, Utc};
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
+use crate::state::rollup::{Bucket, Rollup};
 
 const SESSION_BREAK_SECS: i64 = 120; // gap ≥ 2 min → new session
 const MIN_SESSIONS: usize = 4;
+const LONG_LOOKBACK_DAYS: i64 = 28;
+const LONG_MIN_DAYS: usize = 7;
 
 /// Split a chronologically-sorted sequence of Unix timestamps into sessions.
 /// Returns (session_start, session_end, n_requests) per session.
@@ -67,7 +78,58 @@ fn mean_and_cv(vals: &[f64]) -> (f64, f64) {
     (mean, var.sqrt() / mean)
 }
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+/// Long-horizon cadence from daily / hourly rollups (previous full days).
+struct LongHorizon {
+    days: usize,
+    daily_cv: f64,
+    hour_jaccard: f64,
+    active_fraction: f64,
+}
+
+fn long_horizon(rollup: &Rollup, now: DateTime<Utc>) -> Option<LongHorizon> {
+    let today = now.duration_trunc(Duration::days(1)).ok()?;
+    let since = today - Duration::days(LONG_LOOKBACK_DAYS);
+    let daily: Vec<&Bucket> = rollup
+        .days_since(since)
+        .filter(|b| b.start < today)
+        .collect();
+    if daily.len() < LONG_MIN_DAYS {
+        return None;
+    }
+    let counts: Vec<f64> = daily.iter().map(|b| b.requests as f64).collect();
+    let (_, daily_cv) = mean_and_cv(&counts);
+
+    // Active hours of day, per day; similarity of consecutive active days.
+    let mut hours: BTreeMap<DateTime<Utc>, u32> = BTreeMap::new();
+    for b in rollup
+        .hourly
+        .iter()
+        .filter(|b| b.start >= since && b.start < today)
+    {
+        let day = b.start.duration_trunc(Duration::days(1)).ok()?;
+        *hours.entry(day).or_default() |= 1 << b.start.hour();
+    }
+    let sets: Vec<u32> = hours.into_values().collect();
+    let jaccards: Vec<f64> = sets
+        .windows(2)
+        .map(|w| (w[0] & w[1]).count_ones() as f64 / (w[0] | w[1]).count_ones().max(1) as f64)
+        .collect();
+    let hour_jaccard = if jaccards.is_empty() {
+        0.0
+    } else {
+        jaccards.iter().sum::<f64>() / jaccards.len() as f64
+    };
+
+    let span_days = (today - daily[0].start).num_days().max(1) as f64;
+    Some(LongHorizon {
+        days: daily.len(),
+        daily_cv,
+        hour_jaccard,
+        active_fraction: daily.len() as f64 / span_days,
+    })
+}
+
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let window = store.get_window(&event.account_id)?;
 
     let timestamps: Vec<i64> = {
@@ -77,25 +139,22 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
         ts
     };
 
-    if timestamps.len() < 8 {
-        return None;
-    }
-
-    let sessions = sessions_from_timestamps(&timestamps);
-    if sessions.len() < MIN_SESSIONS {
-        return None;
-    }
+    let sessions = if timestamps.len() >= 8 {
+        sessions_from_timestamps(&timestamps)
+    } else {
+        vec![]
+    };
 
     // Inter-session gaps (seconds) between consecutive sessions.
-    let gaps: Vec<f64> = sessions
-        .windows(2)
-        .map(|w| (w[1].0 - w[0].1) as f64)
-        .filter(|&g| g > 0.0)
-        .collect();
-
-    if gaps.is_empty() {
-        return None;
-    }
+    let gaps: Vec<f64> = if sessions.len() >= MIN_SESSIONS {
+        sessions
+            .windows(2)
+            .map(|w| (w[1].0 - w[0].1) as f64)
+            .filter(|&g| g > 0.0)
+            .collect()
+    } else {
+        vec![]
+    };
 
     let (mean_gap, gap_cv) = mean_and_cv(&gaps);
 
@@ -106,44 +165,73 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
     let mut score = 0.0f32;
     let mut evidence = Vec::new();
 
-    // ── 1. Cron regularity ────────────────────────────────────────────────────
-    if gap_cv < 0.05 {
-        score += 0.55;
-        evidence.push(format!(
-            "cron_regularity:gap={:.0}s_cv={:.3}",
-            mean_gap, gap_cv
-        ));
-    } else if gap_cv < 0.08 {
-        score += 0.40;
-        evidence.push(format!(
-            "cron_regularity:gap={:.0}s_cv={:.3}",
-            mean_gap, gap_cv
-        ));
-    } else if gap_cv < 0.15 {
-        score += 0.20;
-        evidence.push(format!(
-            "semi_regular_gaps:gap={:.0}s_cv={:.3}",
-            mean_gap, gap_cv
-        ));
-    }
+    if !gaps.is_empty() {
+        // ── 1. Cron regularity ────────────────────────────────────────────────
+        if gap_cv < 0.05 {
+            score += 0.55;
+            evidence.push(format!(
+                "cron_regularity:gap={:.0}s_cv={:.3}",
+                mean_gap, gap_cv
+            ));
+        } else if gap_cv < 0.08 {
+            score += 0.40;
+            evidence.push(format!(
+                "cron_regularity:gap={:.0}s_cv={:.3}",
+                mean_gap, gap_cv
+            ));
+        } else if gap_cv < 0.15 {
+            score += 0.20;
+            evidence.push(format!(
+                "semi_regular_gaps:gap={:.0}s_cv={:.3}",
+                mean_gap, gap_cv
+            ));
+        }
+
+        // ── 2. Burst uniformity (compound signal) ─────────────────────────────
+        if size_cv < 0.10 && gap_cv < 0.15 {
+            score += 0.25;
+            evidence.push(format!("burst_uniformity:size_cv={:.3}", size_cv));
+        }
 
-    // ── 2. Burst uniformity (compound signal) ─────────────────────────────────
-    if size_cv < 0.10 && gap_cv < 0.15 {
-        score += 0.25;
-        evidence.push(format!("burst_uniformity:size_cv={:.3}", size_cv));
+        // ── 3. Session count density ───────────────────────────────────────────
+        if sessions.len() > 20 {
+            score += 0.10;
+            evidence.push(format!("high_session_count:{}", sessions.len()));
+        }
     }
 
-    // ── 3. Session count density ───────────────────────────────────────────────
-    if sessions.len() > 20 {
-        score += 0.10;
-        evidence.push(format!("high_session_count:{}", sessions.len()));
+    // ── 4. Multi-week cadence (rollups) ───────────────────────────────────────
+    let long = store
+        .with_account_rollup(&event.account_id, |r| long_horizon(r, event.timestamp))
+        .flatten();
+    if let Some(l) = &long {
+        if l.daily_cv < 0.20 {
+            score += 0.30;
+            evidence.push(format!(
+                "daily_cadence:days={}_cv={:.3}",
+                l.days, l.daily_cv
+            ));
+        }
+        if l.hour_jaccard >= 0.80 {
+            score += 0.25;
+            evidence.push(format!(
+                "recurring_hours:days={}_jaccard={:.2}",
+                l.days, l.hour_jaccard
+            ));
+        }
+        if l.days >= 21 && l.active_fraction >= 0.95 {
+            score += 0.10;
+            evidence.push(format!("no_rest_days:{}", l.days));
+        }
     }
 
     if score < 0.15 {
         return None;
     }
 
-    let confidence = if gap_cv < 0.05 {
+    let confidence = if gaps.is_empty() {
+        0.60 // multi-week cadence only
+    } else if gap_cv < 0.05 {
         0.88
     } else if gap_cv < 0.08 {
         0.75
@@ -169,6 +257,14 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
         serde_json::json!((size_cv * 1000.0).round() / 1000.0),
     );
 
+    if let Some(l) = &long {
+        meta.insert("rollup_days".to_string(), serde_json::json!(l.days));
+        meta.insert(
+            "daily_cv".to_string(),
+            serde_json::json!((l.daily_cv * 1000.0).round() / 1000.0),
+        );
+    }
+
     Some(DetectionSignal {
         worker: WorkerKind::SessionGap,
         account_id: event.account_id.clone(),
@@ -179,3 +275,98 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
         timestamp: Utc::now(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::window::{EventSummary, StateStore};
+
+    const LONG_SIGNALS: [&str; 3] = ["daily_cadence", "recurring_hours", "no_rest_days"];
+
+    fn event(ts: DateTime<Utc>) -> ApiEvent {
+        serde_json::from_value(serde_json::json!({
+            "request_id": "r", "account_id": "acct", "timestamp": ts,
+            "ip_address": "198.51.100.1", "user_agent": "ua", "model": "m",
+            "prompt": "p", "token_count": 100,
+            "payment_method_hash": null, "org_id": null, "country_code": "US",
+            "header_order": [], "ja3_hash": null, "ja3s_hash": null,
+            "h2_settings": null, "tls_library": null, "asn_number": null,
+            "asn_org": null, "max_tokens": null, "system_prompt_hash": null,
+            "campaign_label": null
+        }))
+        .unwrap()
+    }
+
+    /// Rollup-only history: `schedule(day)` gives (hour, requests) pairs for
+    /// `day` days ago.  The 24 h window holds a single current event.
+    fn history(schedule: impl Fn(i64) -> Vec<(i64, usize)>) -> (StateStore, ApiEvent) {
+        let store = StateStore::new();
+        let now = Utc::now();
+        let today = now.duration_trunc(Duration::days(1)).unwrap();
+        for day in 1..=LONG_LOOKBACK_DAYS {
+            for (hour, n) in schedule(day) {
+                for i in 0..n {
+                    let ts = today - Duration::days(day)
+                        + Duration::hours(hour)
+                        + Duration::seconds(i as i64 * 30);
+                    let e = event(ts);
+                    store.rollups().record(&e, &EventSummary::of(&e));
+                }
+            }
+        }
+        let current = event(now);
+        store.ingest(&current);
+        (store, current)
+    }
+
+    #[tokio::test]
+    async fn scheduled_job_fires_every_long_horizon_signal() {
+        // 20 requests at 02:00, 03:00 and 04:00, every day for four weeks.
+        let (store, current) = history(|_| vec![(2, 20), (3, 20), (4, 20)]);
+        let sig = analyze(&current, &store)
+            .await
+            .expect("long-horizon signal");
+        for name in LONG_SIGNALS {
+            assert!(
+                sig.evidence.iter().any(|e| e.starts_with(name)),
+                "{name} in {:?}",
+                sig.evidence
+            );
+        }
+        assert!(sig.evidence.contains(&"no_rest_days:28".to_string()));
+        assert_eq!(sig.confidence, 0.60); // no 24 h sessions behind it
+        assert_eq!(sig.meta["rollup_days"], serde_json::json!(28));
+    }
+
+    #[tokio::test]
+    async fn ordinary_usage_fires_none_of_them() {
+        // Weekdays only, at varying hours, with varying volume.
+        let (store, current) = history(|day| {
+            if day % 7 < 2 {
+                return vec![];
+            }
+            let hour = 8 + (day * 5) % 11;
+            vec![
+                (hour, 2 + (day as usize * 7) % 13),
+                (hour + 3, 1 + day as usize % 4),
+            ]
+        });
+        let evidence = analyze(&current, &store)
+            .await
+            .map(|s| s.evidence)
+            .unwrap_or_default();
+        for name in LONG_SIGNALS {
+            assert!(
+                !evidence.iter().any(|e| e.starts_with(name)),
+                "{name} in {evidence:?}"
+            );
+        }
+
+        // Fewer than LONG_MIN_DAYS active days: no long-horizon view at all.
+        let (store, _) = history(|day| if day <= 3 { vec![(2, 20)] } else { vec![] });
+        let l = store
+            .with_account_rollup("acct", |r| long_horizon(r, Utc::now()))
+            .flatten();
+        assert!(l.is_none());
+    }
+}
@@ -22,14 +22,14 @@ use chrono::Utc;
 use serde_json::json;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
 
 const MIN_BURST_SIZE: usize = 5; // accounts per 1s window to fire signal
 const STRONG_BURST: usize = 12; // accounts per window for high confidence
 const RECUR_MIN_BURSTS: usize = 3; // recurring bursts needed to confirm cadence
 const CADENCE_LOOKBACK: u64 = 300; // seconds of history to scan for cadence
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let bucket = event.timestamp.timestamp() as u64;
 
     // StateStore.ingest() already recorded this account in the bucket.
@@ -27,7 +27,7 @@ use std::collections::HashMap;
 use chrono::Utc;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::state::backend::StateBackend;
 
 /// Common model maximum context sizes (token counts).
 const MODEL_MAXIMA: &[u32] = &[1024, 2048, 4096, 8192, 16384, 32768, 65536, 128000, 200000];
@@ -90,7 +90,7 @@ fn is_arithmetic(vals: &[u32]) -> bool {
     cv < 0.20
 }
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     // Only meaningful when max_tokens is present in the API request.
     let _current_max = event.max_tokens?;
 
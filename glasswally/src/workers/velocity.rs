@@ -2,19 +2,117 @@
 //
 // Velocity worker — timing regularity, RPH, token uniformity, off-hours.
 // Runs on every event. O(n) where n = events in 1hr window.
+//
+// The same aggregates are computed for the event's org, payment method and
+// subnet entity windows (state/entity.rs): keys rotated within one org each
+// stay under the account thresholds, but the org as a whole runs at scripted
+// cadence.  An entity contributes when at least 5 accounts were active on it
+// in the hour and its traffic looks scripted (regular interarrivals or
+// uniform token counts) — a large legitimate org is fast but irregular.
 
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use serde_json::json;
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::{StateStore, W_1HR, W_24HR};
+use crate::state::backend::StateBackend;
+use crate::state::window::{EventSummary, EventWindow, W_1HR, W_24HR};
+
+const ENTITY_MIN_ACCOUNTS: usize = 5;
+const ENTITY_MIN_EVENTS: usize = 20;
+
+/// 1 − CV of interarrival gaps (0 below three gaps).
+fn regularity(ias: &[f64]) -> f32 {
+    if ias.len() < 3 {
+        return 0.0;
+    }
+    let mean = ias.iter().sum::<f64>() / ias.len() as f64;
+    let std = (ias.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / ias.len() as f64).sqrt();
+    let cv = if mean > 0.0 { std / mean } else { 0.0 };
+    (1.0 - cv).max(0.0) as f32
+}
+
+/// CV of token counts (None below five events).
+fn token_cv(evs: &[&EventSummary]) -> Option<f64> {
+    if evs.len() < 5 {
+        return None;
+    }
+    let tokens: Vec<f64> = evs.iter().map(|e| e.token_count as f64).collect();
+    let mean = tokens.iter().sum::<f64>() / tokens.len() as f64;
+    let std = (tokens.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / tokens.len() as f64).sqrt();
+    Some(if mean > 0.0 { std / mean } else { 0.0 })
+}
+
+/// Strongest entity-level velocity component across the event's entities.
+fn entity_velocity(event: &ApiEvent, store: &impl StateBackend) -> (f32, Vec<String>) {
+    let since = event.timestamp - Duration::seconds(W_1HR);
+    let mut best = (0.0f32, Vec::new());
+    for (kind, key) in store.entity_keys(event) {
+        let Some(w) = store.get_entity(kind, &key) else {
+            continue;
+        };
+        let w = w.read();
+        let n_accounts = w.accounts_since(since);
+        let evs = w.events_in(W_1HR);
+        if n_accounts < ENTITY_MIN_ACCOUNTS || evs.len() < ENTITY_MIN_EVENTS {
+            continue;
+        }
+        let reg = regularity(&w.interarrivals(W_1HR));
+        let uniform = token_cv(&evs).is_some_and(|cv| cv < 0.15);
+        if reg <= 0.50 && !uniform {
+            continue;
+        }
+        let rph = w.rate_per_hour(W_1HR);
+        let mut score = 0.0f32;
+        let mut evidence = Vec::new();
+        if reg > 0.70 {
+            score += 0.25;
+            evidence.push(format!("{}_scripted_timing:{:.2}", kind, reg));
+        } else if reg > 0.50 {
+            score += 0.12;
+            evidence.push(format!("{}_semi_regular:{:.2}", kind, reg));
+        }
+        if uniform {
+            score += 0.10;
+            evidence.push(format!("{}_uniform_tokens", kind));
+        }
+        if rph > 200.0 {
+            score += 0.25;
+            evidence.push(format!(
+                "{}_velocity:{:.0}rph_over_{}_accounts",
+                kind, rph, n_accounts
+            ));
+        } else if rph > 60.0 {
+            score += 0.12;
+            evidence.push(format!(
+                "{}_velocity:{:.0}rph_over_{}_accounts",
+                kind, rph, n_accounts
+            ));
+        }
+        if score > best.0 {
+            best = (score, evidence);
+        }
+    }
+    best
+}
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let window = store.get_window(&event.account_id)?;
     let window = window.read();
+    let (entity_score, entity_evidence) = entity_velocity(event, store);
 
     let evs_1h = window.events_in(W_1HR);
     let n = evs_1h.len();
+    if n < 5 && entity_score > 0.0 {
+        return Some(DetectionSignal {
+            worker: WorkerKind::Velocity,
+            account_id: event.account_id.clone(),
+            score: (entity_score * 10000.0).round() / 10000.0,
+            confidence: 0.5,
+            evidence: entity_evidence,
+            meta: [("n_1h".into(), json!(n))].into_iter().collect(),
+            timestamp: Utc::now(),
+        });
+    }
     if n < 5 {
         return Some(DetectionSignal {
             worker: WorkerKind::Velocity,
@@ -42,14 +140,7 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
 
     // Interarrival regularity (CV of gaps)
     let ias = window.interarrivals(W_1HR);
-    let regularity = if ias.len() >= 3 {
-        let mean = ias.iter().sum::<f64>() / ias.len() as f64;
-        let std = (ias.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / ias.len() as f64).sqrt();
-        let cv = if mean > 0.0 { std / mean } else { 0.0 };
-        (1.0 - cv).max(0.0) as f32
-    } else {
-        0.0
-    };
+    let regularity = regularity(&ias);
 
     if regularity > 0.70 {
         score += 0.30;
@@ -60,12 +151,7 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
     }
 
     // Token count uniformity
-    let tokens: Vec<f64> = evs_1h.iter().map(|e| e.token_count as f64).collect();
-    if tokens.len() >= 5 {
-        let mean = tokens.iter().sum::<f64>() / tokens.len() as f64;
-        let std =
-            (tokens.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / tokens.len() as f64).sqrt();
-        let cv = if mean > 0.0 { std / mean } else { 0.0 };
+    if let Some(cv) = token_cv(&evs_1h) {
         if cv < 0.15 {
             score += 0.15;
             evidence.push(format!("uniform_tokens:cv={:.3}", cv));
@@ -91,6 +177,10 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
         evidence.push(format!("off_hours_utc:{:.0}%", off_ratio * 100.0));
     }
 
+    // Org / payment / subnet level
+    score += entity_score;
+    evidence.extend(entity_evidence);
+
     let confidence = (n as f32 / 50.0).min(1.0);
     score = (score * (0.5 + 0.5 * confidence)).min(1.0);
 
@@ -10,19 +10,22 @@
 //   - 32-bit account-specific key: SHA256("gw_wm_v1:" || account_id)
 //   - Encoded as ZWJ (bit=1) / ZWNJ (bit=0) inserted after spaces in text
 //   - Invisible to readers, survives most copy-paste operations
-//   - Detectable by scanning for ZWJ/ZWNJ sequences, comparing to registry
+//   - Detectable by reading the ZWJ/ZWNJ that follow spaces and testing
+//     their agreement with each registered key (exact binomial tail)
 //
 // This worker detects meta-attacks against the watermarking system:
 //   - Prompts asking about invisible Unicode characters (probing)
 //   - Zero-width characters arriving in inbound prompts (strip attempts)
 //   - Accounts flagged for canary injection (returning watermarked content)
+//   - Accounts replaying another account's canaries / watermark
 
 use chrono::Utc;
 use serde_json::json;
 use sha2::{Digest, Sha256};
 
 use crate::events::{ApiEvent, DetectionSignal, WorkerKind};
-use crate::state::window::StateStore;
+use crate::robust_watermark::binomial_tail;
+use crate::state::backend::StateBackend;
 
 const ZWJ: char = '\u{200D}'; // zero-width joiner   → bit 1
 const ZWNJ: char = '\u{200C}'; // zero-width non-joiner → bit 0
@@ -41,14 +44,25 @@ pub fn account_watermark_bits(account_id: &str) -> [bool; 32] {
     bits
 }
 
+/// Whether `c` is a watermark bit (ZWJ or ZWNJ).
+pub fn is_mark(c: char) -> bool {
+    c == ZWJ || c == ZWNJ
+}
+
 /// Embed the account's watermark into a response string.
 ///
 /// Inserts ZWJ (bit=1) or ZWNJ (bit=0) immediately after each space.
 /// The 32-bit sequence cycles across the full text.
 pub fn embed(text: &str, account_id: &str) -> String {
+    embed_from(text, account_id, 0).0
+}
+
+/// `embed` continuing at bit `start_bit` — for text that arrives in pieces
+/// (streamed chunks).  Returns the text and the next bit index.
+pub fn embed_from(text: &str, account_id: &str, start_bit: usize) -> (String, usize) {
     let bits = account_watermark_bits(account_id);
     let mut out = String::with_capacity(text.len() + text.len() / 5);
-    let mut bit_idx = 0usize;
+    let mut bit_idx = start_bit;
 
     for ch in text.chars() {
         out.push(ch);
@@ -57,39 +71,127 @@ pub fn embed(text: &str, account_id: &str) -> String {
             bit_idx += 1;
         }
     }
-    out
+    (out, bit_idx)
 }
 
-/// Scan text for a watermark matching any of the known accounts.
-/// Returns (account_id, confidence 0..1) if ≥85% of bits match.
-pub fn detect(text: &str, accounts: &[String]) -> Option<(String, f32)> {
-    let extracted: Vec<bool> = text
-        .chars()
-        .filter(|&c| c == ZWJ || c == ZWNJ)
-        .map(|c| c == ZWJ)
-        .collect();
+// ── Canary token encoding ─────────────────────────────────────────────────────
+// Per-response canary tokens ride in a separate invisible channel so they do
+// not disturb the ZWJ/ZWNJ watermark: 128 token bits as U+2061 (0) / U+2062
+// (1), framed by U+2063 on both sides.
 
-    if extracted.len() < 8 {
-        return None;
+const CANARY_FRAME: char = '\u{2063}'; // invisible separator
+const CANARY_0: char = '\u{2061}'; // function application
+const CANARY_1: char = '\u{2062}'; // invisible times
+
+/// Invisible encoding of a 32-hex-char canary token.
+pub fn encode_canary(token: &str) -> String {
+    let mut out = String::new();
+    out.push(CANARY_FRAME);
+    for byte in hex::decode(token).unwrap_or_default() {
+        for i in (0..8).rev() {
+            out.push(if (byte >> i) & 1 == 1 {
+                CANARY_1
+            } else {
+                CANARY_0
+            });
+        }
     }
+    out.push(CANARY_FRAME);
+    out
+}
 
-    for account_id in accounts {
-        let expected = account_watermark_bits(account_id);
-        let check_len = extracted.len().min(64);
-        let matches = (0..check_len)
-            .filter(|&i| extracted[i] == expected[i % 32])
-            .count();
-        let confidence = matches as f32 / check_len as f32;
-        if confidence >= 0.85 {
-            return Some((account_id.clone(), confidence));
+/// Canary tokens encoded with `encode_canary` anywhere in `text`.
+pub fn decode_canaries(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    for frame in text.split(CANARY_FRAME).skip(1).step_by(2) {
+        let bits: Vec<u8> = frame
+            .chars()
+            .filter_map(|c| match c {
+                CANARY_0 => Some(0),
+                CANARY_1 => Some(1),
+                _ => None,
+            })
+            .collect();
+        if bits.len() != 128 {
+            continue;
         }
+        let bytes: Vec<u8> = bits
+            .chunks(8)
+            .map(|b| b.iter().fold(0u8, |acc, bit| (acc << 1) | bit))
+            .collect();
+        out.push(hex::encode(bytes));
     }
-    None
+    out
+}
+
+/// Fewest aligned bits `detect` will judge: one full key cycle.
+pub const MIN_ALIGNED_BITS: usize = 32;
+
+/// False-positive rate `detect` allows per scan, across all accounts compared.
+pub const DETECT_ALPHA: f64 = 1e-6;
+
+/// Watermark bits as `embed` writes them: a ZWJ/ZWNJ directly after a space.
+/// Joiners anywhere else — inside Persian or Arabic words, between the code
+/// points of an emoji sequence — are ordinary text and are not read.
+pub fn aligned_bits(text: &str) -> Vec<bool> {
+    let mut out = Vec::new();
+    let mut prev = '\0';
+    for c in text.chars() {
+        if prev == ' ' && is_mark(c) {
+            out.push(c == ZWJ);
+        }
+        prev = c;
+    }
+    out
+}
+
+/// Bits agreeing with the account's key, read from bit 0 as `embed` writes them.
+pub fn matching_bits(bits: &[bool], account_id: &str) -> usize {
+    let expected = account_watermark_bits(account_id);
+    bits.iter()
+        .enumerate()
+        .filter(|&(i, &b)| b == expected[i % 32])
+        .count()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatermarkMatch {
+    pub account_id: String,
+    pub bits: usize,
+    pub matching: usize,
+    pub p_value: f64, // Bonferroni-adjusted over the accounts compared
+}
+
+/// Scan text for a watermark matching one of the known accounts.
+///
+/// Needs at least MIN_ALIGNED_BITS aligned bits; each account is scored with
+/// the exact binomial tail P(Binomial(n, ½) ≥ matching), multiplied by the
+/// number of accounts, and the best one is returned if that stays below
+/// DETECT_ALPHA.
+pub fn detect(text: &str, accounts: &[String]) -> Option<WatermarkMatch> {
+    let bits = aligned_bits(text);
+    if bits.len() < MIN_ALIGNED_BITS {
+        return None;
+    }
+    let n_accounts = accounts.len() as f64;
+    accounts
+        .iter()
+        .map(|account_id| {
+            let matching = matching_bits(&bits, account_id);
+            WatermarkMatch {
+                account_id: account_id.clone(),
+                bits: bits.len(),
+                matching,
+                p_value: (binomial_tail(bits.len(), matching) * n_accounts).min(1.0),
+            }
+        })
+        .filter(|m| m.p_value < DETECT_ALPHA)
+        .min_by(|a, b| a.p_value.total_cmp(&b.p_value))
 }
 
 // ── Detection worker ──────────────────────────────────────────────────────────
 
-pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSignal> {
+pub async fn analyze(event: &ApiEvent, store: &impl StateBackend) -> Option<DetectionSignal> {
     let mut score = 0.0f32;
     let mut evidence = Vec::new();
 
@@ -134,6 +236,20 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
         evidence.push(format!("zwsp_in_prompt:{}_markers", zw_count));
     }
 
+    // ── Another account's canary / watermark replayed (engine/canary_scan.rs) ─
+    let hits = store.canary_hits(&event.account_id);
+    if let Some(best) = hits
+        .iter()
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    {
+        score += 0.60 * best.confidence;
+        evidence.push(format!(
+            "canary_replay:{}_hits origin={}",
+            hits.len(),
+            best.origin_account
+        ));
+    }
+
     // ── Account already under active watermark surveillance ───────────────────
     if store.is_watermarked(&event.account_id) {
         score += 0.05;
@@ -153,6 +269,7 @@ pub async fn analyze(event: &ApiEvent, store: &StateStore) -> Option<DetectionSi
         meta: [
             ("zw_count".into(), json!(zw_count)),
             ("probe_hits".into(), json!(probe_hits.len())),
+            ("canary_hits".into(), json!(hits.len())),
         ]
         .into_iter()
         .collect(),